@@ -5,8 +5,16 @@
  */
 
 use std::fs;
+use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
-use void::{Error, Store};
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::Layer;
+use void::{
+    Cipher, Compression, Credentials, DiffReport, Error, Kdf, KdfParams, MetadataValue,
+    PasswordPolicy, Progress, SharedStore, SortKey, Store, StoreBuilder, Zeroizing, MAX_CHUNK_SIZE,
+    TRASH,
+};
 
 fn gen_file(path: &str, size: usize) {
     let big_chunk: Vec<u8> = (0..size).map(|_| rand::random::<u8>()).collect();
@@ -30,6 +38,39 @@ fn compare_files(file1: &str, file2: &str) {
     assert_eq!(c1, c2);
 }
 
+/// Records the names of spans and the messages of events.
+#[derive(Clone, Default)]
+struct Recorder {
+    spans: Arc<Mutex<Vec<String>>>,
+    events: Arc<Mutex<Vec<String>>>,
+}
+
+impl<S: tracing::Subscriber> Layer<S> for Recorder {
+    fn on_new_span(
+        &self,
+        attrs: &tracing::span::Attributes<'_>,
+        _: &tracing::span::Id,
+        _: Context<'_, S>,
+    ) {
+        let name = attrs.metadata().name().to_string();
+        self.spans.lock().unwrap().push(name);
+    }
+
+    fn on_event(&self, event: &tracing::Event<'_>, _: Context<'_, S>) {
+        struct Message(String);
+        impl tracing::field::Visit for Message {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0 = format!("{value:?}");
+                }
+            }
+        }
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        self.events.lock().unwrap().push(message.0);
+    }
+}
+
 #[test]
 fn test_store() -> Result<(), Error> {
     if Path::new("tmp").exists() {
@@ -151,3 +192,3143 @@ fn test_store() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn test_part_files() -> Result<(), Error> {
+    if Path::new("tmp_part_files").exists() {
+        fs::remove_dir_all("tmp_part_files").unwrap();
+    }
+
+    fs::create_dir_all("tmp_part_files").unwrap();
+    gen_file("tmp_part_files/big", 52428801);
+
+    let mut store = Store::create("tmp_part_files/store", "1234")?;
+    store.add("tmp_part_files/big", "/")?;
+
+    let parts = store.part_files("/big")?;
+    assert_eq!(2, parts.len());
+
+    let store_folder = std::env::current_dir()
+        .unwrap()
+        .join("tmp_part_files/store");
+    let mut on_disk: Vec<String> = fs::read_dir(&store_folder)
+        .unwrap()
+        .map(|entry| entry.unwrap().path().to_string_lossy().to_string())
+        .filter(|path| !path.ends_with("Store.void"))
+        .collect();
+    on_disk.sort();
+    let mut sorted_parts = parts.clone();
+    sorted_parts.sort();
+    assert_eq!(on_disk, sorted_parts);
+
+    assert_eq!(store.part_files("/nope"), Err(Error::FileDoesNotExistError));
+
+    fs::remove_dir_all("tmp_part_files").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_sync() -> Result<(), Error> {
+    if Path::new("tmp_sync").exists() {
+        fs::remove_dir_all("tmp_sync").unwrap();
+    }
+
+    fs::create_dir_all("tmp_sync/folder/sub").unwrap();
+    gen_file("tmp_sync/folder/file1", 512);
+    gen_file("tmp_sync/folder/file2", 512);
+    gen_file("tmp_sync/folder/sub/file3", 512);
+
+    let mut store = Store::create("tmp_sync/store", "1234")?;
+    store.sync("tmp_sync/folder", "/backup", false)?;
+    assert_eq!(3, store.list("/backup")?.len());
+    assert_eq!(1, store.list("/backup/sub")?.len());
+
+    let read_parts = |store: &Store, path: &str| -> Vec<Vec<u8>> {
+        store
+            .part_files(path)
+            .unwrap()
+            .iter()
+            .map(|part| fs::read(part).unwrap())
+            .collect()
+    };
+
+    let file1 = read_parts(&store, "/backup/file1");
+    let file2 = read_parts(&store, "/backup/file2");
+    let file3 = read_parts(&store, "/backup/sub/file3");
+
+    store.tag_add("/backup/file2", "tag")?;
+    gen_file("tmp_sync/folder/file2", 256);
+    store.sync("tmp_sync/folder", "/backup", false)?;
+
+    assert_eq!(file1, read_parts(&store, "/backup/file1"));
+    assert_ne!(file2, read_parts(&store, "/backup/file2"));
+    assert_eq!(file3, read_parts(&store, "/backup/sub/file3"));
+    assert_eq!(vec!["tag".to_string()], store.tag_get("/backup/file2")?);
+    assert_eq!(256, store.list("/backup/file2")?[0].size);
+    assert_eq!(4, dir_ls_count("tmp_sync/store"));
+
+    store.get("/backup/file2", "tmp_sync/got")?;
+    compare_files("tmp_sync/folder/file2", "tmp_sync/got");
+
+    println!("Tests sync with deletion");
+    fs::remove_file("tmp_sync/folder/file1").unwrap();
+    store.sync("tmp_sync/folder", "/backup", false)?;
+    assert_eq!(3, store.list("/backup")?.len());
+    store.sync("tmp_sync/folder", "/backup", true)?;
+    assert_eq!(2, store.list("/backup")?.len());
+    assert_eq!(3, dir_ls_count("tmp_sync/store"));
+
+    println!("Tests that links are kept as links when not followed");
+    std::os::unix::fs::symlink("file2", "tmp_sync/folder/link").unwrap();
+    let mut store = store.with_follow_links(false);
+    store.sync("tmp_sync/folder", "/backup", true)?;
+    assert_eq!(Some("file2"), store.stat("/backup/link")?.link.as_deref());
+    store.sync("tmp_sync/folder", "/backup", true)?;
+    assert_eq!(Some("file2"), store.stat("/backup/link")?.link.as_deref());
+    assert_eq!(3, dir_ls_count("tmp_sync/store"));
+
+    println!("Tests that files synced before an error are saved");
+    fs::create_dir_all("tmp_sync/quota").unwrap();
+    gen_file("tmp_sync/quota/a", 600);
+    gen_file("tmp_sync/quota/b", 600);
+    let (mut store, _) = StoreBuilder::new("tmp_sync/limited", "1234")
+        .quota_bytes(1000)
+        .build()?;
+    assert_eq!(
+        store.sync("tmp_sync/quota", "/", false),
+        Err(Error::QuotaExceeded)
+    );
+    let mut store = Store::open("tmp_sync/limited", "1234")?;
+    assert_eq!(1, store.list("/")?.len());
+
+    fs::remove_dir_all("tmp_sync").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_part_count() -> Result<(), Error> {
+    if Path::new("tmp_part_count").exists() {
+        fs::remove_dir_all("tmp_part_count").unwrap();
+    }
+
+    fs::create_dir_all("tmp_part_count").unwrap();
+    gen_file("tmp_part_count/small", 512);
+    gen_file("tmp_part_count/big", 52428801);
+
+    let mut store = Store::create("tmp_part_count/store", "1234")?;
+    store.add("tmp_part_count/small", "/")?;
+    store.add("tmp_part_count/big", "/")?;
+
+    assert_eq!(1, store.stat("/small")?.part_count());
+    assert!(store.stat("/big")?.part_count() > 1);
+    assert_eq!(0, store.stat("/")?.part_count());
+
+    fs::remove_dir_all("tmp_part_count").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_mv() -> Result<(), Error> {
+    if Path::new("tmp_mv").exists() {
+        fs::remove_dir_all("tmp_mv").unwrap();
+    }
+
+    fs::create_dir_all("tmp_mv").unwrap();
+    gen_file("tmp_mv/x", 512);
+
+    let mut store = Store::create("tmp_mv/store", "1234")?;
+    store.add("tmp_mv/x", "/a/x")?;
+    store.add("tmp_mv/x", "/b/z")?;
+
+    println!("Tests moving into an existing folder");
+    store.mv("/a/x", "/b")?;
+    assert!(store.list("/a")?.is_empty());
+    assert_eq!(1, store.list("/b/x")?.len());
+
+    println!("Tests moving and renaming");
+    store.mv("/b/x", "/c/y")?;
+    assert_eq!(1, store.list("/b")?.len());
+    assert_eq!(1, store.list("/c/y")?.len());
+    store.get("/c/y", "tmp_mv/got")?;
+    compare_files("tmp_mv/x", "tmp_mv/got");
+
+    println!("Tests that files are not overwritten");
+    assert_eq!(store.mv("/c/y", "/b/z"), Err(Error::FileAlreadyExistsError));
+    assert_eq!(store.mv("/c", "/c/d"), Err(Error::CannotMoveError));
+
+    println!("Tests that moves are saved");
+    let mut store = Store::open("tmp_mv/store", "1234")?;
+    assert_eq!(1, store.list("/c/y")?.len());
+
+    fs::remove_dir_all("tmp_mv").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_recovery() -> Result<(), Error> {
+    if Path::new("tmp_recovery").exists() {
+        fs::remove_dir_all("tmp_recovery").unwrap();
+    }
+
+    fs::create_dir_all("tmp_recovery").unwrap();
+    gen_file("tmp_recovery/file", 512);
+
+    let (mut store, phrase) = Store::create_with_recovery("tmp_recovery/store", "1234")?;
+    store.add("tmp_recovery/file", "/")?;
+
+    println!("Tests that the password still opens the store");
+    let store = Store::open("tmp_recovery/store", "1234")?;
+    assert_eq!(1, store.stat("/file")?.part_count());
+
+    println!("Tests opening with the recovery phrase after a wrong password");
+    assert_eq!(
+        Store::open("tmp_recovery/store", "4321").err(),
+        Some(Error::WrongPassword)
+    );
+    let mut store = Store::open_with_recovery("tmp_recovery/store", phrase.as_str())?;
+    store.get("/file", "tmp_recovery/got")?;
+    compare_files("tmp_recovery/file", "tmp_recovery/got");
+
+    let phrase = phrase.to_uppercase().replace('-', " ");
+    assert!(Store::open_with_recovery("tmp_recovery/store", phrase.as_str()).is_ok());
+    assert_eq!(
+        Store::open_with_recovery("tmp_recovery/store", "0000").err(),
+        Some(Error::WrongPassword)
+    );
+
+    println!("Tests stores without a recovery phrase");
+    Store::create("tmp_recovery/plain", "1234")?;
+    assert_eq!(
+        Store::open_with_recovery("tmp_recovery/plain", phrase.as_str()).err(),
+        Some(Error::NoRecoveryKeyError)
+    );
+
+    fs::remove_dir_all("tmp_recovery").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_tar() -> Result<(), Error> {
+    if Path::new("tmp_tar").exists() {
+        fs::remove_dir_all("tmp_tar").unwrap();
+    }
+
+    fs::create_dir_all("tmp_tar/folder/sub").unwrap();
+    fs::create_dir_all("tmp_tar/folder/empty").unwrap();
+    gen_file("tmp_tar/folder/small", 512);
+    gen_file("tmp_tar/folder/sub/big", 52428801);
+
+    let mut store = Store::create("tmp_tar/store", "1234")?;
+    store.add("tmp_tar/folder", "/")?;
+
+    let mut archive: Vec<u8> = vec![];
+    store.export_tar("/folder", &mut archive)?;
+
+    println!("Tests importing into another store");
+    let mut other = Store::create("tmp_tar/other", "1234")?;
+    other.import_tar(archive.as_slice(), "/restored")?;
+    assert_eq!(3, other.list("/restored/folder")?.len());
+    assert!(other.list("/restored/folder/empty")?.is_empty());
+    assert_eq!(2, other.stat("/restored/folder/sub/big")?.part_count());
+
+    let mut other = Store::open("tmp_tar/other", "1234")?;
+    other.get("/restored/folder/small", "tmp_tar/small")?;
+    other.get("/restored/folder/sub/big", "tmp_tar/big")?;
+    compare_files("tmp_tar/folder/small", "tmp_tar/small");
+    compare_files("tmp_tar/folder/sub/big", "tmp_tar/big");
+
+    println!("Tests that files are not overwritten");
+    let mut other = Store::open("tmp_tar/other", "1234")?;
+    assert_eq!(
+        other.import_tar(archive.as_slice(), "/restored"),
+        Err(Error::FileAlreadyExistsError)
+    );
+
+    println!("Tests that a broken archive leaves the store as it was");
+    let parts = dir_ls_count("tmp_tar/other");
+    let broken = &archive[..archive.len() - 4096];
+    assert_eq!(
+        other.import_tar(broken, "/broken"),
+        Err(Error::CannotReadFileError)
+    );
+    assert!(other.stat("/broken").is_err());
+    assert_eq!(parts, dir_ls_count("tmp_tar/other"));
+    let other = Store::open("tmp_tar/other", "1234")?;
+    assert!(other.stat("/broken").is_err());
+
+    println!("Tests exporting the root");
+    let mut archive: Vec<u8> = vec![];
+    store.export_tar("/", &mut archive)?;
+    let mut other = Store::create("tmp_tar/root", "1234")?;
+    other.import_tar(archive.as_slice(), "/")?;
+    assert_eq!(1, other.list("/")?.len());
+    assert_eq!(2, other.stat("/folder/sub/big")?.part_count());
+
+    fs::remove_dir_all("tmp_tar").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_disk_usage() -> Result<(), Error> {
+    if Path::new("tmp_disk_usage").exists() {
+        fs::remove_dir_all("tmp_disk_usage").unwrap();
+    }
+
+    fs::create_dir_all("tmp_disk_usage/folder/sub/deeper").unwrap();
+    fs::create_dir_all("tmp_disk_usage/folder/empty").unwrap();
+    gen_file("tmp_disk_usage/folder/file1", 512);
+    gen_file("tmp_disk_usage/folder/sub/file2", 1024);
+    gen_file("tmp_disk_usage/folder/sub/deeper/file3", 2048);
+
+    let mut store = Store::create("tmp_disk_usage/store", "1234")?;
+    store.add("tmp_disk_usage/folder", "/")?;
+
+    assert_eq!(3584, store.disk_usage("/")?);
+    assert_eq!(3584, store.disk_usage("/folder")?);
+    assert_eq!(3072, store.disk_usage("/folder/sub")?);
+    assert_eq!(1024, store.disk_usage("/folder/sub/file2")?);
+    assert_eq!(0, store.disk_usage("/folder/empty")?);
+    assert_eq!(
+        store.disk_usage("/folder/missing"),
+        Err(Error::FileDoesNotExistError)
+    );
+
+    fs::remove_dir_all("tmp_disk_usage").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_list_with_sizes() -> Result<(), Error> {
+    if Path::new("tmp_list_with_sizes").exists() {
+        fs::remove_dir_all("tmp_list_with_sizes").unwrap();
+    }
+
+    fs::create_dir_all("tmp_list_with_sizes/folder/sub/deeper").unwrap();
+    fs::create_dir_all("tmp_list_with_sizes/folder/empty").unwrap();
+    gen_file("tmp_list_with_sizes/folder/file1", 512);
+    gen_file("tmp_list_with_sizes/folder/sub/file2", 1024);
+    gen_file("tmp_list_with_sizes/folder/sub/deeper/file3", 2048);
+
+    let mut store = Store::create("tmp_list_with_sizes/store", "1234")?;
+    store.add("tmp_list_with_sizes/folder", "/")?;
+
+    let files = store.list_with_sizes("/folder")?;
+    let size = |name: &str| files.iter().find(|file| file.name == name).unwrap().size;
+    assert_eq!(3, files.len());
+    assert_eq!(512, size("file1"));
+    assert_eq!(1024 + 2048, size("sub"));
+    assert_eq!(0, size("empty"));
+    assert_eq!(
+        0,
+        store.list("/folder")?.iter().map(|f| f.size).min().unwrap()
+    );
+
+    println!("Tests that every folder is the sum of its descendants");
+    for file in store.list_with_sizes("*")? {
+        assert_eq!(store.disk_usage(&file.name)?, file.size);
+    }
+    assert_eq!(
+        3584,
+        store
+            .list_with_sizes("/")?
+            .iter()
+            .map(|f| f.size)
+            .sum::<u64>()
+    );
+
+    fs::remove_dir_all("tmp_list_with_sizes").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_add_overwrite() -> Result<(), Error> {
+    if Path::new("tmp_overwrite").exists() {
+        fs::remove_dir_all("tmp_overwrite").unwrap();
+    }
+
+    fs::create_dir_all("tmp_overwrite/new").unwrap();
+    gen_file("tmp_overwrite/file", 52428801);
+    gen_file("tmp_overwrite/new/file", 512);
+
+    let mut store = Store::create("tmp_overwrite/store", "1234")?;
+    store.add("tmp_overwrite/file", "/")?;
+    store.tag_add("/file", "tag")?;
+    let old_parts = store.part_files("/file")?;
+    assert_eq!(2, old_parts.len());
+
+    println!("Tests that add does not overwrite by default");
+    assert_eq!(
+        store.add("tmp_overwrite/new/file", "/file"),
+        Err(Error::FileAlreadyExistsError)
+    );
+
+    println!("Tests overwriting a file");
+    store.add_overwrite("tmp_overwrite/new/file", "/file", true)?;
+    let new_parts = store.part_files("/file")?;
+    assert_eq!(1, new_parts.len());
+    for part in old_parts.iter().filter(|part| !new_parts.contains(part)) {
+        assert!(!Path::new(part).exists());
+    }
+    assert_eq!(512, store.stat("/file")?.size);
+    assert_eq!(vec!["tag".to_string()], store.tag_get("/file")?);
+    assert_eq!(2, dir_ls_count("tmp_overwrite/store"));
+
+    println!("Tests overwriting files in a folder");
+    store.add_overwrite("tmp_overwrite/new/", "/", true)?;
+    assert_eq!(1, store.list("/")?.len());
+
+    let mut store = Store::open("tmp_overwrite/store", "1234")?;
+    store.get("/file", "tmp_overwrite/got")?;
+    compare_files("tmp_overwrite/new/file", "tmp_overwrite/got");
+
+    fs::remove_dir_all("tmp_overwrite").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_link() -> Result<(), Error> {
+    if Path::new("tmp_link").exists() {
+        fs::remove_dir_all("tmp_link").unwrap();
+    }
+
+    fs::create_dir_all("tmp_link").unwrap();
+    gen_file("tmp_link/file", 52428801);
+
+    let mut store = Store::create("tmp_link/store", "1234")?;
+    store.add("tmp_link/file", "/a/file")?;
+    store.link("/a/file", "/b/link")?;
+    assert_eq!(store.part_files("/a/file")?, store.part_files("/b/link")?);
+    assert_eq!(3, dir_ls_count("tmp_link/store"));
+    assert_eq!(
+        store.link("/a/file", "/b/link"),
+        Err(Error::FileAlreadyExistsError)
+    );
+
+    println!("Tests linking into a folder");
+    store.link("/a/file", "/b")?;
+    assert_eq!(2, store.list("/b")?.len());
+
+    println!("Tests that removing the original keeps the shared parts");
+    store.remove("/a/file")?;
+    assert_eq!(3, dir_ls_count("tmp_link/store"));
+    let mut store = Store::open("tmp_link/store", "1234")?;
+    store.get("/b/link", "tmp_link/got")?;
+    compare_files("tmp_link/file", "tmp_link/got");
+
+    println!("Tests that removing the last reference deletes the parts");
+    store.remove("/b/link")?;
+    assert_eq!(3, dir_ls_count("tmp_link/store"));
+    store.remove("/b/file")?;
+    assert_eq!(1, dir_ls_count("tmp_link/store"));
+
+    fs::remove_dir_all("tmp_link").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_tag_rename() -> Result<(), Error> {
+    if Path::new("tmp_tag_rename").exists() {
+        fs::remove_dir_all("tmp_tag_rename").unwrap();
+    }
+
+    fs::create_dir_all("tmp_tag_rename/folder").unwrap();
+    gen_file("tmp_tag_rename/folder/file1", 512);
+    gen_file("tmp_tag_rename/folder/file2", 512);
+    gen_file("tmp_tag_rename/folder/file3", 512);
+
+    let mut store = Store::create("tmp_tag_rename/store", "1234")?;
+    store.add("tmp_tag_rename/folder", "/")?;
+    store.tag_add("/folder", "old")?;
+    store.tag_add("/folder/file1", "old")?;
+    store.tag_add("/folder/file2", "old")?;
+    store.tag_add("/folder/file2", "new")?;
+    store.tag_add("/folder/file3", "other")?;
+
+    store.tag_rename("old", "new")?;
+
+    let mut store = Store::open("tmp_tag_rename/store", "1234")?;
+    let mut tags = store.tag_list();
+    tags.sort();
+    assert_eq!(vec!["new".to_string(), "other".to_string()], tags);
+    assert!(store.tag_search(vec!["old".into()]).is_empty());
+    assert_eq!(3, store.tag_search(vec!["new".into()]).len());
+    assert_eq!(vec!["new".to_string()], store.tag_get("/folder/file2")?);
+    assert_eq!(vec!["other".to_string()], store.tag_get("/folder/file3")?);
+
+    fs::remove_dir_all("tmp_tag_rename").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_tag_counts() -> Result<(), Error> {
+    if Path::new("tmp_tag_counts").exists() {
+        fs::remove_dir_all("tmp_tag_counts").unwrap();
+    }
+
+    fs::create_dir_all("tmp_tag_counts/folder").unwrap();
+    gen_file("tmp_tag_counts/folder/file1", 512);
+    gen_file("tmp_tag_counts/folder/file2", 512);
+
+    let mut store = Store::create("tmp_tag_counts/store", "1234")?;
+    assert!(store.tag_counts().is_empty());
+
+    store.add("tmp_tag_counts/folder", "/")?;
+    store.tag_add("/folder", "red")?;
+    store.tag_add("/folder/file1", "red")?;
+    store.tag_add("/folder/file1", "blue")?;
+    store.tag_add("/folder/file2", "red")?;
+    store.tag_add("/folder/file2", "red")?;
+
+    let counts = store.tag_counts();
+    assert_eq!(2, counts.len());
+    assert_eq!(Some(&3), counts.get("red"));
+    assert_eq!(Some(&1), counts.get("blue"));
+
+    store.tag_rm("/folder", "red")?;
+    assert_eq!(Some(&2), store.tag_counts().get("red"));
+
+    fs::remove_dir_all("tmp_tag_counts").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_statistics() -> Result<(), Error> {
+    if Path::new("tmp_statistics").exists() {
+        fs::remove_dir_all("tmp_statistics").unwrap();
+    }
+
+    fs::create_dir_all("tmp_statistics/folder/sub").unwrap();
+    gen_file("tmp_statistics/folder/file1", 512);
+    gen_file("tmp_statistics/folder/sub/file2", 52428801);
+
+    let mut store = Store::create("tmp_statistics/store", "1234")?;
+    store.add("tmp_statistics/folder", "/")?;
+    store.tag_add("/folder/file1", "a")?;
+    store.tag_add("/folder/file1", "b")?;
+    store.tag_add("/folder/sub", "a")?;
+
+    let stats = store.statistics()?;
+    assert_eq!(2, stats.files);
+    assert_eq!(2, stats.folders);
+    assert_eq!(52429313, stats.total_bytes);
+    assert_eq!(2, stats.tags);
+    assert_eq!(3, stats.parts);
+
+    let disk_size: u64 = fs::read_dir("tmp_statistics/store")
+        .unwrap()
+        .map(|entry| entry.unwrap().metadata().unwrap().len())
+        .sum();
+    assert_eq!(disk_size, stats.disk_size);
+    assert!(stats.disk_size > stats.total_bytes);
+
+    fs::remove_dir_all("tmp_statistics").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_open_errors() -> Result<(), Error> {
+    if Path::new("tmp_open_errors").exists() {
+        fs::remove_dir_all("tmp_open_errors").unwrap();
+    }
+
+    fs::create_dir_all("tmp_open_errors").unwrap();
+    gen_file("tmp_open_errors/file", 512);
+
+    let mut store = Store::create("tmp_open_errors/store", "1234")?;
+    store.add("tmp_open_errors/file", "/")?;
+
+    println!("Tests opening with a wrong password");
+    assert_eq!(
+        Store::open("tmp_open_errors/store", "4321").err(),
+        Some(Error::WrongPassword)
+    );
+    assert!(Store::open("tmp_open_errors/store", "1234").is_ok());
+
+    println!("Tests opening a tampered store");
+    let journal = "tmp_open_errors/store/Store.void";
+    let original = fs::read(journal).unwrap();
+    for index in [original.len() / 4, original.len() / 2] {
+        let mut tampered = original.clone();
+        tampered[index] ^= 0x01;
+        fs::write(journal, &tampered).unwrap();
+        assert_eq!(
+            Store::open("tmp_open_errors/store", "1234").err(),
+            Some(Error::StoreCorrupted)
+        );
+    }
+
+    fs::write(journal, &original[..original.len() / 2]).unwrap();
+    assert_eq!(
+        Store::open("tmp_open_errors/store", "1234").err(),
+        Some(Error::StoreCorrupted)
+    );
+
+    println!("Tests opening a store whose header has a chunk size of 0");
+    let chunk_size: u32 = 0x0BAD_C0DE;
+    StoreBuilder::new("tmp_open_errors/chunks", "1234")
+        .chunk_size(chunk_size.into())
+        .build()?;
+    let journal = "tmp_open_errors/chunks/Store.void";
+    let mut header = fs::read(journal).unwrap();
+    let pattern = chunk_size.to_le_bytes();
+    let at = header.windows(4).position(|w| w == pattern).unwrap();
+    header[at..at + 4].fill(0);
+    fs::write(journal, &header).unwrap();
+    assert_eq!(
+        Store::open("tmp_open_errors/chunks", "1234").err(),
+        Some(Error::StoreCorrupted)
+    );
+
+    fs::remove_dir_all("tmp_open_errors").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_canary() -> Result<(), Error> {
+    if Path::new("tmp_canary").exists() {
+        fs::remove_dir_all("tmp_canary").unwrap();
+    }
+
+    fs::create_dir_all("tmp_canary").unwrap();
+
+    let store = Store::create("tmp_canary/store", "1234")?;
+    let key = store.key();
+
+    assert_eq!(
+        Store::open("tmp_canary/store", "4321").err(),
+        Some(Error::WrongPassword)
+    );
+    assert_eq!(
+        Store::open_with_key("tmp_canary/store", [0u8; 32]).err(),
+        Some(Error::WrongPassword)
+    );
+    assert!(Store::open("tmp_canary/store", "1234").is_ok());
+    assert!(Store::open_with_key("tmp_canary/store", key).is_ok());
+
+    let (_, phrase) = Store::create_with_recovery("tmp_canary/recovery", "1234")?;
+    assert!(Store::open("tmp_canary/recovery", "1234").is_ok());
+    assert!(Store::open_with_recovery("tmp_canary/recovery", phrase.as_str()).is_ok());
+
+    fs::remove_dir_all("tmp_canary").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_tracing() -> Result<(), Error> {
+    if Path::new("tmp_tracing").exists() {
+        fs::remove_dir_all("tmp_tracing").unwrap();
+    }
+
+    fs::create_dir_all("tmp_tracing/folder").unwrap();
+    gen_file("tmp_tracing/folder/file1", 512);
+    gen_file("tmp_tracing/folder/file2", 52428801);
+
+    let recorder = Recorder::default();
+    let subscriber = tracing_subscriber::registry().with(recorder.clone());
+    tracing::subscriber::with_default(subscriber, || -> Result<(), Error> {
+        let mut store = Store::create("tmp_tracing/store", "1234")?;
+        store.add("tmp_tracing/folder", "/")?;
+        Ok(())
+    })?;
+
+    let spans = recorder.spans.lock().unwrap();
+    let events = recorder.events.lock().unwrap();
+    assert_eq!(3, spans.iter().filter(|name| *name == "add").count());
+    assert!(spans.iter().any(|name| name == "save"));
+    assert_eq!(
+        3,
+        events
+            .iter()
+            .filter(|message| *message == "encrypting chunk")
+            .count()
+    );
+
+    fs::remove_dir_all("tmp_tracing").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_builder() -> Result<(), Error> {
+    if Path::new("tmp_builder").exists() {
+        fs::remove_dir_all("tmp_builder").unwrap();
+    }
+
+    fs::create_dir_all("tmp_builder").unwrap();
+    gen_file("tmp_builder/file", 2500000);
+
+    let kdf_params = KdfParams {
+        memory: 1024,
+        iterations: 1,
+        parallelism: 1,
+    };
+    let (mut store, phrase) = StoreBuilder::new("tmp_builder/store", "1234")
+        .cipher(Cipher::ChaCha20Poly1305)
+        .kdf(Kdf::Argon2id)
+        .kdf_params(kdf_params)
+        .compression(Compression::Deflate)
+        .chunk_size(1048576)
+        .with_recovery(true)
+        .build()?;
+    store.add("tmp_builder/file", "/")?;
+    assert_eq!(3, store.stat("/file")?.part_count());
+
+    println!("Tests that the options persist in the header");
+    let mut store = Store::open("tmp_builder/store", "1234")?;
+    assert_eq!(Cipher::ChaCha20Poly1305, store.cipher());
+    assert_eq!(Kdf::Argon2id, store.kdf());
+    assert_eq!(kdf_params, store.kdf_params());
+    assert_eq!(Compression::Deflate, store.compression());
+    assert_eq!(1048576, store.chunk_size());
+    store.get("/file", "tmp_builder/got")?;
+    compare_files("tmp_builder/file", "tmp_builder/got");
+
+    let phrase = phrase.unwrap();
+    assert!(Store::open_with_recovery("tmp_builder/store", phrase.as_str()).is_ok());
+    assert_eq!(
+        Store::open("tmp_builder/store", "4321").err(),
+        Some(Error::WrongPassword)
+    );
+
+    println!("Tests that defaults are kept by create");
+    let store = Store::create("tmp_builder/default", "1234")?;
+    assert_eq!(Cipher::Aes256Gcm, store.cipher());
+    assert_eq!(Kdf::HkdfSha256, store.kdf());
+    assert_eq!(Compression::None, store.compression());
+    assert_eq!(52428800, store.chunk_size());
+
+    println!("Tests the largest chunk size");
+    let (store, _) = StoreBuilder::new("tmp_builder/large_chunks", "1234")
+        .cipher(Cipher::ChaCha20Poly1305)
+        .chunk_size(MAX_CHUNK_SIZE)
+        .build()?;
+    assert_eq!(MAX_CHUNK_SIZE, store.chunk_size());
+
+    println!("Tests invalid options");
+    let result = StoreBuilder::new("tmp_builder/invalid", "1234")
+        .chunk_size(0)
+        .build();
+    assert_eq!(result.err(), Some(Error::InvalidChunkSize));
+    let result = StoreBuilder::new("tmp_builder/invalid", "1234")
+        .chunk_size(MAX_CHUNK_SIZE + 1)
+        .build();
+    assert_eq!(result.err(), Some(Error::InvalidChunkSize));
+    let result = StoreBuilder::new("tmp_builder/invalid", "1234")
+        .cipher(Cipher::ChaCha20Poly1305)
+        .chunk_size(Cipher::Aes256Gcm.max_chunk_size() + 1)
+        .build();
+    assert_eq!(result.err(), Some(Error::InvalidChunkSize));
+    let result = StoreBuilder::new("tmp_builder/invalid", "1234")
+        .chunk_size(Cipher::Aes256Gcm.max_chunk_size())
+        .compression(Compression::Deflate)
+        .build();
+    assert_eq!(result.err(), Some(Error::InvalidChunkSize));
+    let result = StoreBuilder::new("tmp_builder/invalid", "1234")
+        .kdf(Kdf::Argon2id)
+        .kdf_params(KdfParams {
+            memory: 0,
+            ..kdf_params
+        })
+        .build();
+    assert_eq!(result.err(), Some(Error::InvalidKdfParams));
+    assert!(!Path::new("tmp_builder/invalid").exists());
+
+    fs::remove_dir_all("tmp_builder").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_kdf_ids() -> Result<(), Error> {
+    if Path::new("tmp_kdf_ids").exists() {
+        fs::remove_dir_all("tmp_kdf_ids").unwrap();
+    }
+
+    fs::create_dir_all("tmp_kdf_ids").unwrap();
+    gen_file("tmp_kdf_ids/file", 25000);
+
+    let kdf_params = KdfParams {
+        memory: 1024,
+        iterations: 1,
+        parallelism: 1,
+    };
+    for kdf in [Kdf::HkdfSha256, Kdf::HkdfBlake2b, Kdf::Argon2id] {
+        println!("Tests a store created with {kdf}");
+        let path = format!("tmp_kdf_ids/{kdf}");
+        let (mut store, phrase) = StoreBuilder::new(path.as_str(), "1234")
+            .kdf(kdf)
+            .kdf_params(kdf_params)
+            .with_recovery(true)
+            .build()?;
+        store.add("tmp_kdf_ids/file", "/")?;
+
+        let mut store = Store::open(path.as_str(), "1234")?;
+        assert_eq!(kdf, store.kdf());
+        store.get("/file", "tmp_kdf_ids/got")?;
+        compare_files("tmp_kdf_ids/file", "tmp_kdf_ids/got");
+        fs::remove_file("tmp_kdf_ids/got").unwrap();
+
+        let store = Store::open_with_recovery(path.as_str(), phrase.unwrap().as_str())?;
+        assert_eq!(kdf, store.kdf());
+        assert_eq!(
+            Store::open(path.as_str(), "4321").err(),
+            Some(Error::WrongPassword)
+        );
+    }
+
+    fs::remove_dir_all("tmp_kdf_ids").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_read_range() -> Result<(), Error> {
+    if Path::new("tmp_read_range").exists() {
+        fs::remove_dir_all("tmp_read_range").unwrap();
+    }
+
+    fs::create_dir_all("tmp_read_range").unwrap();
+    gen_file("tmp_read_range/file", 5000);
+    let original = fs::read("tmp_read_range/file").unwrap();
+
+    let (mut store, _) = StoreBuilder::new("tmp_read_range/store", "1234")
+        .chunk_size(1024)
+        .build()?;
+    store.add("tmp_read_range/file", "/")?;
+    assert_eq!(5, store.stat("/file")?.part_count());
+
+    println!("Tests ranges inside a chunk and spanning chunk boundaries");
+    assert_eq!(store.read_range("/file", 10, 100)?, original[10..110]);
+    assert_eq!(store.read_range("/file", 1000, 100)?, original[1000..1100]);
+    assert_eq!(store.read_range("/file", 1000, 3000)?, original[1000..4000]);
+    assert_eq!(store.read_range("/file", 0, 5000)?, original);
+
+    println!("Tests ranges past the end of the file");
+    assert_eq!(store.read_range("/file", 4900, 1000)?, original[4900..]);
+    assert!(store.read_range("/file", 6000, 10)?.is_empty());
+    assert!(store.read_range("/file", 10, 0)?.is_empty());
+
+    println!("Tests that folders and missing files cannot be read");
+    fs::create_dir_all("tmp_read_range/folder").unwrap();
+    store.add("tmp_read_range/folder", "/")?;
+    assert_eq!(
+        store.read_range("/folder", 0, 10).err(),
+        Some(Error::FileDoesNotExistError)
+    );
+    assert_eq!(
+        store.read_range("/missing", 0, 10).err(),
+        Some(Error::FileDoesNotExistError)
+    );
+
+    fs::remove_dir_all("tmp_read_range").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_part_lengths() -> Result<(), Error> {
+    if Path::new("tmp_part_lengths").exists() {
+        fs::remove_dir_all("tmp_part_lengths").unwrap();
+    }
+
+    fs::create_dir_all("tmp_part_lengths").unwrap();
+    gen_file("tmp_part_lengths/file1", 5000);
+    gen_file("tmp_part_lengths/file2", 1024);
+    gen_file("tmp_part_lengths/file3", 10);
+
+    let (mut store, _) = StoreBuilder::new("tmp_part_lengths/store", "1234")
+        .chunk_size(1024)
+        .compression(Compression::Deflate)
+        .build()?;
+
+    for (name, parts) in [("file1", 5), ("file2", 1), ("file3", 1)] {
+        store.add(&format!("tmp_part_lengths/{name}"), "/")?;
+        let file = store.stat(&format!("/{name}"))?;
+        assert_eq!(parts, file.part_count());
+        assert!(file.data.iter().all(|data| data.len > 0));
+        let total: u64 = file.data.iter().map(|data| data.len).sum();
+        assert_eq!(file.size, total);
+    }
+
+    println!("Tests that lengths are persisted");
+    let store = Store::open("tmp_part_lengths/store", "1234")?;
+    let file = store.stat("/file1")?;
+    let lengths: Vec<u64> = file.data.iter().map(|data| data.len).collect();
+    assert_eq!(lengths, vec![1024, 1024, 1024, 1024, 904]);
+
+    fs::remove_dir_all("tmp_part_lengths").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_add_from_reader() -> Result<(), Error> {
+    if Path::new("tmp_add_from_reader").exists() {
+        fs::remove_dir_all("tmp_add_from_reader").unwrap();
+    }
+
+    fs::create_dir_all("tmp_add_from_reader").unwrap();
+    let mut store = Store::create("tmp_add_from_reader/store", "1234")?;
+
+    let content: Vec<u8> = (0..5000).map(|i| (i % 256) as u8).collect();
+    store.add_from_reader(&mut content.as_slice(), "/a/b/file", false)?;
+    assert_eq!(store.read_range("/a/b/file", 0, 5000)?, content);
+
+    println!("Tests that existing files are only replaced on request");
+    assert_eq!(
+        store.add_from_reader(&mut &b"new"[..], "/a/b/file", false),
+        Err(Error::FileAlreadyExistsError)
+    );
+    store.add_from_reader(&mut &b"new"[..], "/a/b/file", true)?;
+    assert_eq!(store.read_range("/a/b/file", 0, 5000)?, b"new");
+    assert_eq!(
+        store.add_from_reader(&mut &b"new"[..], "/a/b", true),
+        Err(Error::FileAlreadyExistsError)
+    );
+
+    fs::remove_dir_all("tmp_add_from_reader").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_iter() -> Result<(), Error> {
+    if Path::new("tmp_iter").exists() {
+        fs::remove_dir_all("tmp_iter").unwrap();
+    }
+
+    fs::create_dir_all("tmp_iter/folder/sub1").unwrap();
+    fs::create_dir_all("tmp_iter/folder/sub2").unwrap();
+    gen_file("tmp_iter/folder/file1", 10);
+    gen_file("tmp_iter/folder/sub1/file2", 10);
+    gen_file("tmp_iter/folder/sub2/file3", 10);
+
+    let mut store = Store::create("tmp_iter/store", "1234")?;
+    store.add("tmp_iter/folder", "/")?;
+    store.add("tmp_iter/folder/file1", "/")?;
+
+    let mut iterated: Vec<(u64, String)> = store.iter().map(|file| (file.id, file.name)).collect();
+    let mut listed: Vec<(u64, String)> = store
+        .list("*")?
+        .into_iter()
+        .map(|file| (file.id, file.name))
+        .collect();
+    iterated.sort();
+    listed.sort();
+    assert_eq!(iterated.len(), 7);
+    assert_eq!(iterated, listed);
+
+    println!("Tests that folders come before their contents");
+    let paths: Vec<String> = store.iter().map(|file| file.name).collect();
+    let position = |path: &str| paths.iter().position(|p| p == path).unwrap();
+    assert!(position("/folder") < position("/folder/sub1"));
+    assert!(position("/folder/sub1") < position("/folder/sub1/file2"));
+
+    fs::remove_dir_all("tmp_iter").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_touch() -> Result<(), Error> {
+    if Path::new("tmp_touch").exists() {
+        fs::remove_dir_all("tmp_touch").unwrap();
+    }
+
+    fs::create_dir_all("tmp_touch").unwrap();
+    let mut store = Store::create("tmp_touch/store", "1234")?;
+    store.touch("/a/b/empty")?;
+
+    let file = store.stat("/a/b/empty")?;
+    assert!(file.is_file);
+    assert_eq!(0, file.size);
+    assert_eq!(0, file.part_count());
+    assert!(!store.stat("/a/b")?.is_file);
+
+    println!("Tests that touching again keeps the file");
+    store.touch("/a/b/empty")?;
+    assert_eq!(file.id, store.stat("/a/b/empty")?.id);
+    assert_eq!(store.touch("/a/b"), Err(Error::FileAlreadyExistsError));
+
+    println!("Tests that the empty file survives reopening and can be read");
+    let mut store = Store::open("tmp_touch/store", "1234")?;
+    assert!(store.read_range("/a/b/empty", 0, 10)?.is_empty());
+    store.get("/a/b/empty", "tmp_touch/empty")?;
+    assert_eq!(0, fs::metadata("tmp_touch/empty").unwrap().len());
+
+    fs::remove_dir_all("tmp_touch").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_mkdir() -> Result<(), Error> {
+    if Path::new("tmp_mkdir").exists() {
+        fs::remove_dir_all("tmp_mkdir").unwrap();
+    }
+
+    fs::create_dir_all("tmp_mkdir").unwrap();
+    let mut store = Store::create("tmp_mkdir/store", "1234")?;
+
+    store.mkdir_p("/a/b/c")?;
+    store.mkdir("/a/b/d")?;
+    let mut store = Store::open("tmp_mkdir/store", "1234")?;
+    assert!(store.list("/a/b/c")?.is_empty());
+    let mut names: Vec<String> = store.list("/a/b")?.into_iter().map(|f| f.name).collect();
+    names.sort();
+    assert_eq!(names, vec!["c", "d"]);
+    assert!(!store.stat("/a/b/d")?.is_file);
+
+    println!("Tests mkdir errors");
+    assert_eq!(store.mkdir("/a/b/c"), Err(Error::FileAlreadyExistsError));
+    assert_eq!(store.mkdir("/x/y"), Err(Error::FolderDoesNotExistError));
+    store.touch("/a/file")?;
+    assert_eq!(store.mkdir("/a/file"), Err(Error::FileAlreadyExistsError));
+    assert_eq!(store.mkdir_p("/a/file"), Err(Error::FileAlreadyExistsError));
+    assert_eq!(
+        store.mkdir("/a/file/sub"),
+        Err(Error::CannotCreateDirectoryError)
+    );
+    assert_eq!(
+        store.mkdir_p("/a/file/sub"),
+        Err(Error::CannotCreateDirectoryError)
+    );
+
+    println!("Tests that mkdir_p accepts existing folders");
+    store.mkdir_p("/a/b")?;
+    assert_eq!(2, store.list("/a/b")?.len());
+
+    fs::remove_dir_all("tmp_mkdir").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_backups() -> Result<(), Error> {
+    if Path::new("tmp_backups").exists() {
+        fs::remove_dir_all("tmp_backups").unwrap();
+    }
+
+    fs::create_dir_all("tmp_backups").unwrap();
+    let mut store = Store::create("tmp_backups/store", "1234")?.with_backups(2);
+    let journal = "tmp_backups/store/Store.void";
+    assert!(!Path::new("tmp_backups/store/Store.void.bak").exists());
+
+    let first = fs::read(journal).unwrap();
+    store.touch("/a")?;
+    assert_eq!(fs::read("tmp_backups/store/Store.void.bak").unwrap(), first);
+
+    let second = fs::read(journal).unwrap();
+    store.touch("/b")?;
+    assert_eq!(
+        fs::read("tmp_backups/store/Store.void.bak").unwrap(),
+        second
+    );
+    assert_eq!(
+        fs::read("tmp_backups/store/Store.void.bak.1").unwrap(),
+        first
+    );
+
+    println!("Tests that only the requested number of backups is kept");
+    store.touch("/c")?;
+    assert!(!Path::new("tmp_backups/store/Store.void.bak.2").exists());
+
+    println!("Tests that a backup holds the previous state");
+    fs::copy("tmp_backups/store/Store.void.bak", journal).unwrap();
+    let mut store = Store::open("tmp_backups/store", "1234")?;
+    assert!(store.stat("/b").is_ok());
+    assert!(store.stat("/c").is_err());
+
+    println!("Tests that every save encrypts the filesystem with a new iv");
+    let first = store.save_to_bytes()?;
+    let second = store.save_to_bytes()?;
+    assert_ne!(first, second);
+    for bytes in [first, second] {
+        assert!(Store::open_from_bytes(&bytes, "1234")?.stat("/b").is_ok());
+    }
+
+    fs::remove_dir_all("tmp_backups").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_salvage() -> Result<(), Error> {
+    if Path::new("tmp_salvage").exists() {
+        fs::remove_dir_all("tmp_salvage").unwrap();
+    }
+
+    fs::create_dir_all("tmp_salvage").unwrap();
+    gen_file("tmp_salvage/file1", 3000);
+    gen_file("tmp_salvage/file2", 10);
+
+    let (mut store, _) = StoreBuilder::new("tmp_salvage/store", "1234")
+        .chunk_size(1024)
+        .build()?;
+    store.add("tmp_salvage/file1", "/")?;
+    store.add("tmp_salvage/file2", "/")?;
+
+    let mut expected: Vec<String> = [store.part_files("/file1")?, store.part_files("/file2")?]
+        .concat()
+        .iter()
+        .map(|part| {
+            Path::new(part)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string()
+        })
+        .collect();
+    expected.sort();
+    assert_eq!(4, expected.len());
+
+    fs::remove_file("tmp_salvage/store/Store.void").unwrap();
+    fs::write("tmp_salvage/store/notes.txt", "not a part").unwrap();
+    assert_eq!(
+        Store::open("tmp_salvage/store", "1234").err(),
+        Some(Error::FileDoesNotExistError)
+    );
+    assert_eq!(Store::salvage("tmp_salvage/store")?, expected);
+    assert_eq!(
+        Store::salvage("tmp_salvage/missing"),
+        Err(Error::FolderDoesNotExistError)
+    );
+
+    fs::remove_dir_all("tmp_salvage").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_mirrored_index() -> Result<(), Error> {
+    if Path::new("tmp_mirror").exists() {
+        fs::remove_dir_all("tmp_mirror").unwrap();
+    }
+
+    fs::create_dir_all("tmp_mirror").unwrap();
+    gen_file("tmp_mirror/file", 3000);
+
+    let mut store = Store::create("tmp_mirror/store", "1234")?.with_mirrored_index(true);
+    store.add("tmp_mirror/file", "/")?;
+    let journal = "tmp_mirror/store/Store.void";
+    let mirror = "tmp_mirror/store/Store.void.mirror";
+    assert_eq!(fs::read(journal).unwrap(), fs::read(mirror).unwrap());
+
+    println!("Tests that open recovers from the mirror");
+    let mut bytes = fs::read(journal).unwrap();
+    let middle = bytes.len() / 2;
+    bytes[middle] ^= 0xff;
+    fs::write(journal, &bytes).unwrap();
+    let mut store = Store::open("tmp_mirror/store", "1234")?;
+    store.get("/file", "tmp_mirror/got")?;
+    compare_files("tmp_mirror/file", "tmp_mirror/got");
+
+    fs::write(journal, b"garbage").unwrap();
+    assert!(Store::open("tmp_mirror/store", "1234").is_ok());
+
+    println!("Tests that corruption is reported without a mirror");
+    fs::remove_file(mirror).unwrap();
+    assert_eq!(
+        Store::open("tmp_mirror/store", "1234").err(),
+        Some(Error::StoreCorrupted)
+    );
+
+    fs::remove_dir_all("tmp_mirror").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_add_bytes() -> Result<(), Error> {
+    if Path::new("tmp_add_bytes").exists() {
+        fs::remove_dir_all("tmp_add_bytes").unwrap();
+    }
+
+    fs::create_dir_all("tmp_add_bytes").unwrap();
+    let (mut store, _) = StoreBuilder::new("tmp_add_bytes/store", "1234")
+        .chunk_size(1024)
+        .build()?;
+
+    let content: Vec<u8> = (0..5000).map(|i| (i % 256) as u8).collect();
+    store.add_bytes(&content, "/folder/file", Some("application/x-test"))?;
+    let file = store.stat("/folder/file")?;
+    assert_eq!(5000, file.size);
+    assert_eq!(5, file.part_count());
+    assert_eq!(
+        Some("application/x-test"),
+        file.metadata.get("mimetype").map(String::as_str)
+    );
+
+    let mut store = Store::open("tmp_add_bytes/store", "1234")?;
+    store.get("/folder/file", "tmp_add_bytes/got")?;
+    assert_eq!(fs::read("tmp_add_bytes/got").unwrap(), content);
+
+    println!("Tests mimetype detection, empty slices and existing files");
+    store.add_bytes(b"plain text\n", "/plain", None)?;
+    assert!(!store.stat("/plain")?.metadata.contains_key("mimetype"));
+    store.add_bytes_detecting_mimetype(b"plain text\n", "/text")?;
+    assert!(store.stat("/text")?.metadata.contains_key("mimetype"));
+    store.add_bytes(&[], "/empty", None)?;
+    assert_eq!(0, store.stat("/empty")?.size);
+    assert_eq!(
+        store.add_bytes(b"again", "/text", None),
+        Err(Error::FileAlreadyExistsError)
+    );
+
+    fs::remove_dir_all("tmp_add_bytes").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_exists() -> Result<(), Error> {
+    if Path::new("tmp_exists").exists() {
+        fs::remove_dir_all("tmp_exists").unwrap();
+    }
+
+    fs::create_dir_all("tmp_exists").unwrap();
+    let mut store = Store::create("tmp_exists/store", "1234")?;
+    store.touch("/folder/file")?;
+
+    assert!(store.exists("/")?);
+    assert!(store.exists("/folder")?);
+    assert!(store.exists("/folder/file")?);
+    assert!(store.exists("/folder/file/")?);
+    assert!(!store.exists("/missing")?);
+    assert!(!store.exists("/folder/file/child")?);
+
+    fs::remove_dir_all("tmp_exists").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_is_dir_is_file() -> Result<(), Error> {
+    if Path::new("tmp_is_dir").exists() {
+        fs::remove_dir_all("tmp_is_dir").unwrap();
+    }
+
+    fs::create_dir_all("tmp_is_dir").unwrap();
+    let mut store = Store::create("tmp_is_dir/store", "1234")?;
+    store.touch("/folder/file")?;
+
+    assert!(store.is_file("/folder/file")?);
+    assert!(!store.is_dir("/folder/file")?);
+    assert!(store.is_dir("/folder")?);
+    assert!(!store.is_file("/folder")?);
+    assert!(store.is_dir("/")?);
+    assert_eq!(store.is_dir("/missing"), Err(Error::FileDoesNotExistError));
+    assert_eq!(store.is_file("/missing"), Err(Error::FileDoesNotExistError));
+
+    fs::remove_dir_all("tmp_is_dir").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_error_codes() {
+    let errors = vec![
+        Error::CannotCreateDirectoryError,
+        Error::CannotCreateFileError,
+        Error::CannotEncryptFileError,
+        Error::CannotDecryptFileError,
+        Error::CannotDeserializeError,
+        Error::CannotParseError,
+        Error::CannotReadFileError,
+        Error::CannotRemoveFilesError(vec!["part".into()]),
+        Error::CannotSerializeError,
+        Error::CannotWriteFileError,
+        Error::FileAlreadyExistsError,
+        Error::FileDoesNotExistError,
+        Error::FolderDoesNotExistError,
+        Error::StoreFileAlreadyExistsError,
+        Error::NoSuchMetadataKey,
+        Error::MetadataKeyAlreadyExists,
+        Error::InternalStructureError,
+        Error::InvalidName,
+        Error::CannotMoveError,
+        Error::NoRecoveryKeyError,
+        Error::WrongPassword,
+        Error::StoreCorrupted,
+        Error::InvalidKdfParams,
+        Error::InvalidChunkSize,
+        Error::KeyfileRequired,
+        Error::WeakPassword,
+        Error::QuotaExceeded,
+        Error::IoError(std::io::ErrorKind::PermissionDenied),
+    ];
+
+    let mut codes: Vec<&str> = errors.iter().map(Error::code).collect();
+    for code in &codes {
+        assert!(!code.is_empty());
+        assert!(code.bytes().all(|b| b.is_ascii_lowercase() || b == b'_'));
+    }
+    codes.sort();
+    codes.dedup();
+    assert_eq!(codes.len(), errors.len());
+
+    let error = Error::CannotRemoveFilesError(vec!["part".into()]);
+    assert_eq!(error.clone(), error);
+    assert_eq!(error.clone().code(), "cannot_remove_files");
+}
+
+#[test]
+fn test_error_messages() {
+    let message = format!("{}", Error::FileDoesNotExistError);
+    assert_eq!(message, "The file does not exist.");
+
+    let errors = [
+        Error::WrongPassword,
+        Error::StoreCorrupted,
+        Error::CannotRemoveFilesError(vec!["a".into(), "b".into()]),
+        Error::IoError(std::io::ErrorKind::PermissionDenied),
+    ];
+    for error in errors {
+        let message = error.to_string();
+        assert_ne!(message, format!("{error:?}"));
+        assert!(message.starts_with(|c: char| c.is_uppercase()));
+        assert!(message.ends_with('.'));
+        assert!(message.contains(' '));
+    }
+    assert_eq!(
+        Error::CannotRemoveFilesError(vec!["a".into(), "b".into()]).to_string(),
+        "Could not remove the files a, b."
+    );
+}
+
+#[test]
+fn test_cannot_remove_files() -> Result<(), Error> {
+    if Path::new("tmp_cannot_remove").exists() {
+        fs::remove_dir_all("tmp_cannot_remove").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cannot_remove").unwrap();
+    gen_file("tmp_cannot_remove/file", 3000);
+    let (mut store, _) = StoreBuilder::new("tmp_cannot_remove/store", "1234")
+        .chunk_size(1024)
+        .build()?;
+    store.add("tmp_cannot_remove/file", "/")?;
+    store.add("tmp_cannot_remove/file", "/other")?;
+
+    // A folder in place of a part file cannot be deleted with remove_file,
+    // not even by root, unlike a file in a read-only folder.
+    let parts = store.part_files("/file")?;
+    fs::remove_file(&parts[1]).unwrap();
+    fs::create_dir_all(format!("{}/blocker", parts[1])).unwrap();
+
+    assert_eq!(
+        store.remove("/file"),
+        Err(Error::CannotRemoveFilesError(vec![parts[1].clone()]))
+    );
+    assert!(!Path::new(&parts[0]).exists());
+    assert!(!Path::new(&parts[2]).exists());
+
+    println!("Tests that the file is removed from the index anyway");
+    let mut store = Store::open("tmp_cannot_remove/store", "1234")?;
+    assert!(!store.exists("/file")?);
+
+    println!("Tests that missing part files are not an error");
+    let parts = store.part_files("/other")?;
+    fs::remove_file(&parts[0]).unwrap();
+    store.truncate("/other")?;
+    assert_eq!(0, store.stat("/other")?.size);
+
+    fs::remove_dir_all("tmp_cannot_remove").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_io_errors() -> Result<(), Error> {
+    if Path::new("tmp_io_errors").exists() {
+        fs::remove_dir_all("tmp_io_errors").unwrap();
+    }
+
+    fs::create_dir_all("tmp_io_errors").unwrap();
+    gen_file("tmp_io_errors/file", 10);
+    let mut store = Store::create("tmp_io_errors/store", "1234")?;
+
+    println!("Tests that failures keep the errors they always had");
+    fs::create_dir_all("tmp_io_errors/store/Store.void.tmp/blocker").unwrap();
+    assert_eq!(
+        store.add("tmp_io_errors/file", "/"),
+        Err(Error::CannotWriteFileError)
+    );
+    fs::remove_dir_all("tmp_io_errors/store/Store.void.tmp").unwrap();
+    assert_eq!(
+        store.add("tmp_io_errors/missing", "/"),
+        Err(Error::CannotReadFileError)
+    );
+
+    println!("Tests a permission denied write");
+    let (mut sharded, _) = StoreBuilder::new("tmp_io_errors/sharded", "1234")
+        .shard_levels(1)
+        .build()?;
+    let readonly = fs::Permissions::from_mode(0o555);
+    fs::set_permissions("tmp_io_errors/sharded", readonly).unwrap();
+    // Privileged users can write to read-only folders anyway.
+    if fs::write("tmp_io_errors/sharded/probe", "").is_err() {
+        assert_eq!(
+            sharded.add("tmp_io_errors/file", "/"),
+            Err(Error::IoError(std::io::ErrorKind::PermissionDenied))
+        );
+        assert_eq!(
+            store.get("/file", "tmp_io_errors/sharded/file"),
+            Err(Error::CannotWriteFileError)
+        );
+    }
+    let writable = fs::Permissions::from_mode(0o755);
+    fs::set_permissions("tmp_io_errors/sharded", writable).unwrap();
+
+    fs::remove_dir_all("tmp_io_errors").unwrap();
+    Ok(())
+}
+
+/// Reads the bytes it is given, then fails.
+struct FailingReader(Vec<u8>);
+
+impl std::io::Read for FailingReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.0.is_empty() {
+            return Err(std::io::ErrorKind::BrokenPipe.into());
+        }
+        let len = buf.len().min(self.0.len());
+        buf[..len].copy_from_slice(&self.0[..len]);
+        self.0.drain(..len);
+        Ok(len)
+    }
+}
+
+#[test]
+fn test_failed_overwrite() -> Result<(), Error> {
+    if Path::new("tmp_failed_overwrite").exists() {
+        fs::remove_dir_all("tmp_failed_overwrite").unwrap();
+    }
+
+    fs::create_dir_all("tmp_failed_overwrite").unwrap();
+    for threads in [1, 2] {
+        let store_path = format!("tmp_failed_overwrite/store{threads}");
+        let (store, _) = StoreBuilder::new(store_path.as_str(), "1234")
+            .chunk_size(4)
+            .build()?;
+        let mut store = store.with_threads(threads);
+        store.add_bytes(b"old content", "/file", None)?;
+        store.tag_add("/file", "keep")?;
+        store.metadata_set("/file", "key", "value")?;
+        let parts = fs::read_dir(&store_path).unwrap().count();
+
+        println!("Tests that a failed overwrite keeps the file as it was");
+        let mut reader = FailingReader(b"new content".to_vec());
+        assert_eq!(
+            store.add_from_reader(&mut reader, "/file", true),
+            Err(Error::CannotReadFileError)
+        );
+        assert_eq!(store.read_range("/file", 0, 100)?, b"old content");
+        assert_eq!(store.tag_get("/file")?, ["keep"]);
+        assert_eq!(store.metadata_get("/file", "key")?, "value");
+        assert_eq!(fs::read_dir(&store_path).unwrap().count(), parts);
+
+        println!("Tests that a failed add removes only the new file");
+        let mut reader = FailingReader(b"new content".to_vec());
+        assert_eq!(
+            store.add_from_reader(&mut reader, "/new", false),
+            Err(Error::CannotReadFileError)
+        );
+        assert!(!store.exists("/new")?);
+        assert!(store.exists("/file")?);
+        assert_eq!(fs::read_dir(&store_path).unwrap().count(), parts);
+
+        println!("Tests that a successful overwrite removes the old parts");
+        store.add_from_reader(&mut &b"new"[..], "/file", true)?;
+        assert_eq!(store.read_range("/file", 0, 100)?, b"new");
+        assert_eq!(store.tag_get("/file")?, ["keep"]);
+        assert_eq!(fs::read_dir(&store_path).unwrap().count(), parts - 2);
+    }
+
+    fs::remove_dir_all("tmp_failed_overwrite").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_rekey_file() -> Result<(), Error> {
+    if Path::new("tmp_rekey_file").exists() {
+        fs::remove_dir_all("tmp_rekey_file").unwrap();
+    }
+
+    fs::create_dir_all("tmp_rekey_file").unwrap();
+    gen_file("tmp_rekey_file/file", 5000);
+    let content = fs::read("tmp_rekey_file/file").unwrap();
+
+    let (mut store, _) = StoreBuilder::new("tmp_rekey_file/store", "1234")
+        .chunk_size(1024)
+        .build()?;
+    store.add("tmp_rekey_file/file", "/")?;
+    store.link("/file", "/link")?;
+
+    let old_parts = store.part_files("/file")?;
+    let old_keys: Vec<[u8; 32]> = store.stat("/file")?.data.iter().map(|d| d.key).collect();
+    let old_cipher: Vec<Vec<u8>> = old_parts.iter().map(|p| fs::read(p).unwrap()).collect();
+    assert_eq!(5, old_parts.len());
+
+    store.rekey_file("/file")?;
+
+    println!("Tests that the ciphertext and keys changed");
+    let file = store.stat("/file")?;
+    assert_eq!(5, file.part_count());
+    assert!(file.data.iter().all(|d| !old_keys.contains(&d.key)));
+    for part in store.part_files("/file")? {
+        assert!(!old_cipher.contains(&fs::read(part).unwrap()));
+    }
+    for part in old_parts
+        .iter()
+        .filter(|p| !store.part_files("/file").unwrap().contains(p))
+    {
+        assert!(!Path::new(part).exists());
+    }
+
+    println!("Tests that the plaintext round-trips, also through the link");
+    let mut store = Store::open("tmp_rekey_file/store", "1234")?;
+    assert_eq!(store.part_files("/link")?, store.part_files("/file")?);
+    store.get("/file", "tmp_rekey_file/out")?;
+    assert_eq!(fs::read("tmp_rekey_file/out").unwrap(), content);
+    assert_eq!(store.read_range("/link", 0, 5000)?, content);
+
+    assert_eq!(
+        store.rekey_file("/missing"),
+        Err(Error::FileDoesNotExistError)
+    );
+
+    fs::remove_dir_all("tmp_rekey_file").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_rekey_all() -> Result<(), Error> {
+    if Path::new("tmp_rekey_all").exists() {
+        fs::remove_dir_all("tmp_rekey_all").unwrap();
+    }
+
+    fs::create_dir_all("tmp_rekey_all/folder/sub").unwrap();
+    gen_file("tmp_rekey_all/folder/file1", 3000);
+    gen_file("tmp_rekey_all/folder/sub/file2", 100);
+    gen_file("tmp_rekey_all/folder/sub/file3", 0);
+
+    let (mut store, _) = StoreBuilder::new("tmp_rekey_all/store", "1234")
+        .chunk_size(1024)
+        .compression(Compression::Deflate)
+        .build()?;
+    store.add("tmp_rekey_all/folder", "/")?;
+    store.link("/folder/file1", "/link")?;
+
+    let old_cipher: Vec<Vec<u8>> = Store::salvage("tmp_rekey_all/store")?
+        .iter()
+        .map(|name| fs::read(format!("tmp_rekey_all/store/{name}")).unwrap())
+        .collect();
+    assert_eq!(4, old_cipher.len());
+
+    let mut calls = vec![];
+    store.rekey_all(|done, total| calls.push((done, total)))?;
+    assert_eq!(calls, vec![(1, 4), (2, 4), (3, 4), (4, 4)]);
+
+    println!("Tests that all ciphertext changed and old parts are gone");
+    let parts = Store::salvage("tmp_rekey_all/store")?;
+    assert_eq!(4, parts.len());
+    for name in parts {
+        let cipher = fs::read(format!("tmp_rekey_all/store/{name}")).unwrap();
+        assert!(!old_cipher.contains(&cipher));
+    }
+
+    println!("Tests that the plaintext is preserved");
+    let mut store = Store::open("tmp_rekey_all/store", "1234")?;
+    store.get("/folder", "tmp_rekey_all/out")?;
+    for name in ["file1", "sub/file2", "sub/file3"] {
+        assert_eq!(
+            fs::read(format!("tmp_rekey_all/out/{name}")).unwrap(),
+            fs::read(format!("tmp_rekey_all/folder/{name}")).unwrap()
+        );
+    }
+    assert_eq!(
+        store.read_range("/link", 0, 3000)?,
+        fs::read("tmp_rekey_all/folder/file1").unwrap()
+    );
+
+    fs::remove_dir_all("tmp_rekey_all").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_threads() -> Result<(), Error> {
+    if Path::new("tmp_threads").exists() {
+        fs::remove_dir_all("tmp_threads").unwrap();
+    }
+
+    fs::create_dir_all("tmp_threads/folder/sub").unwrap();
+    for i in 0..40 {
+        let folder = if i % 2 == 0 { "folder" } else { "folder/sub" };
+        gen_file(&format!("tmp_threads/{folder}/file{i}"), i * 300);
+    }
+
+    let (store, _) = StoreBuilder::new("tmp_threads/store", "1234")
+        .chunk_size(1024)
+        .build()?;
+    let mut store = store.with_threads(4);
+    store.add("tmp_threads/folder", "/")?;
+
+    println!("Tests that every part has its own data id and part file");
+    let files: Vec<_> = store.iter().filter(|file| file.is_file).collect();
+    assert_eq!(40, files.len());
+    let mut ids: Vec<u64> = files
+        .iter()
+        .flat_map(|file| file.data.iter().map(|data| data.id))
+        .collect();
+    let parts = ids.len();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(parts, ids.len());
+    assert_eq!(parts, Store::salvage("tmp_threads/store")?.len());
+
+    println!("Tests that all files round-trip");
+    let mut store = Store::open("tmp_threads/store", "1234")?;
+    store.get("/folder", "tmp_threads/out")?;
+    for i in 0..40 {
+        let folder = if i % 2 == 0 { "" } else { "sub/" };
+        assert_eq!(
+            fs::read(format!("tmp_threads/out/{folder}file{i}")).unwrap(),
+            fs::read(format!("tmp_threads/folder/{folder}file{i}")).unwrap()
+        );
+        let file = store.stat(&format!("/folder/{folder}file{i}"))?;
+        assert_eq!(file.size, i as u64 * 300);
+        assert!(file.metadata.contains_key("hash"));
+    }
+
+    println!("Tests that existing files are not overwritten");
+    let mut store = store.with_threads(4);
+    assert_eq!(
+        store.add("tmp_threads/folder/", "/folder"),
+        Err(Error::FileAlreadyExistsError)
+    );
+
+    fs::remove_dir_all("tmp_threads").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_parallel_chunks() -> Result<(), Error> {
+    if Path::new("tmp_parallel_chunks").exists() {
+        fs::remove_dir_all("tmp_parallel_chunks").unwrap();
+    }
+
+    fs::create_dir_all("tmp_parallel_chunks").unwrap();
+    gen_file("tmp_parallel_chunks/big", 157286912);
+
+    let store = Store::create("tmp_parallel_chunks/store", "1234")?;
+    let mut store = store.with_threads(4);
+    store.add("tmp_parallel_chunks/big", "/")?;
+    let file = store.stat("/big")?;
+    assert_eq!(4, file.part_count());
+    assert_eq!(157286912, file.size);
+
+    println!("Tests that the parts are in order");
+    let mut store = Store::open("tmp_parallel_chunks/store", "1234")?;
+    store.get("/big", "tmp_parallel_chunks/big2")?;
+    compare_files("tmp_parallel_chunks/big", "tmp_parallel_chunks/big2");
+
+    println!("Tests that the hash matches a sequential add");
+    let mut other = Store::create("tmp_parallel_chunks/other", "1234")?;
+    other.add("tmp_parallel_chunks/big", "/")?;
+    assert_eq!(
+        store.metadata_get("/big", "hash")?,
+        other.metadata_get("/big", "hash")?
+    );
+
+    println!("Tests chunks of a small chunk size");
+    let (store, _) = StoreBuilder::new("tmp_parallel_chunks/small", "1234")
+        .chunk_size(1024)
+        .build()?;
+    let mut store = store.with_threads(3);
+    let content: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+    store.add_from_reader(&mut content.as_slice(), "/file", false)?;
+    assert_eq!(98, store.stat("/file")?.part_count());
+    assert_eq!(store.read_range("/file", 0, 100_000)?, content);
+
+    fs::remove_dir_all("tmp_parallel_chunks").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_mmap() -> Result<(), Error> {
+    if Path::new("tmp_mmap").exists() {
+        fs::remove_dir_all("tmp_mmap").unwrap();
+    }
+
+    fs::create_dir_all("tmp_mmap").unwrap();
+    gen_file("tmp_mmap/file", 5000);
+
+    let (mut store, _) = StoreBuilder::new("tmp_mmap/store", "1234")
+        .chunk_size(1024)
+        .compression(Compression::Deflate)
+        .build()?;
+    store.add("tmp_mmap/file", "/")?;
+
+    let mut store = Store::open("tmp_mmap/store", "1234")?.with_mmap(false);
+    store.get("/file", "tmp_mmap/buffered")?;
+    let mut store = store.with_mmap(true);
+    store.get("/file", "tmp_mmap/mapped")?;
+    compare_files("tmp_mmap/buffered", "tmp_mmap/mapped");
+    compare_files("tmp_mmap/file", "tmp_mmap/mapped");
+    assert_eq!(
+        store.read_range("/file", 1000, 2000)?,
+        fs::read("tmp_mmap/file").unwrap()[1000..3000]
+    );
+
+    fs::remove_dir_all("tmp_mmap").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_bytes() -> Result<(), Error> {
+    if Path::new("tmp_bytes").exists() {
+        fs::remove_dir_all("tmp_bytes").unwrap();
+    }
+
+    fs::create_dir_all("tmp_bytes").unwrap();
+    gen_file("tmp_bytes/file", 25000);
+
+    let mut store = StoreBuilder::new("tmp_bytes/store", "1234")
+        .chunk_size(10000)
+        .build()?
+        .0;
+    store.mkdir("/a")?;
+    store.add("tmp_bytes/file", "/a/")?;
+    store.link("/a/file", "/b")?;
+    store.metadata_set("/a/file", "key", "value")?;
+    let bytes = store.save_to_bytes()?;
+
+    assert_eq!(
+        Store::open_from_bytes(&bytes, "4321").err(),
+        Some(Error::WrongPassword)
+    );
+    assert_eq!(
+        Store::open_from_bytes(&bytes[..bytes.len() / 2], "1234").err(),
+        Some(Error::CannotDeserializeError)
+    );
+
+    let mut store = Store::open_from_bytes(&bytes, "1234")?;
+    assert_eq!(store.metadata_get("/a/file", "key")?, "value");
+    store.get("/b", "tmp_bytes/got")?;
+    compare_files("tmp_bytes/file", "tmp_bytes/got");
+
+    // Changes are kept in memory until saved to bytes again.
+    store.remove("/a/file")?;
+    store.add_bytes(b"small", "/c", None)?;
+    let bytes = store.save_to_bytes()?;
+    assert_eq!(
+        fs::read_dir("tmp_bytes/store").unwrap().count(),
+        4,
+        "the store folder was modified"
+    );
+
+    let mut store = Store::open_from_bytes(&bytes, "1234")?;
+    assert!(!store.exists("/a/file")?);
+    assert_eq!(store.read_range("/c", 0, 5)?, b"small");
+    fs::remove_file("tmp_bytes/got").unwrap();
+    store.get("/b", "tmp_bytes/got")?;
+    compare_files("tmp_bytes/file", "tmp_bytes/got");
+
+    fs::remove_dir_all("tmp_bytes").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_store() -> Result<(), Error> {
+    if Path::new("tmp_shared").exists() {
+        fs::remove_dir_all("tmp_shared").unwrap();
+    }
+
+    fs::create_dir_all("tmp_shared").unwrap();
+    let store = SharedStore::new(Store::create("tmp_shared/store", "1234")?);
+
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            let store = store.clone();
+            std::thread::spawn(move || {
+                for j in 0..5 {
+                    let content = format!("thread {i} file {j}");
+                    store.add_bytes(content.as_bytes(), &format!("/{i}/{j}"), None)?;
+                }
+                Ok::<(), Error>(())
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+    assert_eq!(store.statistics()?.files, 40);
+    assert!(store.into_inner().is_ok());
+
+    let mut store = Store::open("tmp_shared/store", "1234")?;
+    for i in 0..8 {
+        for j in 0..5 {
+            let content = format!("thread {i} file {j}");
+            let read = store.read_range(&format!("/{i}/{j}"), 0, 100)?;
+            assert_eq!(read, content.as_bytes());
+        }
+    }
+
+    fs::remove_dir_all("tmp_shared").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_least_recently_accessed() -> Result<(), Error> {
+    if Path::new("tmp_accessed").exists() {
+        fs::remove_dir_all("tmp_accessed").unwrap();
+    }
+
+    fs::create_dir_all("tmp_accessed").unwrap();
+    let mut store = Store::create("tmp_accessed/store", "1234")?.with_access_tracking(true);
+    for name in ["/a", "/b", "/c"] {
+        store.add_bytes(name.as_bytes(), name, None)?;
+    }
+
+    let names = |files: Vec<void::File>| -> Vec<String> {
+        files.into_iter().map(|file| file.name).collect()
+    };
+    assert_eq!(names(store.least_recently_accessed(5)), ["/a", "/b", "/c"]);
+
+    // Access times are in seconds.
+    store.read_range("/b", 0, 1)?;
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    store.get("/a", "tmp_accessed/a")?;
+    assert_eq!(names(store.least_recently_accessed(5)), ["/c", "/b", "/a"]);
+    assert_eq!(names(store.least_recently_accessed(1)), ["/c"]);
+
+    // Reads are only recorded when tracking is enabled.
+    let mut store = Store::open("tmp_accessed/store", "1234")?;
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    store.read_range("/b", 0, 1)?;
+    assert_eq!(names(store.least_recently_accessed(5)), ["/c", "/b", "/a"]);
+    assert_eq!(store.stat("/c")?.accessed, 0);
+
+    fs::remove_dir_all("tmp_accessed").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_recursive_tags() -> Result<(), Error> {
+    if Path::new("tmp_recursive_tags").exists() {
+        fs::remove_dir_all("tmp_recursive_tags").unwrap();
+    }
+
+    fs::create_dir_all("tmp_recursive_tags").unwrap();
+    let mut store = Store::create("tmp_recursive_tags/store", "1234")?;
+    store.mkdir_p("/a/b/c")?;
+    for name in ["/a/1", "/a/b/2", "/a/b/c/3", "/4"] {
+        store.add_bytes(name.as_bytes(), name, None)?;
+    }
+
+    let recorder = Recorder::default();
+    let subscriber = tracing_subscriber::registry().with(recorder.clone());
+    tracing::subscriber::with_default(subscriber, || store.tag_add_recursive("/a", "tag"))?;
+    let saves = recorder.spans.lock().unwrap();
+    assert_eq!(1, saves.iter().filter(|name| *name == "save").count());
+
+    let mut tagged: Vec<String> = store
+        .tag_search(vec!["tag".into()])
+        .into_iter()
+        .map(|file| file.name)
+        .collect();
+    tagged.sort();
+    assert_eq!(
+        tagged,
+        ["/a", "/a/1", "/a/b", "/a/b/2", "/a/b/c", "/a/b/c/3"]
+    );
+
+    store.metadata_set_recursive("/a/b", "key", "value")?;
+    assert_eq!(store.metadata_get("/a/b/c/3", "key")?, "value");
+    assert!(store.metadata_get("/a/1", "key").is_err());
+
+    store.tag_rm_recursive("/a/b", "tag")?;
+    assert_eq!(store.tag_get("/a/1")?, ["tag"]);
+    assert!(store.tag_get("/a/b/c/3")?.is_empty());
+
+    store.tag_add_recursive("/", "all")?;
+    assert_eq!(store.tag_search(vec!["all".into()]).len(), 7);
+    assert_eq!(
+        store.tag_add_recursive("/x", "tag"),
+        Err(Error::FileDoesNotExistError)
+    );
+
+    fs::remove_dir_all("tmp_recursive_tags").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_list_sorted() -> Result<(), Error> {
+    if Path::new("tmp_list_sorted").exists() {
+        fs::remove_dir_all("tmp_list_sorted").unwrap();
+    }
+
+    fs::create_dir_all("tmp_list_sorted").unwrap();
+    let mut store = Store::create("tmp_list_sorted/store", "1234")?;
+
+    // Modification times are in seconds.
+    store.add_bytes(b"b", "/b", None)?;
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    store.add_bytes(b"ccc", "/c", None)?;
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    store.add_bytes(b"aa", "/a", None)?;
+    store.mkdir("/d")?;
+
+    let mut names = |by: SortKey, desc: bool| -> Result<Vec<String>, Error> {
+        let files = store.list_sorted("/", by, desc)?;
+        Ok(files.into_iter().map(|file| file.name).collect())
+    };
+    assert_eq!(names(SortKey::Name, false)?, ["a", "b", "c", "d"]);
+    assert_eq!(names(SortKey::Name, true)?, ["d", "c", "b", "a"]);
+    assert_eq!(names(SortKey::Size, false)?, ["d", "b", "a", "c"]);
+    assert_eq!(names(SortKey::Size, true)?, ["c", "a", "b", "d"]);
+    assert_eq!(names(SortKey::Modified, false)?, ["b", "c", "a", "d"]);
+    assert_eq!(names(SortKey::Modified, true)?, ["d", "a", "c", "b"]);
+
+    let files = store.list_sorted_folders_first("/", SortKey::Size, true, true)?;
+    let names: Vec<String> = files.into_iter().map(|file| file.name).collect();
+    assert_eq!(names, ["d", "c", "a", "b"]);
+
+    fs::remove_dir_all("tmp_list_sorted").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_max_depth() -> Result<(), Error> {
+    if Path::new("tmp_max_depth").exists() {
+        fs::remove_dir_all("tmp_max_depth").unwrap();
+    }
+
+    let nested = format!("tmp_max_depth/tree{}", "/d".repeat(1100));
+    fs::create_dir_all(&nested).unwrap();
+    gen_file(&format!("{nested}/file"), 16);
+
+    let mut store = Store::create("tmp_max_depth/store", "1234")?;
+    assert_eq!(
+        store.add("tmp_max_depth/tree", "/"),
+        Err(Error::MaxDepthExceeded)
+    );
+    assert!(store.list("/")?.is_empty());
+    assert_eq!(
+        store.sync("tmp_max_depth/tree", "/", false),
+        Err(Error::MaxDepthExceeded)
+    );
+
+    // The file is 1101 levels below the added folder.
+    let mut store = store.with_max_depth(1100);
+    assert_eq!(
+        store.add("tmp_max_depth/tree", "/"),
+        Err(Error::MaxDepthExceeded)
+    );
+    let mut store = store.with_max_depth(1101);
+    store.add("tmp_max_depth/tree", "/")?;
+    let file = format!("/tree{}/file", "/d".repeat(1100));
+    assert_eq!(store.read_range(&file, 0, 16)?.len(), 16);
+
+    fs::remove_dir_all("tmp_max_depth").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_deep_tree() -> Result<(), Error> {
+    if Path::new("tmp_deep_tree").exists() {
+        fs::remove_dir_all("tmp_deep_tree").unwrap();
+    }
+
+    let chain = "/d".repeat(500);
+    fs::create_dir_all(format!("tmp_deep_tree/in{chain}")).unwrap();
+    gen_file(&format!("tmp_deep_tree/in{chain}/file"), 1024);
+
+    let mut store = Store::create("tmp_deep_tree/store", "1234")?;
+    store.add("tmp_deep_tree/in/", "/")?;
+    assert!(store.is_file(&format!("{chain}/file"))?);
+
+    store.get("/d", "tmp_deep_tree/out")?;
+    compare_files(
+        &format!("tmp_deep_tree/in{chain}/file"),
+        &format!("tmp_deep_tree/out{}/file", &chain[2..]),
+    );
+
+    assert_eq!(
+        store.get("/d", "tmp_deep_tree/out"),
+        Err(Error::FileAlreadyExistsError)
+    );
+
+    fs::remove_dir_all("tmp_deep_tree").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_remove_matching() -> Result<(), Error> {
+    if Path::new("tmp_remove_matching").exists() {
+        fs::remove_dir_all("tmp_remove_matching").unwrap();
+    }
+
+    fs::create_dir_all("tmp_remove_matching").unwrap();
+    let mut store = Store::create("tmp_remove_matching/store", "1234")?;
+    store.mkdir_p("/a/b")?;
+    store.mkdir_p("/c.tmp")?;
+    for name in [
+        "/1.tmp",
+        "/keep",
+        "/a/2.tmp",
+        "/a/b/3.tmp",
+        "/a/b/4",
+        "/c.tmp/5",
+    ] {
+        store.add_bytes(name.as_bytes(), name, None)?;
+    }
+    store.tag_add("/keep", "old")?;
+    store.tag_add("/a/b/4", "old")?;
+    let parts = fs::read_dir("tmp_remove_matching/store")?.count();
+
+    let names = |files: Vec<void::File>| -> Vec<String> {
+        files.into_iter().map(|file| file.name).collect()
+    };
+    let plan = names(store.plan_remove("*.tmp")?);
+    assert_eq!(plan, ["/1.tmp", "/a/2.tmp", "/a/b/3.tmp", "/c.tmp"]);
+    assert!(store.exists("/1.tmp")?);
+
+    assert_eq!(names(store.remove_matching("*.tmp")?), plan);
+    let mut left: Vec<String> = store.iter().map(|file| file.name).collect();
+    left.sort();
+    assert_eq!(left, ["/a", "/a/b", "/a/b/4", "/keep"]);
+    // Each removed file had a single part.
+    let removed = parts - fs::read_dir("tmp_remove_matching/store")?.count();
+    assert_eq!(removed, 4);
+
+    let mut store = Store::open("tmp_remove_matching/store", "1234")?;
+    assert!(!store.exists("/a/2.tmp")?);
+    assert_eq!(
+        store.plan_remove("tag:").err(),
+        Some(Error::CannotParseError)
+    );
+    assert_eq!(
+        store.remove_matching("tag:   ").err(),
+        Some(Error::CannotParseError)
+    );
+    assert_eq!(store.iter().count(), 4);
+    assert_eq!(
+        names(store.remove_matching("tag:old !x")?),
+        ["/a/b/4", "/keep"]
+    );
+    assert_eq!(names(store.remove_matching("/a/*")?), ["/a/b"]);
+    assert!(store.remove_matching("/a/*")?.is_empty());
+    assert_eq!(names(store.remove_matching("/a")?), ["/a"]);
+    assert!(store.iter().next().is_none());
+    assert_eq!(store.plan_remove("[").err(), Some(Error::CannotParseError));
+
+    fs::remove_dir_all("tmp_remove_matching").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_content_hash() -> Result<(), Error> {
+    use blake2::digest::{Update, VariableOutput};
+
+    if Path::new("tmp_content_hash").exists() {
+        fs::remove_dir_all("tmp_content_hash").unwrap();
+    }
+
+    fs::create_dir_all("tmp_content_hash").unwrap();
+    gen_file("tmp_content_hash/file", 10000);
+    let (mut store, _) = StoreBuilder::new("tmp_content_hash/store", "1234")
+        .chunk_size(4096)
+        .build()?;
+    store.add("tmp_content_hash/file", "/file")?;
+
+    let blake2b = |bytes: &[u8]| {
+        let mut hasher = blake2::Blake2bVar::new(32).unwrap();
+        hasher.update(bytes);
+        let mut hash = [0u8; 32];
+        hasher.finalize_variable(&mut hash).unwrap();
+        hash
+    };
+    let expected = blake2b(&fs::read("tmp_content_hash/file").unwrap());
+    assert_eq!(store.content_hash("/file")?, expected);
+
+    // Without the recorded hash, it is computed from the parts and saved.
+    store.metadata_remove("/file", "hash")?;
+    assert_eq!(store.content_hash("/file")?, expected);
+    let mut store = Store::open("tmp_content_hash/store", "1234")?;
+    let hex: String = expected.iter().map(|b| format!("{b:02x}")).collect();
+    assert_eq!(store.metadata_get("/file", "hash")?, hex);
+
+    store.truncate("/file")?;
+    assert_eq!(store.content_hash("/file")?, blake2b(b""));
+
+    println!("Tests that copying metadata keeps the hash of the target");
+    store.add_bytes_detecting_mimetype(b"other", "/other")?;
+    let mimetype = store.metadata_get("/other", "mimetype")?;
+    store.metadata_set("/file", "mimetype", "application/x-test")?;
+    store.metadata_copy("/file", "/other")?;
+    assert_eq!(store.content_hash("/other")?, blake2b(b"other"));
+    assert_eq!(store.metadata_get("/other", "mimetype")?, mimetype);
+
+    store.mkdir("/folder")?;
+    assert_eq!(
+        store.content_hash("/folder"),
+        Err(Error::FileDoesNotExistError)
+    );
+
+    fs::remove_dir_all("tmp_content_hash").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_diff() -> Result<(), Error> {
+    if Path::new("tmp_diff").exists() {
+        fs::remove_dir_all("tmp_diff").unwrap();
+    }
+
+    fs::create_dir_all("tmp_diff").unwrap();
+    let mut ours = Store::create("tmp_diff/ours", "1234")?;
+    let mut theirs = Store::create("tmp_diff/theirs", "4321")?;
+    for store in [&mut ours, &mut theirs] {
+        store.mkdir("/a")?;
+        store.add_bytes(b"same", "/a/same", None)?;
+        store.add_bytes(b"version 1", "/changed", None)?;
+    }
+    assert_eq!(ours.diff(&theirs)?, DiffReport::default());
+
+    theirs.remove("/changed")?;
+    theirs.add_bytes(b"version 2", "/changed", None)?;
+    let report = ours.diff(&theirs)?;
+    assert!(report.only_in_self.is_empty() && report.only_in_other.is_empty());
+    assert_eq!(report.changed, ["/changed"]);
+
+    ours.add_bytes(b"new", "/a/new", None)?;
+    theirs.mkdir("/b")?;
+    theirs.add_bytes(b"same", "/b/same", None)?;
+    let report = theirs.diff(&ours)?;
+    assert_eq!(report.only_in_self, ["/b", "/b/same"]);
+    assert_eq!(report.only_in_other, ["/a/new"]);
+    assert_eq!(report.changed, ["/changed"]);
+
+    println!("Tests that diff changes neither store and skips the trash");
+    ours.metadata_remove("/changed", "hash")?;
+    theirs.trash("/b")?;
+    let report = theirs.diff(&ours)?;
+    assert!(report.only_in_self.is_empty());
+    assert_eq!(report.changed, ["/changed"]);
+    assert!(ours.metadata_get("/changed", "hash").is_err());
+    let mut ours = Store::open("tmp_diff/ours", "1234")?;
+    assert!(ours.metadata_get("/changed", "hash").is_err());
+
+    fs::remove_dir_all("tmp_diff").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_description() -> Result<(), Error> {
+    if Path::new("tmp_description").exists() {
+        fs::remove_dir_all("tmp_description").unwrap();
+    }
+
+    fs::create_dir_all("tmp_description").unwrap();
+    let mut store = Store::create("tmp_description/store", "1234")?;
+    assert_eq!(store.get_description()?, None);
+
+    store.set_description("Holiday photos")?;
+    let mut store = Store::open("tmp_description/store", "1234")?;
+    assert_eq!(store.get_description()?.as_deref(), Some("Holiday photos"));
+
+    let index = fs::read("tmp_description/store/Store.void").unwrap();
+    let plaintext = b"Holiday photos";
+    assert!(!index.windows(plaintext.len()).any(|w| w == plaintext));
+
+    store.set_description("")?;
+    let store = Store::open("tmp_description/store", "1234")?;
+    assert_eq!(store.get_description()?, None);
+
+    fs::remove_dir_all("tmp_description").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_typed_metadata() -> Result<(), Error> {
+    if Path::new("tmp_typed_metadata").exists() {
+        fs::remove_dir_all("tmp_typed_metadata").unwrap();
+    }
+
+    fs::create_dir_all("tmp_typed_metadata").unwrap();
+    let mut store = Store::create("tmp_typed_metadata/store", "1234")?;
+    store.add_bytes(b"a", "/a", None)?;
+    store.add_bytes(b"b", "/b", None)?;
+    store.add_bytes(b"c", "/c", None)?;
+
+    let values = [
+        ("str", MetadataValue::Str("text".into())),
+        ("int", MetadataValue::Int(-42)),
+        ("float", MetadataValue::Float(2.5)),
+        ("bool", MetadataValue::Bool(true)),
+        ("time", MetadataValue::Timestamp(1_700_000_000)),
+    ];
+    for (key, value) in &values {
+        store.metadata_set_typed("/a", key, value.clone())?;
+    }
+
+    let mut store = Store::open("tmp_typed_metadata/store", "1234")?;
+    for (key, value) in &values {
+        assert_eq!(&store.metadata_get_typed("/a", key)?, value);
+    }
+    assert_eq!(store.metadata_get("/a", "int")?, "-42");
+    store.metadata_set("/a", "int", "-42")?;
+    assert_eq!(
+        store.metadata_get_typed("/a", "int")?,
+        MetadataValue::Str("-42".into())
+    );
+
+    store.metadata_set_typed("/a", "year", MetadataValue::Int(1999))?;
+    store.metadata_set_typed("/b", "year", MetadataValue::Float(2005.5))?;
+    store.metadata_set_typed("/c", "year", MetadataValue::Int(2020))?;
+    let mut found: Vec<String> = store
+        .metadata_search("year", MetadataValue::Int(2000)..=MetadataValue::Int(2020))
+        .into_iter()
+        .map(|file| file.name)
+        .collect();
+    found.sort();
+    assert_eq!(found, ["/b", "/c"]);
+    let found = store.metadata_search("year", ..MetadataValue::Int(2000));
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].name, "/a");
+    let found = store.metadata_search("year", MetadataValue::Str("0".into())..);
+    assert!(found.is_empty());
+
+    fs::remove_dir_all("tmp_typed_metadata").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_progress() -> Result<(), Error> {
+    if Path::new("tmp_progress").exists() {
+        fs::remove_dir_all("tmp_progress").unwrap();
+    }
+
+    fs::create_dir_all("tmp_progress/folder/sub").unwrap();
+    gen_file("tmp_progress/folder/a", 1000);
+    gen_file("tmp_progress/folder/sub/b", 3000);
+
+    let done = Progress {
+        files: 2,
+        total_files: 2,
+        bytes: 4000,
+        total_bytes: 4000,
+    };
+    for threads in [1, 2] {
+        let store_path = format!("tmp_progress/store{threads}");
+        let (store, _) = StoreBuilder::new(store_path.as_str(), "1234")
+            .chunk_size(1000)
+            .build()?;
+        let mut store = store.with_threads(threads);
+        let growing = |reports: &[Progress]| reports.windows(2).all(|w| w[0].bytes <= w[1].bytes);
+
+        println!("Tests that bytes are reported as each part is written");
+        let mut reports = vec![];
+        store.add_with_progress("tmp_progress/folder", "/", false, |p| reports.push(p))?;
+        // Once when the files are known, once per part and once per file.
+        assert_eq!(reports.len(), 7);
+        assert_eq!(reports[0].files, 0);
+        assert_eq!(reports[0].total_bytes, 4000);
+        assert!(reports.iter().any(|p| p.files < 2 && p.bytes > 1000));
+        assert!(growing(&reports));
+        assert_eq!(reports[6], done);
+
+        let mut reports = vec![];
+        let out = format!("tmp_progress/out{threads}");
+        store.get_with_progress("/folder", &out, |p| reports.push(p))?;
+        assert_eq!(reports.len(), 7);
+        assert!(growing(&reports));
+        assert_eq!(reports[6], done);
+    }
+
+    fs::remove_dir_all("tmp_progress").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_sharded_parts() -> Result<(), Error> {
+    if Path::new("tmp_sharded").exists() {
+        fs::remove_dir_all("tmp_sharded").unwrap();
+    }
+
+    fs::create_dir_all("tmp_sharded").unwrap();
+    let (mut store, _) = StoreBuilder::new("tmp_sharded/store", "1234")
+        .kdf(Kdf::HkdfSha256)
+        .chunk_size(4)
+        .shard_levels(2)
+        .build()?;
+    store.add_bytes(b"hello world", "/a", None)?;
+
+    let parts = store.part_files("/a")?;
+    assert_eq!(parts.len(), 3);
+    let part = Path::new(&parts[0]);
+    assert!(part.exists());
+    assert!(part.parent().unwrap().ends_with("01/00"));
+    assert_eq!(store.gc()?, 0);
+
+    let mut store = Store::open("tmp_sharded/store", "1234")?;
+    assert_eq!(store.read_range("/a", 0, 11)?, b"hello world");
+
+    let name = part.file_name().unwrap().to_str().unwrap();
+    let orphan = format!("tmp_sharded/store/ff/00/{}ff", &name[..name.len() - 2]);
+    fs::create_dir_all(Path::new(&orphan).parent().unwrap()).unwrap();
+    fs::copy(part, &orphan).unwrap();
+    assert_eq!(Store::salvage("tmp_sharded/store")?.len(), 4);
+    assert_eq!(store.gc()?, 1);
+    assert!(!Path::new(&orphan).exists());
+    assert!(part.exists());
+
+    store.remove("/a")?;
+    assert!(!part.exists());
+
+    fs::remove_dir_all("tmp_sharded").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_list_page() -> Result<(), Error> {
+    if Path::new("tmp_list_page").exists() {
+        fs::remove_dir_all("tmp_list_page").unwrap();
+    }
+
+    fs::create_dir_all("tmp_list_page").unwrap();
+    let mut store = Store::create("tmp_list_page/store", "1234")?;
+    for i in 0..250 {
+        store.touch(&format!("/folder/file{i}"))?;
+    }
+
+    let (first, total) = store.list_page("/folder", 0, 100)?;
+    assert_eq!(total, 250);
+    assert_eq!(first.len(), 100);
+    let (second, total) = store.list_page("/folder", 100, 100)?;
+    assert_eq!(total, 250);
+    assert_eq!(second.len(), 100);
+    assert!(first.iter().all(|a| second.iter().all(|b| a.id != b.id)));
+
+    let all = store.list("/folder")?;
+    let names: Vec<&String> = first.iter().chain(&second).map(|f| &f.name).collect();
+    let expected: Vec<&String> = all.iter().take(200).map(|f| &f.name).collect();
+    assert_eq!(names, expected);
+
+    let (last, _) = store.list_page("/folder", 200, 100)?;
+    assert_eq!(last.len(), 50);
+    let (past, _) = store.list_page("/folder", 300, 100)?;
+    assert!(past.is_empty());
+    assert_eq!(
+        store.list_page("/missing", 0, 10).err(),
+        Some(Error::FolderDoesNotExistError)
+    );
+
+    fs::remove_dir_all("tmp_list_page").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_add_store_into_itself() -> Result<(), Error> {
+    if Path::new("tmp_add_itself").exists() {
+        fs::remove_dir_all("tmp_add_itself").unwrap();
+    }
+
+    fs::create_dir_all("tmp_add_itself/folder").unwrap();
+    gen_file("tmp_add_itself/folder/file", 100);
+    let mut store = Store::create("tmp_add_itself/folder/store", "1234")?;
+    store.add_bytes(b"hello", "/a", None)?;
+
+    store.add("tmp_add_itself/folder", "/")?;
+    assert!(store.exists("/folder/file")?);
+    assert!(!store.exists("/folder/store")?);
+    assert!(!store.exists("/folder/store/Store.void")?);
+
+    store.sync("tmp_add_itself/folder", "/synced", false)?;
+    assert!(store.exists("/synced/file")?);
+    assert!(!store.exists("/synced/store")?);
+
+    fs::remove_dir_all("tmp_add_itself").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_keyfile() -> Result<(), Error> {
+    if Path::new("tmp_keyfile").exists() {
+        fs::remove_dir_all("tmp_keyfile").unwrap();
+    }
+
+    fs::create_dir_all("tmp_keyfile").unwrap();
+    let keyfile: Vec<u8> = (0..4096).map(|_| rand::random::<u8>()).collect();
+
+    let mut store = Store::create_with_keyfile("tmp_keyfile/store", &keyfile)?;
+    store.add_bytes(b"hello", "/a", None)?;
+
+    let mut store = Store::open_with_keyfile("tmp_keyfile/store", &keyfile)?;
+    assert_eq!(store.read_range("/a", 0, 5)?, b"hello");
+
+    println!("Tests that the store does not open without the key file");
+    assert_eq!(
+        Store::open("tmp_keyfile/store", "").err(),
+        Some(Error::KeyfileRequired)
+    );
+    let mut other = keyfile.clone();
+    other[0] ^= 1;
+    assert_eq!(
+        Store::open_with_keyfile("tmp_keyfile/store", &other).err(),
+        Some(Error::WrongPassword)
+    );
+
+    println!("Tests that a key file does not open a password store");
+    Store::create("tmp_keyfile/plain", "1234")?;
+    assert_eq!(
+        Store::open_with_keyfile("tmp_keyfile/plain", &keyfile).err(),
+        Some(Error::WrongPassword)
+    );
+
+    fs::remove_dir_all("tmp_keyfile").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_password_and_keyfile() -> Result<(), Error> {
+    if Path::new("tmp_two_factor").exists() {
+        fs::remove_dir_all("tmp_two_factor").unwrap();
+    }
+
+    fs::create_dir_all("tmp_two_factor").unwrap();
+    let keyfile: Vec<u8> = (0..4096).map(|_| rand::random::<u8>()).collect();
+
+    let mut store =
+        Store::create_with_password_and_keyfile("tmp_two_factor/store", "1234", &keyfile)?;
+    store.add_bytes(b"hello", "/a", None)?;
+    assert_eq!(
+        Store::required_credentials("tmp_two_factor/store")?,
+        Credentials::PasswordAndKeyfile
+    );
+
+    println!("Tests that neither the password nor the key file opens it alone");
+    assert_eq!(
+        Store::open("tmp_two_factor/store", "1234").err(),
+        Some(Error::KeyfileRequired)
+    );
+    assert_eq!(
+        Store::open_with_keyfile("tmp_two_factor/store", &keyfile).err(),
+        Some(Error::WrongPassword)
+    );
+    assert_eq!(
+        Store::open_with_password_and_keyfile("tmp_two_factor/store", "4321", &keyfile).err(),
+        Some(Error::WrongPassword)
+    );
+
+    let mut store =
+        Store::open_with_password_and_keyfile("tmp_two_factor/store", "1234", &keyfile)?;
+    assert_eq!(store.read_range("/a", 0, 5)?, b"hello");
+
+    fs::remove_dir_all("tmp_two_factor").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_add_returning() -> Result<(), Error> {
+    if Path::new("tmp_add_returning").exists() {
+        fs::remove_dir_all("tmp_add_returning").unwrap();
+    }
+
+    fs::create_dir_all("tmp_add_returning/folder").unwrap();
+    gen_file("tmp_add_returning/folder/file", 100);
+    let mut store = Store::create("tmp_add_returning/store", "1234")?;
+
+    println!("Tests adding the contents of a folder under a new name");
+    let added = store.add_returning("tmp_add_returning/folder/", "/renamed")?;
+    assert_eq!(added, "/renamed");
+    let names: Vec<String> = store
+        .list("/renamed")?
+        .into_iter()
+        .map(|f| f.name)
+        .collect();
+    assert_eq!(names, vec!["file"]);
+
+    println!("Tests adding a folder and a file into existing folders");
+    assert_eq!(
+        store.add_returning("tmp_add_returning/folder", "/")?,
+        "/folder"
+    );
+    assert!(store.stat("/folder/file")?.is_file);
+    store.mkdir("/empty")?;
+    let added = store.add_returning("tmp_add_returning/folder/file", "/empty")?;
+    assert_eq!(added, "/empty/file");
+
+    let added = store.add_returning("tmp_add_returning/folder/file", "/other")?;
+    assert_eq!(added, "/other");
+    let names: Vec<String> = store.list("/")?.into_iter().map(|f| f.name).collect();
+    assert!(names.contains(&"other".to_string()));
+
+    fs::remove_dir_all("tmp_add_returning").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_trash() -> Result<(), Error> {
+    if Path::new("tmp_trash").exists() {
+        fs::remove_dir_all("tmp_trash").unwrap();
+    }
+
+    fs::create_dir_all("tmp_trash").unwrap();
+    let mut store = Store::create("tmp_trash/store", "1234")?;
+    store.add_bytes(b"hello", "/folder/a", None)?;
+    store.add_bytes(b"world", "/b", None)?;
+    let parts = store.statistics()?.parts;
+
+    println!("Tests trashing keeps the parts and hides the trash");
+    let trashed = store.trash("/folder/a")?;
+    assert!(trashed.starts_with("/.trash/"));
+    assert!(trashed.ends_with("/folder/a"));
+    assert!(!store.exists("/folder/a")?);
+    assert_eq!(store.read_range(&trashed, 0, 5)?, b"hello");
+    assert_eq!(store.statistics()?.parts, parts);
+    let names: Vec<String> = store.list("/")?.into_iter().map(|f| f.name).collect();
+    assert!(!names.contains(&".trash".to_string()));
+    let mut store = store.with_hidden(true);
+    let names: Vec<String> = store.list("/")?.into_iter().map(|f| f.name).collect();
+    assert!(names.contains(&".trash".to_string()));
+    assert_eq!(store.trash("/.trash").err(), Some(Error::CannotMoveError));
+
+    println!("Tests restoring from the trash");
+    assert_eq!(store.restore_from_trash(&trashed)?, "/folder/a");
+    assert_eq!(store.read_range("/folder/a", 0, 5)?, b"hello");
+    assert!(store.list("/.trash")?.is_empty());
+
+    store.add_bytes(b"again", "/b2", None)?;
+    let trashed = store.trash("/b2")?;
+    store.add_bytes(b"other", "/b2", None)?;
+    assert_eq!(
+        store.restore_from_trash(&trashed).err(),
+        Some(Error::FileAlreadyExistsError)
+    );
+
+    println!("Tests emptying the trash");
+    store.trash("/b")?;
+    store.empty_trash()?;
+    assert!(!store.exists(TRASH)?);
+    assert_eq!(store.statistics()?.parts, parts);
+    assert_eq!(store.read_range("/b2", 0, 5)?, b"other");
+
+    println!("Tests that syncing the root and removing matches keep the trash");
+    store.add_bytes(b"kept", "/kept", None)?;
+    store.tag_add("/kept", "kept")?;
+    let trashed = store.trash("/kept")?;
+    assert!(store.remove_matching("tag:kept")?.is_empty());
+    fs::create_dir_all("tmp_trash/empty").unwrap();
+    store.sync("tmp_trash/empty", "/", true)?;
+    assert!(!store.exists("/b2")?);
+    assert_eq!(store.read_range(&trashed, 0, 4)?, b"kept");
+    let mut store = Store::open("tmp_trash/store", "1234")?;
+    assert_eq!(store.read_range(&trashed, 0, 4)?, b"kept");
+
+    fs::remove_dir_all("tmp_trash").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_hidden() -> Result<(), Error> {
+    if Path::new("tmp_hidden").exists() {
+        fs::remove_dir_all("tmp_hidden").unwrap();
+    }
+
+    fs::create_dir_all("tmp_hidden").unwrap();
+    let mut store = Store::create("tmp_hidden/store", "1234")?;
+    store.add_bytes(b"a", "/folder/.hidden", None)?;
+    store.add_bytes(b"b", "/folder/visible", None)?;
+    store.add_bytes(b"c", "/.config/file", None)?;
+
+    let names = |store: &mut Store, path: &str| -> Result<Vec<String>, Error> {
+        let mut names: Vec<String> = store.list(path)?.into_iter().map(|f| f.name).collect();
+        names.sort();
+        Ok(names)
+    };
+
+    assert_eq!(names(&mut store, "/folder")?, ["visible"]);
+    assert_eq!(names(&mut store, "/")?, ["folder"]);
+    assert_eq!(names(&mut store, "*")?, ["/folder", "/folder/visible"]);
+    assert_eq!(names(&mut store, "/.config")?, ["file"]);
+    assert_eq!(names(&mut store, "/folder/.hidden")?, [".hidden"]);
+
+    println!("Tests that pages skip hidden files like list");
+    let (page, total) = store.list_page("/folder", 0, 10)?;
+    assert_eq!(1, total);
+    assert_eq!(page[0].name, "visible");
+    let (page, total) = store.list_page("/", 0, 1)?;
+    assert_eq!(1, total);
+    assert_eq!(page[0].name, "folder");
+    assert!(store.list_page("/", 1, 1)?.0.is_empty());
+
+    let mut store = store.with_hidden(true);
+    assert_eq!(names(&mut store, "/folder")?, [".hidden", "visible"]);
+    assert_eq!(names(&mut store, "/")?, [".config", "folder"]);
+    assert_eq!(2, store.list_page("/folder", 0, 10)?.1);
+    assert_eq!(2, store.list_page("/", 0, 1)?.1);
+
+    fs::remove_dir_all("tmp_hidden").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_append_bytes() -> Result<(), Error> {
+    use blake2::digest::{Update, VariableOutput};
+
+    if Path::new("tmp_append").exists() {
+        fs::remove_dir_all("tmp_append").unwrap();
+    }
+
+    fs::create_dir_all("tmp_append").unwrap();
+    let (mut store, _) = StoreBuilder::new("tmp_append/store", "1234")
+        .chunk_size(4)
+        .build()?;
+
+    store.append_bytes("/log", b"first ")?;
+    let parts = store.part_files("/log")?;
+    store.append_bytes("/log", b"second ")?;
+    store.append_bytes("/log", b"third")?;
+
+    let expected = b"first second third";
+    assert_eq!(store.stat("/log")?.size, expected.len() as u64);
+    assert_eq!(store.read_range("/log", 0, 100)?, expected);
+    assert_eq!(store.part_files("/log")?[..parts.len()], parts);
+
+    println!("Tests that the content hash covers the appended data");
+    let mut hasher = blake2::Blake2bVar::new(32).unwrap();
+    hasher.update(expected);
+    let mut hash = [0u8; 32];
+    hasher.finalize_variable(&mut hash).unwrap();
+    assert_eq!(store.content_hash("/log")?, hash);
+
+    let mut store = Store::open("tmp_append/store", "1234")?;
+    assert_eq!(store.read_range("/log", 6, 6)?, b"second");
+    store.mkdir("/folder")?;
+    assert_eq!(
+        store.append_bytes("/folder", b"data").err(),
+        Some(Error::FileDoesNotExistError)
+    );
+
+    fs::remove_dir_all("tmp_append").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_snapshot() -> Result<(), Error> {
+    if Path::new("tmp_snapshot").exists() {
+        fs::remove_dir_all("tmp_snapshot").unwrap();
+    }
+
+    fs::create_dir_all("tmp_snapshot").unwrap();
+    let mut store = Store::create("tmp_snapshot/store", "1234")?;
+    store.add_bytes(b"kept", "/kept", None)?;
+
+    let snapshot = store.snapshot();
+    store.add_bytes(b"undone", "/folder/undone", None)?;
+    store.metadata_set("/kept", "key", "value")?;
+    assert!(store.exists("/folder/undone")?);
+
+    store.restore_snapshot(snapshot)?;
+    assert!(!store.exists("/folder/undone")?);
+    assert!(!store.exists("/folder")?);
+    assert_eq!(
+        store.metadata_get("/kept", "key").err(),
+        Some(Error::NoSuchMetadataKey)
+    );
+
+    println!("Tests that the restored index is saved and its orphans collected");
+    let mut store = Store::open("tmp_snapshot/store", "1234")?;
+    assert!(!store.exists("/folder/undone")?);
+    assert_eq!(store.read_range("/kept", 0, 4)?, b"kept");
+    assert_eq!(store.gc()?, 1);
+
+    fs::remove_dir_all("tmp_snapshot").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_rename_store() -> Result<(), Error> {
+    if Path::new("tmp_rename_store").exists() {
+        fs::remove_dir_all("tmp_rename_store").unwrap();
+    }
+
+    fs::create_dir_all("tmp_rename_store/other").unwrap();
+    let mut store = Store::create("tmp_rename_store/store", "1234")?;
+    store.add_bytes(b"before", "/before", None)?;
+
+    assert_eq!(
+        store.rename_store("tmp_rename_store/other").err(),
+        Some(Error::FileAlreadyExistsError)
+    );
+    assert_eq!(
+        store.rename_store("tmp_rename_store/store/inner").err(),
+        Some(Error::CannotMoveError)
+    );
+
+    store.rename_store("tmp_rename_store/renamed")?;
+    assert!(!Path::new("tmp_rename_store/store").exists());
+    store.add_bytes(b"after", "/after", None)?;
+    assert_eq!(store.read_range("/before", 0, 6)?, b"before");
+
+    let mut store = Store::open("tmp_rename_store/renamed", "1234")?;
+    assert_eq!(store.read_range("/after", 0, 5)?, b"after");
+    assert_eq!(store.read_range("/before", 0, 6)?, b"before");
+
+    fs::remove_dir_all("tmp_rename_store").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_password_policy() -> Result<(), Error> {
+    if Path::new("tmp_password_policy").exists() {
+        fs::remove_dir_all("tmp_password_policy").unwrap();
+    }
+
+    fs::create_dir_all("tmp_password_policy").unwrap();
+
+    println!("Tests that blank passwords are rejected by default");
+    for password in ["", "  \t"] {
+        assert_eq!(
+            Store::create("tmp_password_policy/store", password).err(),
+            Some(Error::WeakPassword)
+        );
+    }
+    assert!(!Path::new("tmp_password_policy/store").exists());
+    Store::create("tmp_password_policy/store", "1234")?;
+
+    println!("Tests a stricter policy");
+    let policy = PasswordPolicy {
+        min_length: 8,
+        ..PasswordPolicy::default()
+    };
+    let result = StoreBuilder::new("tmp_password_policy/strict", "1234")
+        .password_policy(policy)
+        .build();
+    assert_eq!(result.err(), Some(Error::WeakPassword));
+    StoreBuilder::new("tmp_password_policy/strict", "correct horse")
+        .password_policy(policy)
+        .build()?;
+
+    println!("Tests that the policy can allow empty passwords");
+    let policy = PasswordPolicy {
+        min_length: 0,
+        reject_blank: false,
+    };
+    StoreBuilder::new("tmp_password_policy/empty", "")
+        .password_policy(policy)
+        .build()?;
+    Store::open("tmp_password_policy/empty", "")?;
+
+    fs::remove_dir_all("tmp_password_policy").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_password_strength() {
+    assert_eq!(void::password_strength(""), 0);
+    assert!(void::password_strength("1234") < 20);
+    assert!(void::password_strength("abc") < 20);
+    assert_eq!(
+        void::password_strength("aaaaaaaaaaaa"),
+        void::password_strength("a")
+    );
+    assert!(void::password_strength("password") < 50);
+    assert!(void::password_strength("Tr0ub4dor&3") > void::password_strength("troubadour"));
+    assert!(void::password_strength("correct horse battery staple") >= 100);
+    assert!(void::password_strength("C0rrect-H0rse-Battery") >= 100);
+}
+
+#[test]
+fn test_secret_password() -> Result<(), Error> {
+    if Path::new("tmp_secret").exists() {
+        fs::remove_dir_all("tmp_secret").unwrap();
+    }
+
+    fs::create_dir_all("tmp_secret").unwrap();
+    let mut store = Store::create_secret("tmp_secret/store", Zeroizing::new("1234".into()))?;
+    store.add_bytes(b"secret", "/file", None)?;
+
+    let secret = Zeroizing::new(String::from("1234"));
+    let mut store = Store::open_secret("tmp_secret/store", secret.clone())?;
+    assert_eq!(store.read_range("/file", 0, 6)?, b"secret");
+
+    assert_eq!(
+        Store::open_secret("tmp_secret/store", Zeroizing::new("4321".into())).err(),
+        Some(Error::WrongPassword)
+    );
+
+    println!("Tests that plain passwords still open the store");
+    let mut store = Store::open("tmp_secret/store", "1234")?;
+    assert_eq!(store.read_range("/file", 0, 6)?, b"secret");
+
+    fs::remove_dir_all("tmp_secret").unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_quota() -> Result<(), Error> {
+    if Path::new("tmp_quota").exists() {
+        fs::remove_dir_all("tmp_quota").unwrap();
+    }
+
+    fs::create_dir_all("tmp_quota/folder").unwrap();
+    gen_file("tmp_quota/folder/file1", 600);
+    gen_file("tmp_quota/folder/file2", 600);
+    gen_file("tmp_quota/small", 300);
+
+    let (mut store, _) = StoreBuilder::new("tmp_quota/store", "1234")
+        .quota_bytes(1000)
+        .build()?;
+    assert_eq!(Some(1000), store.quota_bytes());
+
+    println!("Tests that a folder over the quota is not added at all");
+    assert_eq!(
+        store.add("tmp_quota/folder", "/"),
+        Err(Error::QuotaExceeded)
+    );
+    assert!(!store.exists("/folder")?);
+
+    store.add("tmp_quota/folder/file1", "/file1")?;
+    store.add("tmp_quota/small", "/small")?;
+    assert_eq!(
+        store.add("tmp_quota/folder/file2", "/file2"),
+        Err(Error::QuotaExceeded)
+    );
+    assert_eq!(
+        store.add_bytes(&[0; 101], "/bytes", None),
+        Err(Error::QuotaExceeded)
+    );
+    assert_eq!(
+        store.append_bytes("/small", &[0; 101]),
+        Err(Error::QuotaExceeded)
+    );
+    store.append_bytes("/small", &[0; 100])?;
+    assert_eq!(1000, store.disk_usage("/")?);
+
+    println!("Tests that overwritten files do not count");
+    store.add_overwrite("tmp_quota/folder/file2", "/file1", true)?;
+
+    println!("Tests that files added from readers are checked");
+    assert_eq!(
+        store.add_from_reader(&mut &[0u8; 10][..], "/reader", false),
+        Err(Error::QuotaExceeded)
+    );
+    assert!(!store.exists("/reader")?);
+    store.add_from_reader(&mut &[1u8; 400][..], "/small", true)?;
+    assert_eq!(1000, store.disk_usage("/")?);
+
+    println!("Tests that synced files are checked, counting the replaced ones");
+    fs::create_dir_all("tmp_quota/sync").unwrap();
+    gen_file("tmp_quota/sync/small", 401);
+    assert_eq!(
+        store.sync("tmp_quota/sync", "/", false),
+        Err(Error::QuotaExceeded)
+    );
+    assert_eq!(400, store.stat("/small")?.size);
+    gen_file("tmp_quota/sync/small", 400);
+    store.sync("tmp_quota/sync", "/", false)?;
+    gen_file("tmp_quota/sync/new", 1);
+    assert_eq!(
+        store.sync("tmp_quota/sync", "/", false),
+        Err(Error::QuotaExceeded)
+    );
+    assert!(!store.exists("/new")?);
+
+    println!("Tests that the quota is kept in the store file");
+    let mut store = Store::open("tmp_quota/store", "1234")?;
+    assert_eq!(Some(1000), store.quota_bytes());
+    assert_eq!(store.add_bytes(b"x", "/x", None), Err(Error::QuotaExceeded));
+    store.set_quota_bytes(None)?;
+    store.add("tmp_quota/folder", "/")?;
+    assert_eq!(None, Store::open("tmp_quota/store", "1234")?.quota_bytes());
+
+    fs::remove_dir_all("tmp_quota").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_search_index() -> Result<(), Error> {
+    if Path::new("tmp_search_index").exists() {
+        fs::remove_dir_all("tmp_search_index").unwrap();
+    }
+
+    for folder in 0..20 {
+        let folder = format!("tmp_search_index/big/Folder{folder}");
+        fs::create_dir_all(&folder).unwrap();
+        for file in 0..30 {
+            gen_file(&format!("{folder}/file{file}.txt"), 16);
+        }
+    }
+
+    let mut store = Store::create("tmp_search_index/store", "1234")?;
+    store.add("tmp_search_index/big", "/")?;
+    for folder in 0..20 {
+        let tag = if folder % 2 == 0 { "even" } else { "odd" };
+        store.tag_add_recursive(&format!("/big/Folder{folder}"), tag)?;
+        if folder % 5 == 0 {
+            store.tag_add_recursive(&format!("/big/Folder{folder}"), "fifth")?;
+        }
+    }
+
+    let plain = Store::open("tmp_search_index/store", "1234")?;
+    let mut indexed = Store::open("tmp_search_index/store", "1234")?.with_search_index(true);
+
+    let names = |files: Vec<void::File>| -> Vec<String> {
+        let mut names: Vec<String> = files.into_iter().map(|file| file.name).collect();
+        names.sort();
+        names
+    };
+    let tag_queries = [
+        vec!["even"],
+        vec!["fifth"],
+        vec!["even", "fifth"],
+        vec!["odd", "!fifth"],
+        vec!["!even"],
+        vec!["missing"],
+    ];
+    for query in tag_queries {
+        let query: Vec<String> = query.into_iter().map(String::from).collect();
+        let expected = names(plain.tag_search(query.clone()));
+        assert_eq!(expected, names(indexed.tag_search(query)));
+    }
+    assert_eq!(
+        8 * 31,
+        indexed
+            .tag_search(vec!["odd".into(), "!fifth".into()])
+            .len()
+    );
+
+    for text in ["file1", "FOLDER1", "e1", "7.t", "folder3/", "nothing"] {
+        assert_eq!(names(plain.find(text)), names(indexed.find(text)));
+    }
+    assert_eq!(20 * 11, indexed.find("file2").len());
+    assert_eq!(
+        vec!["/big/Folder13".to_string()],
+        names(indexed.find("LDER13"))
+    );
+
+    println!("Tests that the index follows changes to the store");
+    indexed.tag_add("/big/Folder1/file1.txt", "fifth")?;
+    indexed.mv("/big/Folder2/file3.txt", "/big/renamed.txt")?;
+    indexed.remove("/big/Folder4")?;
+    let plain = Store::open("tmp_search_index/store", "1234")?;
+    for query in [vec!["fifth"], vec!["even"], vec!["odd", "fifth"]] {
+        let query: Vec<String> = query.into_iter().map(String::from).collect();
+        let expected = names(plain.tag_search(query.clone()));
+        assert_eq!(expected, names(indexed.tag_search(query)));
+    }
+    for text in ["renamed", "file3.txt", "folder4"] {
+        assert_eq!(names(plain.find(text)), names(indexed.find(text)));
+    }
+    assert_eq!(
+        vec!["/big/renamed.txt".to_string()],
+        names(indexed.find("renamed"))
+    );
+    assert!(indexed.find("folder4").is_empty());
+
+    fs::remove_dir_all("tmp_search_index").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_get_filtered() -> Result<(), Error> {
+    if Path::new("tmp_get_filtered").exists() {
+        fs::remove_dir_all("tmp_get_filtered").unwrap();
+    }
+
+    fs::create_dir_all("tmp_get_filtered/folder/sub/deeper").unwrap();
+    fs::create_dir_all("tmp_get_filtered/folder/other").unwrap();
+    gen_file("tmp_get_filtered/folder/file1", 512);
+    gen_file("tmp_get_filtered/folder/file2", 512);
+    gen_file("tmp_get_filtered/folder/sub/file3", 512);
+    gen_file("tmp_get_filtered/folder/sub/deeper/file4", 512);
+    gen_file("tmp_get_filtered/folder/other/file5", 512);
+
+    let mut store = Store::create("tmp_get_filtered/store", "1234")?;
+    store.add("tmp_get_filtered/folder", "/")?;
+    store.tag_add("/folder/file1", "export")?;
+    store.tag_add("/folder/sub/deeper/file4", "export")?;
+    store.tag_add("/folder/other", "export")?;
+
+    let tagged = |file: &void::File| file.tags.iter().any(|tag| tag == "export");
+    store.get_filtered("/folder", "tmp_get_filtered/out", &tagged)?;
+
+    let out = Path::new("tmp_get_filtered/out");
+    assert_eq!(
+        fs::read("tmp_get_filtered/folder/file1").unwrap(),
+        fs::read(out.join("file1")).unwrap()
+    );
+    assert_eq!(
+        fs::read("tmp_get_filtered/folder/sub/deeper/file4").unwrap(),
+        fs::read(out.join("sub/deeper/file4")).unwrap()
+    );
+    assert!(!out.join("file2").exists());
+    assert!(!out.join("sub/file3").exists());
+    assert!(!out.join("other").exists());
+
+    println!("Tests that nothing accepted still creates the destination");
+    store.get_filtered("/folder", "tmp_get_filtered/none", &|_| false)?;
+    assert_eq!(0, fs::read_dir("tmp_get_filtered/none").unwrap().count());
+    assert_eq!(
+        store.get_filtered("/folder", "tmp_get_filtered/out", &|_| true),
+        Err(Error::FileAlreadyExistsError)
+    );
+
+    fs::remove_dir_all("tmp_get_filtered").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_id_of_path_of() -> Result<(), Error> {
+    if Path::new("tmp_id_of_path_of").exists() {
+        fs::remove_dir_all("tmp_id_of_path_of").unwrap();
+    }
+
+    fs::create_dir_all("tmp_id_of_path_of/folder/sub").unwrap();
+    gen_file("tmp_id_of_path_of/folder/sub/file", 512);
+
+    let mut store = Store::create("tmp_id_of_path_of/store", "1234")?;
+    store.add("tmp_id_of_path_of/folder", "/")?;
+
+    let file = store.id_of("/folder/sub/file")?;
+    let sub = store.id_of("/folder/sub/")?;
+    assert_eq!(store.stat("/folder/sub/file")?.id, file);
+    assert_eq!("/folder/sub/file", store.path_of(file)?);
+    assert_eq!("/folder/sub", store.path_of(sub)?);
+    assert_eq!("/", store.path_of(store.id_of("/")?)?);
+
+    println!("Tests that ids survive renames and reopening the store");
+    store.mv("/folder/sub/file", "/folder/renamed")?;
+    store.mv("/folder/sub", "/moved")?;
+    let store = Store::open("tmp_id_of_path_of/store", "1234")?;
+    assert_eq!("/folder/renamed", store.path_of(file)?);
+    assert_eq!("/moved", store.path_of(sub)?);
+    assert_eq!(file, store.id_of("/folder/renamed")?);
+
+    assert_eq!(
+        store.id_of("/folder/sub/file"),
+        Err(Error::FileDoesNotExistError)
+    );
+    assert_eq!(store.path_of(u64::MAX), Err(Error::FileDoesNotExistError));
+
+    fs::remove_dir_all("tmp_id_of_path_of").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_id_operations() -> Result<(), Error> {
+    if Path::new("tmp_id_operations").exists() {
+        fs::remove_dir_all("tmp_id_operations").unwrap();
+    }
+
+    fs::create_dir_all("tmp_id_operations/folder").unwrap();
+    gen_file("tmp_id_operations/folder/file1", 512);
+    gen_file("tmp_id_operations/folder/file2", 512);
+
+    let mut store = Store::create("tmp_id_operations/store", "1234")?;
+    store.add("tmp_id_operations/folder", "/")?;
+    let file1 = store.id_of("/folder/file1")?;
+    let file2 = store.id_of("/folder/file2")?;
+
+    store.tag_add_id(file1, "red")?;
+    store.metadata_set_id(file1, "key", "value")?;
+    store.mv_id(file1, "/moved/file")?;
+    assert_eq!("/moved/file", store.path_of(file1)?);
+    assert_eq!(vec!["red".to_string()], store.tag_get("/moved/file")?);
+    assert_eq!("value", store.metadata_get("/moved/file", "key")?);
+    store.tag_rm_id(file1, "red")?;
+    assert!(store.tag_get("/moved/file")?.is_empty());
+
+    store.remove_id(file2)?;
+    assert!(!store.exists("/folder/file2")?);
+
+    println!("Tests ids that do not exist");
+    assert_eq!(store.remove_id(file2), Err(Error::FileDoesNotExistError));
+    assert_eq!(
+        store.mv_id(file2, "/elsewhere"),
+        Err(Error::FileDoesNotExistError)
+    );
+    assert_eq!(
+        store.tag_add_id(file2, "red"),
+        Err(Error::FileDoesNotExistError)
+    );
+    assert_eq!(
+        store.metadata_set_id(file2, "key", "value"),
+        Err(Error::FileDoesNotExistError)
+    );
+    assert!(!store.exists("/elsewhere")?);
+
+    fs::remove_dir_all("tmp_id_operations").unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_close() -> Result<(), Error> {
+    if Path::new("tmp_close").exists() {
+        fs::remove_dir_all("tmp_close").unwrap();
+    }
+
+    fs::create_dir_all("tmp_close").unwrap();
+    let (mut store, _) = StoreBuilder::new("tmp_close/store", "1234")
+        .shard_levels(2)
+        .chunk_size(1024)
+        .build()?;
+    let content: Vec<u8> = (0..5000).map(|i| (i % 256) as u8).collect();
+    store.add_bytes(&content, "/file", None)?;
+    store.close()?;
+
+    let mut store = Store::open("tmp_close/store", "1234")?;
+    assert_eq!(content, store.read_range("/file", 0, 5000)?);
+    store.remove("/file")?;
+    store.close()?;
+
+    println!("Tests closing stores kept in memory");
+    let backend = Arc::new(void::MemoryBackend::default());
+    let (mut store, _) = StoreBuilder::new("", "1234")
+        .backend(backend.clone())
+        .build()?;
+    store.add_bytes(b"memory", "/file", None)?;
+    store.close()?;
+    let mut store = Store::open_backend(backend, "1234")?;
+    assert_eq!(b"memory".to_vec(), store.read_range("/file", 0, 6)?);
+
+    fs::remove_dir_all("tmp_close").unwrap();
+
+    Ok(())
+}
@@ -20,6 +20,89 @@ fn get_output_path() -> PathBuf {
     Path::new(&target).join(build_type)
 }
 
+/// Completes the tags of tag-add, tag-remove and tag-search with the tags in
+/// the store, listed by `void-cli tag-complete`. clap_complete only knows the
+/// static arguments, so the generated scripts are extended here. If a script
+/// does not look as expected, for example after a clap_complete update, it is
+/// left as generated and a warning is printed.
+fn complete_tags(bash_path: &Path, fish_path: &Path, zsh_path: &Path) -> Result<(), Error> {
+    let bash = std::fs::read_to_string(bash_path)?;
+    match bash.rsplit_once("\nif [[ \"${BASH_VERSINFO[0]}\"") {
+        Some((functions, registration)) => {
+            let bash = format!(
+                r#"{functions}
+_void__cli_tags() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" first=0 i
+    for (( i = 1; i < COMP_CWORD; i++ )); do
+        case "${{COMP_WORDS[i]}}" in
+            tag-search) first=$((i + 1)); break ;;
+            tag-add|tag-remove) first=$((i + 2)); break ;;
+        esac
+    done
+
+    if [[ ${{first}} -gt 0 && ${{COMP_CWORD}} -ge ${{first}} && "${{cur}}" != -* ]]; then
+        COMPREPLY=( $(void-cli tag-complete -- "${{cur}}" 2>/dev/null) )
+        return 0
+    fi
+    _void__cli "$@"
+}}
+
+if [[ "${{BASH_VERSINFO[0]}}"{}"#,
+                registration.replace("-F _void__cli ", "-F _void__cli_tags ")
+            );
+            std::fs::write(bash_path, bash)?;
+        }
+        None => println!("cargo:warning=Unexpected bash completion script, tags are not completed"),
+    }
+
+    let mut fish = std::fs::read_to_string(fish_path)?;
+    fish.push_str(
+        r#"complete -c void-cli -n "__fish_void_cli_using_subcommand tag-search" -f -a "(void-cli tag-complete -- (commandline -ct) 2>/dev/null)"
+complete -c void-cli -n "__fish_void_cli_using_subcommand tag-add tag-remove" -a "(void-cli tag-complete -- (commandline -ct) 2>/dev/null)"
+"#,
+    );
+    std::fs::write(fish_path, fish)?;
+
+    let zsh = std::fs::read_to_string(zsh_path)?;
+    let split = zsh
+        .rsplit_once("\nif [ \"$funcstack[1]\"")
+        .filter(|(functions, _)| {
+            functions
+                .lines()
+                .any(|line| line.starts_with("'*::tags -- "))
+        });
+    match split {
+        Some((functions, registration)) => {
+            let zsh = format!(
+                r#"{}
+(( $+functions[_void-cli_tags] )) ||
+_void-cli_tags() {{
+    local -a tags
+    tags=(${{(f)"$(void-cli tag-complete -- "$PREFIX" 2>/dev/null)"}})
+    compadd -a tags
+}}
+
+if [ "$funcstack[1]"{registration}"#,
+                functions
+                    .lines()
+                    .map(|line| {
+                        if line.starts_with("'*::tags -- ") {
+                            line.replace(":_default'", ":_void-cli_tags'")
+                        } else {
+                            line.to_string()
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+            std::fs::write(zsh_path, zsh)?;
+        }
+        None => println!("cargo:warning=Unexpected zsh completion script, tags are not completed"),
+    }
+
+    Ok(())
+}
+
 fn main() -> Result<(), Error> {
     let outdir = match env::var_os("OUT_DIR") {
         None => return Ok(()),
@@ -64,6 +147,8 @@ fn main() -> Result<(), Error> {
         outdir.clone(), // We need to specify where to write to
     )?;
 
+    complete_tags(&bash_path, &fish_path, &zsh_path)?;
+
     let man = clap_mangen::Man::new(cmd);
     let man_path = std::path::PathBuf::from(&outdir)
         .join("share")
@@ -0,0 +1,325 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Key caching agent.
+//!
+//! The agent is a background process that keeps the keys of opened stores in
+//! memory, so that commands run with `--agent` do not need to ask for the
+//! password and derive the key again. It listens on a Unix domain socket only
+//! accessible by the current user and exits after being idle for a while.
+//! Keys are only given to sockets owned by the current user.
+//!
+//! The protocol is line based:
+//!
+//! * `GET <store>` answers `KEY <hex key>` or `NONE`;
+//! * `PUT <hex key> <store>` answers `OK`;
+//! * `PING` answers `PONG`;
+//! * `STOP` answers `OK` and stops the agent.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Makes commands look up and cache store keys in the agent.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether commands should use the agent.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Path of the agent socket. Can be changed with the `VOID_AGENT_SOCK`
+/// environment variable. Without `XDG_RUNTIME_DIR`, the socket goes in a
+/// folder of the temporary directory that only the current user can access,
+/// which is created if missing.
+pub fn socket_path() -> std::io::Result<PathBuf> {
+    if let Some(path) = std::env::var_os("VOID_AGENT_SOCK") {
+        return Ok(path.into());
+    }
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => Ok(Path::new(&dir).join("void-agent.sock")),
+        None => {
+            let uid = unsafe { libc::geteuid() };
+            let dir = std::env::temp_dir().join(format!("void-agent-{uid}"));
+            private_dir(&dir)?;
+            Ok(dir.join("agent.sock"))
+        }
+    }
+}
+
+/// Creates a folder only accessible by the current user, or checks that an
+/// existing one is. Others could otherwise replace the socket in it.
+fn private_dir(dir: &Path) -> std::io::Result<()> {
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(err) if err.kind() != std::io::ErrorKind::AlreadyExists => return Err(err),
+        _ => {}
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || !is_owned(&metadata) || metadata.mode() & 0o077 != 0 {
+        return Err(std::io::ErrorKind::PermissionDenied.into());
+    }
+    Ok(())
+}
+
+/// Whether a file belongs to the current user.
+fn is_owned(metadata: &fs::Metadata) -> bool {
+    metadata.uid() == unsafe { libc::geteuid() }
+}
+
+/// Identifies a store independently of how its path was typed.
+fn store_id(store_path: &str) -> String {
+    fs::canonicalize(store_path)
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_else(|_| store_path.into())
+}
+
+/// Sends a request to the agent and returns its answer.
+fn request(socket: &Path, message: &str) -> Option<String> {
+    let mut stream = UnixStream::connect(socket).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(5))).ok()?;
+    writeln!(stream, "{message}").ok()?;
+    let mut answer = String::new();
+    BufReader::new(stream).read_line(&mut answer).ok()?;
+    Some(answer.trim_end().into())
+}
+
+/// Asks the agent for the key of a store.
+pub fn get_key(store_path: &str) -> Option<[u8; 32]> {
+    get_key_from(&socket_path().ok()?, store_path)
+}
+
+fn get_key_from(socket: &Path, store_path: &str) -> Option<[u8; 32]> {
+    let answer = request(socket, &format!("GET {}", store_id(store_path)))?;
+    let key = hex::decode(answer.strip_prefix("KEY ")?).ok()?;
+    key.try_into().ok()
+}
+
+/// Gives the key of a store to the agent. Does nothing if the agent is not
+/// running, or if its socket belongs to another user.
+pub fn put_key(store_path: &str, key: [u8; 32]) {
+    if let Ok(socket) = socket_path() {
+        put_key_to(&socket, store_path, key);
+    }
+}
+
+fn put_key_to(socket: &Path, store_path: &str, key: [u8; 32]) -> bool {
+    let owned = fs::symlink_metadata(socket).is_ok_and(|metadata| is_owned(&metadata));
+    if !owned {
+        return false;
+    }
+    let message = format!("PUT {} {}", hex::encode(key), store_id(store_path));
+    request(socket, &message).as_deref() == Some("OK")
+}
+
+/// Stops the agent.
+///
+/// # Returns
+///
+/// * Whether there was an agent to stop.
+pub fn stop() -> bool {
+    socket_path().is_ok_and(|socket| request(&socket, "STOP").as_deref() == Some("OK"))
+}
+
+/// Starts the agent in a background process and waits for it to listen.
+///
+/// # Arguments
+///
+/// * `timeout` - Idle time, in seconds, after which the agent exits.
+pub fn start(timeout: u64) -> bool {
+    let Ok(socket) = socket_path() else {
+        return false;
+    };
+    if request(&socket, "PING").is_some() {
+        return true;
+    }
+
+    let Ok(exe) = std::env::current_exe() else {
+        return false;
+    };
+    let child = std::process::Command::new(exe)
+        .args(["agent", "run", "--timeout", &timeout.to_string()])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+    if child.is_err() {
+        return false;
+    }
+
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_secs(5) {
+        if request(&socket, "PING").is_some() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+/// Runs the agent in the current process until it is stopped or stays idle
+/// for `timeout`. The socket is removed when the agent exits.
+///
+/// # Arguments
+///
+/// * `socket` - Path of the socket to listen on.
+/// * `timeout` - Idle time after which the agent exits.
+pub fn serve(socket: &Path, timeout: Duration) -> std::io::Result<()> {
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(std::io::ErrorKind::AddrInUse.into());
+        }
+        fs::remove_file(socket)?;
+    }
+
+    // The socket is created without permissions for others, so that nobody
+    // can connect before they could be changed.
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(socket);
+    unsafe { libc::umask(umask) };
+    let listener = listener?;
+    listener.set_nonblocking(true)?;
+
+    let mut keys: HashMap<String, String> = HashMap::new();
+    let mut last_used = Instant::now();
+
+    while last_used.elapsed() < timeout {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(20));
+                continue;
+            }
+            Err(_) => continue,
+        };
+        last_used = Instant::now();
+        if !handle(stream, &mut keys) {
+            break;
+        }
+    }
+
+    fs::remove_file(socket)
+}
+
+/// Answers a single request.
+///
+/// # Returns
+///
+/// * Whether the agent should keep running.
+fn handle(stream: UnixStream, keys: &mut HashMap<String, String>) -> bool {
+    if stream.set_nonblocking(false).is_err()
+        || stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .is_err()
+    {
+        return true;
+    }
+
+    let mut line = String::new();
+    let mut reader = BufReader::new(&stream);
+    if reader.read_line(&mut line).is_err() {
+        return true;
+    }
+    let line = line.trim_end_matches('\n');
+
+    let (answer, running) = match line.split_once(' ') {
+        Some(("GET", store)) => match keys.get(store) {
+            Some(key) => (format!("KEY {key}"), true),
+            None => ("NONE".into(), true),
+        },
+        Some(("PUT", rest)) => match rest.split_once(' ') {
+            Some((key, store)) if key.len() == 64 && hex::decode(key).is_ok() => {
+                keys.insert(store.into(), key.into());
+                ("OK".into(), true)
+            }
+            _ => ("ERROR".into(), true),
+        },
+        _ if line == "STOP" => ("OK".into(), false),
+        _ if line == "PING" => ("PONG".into(), true),
+        _ => ("ERROR".into(), true),
+    };
+
+    let mut stream = &stream;
+    writeln!(stream, "{answer}").ok();
+    running
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn spawn(socket: &Path, timeout: Duration) -> std::thread::JoinHandle<()> {
+        let path = socket.to_path_buf();
+        let handle = std::thread::spawn(move || serve(&path, timeout).unwrap());
+        while request(socket, "PING").is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        handle
+    }
+
+    #[test]
+    fn test_agent_protocol() {
+        let socket =
+            std::env::temp_dir().join(format!("void-agent-test-{}.sock", std::process::id()));
+        let handle = spawn(&socket, Duration::from_secs(30));
+
+        let key = [7u8; 32];
+        assert_eq!(get_key_from(&socket, "/some/store"), None);
+        assert!(put_key_to(&socket, "/some/store", key));
+        assert_eq!(get_key_from(&socket, "/some/store"), Some(key));
+        assert_eq!(get_key_from(&socket, "/other/store"), None);
+        assert_eq!(
+            request(&socket, "PUT xyz /some/store").as_deref(),
+            Some("ERROR")
+        );
+        assert_eq!(request(&socket, "HELLO").as_deref(), Some("ERROR"));
+        assert_eq!(
+            fs::metadata(&socket).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+
+        assert_eq!(request(&socket, "STOP").as_deref(), Some("OK"));
+        handle.join().unwrap();
+        assert!(!socket.exists());
+    }
+
+    #[test]
+    fn test_private_dir() {
+        let dir = std::env::temp_dir().join(format!("void-agent-dir-{}", std::process::id()));
+        private_dir(&dir).unwrap();
+        let metadata = fs::metadata(&dir).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o700);
+        private_dir(&dir).unwrap();
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(
+            private_dir(&dir).unwrap_err().kind(),
+            std::io::ErrorKind::PermissionDenied
+        );
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_agent_timeout() {
+        let socket =
+            std::env::temp_dir().join(format!("void-agent-timeout-{}.sock", std::process::id()));
+        let handle = spawn(&socket, Duration::from_millis(300));
+
+        assert!(put_key_to(&socket, "/some/store", [1u8; 32]));
+        handle.join().unwrap();
+        assert!(!socket.exists());
+        assert_eq!(get_key_from(&socket, "/some/store"), None);
+    }
+}
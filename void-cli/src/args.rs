@@ -20,9 +20,27 @@ pub enum Commands {
     /// Adds a file or folder to the store
     #[command()]
     Add {
-        /// Path to the store folder
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
         #[arg(short = 's', env = "VOID_STORE")]
-        store_path: String,
+        store_path: Option<String>,
+
+        /// Number of files encrypted at the same time
+        #[arg(long = "threads", default_value_t = 1)]
+        threads: usize,
+
+        /// Adds what symbolic links point to. This is the default
+        #[arg(long = "follow", overrides_with = "store_symlinks")]
+        follow: bool,
+
+        /// Stores symbolic links as links instead of adding what they point to
+        #[arg(long = "store-symlinks", overrides_with = "follow")]
+        store_symlinks: bool,
+
+        /// Records the permissions and modification time of the files, so
+        /// that `get --preserve` can restore them
+        #[arg(long = "preserve")]
+        preserve: bool,
 
         /// Path in the store where it will be saved
         #[arg()]
@@ -33,12 +51,39 @@ pub enum Commands {
         files: Vec<String>,
     },
 
+    /// Synchronizes a folder into the store, skipping unchanged files
+    #[command()]
+    Sync {
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
+        #[arg(short = 's', env = "VOID_STORE")]
+        store_path: Option<String>,
+
+        /// Removes files from the store that are not in the folder anymore
+        #[arg(long = "delete")]
+        delete: bool,
+
+        /// Local folder to synchronize
+        #[arg()]
+        external_path: String,
+
+        /// Path in the store that mirrors the folder
+        #[arg()]
+        internal_path: String,
+    },
+
     /// Get a file or folder from the store (unencrypts it)
     #[command()]
     Get {
-        /// Path to the store folder
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
         #[arg(long = "store", short = 's', env = "VOID_STORE")]
-        store_path: String,
+        store_path: Option<String>,
+
+        /// Restores the permissions and modification time recorded by
+        /// `add --preserve`
+        #[arg(long = "preserve")]
+        preserve: bool,
 
         /// Path in the store where it will be saved
         #[arg()]
@@ -49,16 +94,81 @@ pub enum Commands {
         external_path: String,
     },
 
+    /// Prints the content of a file, or of part of it, to stdout
+    #[command()]
+    Cat {
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
+        #[arg(long = "store", short = 's', env = "VOID_STORE")]
+        store_path: Option<String>,
+
+        /// Path of the file in the store
+        #[arg()]
+        path: String,
+
+        /// Position of the first byte to print
+        #[arg(long = "offset", default_value_t = 0)]
+        offset: u64,
+
+        /// Number of bytes to print. Defaults to the rest of the file
+        #[arg(long = "length")]
+        length: Option<u64>,
+    },
+
+    /// Exports a file or folder from the store as a tar archive (unencrypted)
+    #[command()]
+    Export {
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
+        #[arg(long = "store", short = 's', env = "VOID_STORE")]
+        store_path: Option<String>,
+
+        /// Path in the store to export
+        #[arg()]
+        internal_path: String,
+
+        /// Archive to create, or - for the standard output
+        #[arg()]
+        out: String,
+    },
+
+    /// Imports a tar archive into the store
+    #[command()]
+    Import {
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
+        #[arg(long = "store", short = 's', env = "VOID_STORE")]
+        store_path: Option<String>,
+
+        /// Archive to import, or - for the standard input
+        #[arg(value_name = "IN")]
+        input: String,
+
+        /// Path in the store where it will be extracted
+        #[arg()]
+        internal_path: String,
+    },
+
     /// Removes a file or folder from the store
     #[command()]
     RM {
-        /// Path to the store folder
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
         #[arg(short = 's', env = "VOID_STORE")]
-        store_path: String,
+        store_path: Option<String>,
 
-        /// Path of file or folder to remove from store
+        /// Path of file or folder to remove from store. With --matching, a
+        /// glob like *.tmp, or tag: followed by tags as in tag-search
         #[arg()]
         path: String,
+
+        /// Removes everything matching the path instead
+        #[arg(long = "matching", short = 'm')]
+        matching: bool,
+
+        /// Does not ask for confirmation before removing folders or matches
+        #[arg(long = "yes", short = 'y')]
+        yes: bool,
     },
 
     /// List files in the store
@@ -72,21 +182,138 @@ pub enum Commands {
         #[arg(short = 'H')]
         human: bool,
 
-        /// Path to the store folder
+        /// Prints the list as JSON
+        #[arg(long = "json")]
+        json: bool,
+
+        /// Lists files and folders whose name starts with a dot too
+        #[arg(long = "all", short = 'a')]
+        all: bool,
+
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
         #[arg(long = "store", short = 's', env = "VOID_STORE")]
-        store_path: String,
+        store_path: Option<String>,
 
         /// Path to list
         #[arg()]
         path: String,
     },
 
+    /// Shows information about a file or folder
+    #[command()]
+    Stat {
+        /// Prints human-readable sizes
+        #[arg(short = 'H')]
+        human: bool,
+
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
+        #[arg(long = "store", short = 's', env = "VOID_STORE")]
+        store_path: Option<String>,
+
+        /// Path of file or folder
+        #[arg()]
+        path: String,
+    },
+
+    /// Shows the total size of a file or folder
+    #[command()]
+    DU {
+        /// Prints human-readable sizes
+        #[arg(short = 'H')]
+        human: bool,
+
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
+        #[arg(long = "store", short = 's', env = "VOID_STORE")]
+        store_path: Option<String>,
+
+        /// Path of file or folder
+        #[arg()]
+        path: String,
+    },
+
+    /// Shows a summary of the store
+    #[command()]
+    Info {
+        /// Prints human-readable sizes
+        #[arg(short = 'H')]
+        human: bool,
+
+        /// Prints the summary as JSON
+        #[arg(long = "json")]
+        json: bool,
+
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
+        #[arg(long = "store", short = 's', env = "VOID_STORE")]
+        store_path: Option<String>,
+    },
+
+    /// Mounts the store as a read-only filesystem (requires the fuse feature)
+    #[command()]
+    Mount {
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
+        #[arg(long = "store", short = 's', env = "VOID_STORE")]
+        store_path: Option<String>,
+
+        /// Folder where to mount the store
+        #[arg()]
+        mountpoint: String,
+    },
+
+    /// Serves the store over WebDAV (requires the webdav feature). The
+    /// password must be given in VOID_PSWD or typed in. Clients log in with
+    /// the store password, or with the token in VOID_WEBDAV_TOKEN if set,
+    /// which is required to listen on addresses other than loopback
+    #[command()]
+    Serve {
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
+        #[arg(long = "webdav", value_name = "STORE", env = "VOID_STORE")]
+        store_path: Option<String>,
+
+        /// Address to listen on
+        #[arg(long = "bind", default_value = "127.0.0.1:8080")]
+        bind: String,
+    },
+
+    /// Synchronizes a folder into the store, then again whenever it changes,
+    /// until killed (requires the watch feature). The password must be given
+    /// in VOID_PSWD or typed in
+    #[command()]
+    Watch {
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
+        #[arg(short = 's', env = "VOID_STORE")]
+        store_path: Option<String>,
+
+        /// Removes files from the store that are removed from the folder
+        #[arg(long = "delete")]
+        delete: bool,
+
+        /// Local folder to watch
+        #[arg()]
+        external_path: String,
+
+        /// Path in the store that mirrors the folder
+        #[arg()]
+        internal_path: String,
+    },
+
+    /// Manages the agent that caches store keys between commands
+    #[command(subcommand)]
+    Agent(AgentCommands),
+
     /// Set file metadata
     #[command()]
     MetadataSet {
-        /// Path to the store folder
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
         #[arg(short = 's', env = "VOID_STORE")]
-        store_path: String,
+        store_path: Option<String>,
 
         /// Path of file or folder to set metadata
         #[arg()]
@@ -99,14 +326,19 @@ pub enum Commands {
         /// Metadata value
         #[arg()]
         value: String,
+
+        /// Applies to everything inside the folder too
+        #[arg(long = "recursive", short = 'r')]
+        recursive: bool,
     },
 
     /// Get file metadata
     #[command()]
     MetadataGet {
-        /// Path to the store folder
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
         #[arg(short = 's', env = "VOID_STORE")]
-        store_path: String,
+        store_path: Option<String>,
 
         /// Path of file or folder to get metadata
         #[arg()]
@@ -120,9 +352,10 @@ pub enum Commands {
     /// List file metadata
     #[command()]
     MetadataList {
-        /// Path to the store folder
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
         #[arg(short = 's', env = "VOID_STORE")]
-        store_path: String,
+        store_path: Option<String>,
 
         /// Path of file or folder to list metadata
         #[arg()]
@@ -132,9 +365,10 @@ pub enum Commands {
     /// Remove file metadata
     #[command()]
     MetadataRemove {
-        /// Path to the store folder
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
         #[arg(short = 's', env = "VOID_STORE")]
-        store_path: String,
+        store_path: Option<String>,
 
         /// Path of file or folder to get metadata
         #[arg()]
@@ -148,9 +382,10 @@ pub enum Commands {
     /// Add node tag
     #[command()]
     TagAdd {
-        /// Path to the store folder
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
         #[arg(short = 's', env = "VOID_STORE")]
-        store_path: String,
+        store_path: Option<String>,
 
         /// Path of file or folder to add tag
         #[arg()]
@@ -159,14 +394,19 @@ pub enum Commands {
         /// Tag name
         #[arg()]
         tags: Vec<String>,
+
+        /// Applies to everything inside the folder too
+        #[arg(long = "recursive", short = 'r')]
+        recursive: bool,
     },
 
     /// Remove node tag
     #[command()]
     TagRemove {
-        /// Path to the store folder
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
         #[arg(short = 's', env = "VOID_STORE")]
-        store_path: String,
+        store_path: Option<String>,
 
         /// Path of file or folder to remove tag
         #[arg()]
@@ -175,14 +415,19 @@ pub enum Commands {
         /// Tag name
         #[arg()]
         tags: Vec<String>,
+
+        /// Applies to everything inside the folder too
+        #[arg(long = "recursive", short = 'r')]
+        recursive: bool,
     },
 
     /// Get node tags
     #[command()]
     TagGet {
-        /// Path to the store folder
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
         #[arg(short = 's', env = "VOID_STORE")]
-        store_path: String,
+        store_path: Option<String>,
 
         /// Path of file or folder to get tag
         #[arg()]
@@ -192,9 +437,10 @@ pub enum Commands {
     /// Get node tags
     #[command()]
     TagClear {
-        /// Path to the store folder
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
         #[arg(short = 's', env = "VOID_STORE")]
-        store_path: String,
+        store_path: Option<String>,
 
         /// Path of file or folder to clear tags
         #[arg()]
@@ -204,22 +450,61 @@ pub enum Commands {
     /// List tags in the filesystem
     #[command()]
     TagList {
-        /// Path to the store folder
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
         #[arg(short = 's', env = "VOID_STORE")]
-        store_path: String,
+        store_path: Option<String>,
     },
 
     /// List nodes with tags
     #[command()]
     TagSearch {
-        /// Path to the store folder
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
         #[arg(short = 's', env = "VOID_STORE")]
-        store_path: String,
+        store_path: Option<String>,
 
         /// Tags to search for. tag1 !tag2 will match files that contains tag1 but not tag2
         #[arg()]
         tags: Vec<String>,
     },
+
+    /// List tags starting with a prefix, one per line. Used by the shell
+    /// completions, so it never asks for a password
+    #[command()]
+    TagComplete {
+        /// Path to the store folder. Defaults to the first folder containing a
+        /// store, going up from the current one
+        #[arg(short = 's', env = "VOID_STORE")]
+        store_path: Option<String>,
+
+        /// Prefix of the tags. A leading ! completes excluded tags of tag-search
+        #[arg(default_value = "")]
+        prefix: String,
+    },
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum AgentCommands {
+    /// Starts the agent in the background
+    #[command()]
+    Start {
+        /// Seconds without requests after which the agent exits
+        #[arg(long = "timeout", default_value_t = 900)]
+        timeout: u64,
+    },
+
+    /// Runs the agent in the foreground
+    #[command()]
+    Run {
+        /// Seconds without requests after which the agent exits
+        #[arg(long = "timeout", default_value_t = 900)]
+        timeout: u64,
+    },
+
+    /// Stops the agent, forgetting all keys
+    #[command()]
+    Stop,
 }
 
 static LONG_ABOUT: &str = "
@@ -227,7 +512,14 @@ Void is an encrypted file store.
 
 Its goal is to provide a filesystem-like way of storing encrypted files. You
 can add (encrypt), get (unencrypt) and manage (list, search, remove and move)
-files and folders. It also allows to set/get store-only metadata.";
+files and folders. It also allows to set/get store-only metadata.
+
+Exit status:
+  0  Success
+  1  Other errors
+  2  Wrong password
+  3  File, folder or store not found
+  4  Could not read or write files";
 
 #[derive(Debug, Parser)]
 #[command(author, version, about = LONG_ABOUT)]
@@ -238,4 +530,22 @@ pub struct Arguments {
     /// Password
     #[arg(global = true, long = "password", short = 'p', env = "VOID_PSWD")]
     pub password: Option<String>,
+
+    /// Key file that opens the store, alone or with the password. On create,
+    /// an empty password makes the key file alone open the store
+    #[arg(global = true, long = "keyfile", env = "VOID_KEYFILE")]
+    pub keyfile: Option<String>,
+
+    /// Uses the agent to cache the store key, so the password is asked once
+    #[arg(global = true, long = "agent", env = "VOID_AGENT")]
+    pub agent: bool,
+
+    /// Prints only results and errors
+    #[arg(global = true, long = "quiet", short = 'q', conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Prints details about each file, and about each chunk if built with the
+    /// tracing feature
+    #[arg(global = true, long = "verbose", short = 'v')]
+    pub verbose: bool,
 }
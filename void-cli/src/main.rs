@@ -4,15 +4,37 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
+mod agent;
 mod args;
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+mod mount;
 mod store;
+#[cfg(feature = "watch")]
+mod watch;
+#[cfg(feature = "webdav")]
+mod webdav;
 
-use args::{Arguments, Commands, Parser};
+use args::{AgentCommands, Arguments, Commands, Parser};
 use rpassword;
+use void::{Error, Error::*};
 
 fn main() {
     let options = Arguments::parse();
 
+    if options.agent {
+        agent::enable();
+    }
+
+    store::set_output(options.quiet, options.verbose);
+    store::set_keyfile(options.keyfile.clone());
+    #[cfg(feature = "tracing")]
+    if options.verbose {
+        tracing_subscriber::fmt()
+            .with_max_level(tracing_subscriber::filter::LevelFilter::TRACE)
+            .with_writer(std::io::stderr)
+            .init();
+    }
+
     match options.command {
         Commands::Create { store_name } => loop {
             let pswd = read_password(options.password.clone());
@@ -23,8 +45,8 @@ fn main() {
                 continue;
             }
 
-            if store::create_store(store_name, pswd).is_none() {
-                std::process::exit(1);
+            if let Err(error) = store::create_store(store_name, pswd) {
+                std::process::exit(exit_code(&error));
             }
 
             break;
@@ -32,54 +54,222 @@ fn main() {
 
         Commands::Add {
             store_path,
+            threads,
+            follow: _,
+            store_symlinks,
+            preserve,
             files,
             internal_path,
         } => {
-            let pswd = read_password(options.password);
-            if store::add(store_path, internal_path, files, pswd).is_none() {
-                std::process::exit(1);
+            let pswd = options.password;
+            let follow_links = !store_symlinks;
+            if let Err(error) = store::add(
+                store_path,
+                internal_path,
+                files,
+                threads,
+                follow_links,
+                preserve,
+                pswd,
+            ) {
+                std::process::exit(exit_code(&error));
+            }
+        }
+
+        Commands::Sync {
+            store_path,
+            delete,
+            external_path,
+            internal_path,
+        } => {
+            let pswd = options.password;
+            if let Err(error) = store::sync(store_path, external_path, internal_path, delete, pswd)
+            {
+                std::process::exit(exit_code(&error));
             }
         }
 
         Commands::Get {
             store_path,
+            preserve,
             internal_path,
             external_path,
         } => {
-            let pswd = read_password(options.password);
-            if store::get(store_path, internal_path, external_path, pswd).is_none() {
-                std::process::exit(1);
+            let pswd = options.password;
+            if let Err(error) = store::get(store_path, internal_path, external_path, preserve, pswd)
+            {
+                std::process::exit(exit_code(&error));
+            }
+        }
+
+        Commands::Cat {
+            store_path,
+            path,
+            offset,
+            length,
+        } => {
+            let pswd = options.password;
+            if let Err(error) = store::cat(store_path, path, offset, length, pswd) {
+                std::process::exit(exit_code(&error));
+            }
+        }
+
+        Commands::Export {
+            store_path,
+            internal_path,
+            out,
+        } => {
+            let pswd = options.password;
+            if let Err(error) = store::export(store_path, internal_path, out, pswd) {
+                std::process::exit(exit_code(&error));
+            }
+        }
+
+        Commands::Import {
+            store_path,
+            input,
+            internal_path,
+        } => {
+            let pswd = options.password;
+            if let Err(error) = store::import(store_path, input, internal_path, pswd) {
+                std::process::exit(exit_code(&error));
             }
         }
 
         Commands::LS {
             human,
+            json,
             store_path,
             path,
             list,
+            all,
+        } => {
+            let pswd = options.password;
+            if let Err(error) = store::list(store_path, path, pswd, human, list, json, all) {
+                std::process::exit(exit_code(&error));
+            }
+        }
+
+        Commands::Stat {
+            human,
+            store_path,
+            path,
+        } => {
+            let pswd = options.password;
+            if let Err(error) = store::stat(store_path, path, pswd, human) {
+                std::process::exit(exit_code(&error));
+            }
+        }
+
+        Commands::DU {
+            human,
+            store_path,
+            path,
+        } => {
+            let pswd = options.password;
+            if let Err(error) = store::disk_usage(store_path, path, pswd, human) {
+                std::process::exit(exit_code(&error));
+            }
+        }
+
+        Commands::Info {
+            human,
+            json,
+            store_path,
+        } => {
+            let pswd = options.password;
+            if let Err(error) = store::info(store_path, pswd, human, json) {
+                std::process::exit(exit_code(&error));
+            }
+        }
+
+        Commands::Mount {
+            store_path,
+            mountpoint,
         } => {
-            let pswd = read_password(options.password);
-            if store::list(store_path, path, pswd, human, list).is_none() {
+            let pswd = options.password;
+            if let Err(error) = store::mount(store_path, mountpoint, pswd) {
+                std::process::exit(exit_code(&error));
+            }
+        }
+
+        Commands::Serve { store_path, bind } => {
+            // The server runs for a long time, so the password must not be
+            // visible in its command line.
+            let pswd = std::env::var("VOID_PSWD").ok();
+            if options.password.is_some() && options.password != pswd {
+                eprint!("The password must be given in VOID_PSWD or typed in.");
                 std::process::exit(1);
             }
+            if let Err(error) = store::serve(store_path, bind, pswd) {
+                std::process::exit(exit_code(&error));
+            }
         }
 
-        Commands::RM { store_path, path } => {
-            let pswd = read_password(options.password);
-            if store::remove(store_path, path, pswd).is_none() {
+        Commands::Watch {
+            store_path,
+            delete,
+            external_path,
+            internal_path,
+        } => {
+            // Watching runs for a long time, so the password must not be
+            // visible in its command line.
+            let pswd = std::env::var("VOID_PSWD").ok();
+            if options.password.is_some() && options.password != pswd {
+                eprint!("The password must be given in VOID_PSWD or typed in.");
                 std::process::exit(1);
             }
+            if let Err(error) = store::watch(store_path, external_path, internal_path, delete, pswd)
+            {
+                std::process::exit(exit_code(&error));
+            }
+        }
+
+        Commands::RM {
+            store_path,
+            path,
+            matching,
+            yes,
+        } => {
+            let pswd = options.password;
+            if let Err(error) = store::remove(store_path, path, pswd, matching, yes) {
+                std::process::exit(exit_code(&error));
+            }
         }
 
+        Commands::Agent(command) => match command {
+            AgentCommands::Start { timeout } => {
+                if !agent::start(timeout) {
+                    eprint!("Could not start the agent.");
+                    std::process::exit(1);
+                }
+            }
+            AgentCommands::Run { timeout } => {
+                let timeout = std::time::Duration::from_secs(timeout);
+                let served = agent::socket_path().and_then(|socket| agent::serve(&socket, timeout));
+                if let Err(err) = served {
+                    eprint!("Could not run the agent: {err}");
+                    std::process::exit(1);
+                }
+            }
+            AgentCommands::Stop => {
+                if !agent::stop() {
+                    eprint!("The agent is not running.");
+                    std::process::exit(1);
+                }
+            }
+        },
+
         Commands::MetadataSet {
             store_path,
             path,
             key,
             value,
+            recursive,
         } => {
-            let pswd = read_password(options.password);
-            if store::metadata_set(store_path, path, pswd, key, value).is_none() {
-                std::process::exit(1);
+            let pswd = options.password;
+            if let Err(error) = store::metadata_set(store_path, path, pswd, key, value, recursive) {
+                std::process::exit(exit_code(&error));
             }
         }
 
@@ -88,16 +278,16 @@ fn main() {
             path,
             key,
         } => {
-            let pswd = read_password(options.password);
-            if store::metadata_get(store_path, path, pswd, key).is_none() {
-                std::process::exit(1);
+            let pswd = options.password;
+            if let Err(error) = store::metadata_get(store_path, path, pswd, key) {
+                std::process::exit(exit_code(&error));
             }
         }
 
         Commands::MetadataList { store_path, path } => {
-            let pswd = read_password(options.password);
-            if store::metadata_list(store_path, path, pswd).is_none() {
-                std::process::exit(1);
+            let pswd = options.password;
+            if let Err(error) = store::metadata_list(store_path, path, pswd) {
+                std::process::exit(exit_code(&error));
             }
         }
 
@@ -106,9 +296,9 @@ fn main() {
             path,
             key,
         } => {
-            let pswd = read_password(options.password);
-            if store::metadata_remove(store_path, path, pswd, key).is_none() {
-                std::process::exit(1);
+            let pswd = options.password;
+            if let Err(error) = store::metadata_remove(store_path, path, pswd, key) {
+                std::process::exit(exit_code(&error));
             }
         }
 
@@ -116,12 +306,11 @@ fn main() {
             store_path,
             path,
             tags,
+            recursive,
         } => {
-            let pswd = read_password(options.password);
-            for tag in tags {
-                if store::tag_add(store_path.clone(), path.clone(), pswd.clone(), tag).is_none() {
-                    std::process::exit(1);
-                }
+            let pswd = options.password;
+            if let Err(error) = store::tag_add(store_path, path, pswd, tags, recursive) {
+                std::process::exit(exit_code(&error));
             }
         }
 
@@ -129,43 +318,62 @@ fn main() {
             store_path,
             path,
             tags,
+            recursive,
         } => {
-            let pswd = read_password(options.password);
-            for tag in tags {
-                if store::tag_remove(store_path.clone(), path.clone(), pswd.clone(), tag).is_none()
-                {
-                    std::process::exit(1);
-                }
+            let pswd = options.password;
+            if let Err(error) = store::tag_remove(store_path, path, pswd, tags, recursive) {
+                std::process::exit(exit_code(&error));
             }
         }
 
         Commands::TagGet { store_path, path } => {
-            let pswd = read_password(options.password);
-            if store::tag_get(store_path, path, pswd).is_none() {
-                std::process::exit(1);
+            let pswd = options.password;
+            if let Err(error) = store::tag_get(store_path, path, pswd) {
+                std::process::exit(exit_code(&error));
             }
         }
 
         Commands::TagList { store_path } => {
-            let pswd = read_password(options.password);
-            if store::tag_list(store_path, pswd).is_none() {
-                std::process::exit(1);
+            let pswd = options.password;
+            if let Err(error) = store::tag_list(store_path, pswd) {
+                std::process::exit(exit_code(&error));
             }
         }
 
         Commands::TagClear { store_path, path } => {
-            let pswd = read_password(options.password);
-            if store::tag_clear(store_path, path, pswd).is_none() {
-                std::process::exit(1);
+            let pswd = options.password;
+            if let Err(error) = store::tag_clear(store_path, path, pswd) {
+                std::process::exit(exit_code(&error));
             }
         }
 
         Commands::TagSearch { store_path, tags } => {
-            let pswd = read_password(options.password);
-            if store::tag_search(store_path, tags, pswd).is_none() {
-                std::process::exit(1);
+            let pswd = options.password;
+            if let Err(error) = store::tag_search(store_path, tags, pswd) {
+                std::process::exit(exit_code(&error));
             }
         }
+
+        Commands::TagComplete { store_path, prefix } => {
+            store::tag_complete(store_path, prefix, options.password);
+        }
+    }
+}
+
+/// Exit code of a failed command, so that scripts can tell the kind of error
+/// apart. Listed in `--help`.
+fn exit_code(error: &Error) -> i32 {
+    match error {
+        WrongPassword | KeyfileRequired => 2,
+        FileDoesNotExistError | FolderDoesNotExistError => 3,
+        IoError(std::io::ErrorKind::Unsupported) => 1,
+        IoError(_)
+        | CannotReadFileError
+        | CannotWriteFileError
+        | CannotCreateFileError
+        | CannotCreateDirectoryError
+        | CannotRemoveFilesError(_) => 4,
+        _ => 1,
     }
 }
 
@@ -0,0 +1,207 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Read-only FUSE view of a store.
+//!
+//! Inode numbers are the store node ids plus one, as FUSE reserves inode 1
+//! for the root and the root of the store has id 0. The store path of every
+//! inode seen by the kernel is remembered, so that lookups can be answered
+//! with the path based `Store` API.
+
+use fuser::{
+    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry,
+    Request, FUSE_ROOT_ID,
+};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::time::{Duration, UNIX_EPOCH};
+use void::{File, Store};
+
+/// How long the kernel may cache attributes and entries. The mount is
+/// read-only, so nothing changes under it.
+const TTL: Duration = Duration::from_secs(60);
+
+pub struct VoidFs {
+    store: Store,
+    paths: HashMap<u64, String>,
+}
+
+impl VoidFs {
+    pub fn new(store: Store) -> VoidFs {
+        let paths = HashMap::from([(FUSE_ROOT_ID, "/".to_string())]);
+        VoidFs { store, paths }
+    }
+
+    fn child_path(&self, parent: u64, name: &str) -> Option<String> {
+        let parent = self.paths.get(&parent)?;
+        Some(format!("{}/{name}", parent.trim_end_matches('/')))
+    }
+
+    fn attr(&self, file: &File) -> FileAttr {
+        let modified = UNIX_EPOCH + Duration::from_secs(file.modified);
+        let created = UNIX_EPOCH + Duration::from_secs(file.created);
+        let (kind, perm, nlink) = if file.is_file {
+            (FileType::RegularFile, 0o444, 1)
+        } else {
+            (FileType::Directory, 0o555, 2)
+        };
+        FileAttr {
+            ino: file.id + 1,
+            size: file.size,
+            blocks: file.size.div_ceil(512),
+            atime: modified,
+            mtime: modified,
+            ctime: modified,
+            crtime: created,
+            kind,
+            perm,
+            nlink,
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        }
+    }
+}
+
+impl Filesystem for VoidFs {
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let Some(path) = name.to_str().and_then(|name| self.child_path(parent, name)) else {
+            return reply.error(libc::ENOENT);
+        };
+        match self.store.stat(&path) {
+            Ok(file) => {
+                self.paths.insert(file.id + 1, path);
+                reply.entry(&TTL, &self.attr(&file), 0);
+            }
+            Err(_) => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        let Some(path) = self.paths.get(&ino) else {
+            return reply.error(libc::ENOENT);
+        };
+        match self.store.stat(path) {
+            Ok(file) => reply.attr(&TTL, &self.attr(&file)),
+            Err(_) => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let Some(path) = self.paths.get(&ino).cloned() else {
+            return reply.error(libc::ENOENT);
+        };
+        match self.store.read_range(&path, offset as u64, size as u64) {
+            Ok(bytes) => reply.data(&bytes),
+            Err(_) => reply.error(libc::EIO),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let Some(path) = self.paths.get(&ino).cloned() else {
+            return reply.error(libc::ENOENT);
+        };
+        let children = match self.store.list(&path) {
+            Ok(children) => children,
+            Err(_) => return reply.error(libc::ENOTDIR),
+        };
+
+        let mut entries = vec![
+            (ino, FileType::Directory, ".".to_string()),
+            (ino, FileType::Directory, "..".to_string()),
+        ];
+        for child in children {
+            let kind = if child.is_file {
+                FileType::RegularFile
+            } else {
+                FileType::Directory
+            };
+            if let Some(child_path) = self.child_path(ino, &child.name) {
+                self.paths.insert(child.id + 1, child_path);
+            }
+            entries.push((child.id + 1, kind, child.name));
+        }
+
+        for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+            if reply.add(ino, (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+/// Options used to mount a store.
+pub fn mount_options() -> Vec<MountOption> {
+    vec![
+        MountOption::RO,
+        MountOption::FSName("void".into()),
+        MountOption::DefaultPermissions,
+    ]
+}
+
+/// Mounts the store and blocks until it is unmounted.
+pub fn mount(store: Store, mountpoint: &str) -> std::io::Result<()> {
+    fuser::mount2(VoidFs::new(store), mountpoint, &mount_options())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_mount() {
+        let root = std::env::temp_dir().join(format!("void-mount-{}", std::process::id()));
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+        fs::create_dir_all(root.join("files/folder")).unwrap();
+        fs::create_dir_all(root.join("mnt")).unwrap();
+        let content: Vec<u8> = (0..5000).map(|i| (i % 256) as u8).collect();
+        fs::write(root.join("files/folder/file"), &content).unwrap();
+
+        let store_path = root.join("store").to_string_lossy().to_string();
+        let mut store = Store::create(store_path, "1234".to_string()).unwrap();
+        store
+            .add(&root.join("files/folder").to_string_lossy(), "/")
+            .unwrap();
+
+        let session =
+            fuser::spawn_mount2(VoidFs::new(store), root.join("mnt"), &mount_options()).unwrap();
+
+        let mut names: Vec<String> = fs::read_dir(root.join("mnt/folder"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["file"]);
+        assert_eq!(fs::read(root.join("mnt/folder/file")).unwrap(), content);
+        assert!(fs::write(root.join("mnt/folder/other"), b"data").is_err());
+
+        session.join();
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
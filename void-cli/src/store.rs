@@ -4,144 +4,557 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
+use crate::agent;
 use prettytable::{cell, row, Cell, Row, Table};
-use std::cmp::Ordering;
 use std::collections::HashMap;
-use void::{Error::*, Store};
+use std::fs;
+use std::io::{IsTerminal, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::OnceLock;
+use void::{Credentials, Error, Error::*, Progress, SortKey, Store, StoreBuilder};
 
-fn open_store(path: String, password: String) -> Option<Store> {
-    Store::open(path, password)
-        .map_err(|error| {
-            let msg = match &error {
-                FolderDoesNotExistError => "The specified Store does not exist.".into(),
-                FileDoesNotExistError => "The specified Store does not exist.".into(),
-                CannotReadFileError => "Cannot read the store file.".into(),
-                CannotDeserializeError => {
-                    "Could not deserialize the store file. Is it corrupt? Or wrong password?".into()
-                }
-                err => format!("Unexpected error ocurred: {err:?}"),
-            };
-            eprint!("{msg}");
-            error
-        })
-        .ok()
+static QUIET: AtomicBool = AtomicBool::new(false);
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+static KEYFILE: OnceLock<String> = OnceLock::new();
+
+/// Password strength, as estimated by `void::password_strength`, below which
+/// `create` warns that the password is weak.
+const WEAK_PASSWORD: u8 = 50;
+
+/// Sets how much the commands print besides their results and errors.
+///
+/// # Arguments
+///
+/// * `quiet` - Suppresses progress messages.
+/// * `verbose` - Prints details about each file.
+pub fn set_output(quiet: bool, verbose: bool) {
+    QUIET.store(quiet, AtomicOrdering::Relaxed);
+    VERBOSE.store(verbose, AtomicOrdering::Relaxed);
 }
 
-pub fn create_store(path: String, password: String) -> Option<()> {
-    Store::create(path, password)
-        .map_err(|error| {
-            let msg = match &error {
-                CannotCreateDirectoryError => "Could not create folder.".into(),
-                CannotSerializeError => "Could not serialize store.".into(),
-                CannotWriteFileError => "Could not write to store file.".into(),
-                CannotCreateFileError => "Could not create store file.".into(),
-                FileAlreadyExistsError => "Store already exists.".into(),
-                err => format!("Unknown error occurred: {err:?}"),
+/// Sets the key file given with `--keyfile`, used to create and open stores.
+pub fn set_keyfile(path: Option<String>) {
+    if let Some(path) = path {
+        let _ = KEYFILE.set(path);
+    }
+}
+
+/// Reads the key file given with `--keyfile`, if any.
+fn read_keyfile() -> Result<Option<Vec<u8>>, Error> {
+    let Some(path) = KEYFILE.get() else {
+        return Ok(None);
+    };
+    fs::read(path).map(Some).map_err(|error| {
+        eprint!("Could not read the key file {path}.");
+        error.into()
+    })
+}
+
+/// Prints a progress message, unless `--quiet` was given.
+fn progress(msg: &str) {
+    if !QUIET.load(AtomicOrdering::Relaxed) {
+        println!("{msg}");
+    }
+}
+
+/// Whether `--verbose` was given.
+fn is_verbose() -> bool {
+    VERBOSE.load(AtomicOrdering::Relaxed)
+}
+
+/// Progress bar drawn on stderr while files are added or written out.
+/// Draws nothing under `--quiet` or when stderr is not a terminal.
+struct ProgressBar {
+    bar: indicatif::ProgressBar,
+}
+
+impl ProgressBar {
+    const TEMPLATE: &'static str = "[{bar:30}] {msg} {bytes}/{total_bytes}";
+
+    fn new() -> ProgressBar {
+        if QUIET.load(AtomicOrdering::Relaxed) || !std::io::stderr().is_terminal() {
+            return ProgressBar {
+                bar: indicatif::ProgressBar::hidden(),
             };
-            eprint!("{msg}");
-            error
-        })
-        .ok()?;
+        }
+        let style = indicatif::ProgressStyle::with_template(Self::TEMPLATE)
+            .unwrap_or_else(|_| indicatif::ProgressStyle::default_bar())
+            .progress_chars("#>-");
+        ProgressBar {
+            bar: indicatif::ProgressBar::new(0).with_style(style),
+        }
+    }
+
+    /// Moves the bar to the given progress.
+    fn update(&self, progress: Progress) {
+        self.bar.set_length(progress.total_bytes);
+        self.bar.set_position(progress.bytes);
+        self.bar.set_message(format!(
+            "{}/{} files,",
+            progress.files, progress.total_files
+        ));
+    }
+
+    /// Leaves the bar as it is, so that what is printed next starts on its
+    /// own line.
+    fn finish(&self) {
+        self.bar.abandon();
+    }
+}
 
-    println!("Store created.");
-    Some(())
+/// Finds the store a command works on when none is given: the first folder
+/// containing a `Store.void` file, going up from the current one, like git
+/// finds its repository.
+pub fn find_store(dir: &Path) -> Option<String> {
+    dir.ancestors()
+        .find(|dir| dir.join("Store.void").is_file())
+        .map(|dir| dir.to_string_lossy().to_string())
+}
+
+fn open_store(path: Option<String>, password: Option<String>) -> Result<Store, Error> {
+    let path = path.or_else(|| find_store(&std::env::current_dir().ok()?));
+    let Some(path) = path else {
+        eprint!("No store given and none found in the current folder or its parents.");
+        return Err(FolderDoesNotExistError);
+    };
+
+    if agent::is_enabled() {
+        if let Some(store) =
+            agent::get_key(&path).and_then(|key| Store::open_with_key(&path, key).ok())
+        {
+            return Ok(store);
+        }
+    }
+
+    // The password is only asked for when the store needs it along with the
+    // key file.
+    let store = match read_keyfile()? {
+        None => Store::open(path.clone(), crate::read_password(password)),
+        Some(keyfile) => match Store::required_credentials(&path) {
+            Ok(Credentials::PasswordAndKeyfile) => {
+                let password = crate::read_password(password);
+                Store::open_with_password_and_keyfile(path.clone(), password, &keyfile)
+            }
+            Ok(_) => Store::open_with_keyfile(path.clone(), &keyfile),
+            Err(error) => Err(error),
+        },
+    };
+
+    let store = store.map_err(|error| {
+        let msg = match &error {
+            FolderDoesNotExistError => "The specified Store does not exist.".into(),
+            FileDoesNotExistError => "The specified Store does not exist.".into(),
+            CannotReadFileError => "Cannot read the store file.".into(),
+            WrongPassword => "Wrong password.".into(),
+            KeyfileRequired => "The store needs its key file, given with --keyfile.".into(),
+            StoreCorrupted => "The store file is corrupted.".into(),
+            err => format!("Unexpected error ocurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })?;
+
+    if agent::is_enabled() {
+        agent::put_key(&path, store.key());
+    }
+
+    Ok(store)
+}
+
+pub fn create_store(path: String, password: String) -> Result<(), Error> {
+    if !password.is_empty() && void::password_strength(&password) < WEAK_PASSWORD {
+        eprintln!("Warning: the password is weak and could be guessed.");
+    }
+
+    let mut builder = StoreBuilder::new(path, password);
+    if let Some(keyfile) = read_keyfile()? {
+        builder = builder.keyfile(&keyfile);
+    }
+
+    builder.build().map_err(|error| {
+        let msg = match &error {
+            CannotCreateDirectoryError => "Could not create folder.".into(),
+            CannotSerializeError => "Could not serialize store.".into(),
+            CannotWriteFileError => "Could not write to store file.".into(),
+            CannotCreateFileError => "Could not create store file.".into(),
+            FileAlreadyExistsError => "Store already exists.".into(),
+            WeakPassword => "The password cannot be blank.".into(),
+            err => format!("Unknown error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })?;
+
+    progress("Store created.");
+    Ok(())
 }
 
 pub fn add(
-    store_path: String,
+    store_path: Option<String>,
     internal_path: String,
     files: Vec<String>,
-    password: String,
-) -> Option<()> {
-    let mut store = open_store(store_path, password)?;
+    threads: usize,
+    follow_links: bool,
+    preserve: bool,
+    password: Option<String>,
+) -> Result<(), Error> {
+    let mut store = open_store(store_path, password)?
+        .with_threads(threads)
+        .with_follow_links(follow_links)
+        .with_preserve(preserve);
 
     for file in files {
-        println!("Adding {} into {}", file, &internal_path);
-        store
-            .add(&file, &internal_path)
-            .map_err(|error| {
-                let msg = match &error {
-                    CannotReadFileError => format!("Cannot read file {file}."),
-                    CannotWriteFileError => format!("Cannot write file {file} into store."),
-                    CannotCreateFileError => format!("Cannot write file {file} into store."),
-                    FileDoesNotExistError => format!("File {file} does not exist."),
-                    CannotSerializeError => "Error saving: could not serialize.".into(),
-                    FileAlreadyExistsError => "Hash collision ocurred?".into(),
-                    StoreFileAlreadyExistsError => {
-                        "A file with same name in same path already exists.".into()
-                    }
-                    err => format!("An error occurred: {err:?}"),
-                };
-                eprint!("{msg}");
-                error
-            })
-            .ok()?;
+        progress(&format!("Adding {} into {}", file, &internal_path));
+        let bar = ProgressBar::new();
+        let result = store.add_with_progress(&file, &internal_path, false, |p| bar.update(p));
+        bar.finish();
+        result.map_err(|error| {
+            let msg = match &error {
+                CannotReadFileError => format!("Cannot read file {file}."),
+                CannotWriteFileError => format!("Cannot write file {file} into store."),
+                CannotCreateFileError => format!("Cannot write file {file} into store."),
+                FileDoesNotExistError => format!("File {file} does not exist."),
+                CannotSerializeError => "Error saving: could not serialize.".into(),
+                FileAlreadyExistsError => "Hash collision ocurred?".into(),
+                StoreFileAlreadyExistsError => {
+                    "A file with same name in same path already exists.".into()
+                }
+                InvalidName => format!("Invalid file name in {internal_path}."),
+                MaxDepthExceeded => format!("{file} is nested too deeply."),
+                QuotaExceeded => format!("Adding {file} would exceed the store quota."),
+                IoError(kind) => format!("Cannot add {file}: {kind}."),
+                err => format!("An error occurred: {err:?}"),
+            };
+            eprint!("{msg}");
+            error
+        })?;
+
+        if is_verbose() {
+            let name = Path::new(&file).file_name().unwrap_or_default();
+            let added = format!(
+                "{}/{}",
+                internal_path.trim_end_matches('/'),
+                name.to_string_lossy()
+            );
+            let inside = format!("{added}/");
+            for entry in store
+                .iter()
+                .filter(|entry| entry.is_file)
+                .filter(|entry| entry.name == added || entry.name.starts_with(&inside))
+            {
+                println!(
+                    "  {}: {} bytes in {} parts",
+                    entry.name,
+                    entry.size,
+                    entry.part_count()
+                );
+            }
+        }
     }
 
-    Some(())
+    Ok(())
 }
 
-pub fn get(
-    store_path: String,
-    internal_path: String,
+pub fn sync(
+    store_path: Option<String>,
     external_path: String,
-    password: String,
-) -> Option<()> {
-    open_store(store_path, password)?
-        .get(&internal_path, &external_path)
+    internal_path: String,
+    delete: bool,
+    password: Option<String>,
+) -> Result<(), Error> {
+    let mut store = open_store(store_path, password)?;
+
+    progress(&format!(
+        "Synchronizing {} into {}",
+        external_path, &internal_path
+    ));
+    store
+        .sync(&external_path, &internal_path, delete)
         .map_err(|error| {
             let msg = match &error {
-                CannotWriteFileError => format!("Cannot write file {external_path}."),
-                CannotCreateFileError => format!("Cannot write file {external_path}."),
-                FileAlreadyExistsError => format!("File {external_path} already exists"),
+                FolderDoesNotExistError => format!("Folder {external_path} does not exist."),
+                CannotReadFileError => format!("Cannot read files in {external_path}."),
+                CannotWriteFileError => "Cannot write files into store.".into(),
+                FileAlreadyExistsError => {
+                    "A folder in the store has the same path as a file being synchronized.".into()
+                }
+                MaxDepthExceeded => format!("{external_path} is nested too deeply."),
                 err => format!("An error occurred: {err:?}"),
             };
             eprint!("{msg}");
             error
         })
-        .ok()
 }
 
-pub fn remove(store_path: String, path: String, password: String) -> Option<()> {
-    open_store(store_path, password)?
-        .remove(&path)
-        .map_err(|error| {
-            let err = &error;
-            let msg = format!("An error occurred: {err:?}");
-            eprint!("{msg}");
+pub fn get(
+    store_path: Option<String>,
+    internal_path: String,
+    external_path: String,
+    preserve: bool,
+    password: Option<String>,
+) -> Result<(), Error> {
+    let mut store = open_store(store_path, password)?.with_preserve(preserve);
+    let bar = ProgressBar::new();
+    let result = store.get_with_progress(&internal_path, &external_path, |p| bar.update(p));
+    bar.finish();
+    result.map_err(|error| {
+        let msg = match &error {
+            CannotWriteFileError => format!("Cannot write file {external_path}."),
+            CannotCreateFileError => format!("Cannot write file {external_path}."),
+            FileAlreadyExistsError => format!("File {external_path} already exists"),
+            IoError(kind) => format!("Cannot write file {external_path}: {kind}."),
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })
+}
+
+pub fn cat(
+    store_path: Option<String>,
+    path: String,
+    offset: u64,
+    length: Option<u64>,
+    password: Option<String>,
+) -> Result<(), Error> {
+    let mut store = open_store(store_path, password)?;
+
+    let file = store.stat(&path).map_err(|error| {
+        let msg = match &error {
+            FileDoesNotExistError => format!("File {path} does not exist."),
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })?;
+    if !file.is_file {
+        eprint!("{path} is a folder.");
+        return Err(FileDoesNotExistError);
+    }
+
+    let end = match length {
+        Some(length) => offset.saturating_add(length).min(file.size),
+        None => file.size,
+    };
+    let chunk_size = store.chunk_size();
+    let mut stdout = std::io::stdout().lock();
+    let mut position = offset;
+    while position < end {
+        let len = chunk_size.min(end - position);
+        let bytes = store.read_range(&path, position, len).map_err(|error| {
+            eprint!("An error occurred: {error:?}");
             error
-        })
-        .ok()
+        })?;
+        if bytes.is_empty() {
+            break;
+        }
+        stdout.write_all(&bytes).map_err(|error| {
+            eprint!("Cannot write to stdout.");
+            Error::from(error)
+        })?;
+        position += bytes.len() as u64;
+    }
+
+    Ok(())
+}
+
+pub fn export(
+    store_path: Option<String>,
+    internal_path: String,
+    out: String,
+    password: Option<String>,
+) -> Result<(), Error> {
+    let store = open_store(store_path, password)?;
+
+    let writer: Box<dyn Write> = if out == "-" {
+        Box::new(std::io::stdout().lock())
+    } else {
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&out)
+            .map_err(|error| {
+                eprint!("Cannot create file {out}.");
+                Error::from(error)
+            })?;
+        Box::new(std::io::BufWriter::new(file))
+    };
+
+    store.export_tar(&internal_path, writer).map_err(|error| {
+        let msg = match &error {
+            FileDoesNotExistError => format!("{internal_path} does not exist in the store."),
+            CannotWriteFileError => format!("Cannot write archive {out}."),
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })
+}
+
+pub fn import(
+    store_path: Option<String>,
+    input: String,
+    internal_path: String,
+    password: Option<String>,
+) -> Result<(), Error> {
+    let mut store = open_store(store_path, password)?;
+
+    let reader: Box<dyn Read> = if input == "-" {
+        Box::new(std::io::stdin().lock())
+    } else {
+        let file = fs::File::open(&input).map_err(|error| {
+            eprint!("Cannot open file {input}.");
+            Error::from(error)
+        })?;
+        Box::new(std::io::BufReader::new(file))
+    };
+
+    store.import_tar(reader, &internal_path).map_err(|error| {
+        let msg = match &error {
+            CannotReadFileError => format!("Cannot read archive {input}."),
+            CannotWriteFileError => "Cannot write files into store.".into(),
+            FileAlreadyExistsError => {
+                format!("A file in the archive already exists in {internal_path}.")
+            }
+            InvalidName => "Invalid file name in the archive.".into(),
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })
+}
+
+pub fn remove(
+    store_path: Option<String>,
+    path: String,
+    password: Option<String>,
+    matching: bool,
+    yes: bool,
+) -> Result<(), Error> {
+    if matching {
+        if let Some(tags) = path.strip_prefix("tag:") {
+            if tags.trim().is_empty() {
+                eprint!("tag: needs at least one tag.");
+                return Err(CannotParseError);
+            }
+        }
+    }
+
+    let mut store = open_store(store_path, password)?;
+
+    let report = |error: Error| {
+        let msg = match &error {
+            CannotRemoveFilesError(files) => format!(
+                "{path} was removed, but these files could not be deleted:\n{}",
+                files.join("\n")
+            ),
+            CannotParseError if matching => format!("{path} is not a valid glob."),
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    };
+
+    let targets: Vec<String> = if matching {
+        let plan = store.plan_remove(&path).map_err(report)?;
+        plan.into_iter().map(|file| file.name).collect()
+    } else if store.is_dir(&path).unwrap_or(false) {
+        vec![path.clone()]
+    } else {
+        vec![]
+    };
+
+    if !targets.is_empty() {
+        let count = store
+            .iter()
+            .filter(|file| file.is_file)
+            .filter(|file| {
+                targets.iter().any(|target| {
+                    let folder = format!("{}/", target.trim_end_matches('/'));
+                    &file.name == target || file.name.starts_with(&folder)
+                })
+            })
+            .count();
+        if !yes && !std::io::stdin().is_terminal() {
+            eprint!("This would delete {count} files. Pass --yes to confirm.");
+            return Err(IoError(std::io::ErrorKind::PermissionDenied));
+        }
+        let question = format!("This will delete {count} files. Continue?");
+        if !confirm(&question, yes) {
+            eprint!("Nothing was removed.");
+            return Ok(());
+        }
+    }
+
+    if matching {
+        store.remove_matching(&path).map(|_| ()).map_err(report)
+    } else {
+        store.remove(&path).map_err(report)
+    }
+}
+
+/// Asks the user to confirm a destructive operation. Counts as confirmed
+/// when `--yes` was given. Callers refuse to go on without a terminal.
+///
+/// # Arguments
+///
+/// * `question` - Question to ask, answered with y or n.
+/// * `yes` - Whether `--yes` was given.
+fn confirm(question: &str, yes: bool) -> bool {
+    if yes {
+        return true;
+    }
+
+    print!("{question} [y/N] ");
+    std::io::stdout().flush().ok();
+
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
 }
 
 pub fn list(
-    store_path: String,
+    store_path: Option<String>,
     path: String,
-    password: String,
+    password: Option<String>,
     human: bool,
     list: bool,
-) -> Option<()> {
-    let mut store = open_store(store_path, password)?;
+    json: bool,
+    all: bool,
+) -> Result<(), Error> {
+    let mut store = open_store(store_path, password)?.with_hidden(all);
 
-    let mut files = store
-        .list(&path)
+    let files = store
+        .list_sorted_folders_first(&path, SortKey::Name, false, true)
         .map_err(|error| {
             let err = &error;
             let msg = format!("An error occurred: {err:?}");
             eprint!("{msg}");
             error
-        })
-        .ok()?;
+        })?;
 
-    files.sort_by(|a, b| {
-        if !a.is_file && b.is_file {
-            Ordering::Less
-        } else if a.is_file && !b.is_file {
-            Ordering::Greater
-        } else {
-            a.name.cmp(&b.name)
-        }
-    });
+    if json {
+        let files: Vec<serde_json::Value> = files
+            .iter()
+            .map(|file| {
+                serde_json::json!({
+                    "name": file.name,
+                    "size": file.size,
+                    "is_file": file.is_file,
+                    "parts": file.part_count(),
+                    "created": file.created,
+                    "modified": file.modified,
+                    "tags": file.tags,
+                    "metadata": file.metadata,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::Value::Array(files));
+        return Ok(());
+    }
 
     let files: Vec<(String, String)> = files
         .iter()
@@ -161,7 +574,7 @@ pub fn list(
         .collect();
 
     if files.is_empty() {
-        return Some(());
+        return Ok(());
     }
 
     let mut table = Table::new();
@@ -173,8 +586,8 @@ pub fn list(
         }
     } else {
         let cells: Vec<Cell> = files.iter().map(|file| cell![file.0]).collect();
-        let max_width = files.iter().map(|file| file.0.len()).max()?;
-        let term_width = term_size::dimensions()?.0;
+        let max_width = files.iter().map(|file| file.0.len()).max().unwrap_or(1);
+        let term_width = term_size::dimensions().map_or(80, |(width, _)| width);
         let cells_per_row: usize = if term_width >= max_width {
             term_width / max_width
         } else {
@@ -188,79 +601,315 @@ pub fn list(
 
     table.printstd();
 
-    Some(())
+    Ok(())
+}
+
+pub fn stat(
+    store_path: Option<String>,
+    path: String,
+    password: Option<String>,
+    human: bool,
+) -> Result<(), Error> {
+    let store = open_store(store_path, password)?;
+
+    let file = store.stat(&path).map_err(|error| {
+        let msg = match &error {
+            FileDoesNotExistError => format!("File {path} does not exist."),
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })?;
+
+    let size: String = if human {
+        bytesize::ByteSize(file.size).to_string()
+    } else {
+        file.size.to_string()
+    };
+    let kind = if file.is_file { "file" } else { "folder" };
+    let mut tags = file.tags.clone();
+    tags.sort();
+
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_CLEAN);
+    table.add_row(row!["Name", file.name]);
+    table.add_row(row!["Type", kind]);
+    table.add_row(row!["Size", size]);
+    table.add_row(row!["Parts", file.part_count()]);
+    table.add_row(row!["Created", file.created]);
+    table.add_row(row!["Modified", file.modified]);
+    table.add_row(row!["Tags", tags.join(", ")]);
+    table.printstd();
+
+    Ok(())
+}
+
+pub fn disk_usage(
+    store_path: Option<String>,
+    path: String,
+    password: Option<String>,
+    human: bool,
+) -> Result<(), Error> {
+    let store = open_store(store_path, password)?;
+
+    let size = store.disk_usage(&path).map_err(|error| {
+        let msg = match &error {
+            FileDoesNotExistError => format!("File {path} does not exist."),
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })?;
+
+    if human {
+        println!("{}\t{path}", bytesize::ByteSize(size));
+    } else {
+        println!("{size}\t{path}");
+    }
+
+    Ok(())
+}
+
+#[cfg(all(feature = "fuse", target_os = "linux"))]
+pub fn mount(
+    store_path: Option<String>,
+    mountpoint: String,
+    password: Option<String>,
+) -> Result<(), Error> {
+    // Hidden files are left for the programs reading the mount to hide.
+    let store = open_store(store_path, password)?.with_hidden(true);
+
+    crate::mount::mount(store, &mountpoint).map_err(|error| {
+        eprint!("Could not mount the store on {mountpoint}: {error}");
+        error.into()
+    })
+}
+
+#[cfg(not(all(feature = "fuse", target_os = "linux")))]
+pub fn mount(
+    _store_path: Option<String>,
+    _mountpoint: String,
+    _password: Option<String>,
+) -> Result<(), Error> {
+    eprint!("void-cli was built without FUSE support.");
+    Err(IoError(std::io::ErrorKind::Unsupported))
+}
+
+#[cfg(feature = "webdav")]
+pub fn serve(
+    store_path: Option<String>,
+    bind: String,
+    password: Option<String>,
+) -> Result<(), Error> {
+    // Clients log in with the token if there is one, otherwise with the
+    // store password, which must then not be sent over the network.
+    let token = std::env::var("VOID_WEBDAV_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty());
+    if token.is_none() && !crate::webdav::is_loopback(&bind) {
+        eprint!("Listening on {bind} needs a token in VOID_WEBDAV_TOKEN.");
+        return Err(IoError(std::io::ErrorKind::PermissionDenied));
+    }
+    let (secret, password) = match token {
+        Some(token) => (token, password),
+        None => {
+            let password = crate::read_password(password);
+            (password.clone(), Some(password))
+        }
+    };
+    if secret.is_empty() {
+        eprint!("A store without password needs a token in VOID_WEBDAV_TOKEN.");
+        return Err(IoError(std::io::ErrorKind::PermissionDenied));
+    }
+
+    // Hidden files are left for WebDAV clients to hide.
+    let store = open_store(store_path, password)?.with_hidden(true);
+
+    crate::webdav::serve(store, &bind, &secret).map_err(|error| {
+        eprint!("Could not listen on {bind}: {error}");
+        error.into()
+    })
+}
+
+#[cfg(not(feature = "webdav"))]
+pub fn serve(
+    _store_path: Option<String>,
+    _bind: String,
+    _password: Option<String>,
+) -> Result<(), Error> {
+    eprint!("void-cli was built without WebDAV support.");
+    Err(IoError(std::io::ErrorKind::Unsupported))
+}
+
+#[cfg(feature = "watch")]
+pub fn watch(
+    store_path: Option<String>,
+    external_path: String,
+    internal_path: String,
+    delete: bool,
+    password: Option<String>,
+) -> Result<(), Error> {
+    let store = open_store(store_path, password)?;
+
+    progress(&format!(
+        "Watching {} into {}",
+        external_path, &internal_path
+    ));
+    crate::watch::watch(store, &external_path, &internal_path, delete).map_err(|error| {
+        let msg = match &error {
+            FolderDoesNotExistError => format!("Folder {external_path} does not exist."),
+            CannotReadFileError => format!("Cannot read files in {external_path}."),
+            CannotWriteFileError => "Cannot write files into store.".into(),
+            MaxDepthExceeded => format!("{external_path} is nested too deeply."),
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })
+}
+
+#[cfg(not(feature = "watch"))]
+pub fn watch(
+    _store_path: Option<String>,
+    _external_path: String,
+    _internal_path: String,
+    _delete: bool,
+    _password: Option<String>,
+) -> Result<(), Error> {
+    eprint!("void-cli was built without watch support.");
+    Err(IoError(std::io::ErrorKind::Unsupported))
+}
+
+pub fn info(
+    store_path: Option<String>,
+    password: Option<String>,
+    human: bool,
+    json: bool,
+) -> Result<(), Error> {
+    let store = open_store(store_path, password)?;
+
+    let stats = store.statistics().map_err(|error| {
+        let err = &error;
+        let msg = format!("An error occurred: {err:?}");
+        eprint!("{msg}");
+        error
+    })?;
+
+    if json {
+        let info = serde_json::json!({
+            "files": stats.files,
+            "folders": stats.folders,
+            "total_bytes": stats.total_bytes,
+            "tags": stats.tags,
+            "parts": stats.parts,
+            "disk_size": stats.disk_size,
+            "cipher": store.cipher().to_string(),
+            "kdf": store.kdf().to_string(),
+        });
+        println!("{info}");
+        return Ok(());
+    }
+
+    let size = |size: u64| -> String {
+        if human {
+            bytesize::ByteSize(size).to_string()
+        } else {
+            size.to_string()
+        }
+    };
+
+    let mut table = Table::new();
+    table.set_format(*prettytable::format::consts::FORMAT_CLEAN);
+    table.add_row(row!["Files", stats.files]);
+    table.add_row(row!["Folders", stats.folders]);
+    table.add_row(row!["Total size", size(stats.total_bytes)]);
+    table.add_row(row!["Tags", stats.tags]);
+    table.add_row(row!["Parts", stats.parts]);
+    table.add_row(row!["On-disk size", size(stats.disk_size)]);
+    table.add_row(row!["Cipher", store.cipher()]);
+    table.add_row(row!["KDF", store.kdf()]);
+    table.printstd();
+
+    Ok(())
 }
 
 pub fn metadata_set(
-    store_path: String,
+    store_path: Option<String>,
     path: String,
-    password: String,
+    password: Option<String>,
     key: String,
     value: String,
-) -> Option<()> {
+    recursive: bool,
+) -> Result<(), Error> {
     let mut store = open_store(store_path, password)?;
 
-    store
-        .metadata_set(&path, &key, &value)
-        .map_err(|error| {
-            let msg = match &error {
-                CannotSerializeError => "Error saving: could not serialize.".into(),
-                FileAlreadyExistsError => "Hash collision ocurred?".into(),
-                StoreFileAlreadyExistsError => {
-                    "A file with same name in same path already exists.".into()
-                }
-                err => format!("An error occurred: {err:?}"),
-            };
-            eprint!("{msg}");
-            error
-        })
-        .ok()?;
+    let result = if recursive {
+        store.metadata_set_recursive(&path, &key, &value)
+    } else {
+        store.metadata_set(&path, &key, &value)
+    };
+
+    result.map_err(|error| {
+        let msg = match &error {
+            CannotSerializeError => "Error saving: could not serialize.".into(),
+            FileAlreadyExistsError => "Hash collision ocurred?".into(),
+            StoreFileAlreadyExistsError => {
+                "A file with same name in same path already exists.".into()
+            }
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })?;
 
-    Some(())
+    Ok(())
 }
 
-pub fn metadata_get(store_path: String, path: String, password: String, key: String) -> Option<()> {
+pub fn metadata_get(
+    store_path: Option<String>,
+    path: String,
+    password: Option<String>,
+    key: String,
+) -> Result<(), Error> {
     let mut store = open_store(store_path, password)?;
 
-    let value = store
-        .metadata_get(&path, &key)
-        .map_err(|error| {
-            let msg = match &error {
-                CannotSerializeError => "Error saving: could not serialize.".into(),
-                FileAlreadyExistsError => "Hash collision ocurred?".into(),
-                StoreFileAlreadyExistsError => {
-                    "A file with same name in same path already exists.".into()
-                }
-                err => format!("An error occurred: {err:?}"),
-            };
-            eprint!("{msg}");
-            error
-        })
-        .ok()?;
+    let value = store.metadata_get(&path, &key).map_err(|error| {
+        let msg = match &error {
+            CannotSerializeError => "Error saving: could not serialize.".into(),
+            FileAlreadyExistsError => "Hash collision ocurred?".into(),
+            StoreFileAlreadyExistsError => {
+                "A file with same name in same path already exists.".into()
+            }
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })?;
 
     println!("{key}: {value}");
 
-    Some(())
+    Ok(())
 }
 
-pub fn metadata_list(store_path: String, path: String, password: String) -> Option<()> {
+pub fn metadata_list(
+    store_path: Option<String>,
+    path: String,
+    password: Option<String>,
+) -> Result<(), Error> {
     let mut store = open_store(store_path, password)?;
 
-    let map: HashMap<String, String> = store
-        .metadata_list(&path)
-        .map_err(|error| {
-            let msg = match &error {
-                CannotSerializeError => "Error saving: could not serialize.".into(),
-                FileAlreadyExistsError => "Hash collision ocurred?".into(),
-                StoreFileAlreadyExistsError => {
-                    "A file with same name in same path already exists.".into()
-                }
-                err => format!("An error occurred: {err:?}"),
-            };
-            eprint!("{msg}");
-            error
-        })
-        .ok()?;
+    let map: HashMap<String, String> = store.metadata_list(&path).map_err(|error| {
+        let msg = match &error {
+            CannotSerializeError => "Error saving: could not serialize.".into(),
+            FileAlreadyExistsError => "Hash collision ocurred?".into(),
+            StoreFileAlreadyExistsError => {
+                "A file with same name in same path already exists.".into()
+            }
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })?;
 
     let mut table = Table::new();
     table.set_format(*prettytable::format::consts::FORMAT_CLEAN);
@@ -273,103 +922,123 @@ pub fn metadata_list(store_path: String, path: String, password: String) -> Opti
 
     table.printstd();
 
-    Some(())
+    Ok(())
 }
 
 pub fn metadata_remove(
-    store_path: String,
+    store_path: Option<String>,
     path: String,
-    password: String,
+    password: Option<String>,
     key: String,
-) -> Option<()> {
+) -> Result<(), Error> {
     let mut store = open_store(store_path, password)?;
 
-    store
-        .metadata_remove(&path, &key)
-        .map_err(|error| {
-            let msg = match &error {
-                CannotSerializeError => "Error saving: could not serialize.".into(),
-                FileAlreadyExistsError => "Hash collision ocurred?".into(),
-                StoreFileAlreadyExistsError => {
-                    "A file with same name in same path already exists.".into()
-                }
-                err => format!("An error occurred: {err:?}"),
-            };
-            eprint!("{msg}");
-            error
-        })
-        .ok()?;
+    store.metadata_remove(&path, &key).map_err(|error| {
+        let msg = match &error {
+            CannotSerializeError => "Error saving: could not serialize.".into(),
+            FileAlreadyExistsError => "Hash collision ocurred?".into(),
+            StoreFileAlreadyExistsError => {
+                "A file with same name in same path already exists.".into()
+            }
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })?;
 
-    Some(())
+    Ok(())
 }
 
-pub fn tag_add(store_path: String, path: String, password: String, tag: String) -> Option<()> {
+pub fn tag_add(
+    store_path: Option<String>,
+    path: String,
+    password: Option<String>,
+    tags: Vec<String>,
+    recursive: bool,
+) -> Result<(), Error> {
     let mut store = open_store(store_path, password)?;
 
-    store
-        .tag_add(&path, &tag)
-        .map_err(|error| {
-            let msg = match &error {
-                CannotSerializeError => "Error saving: could not serialize.".into(),
-                FileAlreadyExistsError => "Hash collision ocurred?".into(),
-                StoreFileAlreadyExistsError => {
-                    "A file with same name in same path already exists.".into()
-                }
-                err => format!("An error occurred: {err:?}"),
-            };
-            eprint!("{msg}");
-            error
-        })
-        .ok()?;
+    let result = tags.iter().try_for_each(|tag| {
+        if recursive {
+            store.tag_add_recursive(&path, tag)
+        } else {
+            store.tag_add(&path, tag)
+        }
+    });
 
-    Some(())
+    result.map_err(|error| {
+        let msg = match &error {
+            CannotSerializeError => "Error saving: could not serialize.".into(),
+            FileAlreadyExistsError => "Hash collision ocurred?".into(),
+            StoreFileAlreadyExistsError => {
+                "A file with same name in same path already exists.".into()
+            }
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })?;
+
+    Ok(())
 }
 
-pub fn tag_remove(store_path: String, path: String, password: String, tag: String) -> Option<()> {
+pub fn tag_remove(
+    store_path: Option<String>,
+    path: String,
+    password: Option<String>,
+    tags: Vec<String>,
+    recursive: bool,
+) -> Result<(), Error> {
     let mut store = open_store(store_path, password)?;
 
-    store
-        .tag_rm(&path, &tag)
-        .map_err(|error| {
-            let msg = match &error {
-                CannotSerializeError => "Error saving: could not serialize.".into(),
-                FileAlreadyExistsError => "Hash collision ocurred?".into(),
-                StoreFileAlreadyExistsError => {
-                    "A file with same name in same path already exists.".into()
-                }
-                err => format!("An error occurred: {err:?}"),
-            };
-            eprint!("{msg}");
-            error
-        })
-        .ok()?;
+    let result = tags.iter().try_for_each(|tag| {
+        if recursive {
+            store.tag_rm_recursive(&path, tag)
+        } else {
+            store.tag_rm(&path, tag)
+        }
+    });
 
-    Some(())
+    result.map_err(|error| {
+        let msg = match &error {
+            CannotSerializeError => "Error saving: could not serialize.".into(),
+            FileAlreadyExistsError => "Hash collision ocurred?".into(),
+            StoreFileAlreadyExistsError => {
+                "A file with same name in same path already exists.".into()
+            }
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })?;
+
+    Ok(())
 }
 
-pub fn tag_clear(store_path: String, path: String, password: String) -> Option<()> {
+pub fn tag_clear(
+    store_path: Option<String>,
+    path: String,
+    password: Option<String>,
+) -> Result<(), Error> {
     let mut store = open_store(store_path, password)?;
 
-    store
-        .tag_clear(&path)
-        .map_err(|error| {
-            let msg = match &error {
-                CannotSerializeError => "Error saving: could not serialize.".into(),
-                FileAlreadyExistsError => "Hash collision ocurred?".into(),
-                StoreFileAlreadyExistsError => {
-                    "A file with same name in same path already exists.".into()
-                }
-                err => format!("An error occurred: {err:?}"),
-            };
-            eprint!("{msg}");
-            error
-        })
-        .ok()?;
+    store.tag_clear(&path).map_err(|error| {
+        let msg = match &error {
+            CannotSerializeError => "Error saving: could not serialize.".into(),
+            FileAlreadyExistsError => "Hash collision ocurred?".into(),
+            StoreFileAlreadyExistsError => {
+                "A file with same name in same path already exists.".into()
+            }
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })?;
 
-    Some(())
+    Ok(())
 }
 
-pub fn tag_list(store_path: String, password: String) -> Option<()> {
+pub fn tag_list(store_path: Option<String>, password: Option<String>) -> Result<(), Error> {
     let store = open_store(store_path, password)?;
 
     let mut tags = store.tag_list();
@@ -384,27 +1053,28 @@ pub fn tag_list(store_path: String, password: String) -> Option<()> {
 
     table.printstd();
 
-    Some(())
+    Ok(())
 }
 
-pub fn tag_get(store_path: String, path: String, password: String) -> Option<()> {
+pub fn tag_get(
+    store_path: Option<String>,
+    path: String,
+    password: Option<String>,
+) -> Result<(), Error> {
     let mut store = open_store(store_path, password)?;
 
-    let mut tags = store
-        .tag_get(&path)
-        .map_err(|error| {
-            let msg = match &error {
-                CannotSerializeError => "Error saving: could not serialize.".into(),
-                FileAlreadyExistsError => "Hash collision ocurred?".into(),
-                StoreFileAlreadyExistsError => {
-                    "A file with same name in same path already exists.".into()
-                }
-                err => format!("An error occurred: {err:?}"),
-            };
-            eprint!("{msg}");
-            error
-        })
-        .ok()?;
+    let mut tags = store.tag_get(&path).map_err(|error| {
+        let msg = match &error {
+            CannotSerializeError => "Error saving: could not serialize.".into(),
+            FileAlreadyExistsError => "Hash collision ocurred?".into(),
+            StoreFileAlreadyExistsError => {
+                "A file with same name in same path already exists.".into()
+            }
+            err => format!("An error occurred: {err:?}"),
+        };
+        eprint!("{msg}");
+        error
+    })?;
     tags.sort();
 
     let mut table = Table::new();
@@ -416,10 +1086,14 @@ pub fn tag_get(store_path: String, path: String, password: String) -> Option<()>
 
     table.printstd();
 
-    Some(())
+    Ok(())
 }
 
-pub fn tag_search(store_path: String, tags: Vec<String>, password: String) -> Option<()> {
+pub fn tag_search(
+    store_path: Option<String>,
+    tags: Vec<String>,
+    password: Option<String>,
+) -> Result<(), Error> {
     let store = open_store(store_path, password)?;
 
     let files = store.tag_search(tags);
@@ -433,5 +1107,64 @@ pub fn tag_search(store_path: String, tags: Vec<String>, password: String) -> Op
 
     table.printstd();
 
-    Some(())
+    Ok(())
+}
+
+/// Prints the tags starting with `prefix`, for the shell completions. It runs
+/// on every <TAB>, so it never asks for a password and prints nothing when the
+/// store cannot be opened without one.
+pub fn tag_complete(store_path: Option<String>, prefix: String, password: Option<String>) {
+    let path = store_path.or_else(|| find_store(&std::env::current_dir().ok()?));
+    let Some(path) = path else {
+        return;
+    };
+
+    let key = agent::is_enabled().then(|| agent::get_key(&path)).flatten();
+    let store = match (key, password) {
+        (Some(key), _) => Store::open_with_key(&path, key),
+        (None, Some(password)) => Store::open(path, password),
+        (None, None) => return,
+    };
+
+    if let Ok(store) = store {
+        for tag in complete_tags(store.tag_list(), &prefix) {
+            println!("{tag}");
+        }
+    }
+}
+
+/// Returns the sorted tags starting with `prefix`. A prefix starting with `!`
+/// completes the tags excluded by `tag-search`, keeping the `!`.
+fn complete_tags(tags: Vec<String>, prefix: &str) -> Vec<String> {
+    let (negation, prefix) = match prefix.strip_prefix('!') {
+        Some(prefix) => ("!", prefix),
+        None => ("", prefix),
+    };
+
+    let mut tags: Vec<String> = tags
+        .into_iter()
+        .filter(|tag| tag.starts_with(prefix))
+        .map(|tag| format!("{negation}{tag}"))
+        .collect();
+    tags.sort();
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete_tags() {
+        let tags = vec!["music".into(), "movies".into(), "work".into(), "m".into()];
+
+        assert_eq!(complete_tags(tags.clone(), "mo"), vec!["movies"]);
+        assert_eq!(
+            complete_tags(tags.clone(), "m"),
+            vec!["m", "movies", "music"]
+        );
+        assert_eq!(complete_tags(tags.clone(), "!mu"), vec!["!music"]);
+        assert_eq!(complete_tags(tags.clone(), "").len(), 4);
+        assert!(complete_tags(tags, "x").is_empty());
+    }
 }
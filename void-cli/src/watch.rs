@@ -0,0 +1,63 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Keeps a folder synchronized into a store while it changes.
+//!
+//! Changes are reported by the operating system through `notify` and
+//! debounced, so a burst of writes is synchronized once. They are
+//! synchronized with `Store::sync`, which only re-encrypts the files that
+//! changed and saves the index once.
+
+use notify_debouncer_mini::notify::RecursiveMode;
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+use void::{Error, Store};
+
+/// Time without changes after which the changed files are synchronized.
+/// Failed synchronizations are retried after the same time.
+const DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Synchronizes `dir` into `prefix`, then again whenever files in it change,
+/// until the process is killed.
+///
+/// # Arguments
+///
+/// * `store` - Store to synchronize into.
+/// * `dir` - Folder in the disk.
+/// * `prefix` - Path in the store that mirrors the folder.
+/// * `delete` - Whether to remove files from the store that are removed
+///   from the folder.
+pub fn watch(mut store: Store, dir: &str, prefix: &str, delete: bool) -> Result<(), Error> {
+    store.sync(dir, prefix, delete)?;
+
+    let (sender, receiver) = mpsc::channel::<DebounceEventResult>();
+    let mut debouncer = new_debouncer(DEBOUNCE, sender).map_err(|_| Error::CannotReadFileError)?;
+    debouncer
+        .watcher()
+        .watch(Path::new(dir), RecursiveMode::Recursive)
+        .map_err(|_| Error::CannotReadFileError)?;
+
+    let mut pending = false;
+    loop {
+        match receiver.recv_timeout(DEBOUNCE) {
+            Ok(Ok(_)) => pending = true,
+            Ok(Err(err)) => eprintln!("Could not watch {dir}: {err}"),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        if pending {
+            match store.sync(dir, prefix, delete) {
+                Ok(()) => pending = false,
+                // Tried again later, the file may have been in the middle of
+                // a write.
+                Err(err) => eprintln!("Could not synchronize {dir}: {err}"),
+            }
+        }
+    }
+}
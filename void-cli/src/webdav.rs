@@ -0,0 +1,430 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Minimal WebDAV server exposing a store.
+//!
+//! Only what file managers and `curl` need is implemented: `OPTIONS`,
+//! `PROPFIND` (depth 0 and 1), `GET`, `HEAD`, `PUT` and `DELETE`. Requests are
+//! answered one at a time and every connection is closed after its response.
+//! Files are decrypted and encrypted chunk by chunk, so they are never held
+//! in memory or written to disk in the clear.
+//!
+//! Every request must log in with HTTP Basic authentication. The user name is
+//! ignored and the password must be the secret the server was started with.
+
+use base64::Engine;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use void::{Error, File, Store};
+
+struct Request {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+}
+
+/// How long a client may keep the server waiting on a read or a write.
+/// Requests are handled one at a time, so a stalled client would otherwise
+/// block every other one.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the store on `bind` until the process is killed. Clients log in
+/// with `secret` as their password.
+pub fn serve(store: Store, bind: &str, secret: &str) -> io::Result<()> {
+    serve_on(store, TcpListener::bind(bind)?, secret)
+}
+
+/// Serves the store on an already bound listener.
+pub fn serve_on(mut store: Store, listener: TcpListener, secret: &str) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        if stream.set_read_timeout(Some(TIMEOUT)).is_err()
+            || stream.set_write_timeout(Some(TIMEOUT)).is_err()
+        {
+            continue;
+        }
+        // A client going away mid request must not stop the server.
+        handle(&mut store, stream, secret).ok();
+    }
+    Ok(())
+}
+
+/// Whether every address `bind` resolves to is only reachable from this
+/// computer.
+pub fn is_loopback(bind: &str) -> bool {
+    use std::net::ToSocketAddrs;
+    bind.to_socket_addrs()
+        .map(|addrs| {
+            let addrs: Vec<_> = addrs.collect();
+            !addrs.is_empty() && addrs.iter().all(|addr| addr.ip().is_loopback())
+        })
+        .unwrap_or(false)
+}
+
+fn handle(store: &mut Store, stream: TcpStream, secret: &str) -> io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut writer = &stream;
+
+    let Some(request) = read_request(&mut reader)? else {
+        return respond(&mut writer, "400 Bad Request", &[], b"");
+    };
+
+    if !is_authorized(&request, secret) {
+        return respond(
+            &mut writer,
+            "401 Unauthorized",
+            &[("WWW-Authenticate", "Basic realm=\"void\"".into())],
+            b"",
+        );
+    }
+
+    match request.method.as_str() {
+        "OPTIONS" => respond(
+            &mut writer,
+            "200 OK",
+            &[
+                ("DAV", "1".into()),
+                ("Allow", "OPTIONS, PROPFIND, GET, HEAD, PUT, DELETE".into()),
+            ],
+            b"",
+        ),
+        "PROPFIND" => propfind(store, &request, &mut writer),
+        "GET" | "HEAD" => get(store, &request, &mut writer),
+        "PUT" => put(store, &request, &mut reader, &mut writer),
+        "DELETE" => match store.remove(&request.path) {
+            Ok(()) => respond(&mut writer, "204 No Content", &[], b""),
+            Err(Error::FileDoesNotExistError) => respond(&mut writer, "404 Not Found", &[], b""),
+            Err(_) => respond(&mut writer, "500 Internal Server Error", &[], b""),
+        },
+        _ => respond(&mut writer, "405 Method Not Allowed", &[], b""),
+    }
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Option<Request>> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+    let Some(path) = decode_path(target.split('?').next().unwrap_or_default()) else {
+        return Ok(None);
+    };
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    Ok(Some(Request {
+        method: method.to_uppercase(),
+        path,
+        headers,
+    }))
+}
+
+/// Checks the Basic credentials of a request against `secret`.
+fn is_authorized(request: &Request, secret: &str) -> bool {
+    let Some(value) = request.headers.get("authorization") else {
+        return false;
+    };
+    let Some((scheme, credentials)) = value.split_once(' ') else {
+        return false;
+    };
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return false;
+    }
+    let Ok(credentials) = base64::engine::general_purpose::STANDARD.decode(credentials.trim())
+    else {
+        return false;
+    };
+    let Some(colon) = credentials.iter().position(|&byte| byte == b':') else {
+        return false;
+    };
+
+    // Compared without stopping at the first difference, so the time taken
+    // does not tell how much of the password was right.
+    let password = &credentials[colon + 1..];
+    password.len() == secret.len()
+        && password
+            .iter()
+            .zip(secret.as_bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn respond<W: Write>(
+    writer: &mut W,
+    status: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> io::Result<()> {
+    write!(writer, "HTTP/1.1 {status}\r\n")?;
+    for (name, value) in headers {
+        write!(writer, "{name}: {value}\r\n")?;
+    }
+    if !headers.iter().any(|(name, _)| *name == "Content-Length") {
+        write!(writer, "Content-Length: {}\r\n", body.len())?;
+    }
+    write!(writer, "Connection: close\r\n\r\n")?;
+    writer.write_all(body)?;
+    writer.flush()
+}
+
+fn get<W: Write>(store: &mut Store, request: &Request, writer: &mut W) -> io::Result<()> {
+    let file = match store.stat(&request.path) {
+        Ok(file) if file.is_file => file,
+        Ok(_) => return respond(writer, "405 Method Not Allowed", &[], b""),
+        Err(_) => return respond(writer, "404 Not Found", &[], b""),
+    };
+
+    let mimetype = file
+        .metadata
+        .get("mimetype")
+        .cloned()
+        .unwrap_or_else(|| "application/octet-stream".into());
+    let headers = [
+        ("Content-Type", mimetype),
+        ("Content-Length", file.size.to_string()),
+    ];
+    if request.method == "HEAD" {
+        return respond(writer, "200 OK", &headers, b"");
+    }
+
+    // Reading whole chunks decrypts each part exactly once.
+    let chunk_size = store.chunk_size();
+    let mut offset = 0;
+    write!(writer, "HTTP/1.1 200 OK\r\n")?;
+    for (name, value) in headers {
+        write!(writer, "{name}: {value}\r\n")?;
+    }
+    write!(writer, "Connection: close\r\n\r\n")?;
+    while offset < file.size {
+        let bytes = store
+            .read_range(&request.path, offset, chunk_size)
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?;
+        if bytes.is_empty() {
+            break;
+        }
+        writer.write_all(&bytes)?;
+        offset += bytes.len() as u64;
+    }
+    writer.flush()
+}
+
+fn put<R: Read, W: Write>(
+    store: &mut Store,
+    request: &Request,
+    reader: &mut R,
+    writer: &mut W,
+) -> io::Result<()> {
+    let Some(len) = request
+        .headers
+        .get("content-length")
+        .and_then(|len| len.parse::<u64>().ok())
+    else {
+        return respond(writer, "411 Length Required", &[], b"");
+    };
+
+    let existed = store.stat(&request.path).is_ok();
+    let mut body = Body {
+        reader,
+        remaining: len,
+    };
+    let added = store.add_from_reader(&mut body, &request.path, true);
+    if body.remaining > 0 {
+        return respond(writer, "400 Bad Request", &[], b"");
+    }
+    match added {
+        Ok(()) if existed => respond(writer, "204 No Content", &[], b""),
+        Ok(()) => respond(writer, "201 Created", &[], b""),
+        Err(Error::FileAlreadyExistsError) => respond(writer, "409 Conflict", &[], b""),
+        Err(_) => respond(writer, "500 Internal Server Error", &[], b""),
+    }
+}
+
+/// Body of a request, `remaining` bytes long. Ends with an error if the
+/// connection ends first, so a short body is never stored as a whole file.
+struct Body<'a, R> {
+    reader: &'a mut R,
+    remaining: u64,
+}
+
+impl<R: Read> Read for Body<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+        let len = buf
+            .len()
+            .min(self.remaining.try_into().unwrap_or(usize::MAX));
+        let read = self.reader.read(&mut buf[..len])?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+fn propfind<W: Write>(store: &mut Store, request: &Request, writer: &mut W) -> io::Result<()> {
+    let path = request.path.trim_end_matches('/');
+    let path = if path.is_empty() { "/" } else { path };
+
+    let entry = if path == "/" {
+        None
+    } else {
+        match store.stat(path) {
+            Ok(file) => Some(file),
+            Err(_) => return respond(writer, "404 Not Found", &[], b""),
+        }
+    };
+
+    let mut body = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    body.push_str("<D:multistatus xmlns:D=\"DAV:\">\n");
+    body.push_str(&prop_response(path, entry.as_ref()));
+
+    let is_dir = entry.as_ref().is_none_or(|file| !file.is_file);
+    let depth = request.headers.get("depth").map(String::as_str);
+    if is_dir && depth != Some("0") {
+        let children = store.list(path).unwrap_or_default();
+        for child in children {
+            let child_path = format!("{}/{}", path.trim_end_matches('/'), child.name);
+            body.push_str(&prop_response(&child_path, Some(&child)));
+        }
+    }
+    body.push_str("</D:multistatus>\n");
+
+    respond(
+        writer,
+        "207 Multi-Status",
+        &[("Content-Type", "application/xml; charset=utf-8".into())],
+        body.as_bytes(),
+    )
+}
+
+/// Builds the `<D:response>` element of a file. `None` is the root folder.
+fn prop_response(path: &str, file: Option<&File>) -> String {
+    let is_file = file.is_some_and(|file| file.is_file);
+    let name = path.rsplit('/').next().unwrap_or_default();
+    let mut href = encode_path(path);
+    if !is_file && !href.ends_with('/') {
+        href.push('/');
+    }
+
+    let mut props = format!("<D:displayname>{}</D:displayname>", escape_xml(name));
+    match file {
+        Some(file) if file.is_file => {
+            props.push_str("<D:resourcetype/>");
+            props.push_str(&format!(
+                "<D:getcontentlength>{}</D:getcontentlength>",
+                file.size
+            ));
+            if let Some(mimetype) = file.metadata.get("mimetype") {
+                props.push_str(&format!(
+                    "<D:getcontenttype>{}</D:getcontenttype>",
+                    escape_xml(mimetype)
+                ));
+            }
+        }
+        _ => props.push_str("<D:resourcetype><D:collection/></D:resourcetype>"),
+    }
+
+    format!(
+        "<D:response><D:href>{href}</D:href><D:propstat><D:prop>{props}</D:prop>\
+         <D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n"
+    )
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Decodes the percent-encoded path of a request.
+fn decode_path(path: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut iter = path.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Percent-encodes a store path to be used in an URL.
+fn encode_path(path: &str) -> String {
+    path.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths() {
+        assert_eq!(decode_path("/a%20b/c").as_deref(), Some("/a b/c"));
+        assert_eq!(decode_path("/%C3%A1").as_deref(), Some("/á"));
+        assert_eq!(decode_path("/%2"), None);
+        assert_eq!(encode_path("/a b/á"), "/a%20b/%C3%A1");
+        assert_eq!(
+            decode_path(&encode_path("/x&y/z?")).as_deref(),
+            Some("/x&y/z?")
+        );
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let request = |authorization: Option<&str>| Request {
+            method: "GET".into(),
+            path: "/".into(),
+            headers: authorization
+                .map(|value| HashMap::from([("authorization".into(), value.into())]))
+                .unwrap_or_default(),
+        };
+        // "void:1234", "void:wrong" and ":1234"
+        assert!(is_authorized(&request(Some("Basic dm9pZDoxMjM0")), "1234"));
+        assert!(is_authorized(&request(Some("basic OjEyMzQ=")), "1234"));
+        assert!(!is_authorized(
+            &request(Some("Basic dm9pZDp3cm9uZw==")),
+            "1234"
+        ));
+        assert!(!is_authorized(&request(Some("Bearer 1234")), "1234"));
+        assert!(!is_authorized(&request(Some("Basic !!!")), "1234"));
+        assert!(!is_authorized(&request(None), "1234"));
+        assert!(is_loopback("127.0.0.1:8080"));
+        assert!(is_loopback("[::1]:8080"));
+        assert!(!is_loopback("0.0.0.0:8080"));
+    }
+}
@@ -0,0 +1,897 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn void_cli(args: &[&str]) -> Output {
+    void_cli_env(args, &[("VOID_PSWD", "1234")])
+}
+
+fn void_cli_env(args: &[&str], envs: &[(&str, &str)]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_void-cli"))
+        .args(args)
+        .env_remove("VOID_PSWD")
+        .env_remove("VOID_STORE")
+        .env_remove("VOID_AGENT")
+        .env_remove("VOID_KEYFILE")
+        .envs(envs.iter().copied())
+        .output()
+        .expect("Could not run void-cli")
+}
+
+fn gen_file(path: &str, size: usize) {
+    let content: Vec<u8> = (0..size).map(|i| (i % 256) as u8).collect();
+    fs::write(path, content).unwrap();
+}
+
+#[test]
+fn test_info() {
+    if Path::new("tmp_cli_info").exists() {
+        fs::remove_dir_all("tmp_cli_info").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_info/folder/sub").unwrap();
+    gen_file("tmp_cli_info/folder/file1", 512);
+    gen_file("tmp_cli_info/folder/sub/file2", 1024);
+
+    assert!(void_cli(&["create", "tmp_cli_info/store"]).status.success());
+    let output = void_cli(&[
+        "add",
+        "-s",
+        "tmp_cli_info/store",
+        "/",
+        "tmp_cli_info/folder",
+    ]);
+    assert!(output.status.success());
+    let output = void_cli(&["tag-add", "-s", "tmp_cli_info/store", "/folder", "tag"]);
+    assert!(output.status.success());
+
+    let output = void_cli(&["info", "-s", "tmp_cli_info/store"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<Vec<&str>> = stdout
+        .lines()
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    assert!(lines.contains(&vec!["Files", "2"]));
+    assert!(lines.contains(&vec!["Folders", "2"]));
+    assert!(lines.contains(&vec!["Total", "size", "1536"]));
+    assert!(lines.contains(&vec!["Tags", "1"]));
+    assert!(lines.contains(&vec!["Parts", "2"]));
+    assert!(lines.contains(&vec!["Cipher", "AES-256-GCM"]));
+
+    let output = void_cli(&["info", "--json", "-s", "tmp_cli_info/store"]);
+    assert!(output.status.success());
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(info["files"], 2);
+    assert_eq!(info["folders"], 2);
+    assert_eq!(info["total_bytes"], 1536);
+    assert_eq!(info["tags"], 1);
+    assert_eq!(info["parts"], 2);
+    assert_eq!(info["kdf"], "HKDF-SHA256");
+    assert!(info["disk_size"].as_u64().unwrap() > 1536);
+
+    fs::remove_dir_all("tmp_cli_info").unwrap();
+}
+
+#[test]
+fn test_agent() {
+    if Path::new("tmp_cli_agent").exists() {
+        fs::remove_dir_all("tmp_cli_agent").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_agent").unwrap();
+    gen_file("tmp_cli_agent/file", 512);
+    assert!(void_cli(&["create", "tmp_cli_agent/store"])
+        .status
+        .success());
+
+    let socket = std::env::current_dir()
+        .unwrap()
+        .join("tmp_cli_agent/agent.sock");
+    let socket = socket.to_str().unwrap();
+    let right = [("VOID_AGENT_SOCK", socket), ("VOID_PSWD", "1234")];
+    let wrong = [("VOID_AGENT_SOCK", socket), ("VOID_PSWD", "4321")];
+    let add = [
+        "add",
+        "-s",
+        "tmp_cli_agent/store",
+        "/",
+        "tmp_cli_agent/file",
+    ];
+    let stat = ["stat", "-s", "tmp_cli_agent/store", "/file"];
+
+    let output = void_cli_env(&["agent", "start", "--timeout", "60"], &right);
+    assert!(output.status.success());
+
+    println!("Tests that the key is cached only with --agent");
+    assert!(void_cli_env(&add, &right).status.success());
+    assert!(!void_cli_env(&stat, &wrong).status.success());
+    assert!(void_cli_env(&[&stat[..], &["--agent"]].concat(), &right)
+        .status
+        .success());
+    assert!(void_cli_env(&[&stat[..], &["--agent"]].concat(), &wrong)
+        .status
+        .success());
+
+    println!("Tests that stopping the agent forgets the key");
+    assert!(void_cli_env(&["agent", "stop"], &right).status.success());
+    assert!(!void_cli_env(&[&stat[..], &["--agent"]].concat(), &wrong)
+        .status
+        .success());
+    assert!(!void_cli_env(&["agent", "stop"], &right).status.success());
+
+    fs::remove_dir_all("tmp_cli_agent").unwrap();
+}
+
+#[cfg(feature = "webdav")]
+#[test]
+fn test_webdav() {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    if Path::new("tmp_cli_webdav").exists() {
+        fs::remove_dir_all("tmp_cli_webdav").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_webdav/folder").unwrap();
+    gen_file("tmp_cli_webdav/folder/file", 5000);
+    assert!(void_cli(&["create", "tmp_cli_webdav/store"])
+        .status
+        .success());
+    let output = void_cli(&[
+        "add",
+        "-s",
+        "tmp_cli_webdav/store",
+        "/",
+        "tmp_cli_webdav/folder",
+    ]);
+    assert!(output.status.success());
+
+    println!("Tests that the password cannot be given as an argument");
+    let output = void_cli_env(
+        &["serve", "--webdav", "tmp_cli_webdav/store", "-p", "1234"],
+        &[],
+    );
+    assert!(!output.status.success());
+
+    println!("Tests that other addresses than loopback need a token");
+    let output = void_cli_env(
+        &[
+            "serve",
+            "--webdav",
+            "tmp_cli_webdav/store",
+            "--bind",
+            "0.0.0.0:0",
+        ],
+        &[("VOID_PSWD", "1234")],
+    );
+    assert!(!output.status.success());
+
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let bind = format!("127.0.0.1:{port}");
+    let mut server = Command::new(env!("CARGO_BIN_EXE_void-cli"))
+        .args(["serve", "--webdav", "tmp_cli_webdav/store", "--bind", &bind])
+        .env("VOID_PSWD", "1234")
+        .env_remove("VOID_STORE")
+        .spawn()
+        .unwrap();
+
+    let unauthorized = |request: &str| -> Vec<u8> {
+        let started = std::time::Instant::now();
+        let mut stream = loop {
+            match TcpStream::connect(&bind) {
+                Ok(stream) => break stream,
+                Err(_) if started.elapsed().as_secs() < 10 => {
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+                Err(err) => panic!("Could not connect to the server: {err}"),
+            }
+        };
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        response
+    };
+    // Logs in as "void:1234".
+    let request = |request: &str| -> Vec<u8> {
+        let (line, rest) = request.split_once("\r\n").unwrap();
+        unauthorized(&format!(
+            "{line}\r\nAuthorization: Basic dm9pZDoxMjM0\r\n{rest}"
+        ))
+    };
+
+    println!("Tests that requests without the password are refused");
+    let response = unauthorized("PROPFIND /folder HTTP/1.1\r\nDepth: 1\r\n\r\n");
+    assert!(response.starts_with(b"HTTP/1.1 401"));
+    let response = unauthorized("GET /folder/file HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with(b"HTTP/1.1 401"));
+    let response =
+        unauthorized("GET /folder/file HTTP/1.1\r\nAuthorization: Basic dm9pZDp3cm9uZw==\r\n\r\n");
+    assert!(response.starts_with(b"HTTP/1.1 401"));
+
+    println!("Tests GET of a known file");
+    let response = request("GET /folder/file HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    let head = String::from_utf8_lossy(&response[..split]);
+    assert!(head.starts_with("HTTP/1.1 200 OK"));
+    assert!(head.contains("Content-Length: 5000"));
+    assert_eq!(
+        &response[split + 4..],
+        fs::read("tmp_cli_webdav/folder/file").unwrap()
+    );
+
+    println!("Tests PROPFIND and PUT");
+    let response = request("PROPFIND /folder HTTP/1.1\r\nDepth: 1\r\n\r\n");
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 207"));
+    assert!(response.contains("<D:href>/folder/file</D:href>"));
+    assert!(response.contains("<D:getcontentlength>5000</D:getcontentlength>"));
+
+    let response = request("PUT /new/file HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello");
+    assert!(response.starts_with(b"HTTP/1.1 201"));
+    let response = request("GET /new/file HTTP/1.1\r\n\r\n");
+    assert!(response.ends_with(b"\r\n\r\nhello"));
+    let response = request("GET /missing HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with(b"HTTP/1.1 404"));
+
+    println!("Tests that a body shorter than its length is not stored");
+    let started = std::time::Instant::now();
+    let mut stream = TcpStream::connect(&bind).unwrap();
+    stream
+        .write_all(b"PUT /short HTTP/1.1\r\nAuthorization: Basic dm9pZDoxMjM0\r\n")
+        .unwrap();
+    stream
+        .write_all(b"Content-Length: 10\r\n\r\nhello")
+        .unwrap();
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    assert!(response.starts_with(b"HTTP/1.1 400"));
+    assert!(started.elapsed().as_secs() < 10);
+    let response = request("GET /short HTTP/1.1\r\n\r\n");
+    assert!(response.starts_with(b"HTTP/1.1 404"));
+
+    println!("Tests that a client sending nothing does not block the others");
+    let started = std::time::Instant::now();
+    let idle = TcpStream::connect(&bind).unwrap();
+    let mut partial = TcpStream::connect(&bind).unwrap();
+    partial.write_all(b"GET /folder/file HTTP/1.1\r\n").unwrap();
+    let response = request("GET /new/file HTTP/1.1\r\n\r\n");
+    assert!(response.ends_with(b"\r\n\r\nhello"));
+    assert!(started.elapsed().as_secs() < 20);
+    drop((idle, partial));
+
+    server.kill().unwrap();
+    server.wait().unwrap();
+    fs::remove_dir_all("tmp_cli_webdav").unwrap();
+}
+
+#[test]
+fn test_cat() {
+    if Path::new("tmp_cli_cat").exists() {
+        fs::remove_dir_all("tmp_cli_cat").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_cat").unwrap();
+    gen_file("tmp_cli_cat/file", 5000);
+    let content = fs::read("tmp_cli_cat/file").unwrap();
+    let (mut store, _) = void::StoreBuilder::new("tmp_cli_cat/store", "1234")
+        .chunk_size(1024)
+        .build()
+        .unwrap();
+    store.add("tmp_cli_cat/file", "/").unwrap();
+    assert_eq!(5, store.stat("/file").unwrap().part_count());
+
+    let cat =
+        |args: &[&str]| void_cli(&[&["cat", "-s", "tmp_cli_cat/store", "/file"], args].concat());
+
+    println!("Tests a range spanning several chunks");
+    let output = cat(&["--offset", "1000", "--length", "2100"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, content[1000..3100]);
+
+    println!("Tests the default length and ranges past the end");
+    assert_eq!(cat(&[]).stdout, content);
+    assert_eq!(cat(&["--offset", "4990"]).stdout, content[4990..]);
+    assert_eq!(
+        cat(&["--offset", "4990", "--length", "100"]).stdout,
+        content[4990..]
+    );
+    let output = cat(&["--offset", "6000"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(!void_cli(&["cat", "-s", "tmp_cli_cat/store", "/missing"])
+        .status
+        .success());
+
+    fs::remove_dir_all("tmp_cli_cat").unwrap();
+}
+
+#[test]
+fn test_store_discovery() {
+    if Path::new("tmp_cli_discovery").exists() {
+        fs::remove_dir_all("tmp_cli_discovery").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_discovery").unwrap();
+    gen_file("tmp_cli_discovery/file", 512);
+    assert!(void_cli(&["create", "tmp_cli_discovery/store"])
+        .status
+        .success());
+    let output = void_cli(&[
+        "add",
+        "-s",
+        "tmp_cli_discovery/store",
+        "/",
+        "tmp_cli_discovery/file",
+    ]);
+    assert!(output.status.success());
+    fs::create_dir_all("tmp_cli_discovery/store/sub/dir").unwrap();
+
+    let run = |dir: &str| {
+        Command::new(env!("CARGO_BIN_EXE_void-cli"))
+            .args(["stat", "/file"])
+            .current_dir(dir)
+            .env("VOID_PSWD", "1234")
+            .env_remove("VOID_STORE")
+            .env_remove("VOID_AGENT")
+            .output()
+            .expect("Could not run void-cli")
+    };
+
+    println!("Tests that the store is found from a subdirectory");
+    assert!(run("tmp_cli_discovery/store/sub/dir").status.success());
+    assert!(run("tmp_cli_discovery/store").status.success());
+
+    println!("Tests that nothing is found outside of the store");
+    let output = run("tmp_cli_discovery");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No store given"));
+
+    fs::remove_dir_all("tmp_cli_discovery").unwrap();
+}
+
+#[test]
+fn test_exit_codes() {
+    if Path::new("tmp_cli_exit_codes").exists() {
+        fs::remove_dir_all("tmp_cli_exit_codes").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_exit_codes").unwrap();
+    assert!(void_cli(&["create", "tmp_cli_exit_codes/store"])
+        .status
+        .success());
+
+    let stat = ["stat", "-s", "tmp_cli_exit_codes/store", "/"];
+    let output = void_cli_env(&stat, &[("VOID_PSWD", "4321")]);
+    assert_eq!(output.status.code(), Some(2));
+
+    let get = [
+        "get",
+        "-s",
+        "tmp_cli_exit_codes/store",
+        "/missing",
+        "tmp_cli_exit_codes/out",
+    ];
+    assert_eq!(void_cli(&get).status.code(), Some(3));
+
+    fs::remove_dir_all("tmp_cli_exit_codes").unwrap();
+}
+
+#[test]
+fn test_quiet_verbose() {
+    if Path::new("tmp_cli_quiet").exists() {
+        fs::remove_dir_all("tmp_cli_quiet").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_quiet/folder").unwrap();
+    gen_file("tmp_cli_quiet/folder/file", 512);
+    let output = void_cli(&["create", "-q", "tmp_cli_quiet/store"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    println!("Tests that --quiet silences a successful add");
+    let add = ["add", "-s", "tmp_cli_quiet/store", "/"];
+    let output = void_cli(&[&add[..], &["--quiet", "tmp_cli_quiet/folder"]].concat());
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    println!("Tests that --verbose prints each added file");
+    let output = void_cli(&[&add[..], &["-v", "tmp_cli_quiet/folder/file"]].concat());
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("/file: 512 bytes in 1 parts"));
+
+    let output = void_cli(&["stat", "-q", "-s", "tmp_cli_quiet/store", "/folder/file"]);
+    assert!(output.status.success());
+    assert!(!output.stdout.is_empty());
+    assert!(
+        !void_cli(&["stat", "-q", "-v", "-s", "tmp_cli_quiet/store", "/"])
+            .status
+            .success()
+    );
+
+    fs::remove_dir_all("tmp_cli_quiet").unwrap();
+}
+
+#[test]
+fn test_tag_complete() {
+    if Path::new("tmp_cli_tag_complete").exists() {
+        fs::remove_dir_all("tmp_cli_tag_complete").unwrap();
+    }
+
+    let store = "tmp_cli_tag_complete/store";
+    assert!(void_cli(&["create", store]).status.success());
+    fs::create_dir_all("tmp_cli_tag_complete/a").unwrap();
+    assert!(
+        void_cli(&["add", "-s", store, "/", "tmp_cli_tag_complete/a"])
+            .status
+            .success()
+    );
+    let output = void_cli(&["tag-add", "-s", store, "/a", "music", "movies", "work"]);
+    assert!(output.status.success());
+
+    let output = void_cli(&["tag-complete", "-s", store, "m"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "movies\nmusic\n");
+
+    let output = void_cli(&["tag-complete", "-s", store, "--", "!w"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "!work\n");
+
+    println!("Tests that completion never asks for a password");
+    let output = void_cli_env(&["tag-complete", "-s", store, "m"], &[]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    fs::remove_dir_all("tmp_cli_tag_complete").unwrap();
+}
+
+#[test]
+fn test_recursive() {
+    if Path::new("tmp_cli_recursive").exists() {
+        fs::remove_dir_all("tmp_cli_recursive").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_recursive/folder/sub").unwrap();
+    gen_file("tmp_cli_recursive/folder/file1", 16);
+    gen_file("tmp_cli_recursive/folder/sub/file2", 16);
+
+    let store = "tmp_cli_recursive/store";
+    assert!(void_cli(&["create", store]).status.success());
+    let output = void_cli(&["add", "-s", store, "/", "tmp_cli_recursive/folder"]);
+    assert!(output.status.success());
+    let output = void_cli(&["tag-add", "-s", store, "-r", "/folder", "tag"]);
+    assert!(output.status.success());
+
+    let output = void_cli(&["tag-search", "-s", store, "tag"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut files: Vec<&str> = stdout.split_whitespace().collect();
+    files.sort();
+    assert_eq!(
+        files,
+        [
+            "/folder",
+            "/folder/file1",
+            "/folder/sub",
+            "/folder/sub/file2"
+        ]
+    );
+
+    let output = void_cli(&["tag-remove", "-s", store, "--recursive", "/folder", "tag"]);
+    assert!(output.status.success());
+    let output = void_cli(&["tag-search", "-s", store, "tag"]);
+    assert!(output.stdout.iter().all(u8::is_ascii_whitespace));
+
+    fs::remove_dir_all("tmp_cli_recursive").unwrap();
+}
+
+#[test]
+fn test_remove_confirmation() {
+    if Path::new("tmp_cli_rm").exists() {
+        fs::remove_dir_all("tmp_cli_rm").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_rm/folder/sub").unwrap();
+    for file in ["a.tmp", "b", "sub/c.tmp", "sub/d"] {
+        gen_file(&format!("tmp_cli_rm/folder/{file}"), 16);
+    }
+
+    let store = "tmp_cli_rm/store";
+    assert!(void_cli(&["create", store]).status.success());
+    assert!(void_cli(&["add", "-s", store, "/", "tmp_cli_rm/folder"])
+        .status
+        .success());
+    let ls = |path: &str| {
+        let output = void_cli(&["ls", "-s", store, path]);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    println!("Tests that --yes removes the matches without asking");
+    let output = void_cli(&["rm", "-s", store, "--yes", "-m", "*.tmp"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(!ls("/folder").contains("a.tmp"));
+    assert!(!ls("/folder/sub").contains("c.tmp"));
+
+    println!("Tests that nothing is removed without a terminal or --yes");
+    let output = void_cli(&["rm", "-s", store, "/folder/sub"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(ls("/folder").contains("sub"));
+    let output = void_cli(&["rm", "-s", store, "-y", "/folder/sub"]);
+    assert!(output.status.success());
+    assert!(!ls("/folder").contains("sub"));
+
+    println!("Tests that tag: without tags is refused");
+    for query in ["tag:", "tag:   "] {
+        let output = void_cli(&["rm", "-s", store, "-y", "-m", query]);
+        assert!(!output.status.success());
+        assert!(ls("/folder").contains('b'));
+    }
+
+    let output = void_cli(&["rm", "-s", store, "-m", "tag:missing"]);
+    assert!(output.status.success());
+    assert!(ls("/folder").contains('b'));
+
+    fs::remove_dir_all("tmp_cli_rm").unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_add_symlinks() {
+    if Path::new("tmp_cli_symlinks").exists() {
+        fs::remove_dir_all("tmp_cli_symlinks").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_symlinks/folder").unwrap();
+    gen_file("tmp_cli_symlinks/folder/file", 16);
+    std::os::unix::fs::symlink("file", "tmp_cli_symlinks/folder/link").unwrap();
+
+    let store = "tmp_cli_symlinks/store";
+    assert!(void_cli(&["create", store]).status.success());
+
+    let output = void_cli(&["add", "-s", store, "/follow", "tmp_cli_symlinks/folder"]);
+    assert!(output.status.success());
+    let output = void_cli(&["cat", "-s", store, "/follow/folder/link"]);
+    assert!(output.status.success());
+    assert_eq!(
+        output.stdout,
+        fs::read("tmp_cli_symlinks/folder/file").unwrap()
+    );
+    let output = void_cli(&[
+        "metadata-get",
+        "-s",
+        store,
+        "/follow/folder/link",
+        "symlink",
+    ]);
+    assert!(!output.status.success());
+
+    let output = void_cli(&[
+        "add",
+        "-s",
+        store,
+        "--store-symlinks",
+        "/links",
+        "tmp_cli_symlinks/folder",
+    ]);
+    assert!(output.status.success());
+    let output = void_cli(&["cat", "-s", store, "/links/folder/link"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let output = void_cli(&["metadata-get", "-s", store, "/links/folder/link", "symlink"]);
+    assert!(!output.status.success());
+
+    let output = void_cli(&["get", "-s", store, "/links/folder", "tmp_cli_symlinks/out"]);
+    assert!(output.status.success());
+    let target = fs::read_link("tmp_cli_symlinks/out/link").unwrap();
+    assert_eq!(target, Path::new("file"));
+
+    println!("Tests that metadata cannot turn a file into a link");
+    let output = void_cli(&[
+        "metadata-set",
+        "-s",
+        store,
+        "/links/folder/file",
+        "symlink",
+        "/etc/passwd",
+    ]);
+    assert!(output.status.success());
+    let output = void_cli(&["get", "-s", store, "/links/folder", "tmp_cli_symlinks/out2"]);
+    assert!(output.status.success());
+    let file = fs::symlink_metadata("tmp_cli_symlinks/out2/file").unwrap();
+    assert!(file.file_type().is_file());
+    assert_eq!(
+        fs::read("tmp_cli_symlinks/out2/file").unwrap(),
+        fs::read("tmp_cli_symlinks/folder/file").unwrap()
+    );
+
+    fs::remove_dir_all("tmp_cli_symlinks").unwrap();
+}
+
+#[test]
+fn test_progress_without_terminal() {
+    if Path::new("tmp_cli_progress").exists() {
+        fs::remove_dir_all("tmp_cli_progress").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_progress/folder").unwrap();
+    gen_file("tmp_cli_progress/folder/file1", 512);
+    gen_file("tmp_cli_progress/folder/file2", 1024);
+
+    let store = "tmp_cli_progress/store";
+    assert!(void_cli(&["create", store]).status.success());
+    let output = void_cli(&["add", "-s", store, "/", "tmp_cli_progress/folder"]);
+    assert!(output.status.success());
+    assert!(!output.stderr.contains(&b'\r'));
+    let output = void_cli(&["get", "-s", store, "/folder", "tmp_cli_progress/out"]);
+    assert!(output.status.success());
+    assert!(!output.stderr.contains(&b'\r'));
+    assert_eq!(
+        fs::read("tmp_cli_progress/out/file2").unwrap(),
+        fs::read("tmp_cli_progress/folder/file2").unwrap()
+    );
+
+    fs::remove_dir_all("tmp_cli_progress").unwrap();
+}
+
+#[cfg(feature = "watch")]
+#[test]
+fn test_watch() {
+    use std::time::{Duration, Instant};
+
+    if Path::new("tmp_cli_watch").exists() {
+        fs::remove_dir_all("tmp_cli_watch").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_watch/folder").unwrap();
+    fs::write("tmp_cli_watch/folder/file", b"first").unwrap();
+    let store = "tmp_cli_watch/store";
+    assert!(void_cli(&["create", store]).status.success());
+
+    let mut watcher = Command::new(env!("CARGO_BIN_EXE_void-cli"))
+        .args(["watch", "-s", store, "tmp_cli_watch/folder", "/folder"])
+        .env("VOID_PSWD", "1234")
+        .env_remove("VOID_STORE")
+        .spawn()
+        .unwrap();
+
+    let wait_for = |content: &[u8]| {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(20) {
+            let output = void_cli(&["cat", "-s", store, "/folder/file"]);
+            if output.status.success() && output.stdout == content {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        false
+    };
+
+    println!("Tests the folder is synchronized when watching starts");
+    assert!(wait_for(b"first"));
+
+    println!("Tests a changed file is synchronized again");
+    fs::write("tmp_cli_watch/folder/file", b"second version").unwrap();
+    let synced = wait_for(b"second version");
+
+    watcher.kill().unwrap();
+    watcher.wait().unwrap();
+    assert!(synced);
+
+    fs::remove_dir_all("tmp_cli_watch").unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_preserve() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    if Path::new("tmp_cli_preserve").exists() {
+        fs::remove_dir_all("tmp_cli_preserve").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_preserve/folder").unwrap();
+    gen_file("tmp_cli_preserve/folder/file", 16);
+    let mtime = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    let file = fs::File::options()
+        .write(true)
+        .open("tmp_cli_preserve/folder/file")
+        .unwrap();
+    file.set_modified(mtime).unwrap();
+    file.set_permissions(fs::Permissions::from_mode(0o604))
+        .unwrap();
+    drop(file);
+
+    let store = "tmp_cli_preserve/store";
+    assert!(void_cli(&["create", store]).status.success());
+    let output = void_cli(&[
+        "add",
+        "-s",
+        store,
+        "--preserve",
+        "/",
+        "tmp_cli_preserve/folder",
+    ]);
+    assert!(output.status.success());
+
+    let output = void_cli(&[
+        "get",
+        "-s",
+        store,
+        "--preserve",
+        "/folder",
+        "tmp_cli_preserve/out",
+    ]);
+    assert!(output.status.success());
+    let metadata = fs::metadata("tmp_cli_preserve/out/file").unwrap();
+    assert_eq!(metadata.permissions().mode() & 0o7777, 0o604);
+    assert_eq!(metadata.modified().unwrap(), mtime);
+
+    let output = void_cli(&["get", "-s", store, "/folder", "tmp_cli_preserve/plain"]);
+    assert!(output.status.success());
+    let metadata = fs::metadata("tmp_cli_preserve/plain/file").unwrap();
+    assert_ne!(metadata.permissions().mode() & 0o7777, 0o604);
+    assert_ne!(metadata.modified().unwrap(), mtime);
+
+    fs::remove_dir_all("tmp_cli_preserve").unwrap();
+}
+
+#[test]
+fn test_keyfile() {
+    if Path::new("tmp_cli_keyfile").exists() {
+        fs::remove_dir_all("tmp_cli_keyfile").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_keyfile").unwrap();
+    gen_file("tmp_cli_keyfile/key", 1024);
+
+    let keyfile = ["--keyfile", "tmp_cli_keyfile/key"];
+    let create = [&["create", "tmp_cli_keyfile/store"][..], &keyfile].concat();
+    assert!(void_cli(&create).status.success());
+
+    let stat = ["stat", "-s", "tmp_cli_keyfile/store", "/"];
+    assert!(void_cli(&[&stat[..], &keyfile].concat()).status.success());
+
+    let output = void_cli(&stat);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--keyfile"));
+
+    let output = void_cli_env(&[&stat[..], &keyfile].concat(), &[("VOID_PSWD", "4321")]);
+    assert_eq!(output.status.code(), Some(2));
+
+    fs::remove_dir_all("tmp_cli_keyfile").unwrap();
+}
+
+#[test]
+fn test_hidden() {
+    if Path::new("tmp_cli_hidden").exists() {
+        fs::remove_dir_all("tmp_cli_hidden").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_hidden/folder").unwrap();
+    gen_file("tmp_cli_hidden/folder/.hidden", 16);
+    gen_file("tmp_cli_hidden/folder/visible", 16);
+
+    let store = "tmp_cli_hidden/store";
+    assert!(void_cli(&["create", store]).status.success());
+    let output = void_cli(&["add", "-s", store, "/", "tmp_cli_hidden/folder"]);
+    assert!(output.status.success());
+
+    let names = |args: &[&str]| {
+        let output = void_cli(args);
+        assert!(output.status.success());
+        let files: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let mut names: Vec<String> = files
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|file| file["name"].as_str().unwrap().to_string())
+            .collect();
+        names.sort();
+        names
+    };
+
+    assert_eq!(
+        names(&["ls", "--json", "-s", store, "/folder"]),
+        ["visible"]
+    );
+    assert_eq!(
+        names(&["ls", "--json", "-a", "-s", store, "/folder"]),
+        [".hidden", "visible"]
+    );
+
+    fs::remove_dir_all("tmp_cli_hidden").unwrap();
+}
+
+#[test]
+fn test_tar() {
+    if Path::new("tmp_cli_tar").exists() {
+        fs::remove_dir_all("tmp_cli_tar").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_tar/folder/sub").unwrap();
+    gen_file("tmp_cli_tar/folder/small", 512);
+    gen_file("tmp_cli_tar/folder/sub/big", 20000);
+
+    let store = "tmp_cli_tar/store";
+    assert!(void_cli(&["create", store]).status.success());
+    let output = void_cli(&["add", "-s", store, "/", "tmp_cli_tar/folder"]);
+    assert!(output.status.success());
+
+    println!("Tests exporting into and importing from a file");
+    let output = void_cli(&["export", "-s", store, "/folder", "tmp_cli_tar/folder.tar"]);
+    assert!(output.status.success());
+    let output = void_cli(&["import", "-s", store, "tmp_cli_tar/folder.tar", "/restored"]);
+    assert!(output.status.success());
+    let output = void_cli(&["cat", "-s", store, "/restored/folder/sub/big"]);
+    assert!(output.status.success());
+    assert_eq!(
+        output.stdout,
+        fs::read("tmp_cli_tar/folder/sub/big").unwrap()
+    );
+
+    println!("Tests that a broken archive leaves the store as it was");
+    let archive = fs::read("tmp_cli_tar/folder.tar").unwrap();
+    fs::write("tmp_cli_tar/broken.tar", &archive[..archive.len() - 4096]).unwrap();
+    let parts = fs::read_dir(store).unwrap().count();
+    let output = void_cli(&["import", "-s", store, "tmp_cli_tar/broken.tar", "/broken"]);
+    assert!(!output.status.success());
+    assert!(!void_cli(&["ls", "-s", store, "/broken"]).status.success());
+    assert_eq!(parts, fs::read_dir(store).unwrap().count());
+
+    fs::remove_dir_all("tmp_cli_tar").unwrap();
+}
+
+#[test]
+fn test_du() {
+    if Path::new("tmp_cli_du").exists() {
+        fs::remove_dir_all("tmp_cli_du").unwrap();
+    }
+
+    fs::create_dir_all("tmp_cli_du/folder/sub").unwrap();
+    gen_file("tmp_cli_du/folder/file1", 512);
+    gen_file("tmp_cli_du/folder/sub/file2", 1024);
+    gen_file("tmp_cli_du/folder/sub/file3", 2048);
+
+    let store = "tmp_cli_du/store";
+    assert!(void_cli(&["create", store]).status.success());
+    let output = void_cli(&["add", "-s", store, "/", "tmp_cli_du/folder"]);
+    assert!(output.status.success());
+
+    let du = |path: &str| {
+        let output = void_cli(&["du", "-s", store, path]);
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    println!("Tests the size of folders and files");
+    assert_eq!(du("/folder"), "3584\t/folder\n");
+    assert_eq!(du("/folder/sub"), "3072\t/folder/sub\n");
+    assert_eq!(du("/folder/file1"), "512\t/folder/file1\n");
+
+    println!("Tests human-readable sizes");
+    let output = void_cli(&["du", "-H", "-s", store, "/folder"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, format!("{}\t/folder\n", bytesize::ByteSize(3584)));
+
+    println!("Tests a missing path");
+    let output = void_cli(&["du", "-s", store, "/missing"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+
+    fs::remove_dir_all("tmp_cli_du").unwrap();
+}
@@ -0,0 +1,21 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Feeds arbitrary bytes to the store header and filesystem deserializers.
+//!
+//! Run with `cargo fuzz run deserialize` from the `void` folder.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use void::fuzzing::{deserialize_filesystem, deserialize_store_file};
+use void::Error;
+
+fuzz_target!(|data: &[u8]| {
+    for result in [deserialize_store_file(data), deserialize_filesystem(data)] {
+        assert!(matches!(result, Ok(()) | Err(Error::CannotDeserializeError)));
+    }
+});
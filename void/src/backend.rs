@@ -0,0 +1,651 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Where the bytes of a store are kept.
+//!
+//! A store is made of index files (the store file, its mirror and backups)
+//! and of encrypted part files. `Store` only reads and writes them through
+//! the `Backend` trait, so the same store can live in a folder, in memory or
+//! anywhere else a backend is written for.
+
+use super::path::Path;
+use super::store::Error;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::fs;
+use std::io::{Read, Write};
+use std::sync::{Mutex, MutexGuard};
+
+/// Name of the store file.
+pub(crate) const INDEX: &str = "Store.void";
+
+/// Most levels of subfolders part files can be sharded into, one per byte of
+/// the part id.
+pub const MAX_SHARD_LEVELS: u8 = 8;
+
+/// Storage of the index and part files of a store. Everything given to a
+/// backend is already encrypted.
+///
+/// # Example
+///
+/// ```ignore
+/// let backend = Arc::new(MemoryBackend::default());
+/// let (store, _) = StoreBuilder::new("", "password")
+///     .backend(backend.clone())
+///     .build()?;
+/// let store = Store::open_backend(backend, "password")?;
+/// ```
+pub trait Backend: Debug + Send + Sync {
+    /// Reads the index file `name`.
+    ///
+    /// # Returns
+    ///
+    /// * `FileDoesNotExistError` if there is no such index file.
+    fn read_index(&self, name: &str) -> Result<Vec<u8>, Error>;
+
+    /// Replaces the index file `name`. It must never be left half written.
+    fn write_index(&self, name: &str, bytes: &[u8]) -> Result<(), Error>;
+
+    /// Reads the encrypted content of the part `id` into `buffer`, replacing
+    /// what it had.
+    fn read_part(&self, id: u64, buffer: &mut Vec<u8>) -> Result<(), Error>;
+
+    /// Writes the encrypted content of the part `id`.
+    fn write_part(&self, id: u64, content: &[u8]) -> Result<(), Error>;
+
+    /// Deletes the part `id`. Missing parts are not an error.
+    fn remove_part(&self, id: u64) -> Result<(), Error>;
+
+    /// Lists the ids of all parts, including those no longer in the index.
+    fn list_parts(&self) -> Result<Vec<u64>, Error>;
+
+    /// Describes where the part `id` is kept, for error messages.
+    fn part_location(&self, id: u64) -> String;
+
+    /// Returns the number of bytes taken by the index and part files.
+    fn size(&self) -> Result<u64, Error>;
+
+    /// Waits until everything written so far is kept where it survives a
+    /// crash, for backends that do not write it there right away.
+    fn sync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Returns the folder the store is kept in, if it is kept in a folder of
+    /// the local filesystem.
+    fn folder(&self) -> Option<&str> {
+        None
+    }
+
+    /// Maps the part `id` into memory, if parts are kept in files.
+    #[cfg(feature = "mmap")]
+    fn map_part(&self, _id: u64) -> Option<Result<memmap2::Mmap, Error>> {
+        None
+    }
+}
+
+/// Returns the name of the file that holds the encrypted part `id`: the id
+/// in lowercase hex, zero padded to 16 digits, so names sort like ids.
+pub(crate) fn part_name(id: u64) -> String {
+    format!("{id:016x}")
+}
+
+/// Returns the name parts had before `part_name`: the same digits, zero
+/// padded to 32. Parts with such names are still read, and are renamed when
+/// written again.
+pub(crate) fn legacy_part_name(id: u64) -> String {
+    format!("{id:032x}")
+}
+
+/// Returns the id of the part kept in a file, if `name` is a part name in
+/// either scheme.
+pub(crate) fn parse_part_name(name: &str) -> Option<u64> {
+    let is_hex = name.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+    if (name.len() == 16 || name.len() == 32) && is_hex {
+        u64::from_str_radix(name, 16).ok()
+    } else {
+        None
+    }
+}
+
+/// Replaces a file without ever leaving it half written: the content is
+/// written to a temporary file next to it, flushed to disk and renamed over
+/// the original. The temporary file is removed if writing fails.
+///
+/// # Arguments
+///
+/// * `path` - Path of the file to replace.
+/// * `write` - Writes the new content to the temporary file.
+fn write_atomically<F>(path: &str, write: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut fs::File) -> std::io::Result<()>,
+{
+    let tmp_path = format!("{path}.tmp");
+    let result = fs::File::create(&tmp_path).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()
+    });
+
+    if result.and_then(|_| fs::rename(&tmp_path, path)).is_err() {
+        fs::remove_file(&tmp_path).ok();
+        return Err(Error::CannotWriteFileError);
+    }
+
+    Ok(())
+}
+
+/// Keeps a store in a folder, one file per index and per part. This is where
+/// `Store::create` and `Store::open` keep stores.
+///
+/// Part files are kept directly in the folder, or sharded into subfolders to
+/// keep folders small, see `with_shard_levels`.
+#[derive(Debug)]
+pub struct FsBackend {
+    folder: String,
+    shard_levels: u8,
+    // Part files written since the last `sync`.
+    unsynced: Mutex<HashSet<String>>,
+}
+
+impl FsBackend {
+    /// Opens the store folder in `path`.
+    pub fn open(path: &str) -> Result<FsBackend, Error> {
+        let folder = Path::new(&path.to_string()).ok_or(Error::CannotParseError)?;
+        if !folder.exists() {
+            return Err(Error::FolderDoesNotExistError);
+        }
+
+        Ok(FsBackend {
+            folder: folder.path,
+            shard_levels: 0,
+            unsynced: Mutex::default(),
+        })
+    }
+
+    /// Creates the store folder in `path`, with an empty store file.
+    pub fn create(path: &str) -> Result<FsBackend, Error> {
+        let folder = Path::new(&path.to_string()).ok_or(Error::CannotParseError)?;
+        if fs::create_dir_all(&folder.path).is_err() {
+            return Err(Error::CannotCreateDirectoryError);
+        }
+
+        let backend = FsBackend {
+            folder: folder.path,
+            shard_levels: 0,
+            unsynced: Mutex::default(),
+        };
+        if fs::write(backend.file(INDEX), "").is_err() {
+            return Err(Error::CannotWriteFileError);
+        }
+
+        Ok(backend)
+    }
+
+    /// Keeps each part file in `levels` levels of subfolders, named after
+    /// the bytes of the part id, lowest first: with two levels, the part
+    /// `0x1234` goes in `34/12/`. Ids are handed out in order, so their lowest
+    /// bytes spread parts evenly. Zero, the default, keeps parts in the store
+    /// folder. Capped at `MAX_SHARD_LEVELS`.
+    ///
+    /// # Arguments
+    ///
+    /// * `levels` - Number of levels of subfolders.
+    pub fn with_shard_levels(mut self, levels: u8) -> FsBackend {
+        self.shard_levels = levels.min(MAX_SHARD_LEVELS);
+        self
+    }
+
+    fn file(&self, name: &str) -> String {
+        format!("{}/{name}", self.folder.trim_end_matches('/'))
+    }
+
+    /// Returns the subfolder, relative to the store folder, that holds the
+    /// part `id`. Empty if parts are not sharded.
+    fn shard(&self, id: u64) -> String {
+        id.to_le_bytes()
+            .iter()
+            .take(self.shard_levels as usize)
+            .map(|byte| format!("{byte:02x}/"))
+            .collect()
+    }
+
+    /// Returns the path of the file named `name` that holds the part `id`.
+    fn part_file(&self, id: u64, name: &str) -> String {
+        self.file(&format!("{}{name}", self.shard(id)))
+    }
+}
+
+impl Backend for FsBackend {
+    fn read_index(&self, name: &str) -> Result<Vec<u8>, Error> {
+        fs::read(self.file(name)).map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => Error::FileDoesNotExistError,
+            _ => Error::CannotReadFileError,
+        })
+    }
+
+    fn write_index(&self, name: &str, bytes: &[u8]) -> Result<(), Error> {
+        // Does not bring back a store whose folder was deleted.
+        if fs::metadata(self.file(INDEX)).is_err() {
+            return Err(Error::FileDoesNotExistError);
+        }
+
+        write_atomically(&self.file(name), |file| file.write_all(bytes))
+    }
+
+    fn read_part(&self, id: u64, buffer: &mut Vec<u8>) -> Result<(), Error> {
+        buffer.clear();
+        fs::File::open(self.part_location(id))
+            .and_then(|mut file| file.read_to_end(buffer))
+            .map_err(|_| Error::CannotReadFileError)?;
+        Ok(())
+    }
+
+    fn write_part(&self, id: u64, content: &[u8]) -> Result<(), Error> {
+        if self.shard_levels > 0 {
+            fs::create_dir_all(self.file(&self.shard(id)))?;
+        }
+        let path = self.part_file(id, &part_name(id));
+        if fs::write(&path, content).is_err() {
+            fs::remove_file(&path).ok();
+            return Err(Error::CannotWriteFileError);
+        }
+        fs::remove_file(self.part_file(id, &legacy_part_name(id))).ok();
+        lock(&self.unsynced).insert(path);
+        Ok(())
+    }
+
+    fn remove_part(&self, id: u64) -> Result<(), Error> {
+        for name in [part_name(id), legacy_part_name(id)] {
+            match fs::remove_file(self.part_file(id, &name)) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn list_parts(&self) -> Result<Vec<u64>, Error> {
+        if fs::metadata(&self.folder).is_err() {
+            return Err(Error::CannotReadFileError);
+        }
+
+        // Parts are looked for in every level of subfolders, whatever the
+        // sharding, so parts left by another layout are listed too.
+        let entries = walkdir::WalkDir::new(&self.folder)
+            .min_depth(1)
+            .max_depth(MAX_SHARD_LEVELS as usize + 1);
+
+        let mut parts: Vec<u64> = entries
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| parse_part_name(entry.file_name().to_str()?))
+            .collect();
+        parts.sort();
+        parts.dedup();
+        Ok(parts)
+    }
+
+    fn folder(&self) -> Option<&str> {
+        Some(&self.folder)
+    }
+
+    /// Flushes the part files written since the last call to disk, and the
+    /// folders they and the store files are in, so that renames and new
+    /// files are kept too. Store files are flushed when written.
+    fn sync(&self) -> Result<(), Error> {
+        let mut unsynced = lock(&self.unsynced);
+        let root = std::path::Path::new(&self.folder);
+        let mut folders = HashSet::from([root]);
+        for path in unsynced.iter() {
+            // Parts removed since are not an error.
+            if let Ok(file) = fs::File::open(path) {
+                file.sync_all()?;
+            }
+            for folder in std::path::Path::new(path).ancestors().skip(1) {
+                folders.insert(folder);
+                if folder == root {
+                    break;
+                }
+            }
+        }
+        // Only folders on unix can be opened to be flushed.
+        #[cfg(unix)]
+        for folder in folders {
+            fs::File::open(folder)?.sync_all()?;
+        }
+
+        unsynced.clear();
+        Ok(())
+    }
+
+    fn part_location(&self, id: u64) -> String {
+        let path = self.part_file(id, &part_name(id));
+        let legacy = self.part_file(id, &legacy_part_name(id));
+        if fs::metadata(&path).is_err() && fs::metadata(&legacy).is_ok() {
+            legacy
+        } else {
+            path
+        }
+    }
+
+    fn size(&self) -> Result<u64, Error> {
+        let mut size = 0;
+        for entry in walkdir::WalkDir::new(&self.folder) {
+            let entry = entry.map_err(|_| Error::CannotReadFileError)?;
+            let metadata = entry.metadata().map_err(|_| Error::CannotReadFileError)?;
+            if metadata.is_file() {
+                size += metadata.len();
+            }
+        }
+        Ok(size)
+    }
+
+    #[cfg(feature = "mmap")]
+    fn map_part(&self, id: u64) -> Option<Result<memmap2::Mmap, Error>> {
+        let map = fs::File::open(self.part_location(id)).and_then(|file| {
+            // Part files are written whole when created and never changed
+            // afterwards, so the mapping cannot change under us.
+            unsafe { memmap2::Mmap::map(&file) }
+        });
+        Some(map.map_err(|_| Error::CannotReadFileError))
+    }
+}
+
+/// Keeps a store in memory. It is lost when dropped, unless saved with
+/// `Store::save_to_bytes`.
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    indexes: Mutex<HashMap<String, Vec<u8>>>,
+    parts: Mutex<HashMap<u64, Vec<u8>>>,
+}
+
+/// Locks a mutex, ignoring poisoning: the maps are never left inconsistent
+/// by a panicking thread.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+impl MemoryBackend {
+    /// Creates a backend holding the given index and parts.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Content of the store file.
+    /// * `parts` - Encrypted content of each part, by id.
+    pub(crate) fn new(index: Vec<u8>, parts: HashMap<u64, Vec<u8>>) -> MemoryBackend {
+        MemoryBackend {
+            indexes: Mutex::new(HashMap::from([(INDEX.to_string(), index)])),
+            parts: Mutex::new(parts),
+        }
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn read_index(&self, name: &str) -> Result<Vec<u8>, Error> {
+        let indexes = lock(&self.indexes);
+        indexes
+            .get(name)
+            .cloned()
+            .ok_or(Error::FileDoesNotExistError)
+    }
+
+    fn write_index(&self, name: &str, bytes: &[u8]) -> Result<(), Error> {
+        lock(&self.indexes).insert(name.into(), bytes.into());
+        Ok(())
+    }
+
+    fn read_part(&self, id: u64, buffer: &mut Vec<u8>) -> Result<(), Error> {
+        let parts = lock(&self.parts);
+        let part = parts.get(&id).ok_or(Error::CannotReadFileError)?;
+        buffer.clear();
+        buffer.extend_from_slice(part);
+        Ok(())
+    }
+
+    fn write_part(&self, id: u64, content: &[u8]) -> Result<(), Error> {
+        lock(&self.parts).insert(id, content.into());
+        Ok(())
+    }
+
+    fn remove_part(&self, id: u64) -> Result<(), Error> {
+        lock(&self.parts).remove(&id);
+        Ok(())
+    }
+
+    fn list_parts(&self) -> Result<Vec<u64>, Error> {
+        Ok(lock(&self.parts).keys().copied().collect())
+    }
+
+    fn part_location(&self, id: u64) -> String {
+        part_name(id)
+    }
+
+    fn size(&self) -> Result<u64, Error> {
+        let indexes: usize = lock(&self.indexes).values().map(Vec::len).sum();
+        let parts: usize = lock(&self.parts).values().map(Vec::len).sum();
+        Ok((indexes + parts) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Kdf, Store, StoreBuilder};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_part_name() {
+        assert_eq!(part_name(0), "0000000000000000");
+        assert_eq!(part_name(1), "0000000000000001");
+        assert_eq!(part_name(255), "00000000000000ff");
+        assert_eq!(part_name(u64::MAX), "ffffffffffffffff");
+        for id in [0, 1, 255, u64::MAX] {
+            assert_eq!(parse_part_name(&part_name(id)), Some(id));
+            assert_eq!(parse_part_name(&legacy_part_name(id)), Some(id));
+        }
+        assert_eq!(legacy_part_name(255), "000000000000000000000000000000ff");
+        assert_eq!(parse_part_name("00000000000000FF"), None);
+        assert_eq!(parse_part_name("Store.void"), None);
+    }
+
+    #[test]
+    fn test_legacy_part_names() {
+        let folder = std::env::temp_dir().join(format!("void-legacy-{}", std::process::id()));
+        let backend = FsBackend::create(folder.to_str().unwrap()).unwrap();
+        fs::write(backend.file(&legacy_part_name(1)), b"old").unwrap();
+
+        let mut buffer = vec![];
+        backend.read_part(1, &mut buffer).unwrap();
+        assert_eq!(buffer, b"old");
+        assert_eq!(backend.list_parts().unwrap(), [1]);
+
+        backend.write_part(1, b"new").unwrap();
+        assert!(fs::metadata(backend.file(&legacy_part_name(1))).is_err());
+        backend.read_part(1, &mut buffer).unwrap();
+        assert_eq!(buffer, b"new");
+        assert_eq!(backend.part_location(1), backend.file(&part_name(1)));
+
+        backend.remove_part(1).unwrap();
+        assert!(backend.list_parts().unwrap().is_empty());
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_sync() {
+        let folder = std::env::temp_dir().join(format!("void-sync-{}", std::process::id()));
+        let backend = FsBackend::create(folder.to_str().unwrap())
+            .unwrap()
+            .with_shard_levels(2);
+        backend.write_part(1, b"one").unwrap();
+        backend.write_part(0x1234, b"two").unwrap();
+        backend.write_part(2, b"removed").unwrap();
+        backend.remove_part(2).unwrap();
+        assert_eq!(lock(&backend.unsynced).len(), 3);
+
+        backend.sync().unwrap();
+        assert!(lock(&backend.unsynced).is_empty());
+        backend.sync().unwrap();
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_write_atomically() {
+        let folder = std::env::temp_dir().join(format!("void-atomic-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("Store.void").to_string_lossy().to_string();
+        let tmp_path = format!("{path}.tmp");
+
+        write_atomically(&path, |file| file.write_all(b"original")).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"original");
+        assert!(!std::path::Path::new(&tmp_path).exists());
+
+        let result = write_atomically(&path, |file| {
+            file.write_all(b"partial")?;
+            Err(std::io::ErrorKind::WriteZero.into())
+        });
+        assert_eq!(result, Err(Error::CannotWriteFileError));
+        assert_eq!(fs::read(&path).unwrap(), b"original");
+        assert!(!std::path::Path::new(&tmp_path).exists());
+
+        write_atomically(&path, |file| file.write_all(b"replaced")).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"replaced");
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_memory_backend() {
+        let backend = MemoryBackend::new(b"index".to_vec(), HashMap::new());
+        assert_eq!(backend.read_index(INDEX).unwrap(), b"index");
+        assert_eq!(
+            backend.read_index("Store.void.mirror"),
+            Err(Error::FileDoesNotExistError)
+        );
+
+        let mut buffer = vec![1, 2, 3];
+        backend.write_part(7, b"part").unwrap();
+        backend.read_part(7, &mut buffer).unwrap();
+        assert_eq!(buffer, b"part");
+        assert_eq!(backend.size().unwrap(), 9);
+
+        backend.remove_part(7).unwrap();
+        backend.remove_part(7).unwrap();
+        assert_eq!(
+            backend.read_part(7, &mut buffer),
+            Err(Error::CannotReadFileError)
+        );
+    }
+
+    /// Keeps the store in memory, recording every call and failing part
+    /// writes on demand.
+    #[derive(Debug, Default)]
+    struct MockBackend {
+        inner: MemoryBackend,
+        calls: Mutex<Vec<String>>,
+        /// Part writes that succeed before the others fail, if limited.
+        writes_left: Mutex<Option<usize>>,
+    }
+
+    impl MockBackend {
+        fn record(&self, call: String) {
+            lock(&self.calls).push(call);
+        }
+    }
+
+    impl Backend for MockBackend {
+        fn read_index(&self, name: &str) -> Result<Vec<u8>, Error> {
+            self.record(format!("read_index {name}"));
+            self.inner.read_index(name)
+        }
+
+        fn write_index(&self, name: &str, bytes: &[u8]) -> Result<(), Error> {
+            self.record(format!("write_index {name}"));
+            self.inner.write_index(name, bytes)
+        }
+
+        fn read_part(&self, id: u64, buffer: &mut Vec<u8>) -> Result<(), Error> {
+            self.record(format!("read_part {id}"));
+            self.inner.read_part(id, buffer)
+        }
+
+        fn write_part(&self, id: u64, content: &[u8]) -> Result<(), Error> {
+            self.record(format!("write_part {id}"));
+            if let Some(left) = lock(&self.writes_left).as_mut() {
+                if *left == 0 {
+                    return Err(Error::CannotWriteFileError);
+                }
+                *left -= 1;
+            }
+            self.inner.write_part(id, content)
+        }
+
+        fn remove_part(&self, id: u64) -> Result<(), Error> {
+            self.record(format!("remove_part {id}"));
+            self.inner.remove_part(id)
+        }
+
+        fn list_parts(&self) -> Result<Vec<u64>, Error> {
+            self.inner.list_parts()
+        }
+
+        fn part_location(&self, id: u64) -> String {
+            self.inner.part_location(id)
+        }
+
+        fn size(&self) -> Result<u64, Error> {
+            self.inner.size()
+        }
+    }
+
+    #[test]
+    fn test_mock_backend() {
+        let backend = Arc::new(MockBackend::default());
+        let (mut store, _) = StoreBuilder::new("tmp_mock_backend", "1234")
+            .kdf(Kdf::HkdfSha256)
+            .chunk_size(4)
+            .backend(backend.clone())
+            .build()
+            .unwrap();
+        assert!(!std::path::Path::new("tmp_mock_backend").exists());
+
+        store.add_bytes(b"hello world", "/a", None).unwrap();
+        assert_eq!(backend.list_parts().unwrap().len(), 3);
+        assert_eq!(
+            lock(&backend.calls).last().map(String::as_str),
+            Some("write_index Store.void")
+        );
+
+        let mut store = Store::open_backend(backend.clone(), "1234").unwrap();
+        assert_eq!(store.read_range("/a", 0, 11).unwrap(), b"hello world");
+        assert_eq!(
+            Store::open_backend(backend.clone(), "4321").err(),
+            Some(Error::WrongPassword)
+        );
+
+        *lock(&backend.writes_left) = Some(0);
+        assert!(store.add_bytes(b"lost", "/b", None).is_err());
+        assert!(!store.exists("/b").unwrap());
+
+        // The second part fails, so only the first one is undone.
+        *lock(&backend.writes_left) = Some(1);
+        assert!(store.append_bytes("/a", b" appended").is_err());
+        assert_eq!(store.read_range("/a", 0, 100).unwrap(), b"hello world");
+        assert_eq!(store.stat("/a").unwrap().part_count(), 3);
+        assert_eq!(backend.list_parts().unwrap().len(), 3);
+        *lock(&backend.writes_left) = None;
+
+        lock(&backend.calls).clear();
+        store.remove("/a").unwrap();
+        assert!(backend.list_parts().unwrap().is_empty());
+        let removed = lock(&backend.calls)
+            .iter()
+            .filter(|call| call.starts_with("remove_part"))
+            .count();
+        assert_eq!(removed, 3);
+    }
+}
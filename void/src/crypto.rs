@@ -4,15 +4,17 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use super::store::Error;
+use super::store::{Cipher, Error, Kdf, KdfParams};
 use aes_gcm::{
-    aead::{Aead, KeyInit, consts::U16},
+    aead::{Aead, AeadInPlace, KeyInit, consts::U16},
     aes::Aes256,
     AesGcm, Nonce,
 };
+use argon2::{Algorithm, Argon2, Params, Version};
 use blake2::digest::{Update, VariableOutput};
-use blake2::Blake2bVar;
-use hkdf::Hkdf;
+use blake2::{Blake2b512, Blake2bVar};
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::{Hkdf, SimpleHkdf};
 use sha2::Sha256;
 use std::result::Result;
 use std::vec::Vec;
@@ -43,6 +45,41 @@ pub(crate) fn hash(data: &[u8], salt: &[u8; 16]) -> [u8; 32] {
     hash
 }
 
+/// Incremental Blake2B hasher, for data that is read in chunks.
+/// Unlike `hash`, it does not use a salt.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut hasher = Hasher::new();
+/// hasher.update(b"Hello ");
+/// hasher.update(b"World!");
+/// let hash = hasher.finalize();
+/// ```
+pub(crate) struct Hasher {
+    hasher: Blake2bVar,
+}
+
+impl Hasher {
+    pub(crate) fn new() -> Hasher {
+        Hasher {
+            hasher: Blake2bVar::new(32).unwrap(),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
+
+    pub(crate) fn finalize(self) -> [u8; 32] {
+        let mut hash = [0u8; 32];
+        self.hasher
+            .finalize_variable(&mut hash)
+            .expect("Error hashing");
+        hash
+    }
+}
+
 /// Returns a [u8; 16] array with the value of the UUID.
 /// It uses UUIDv4.
 ///
@@ -85,6 +122,76 @@ pub(crate) fn derive_key(pswd: &str, salt: &[u8; 16], iv: &[u8; 16]) -> [u8; 32]
     key
 }
 
+/// Returns a [u8; 32] array with the value of the key.
+/// It uses HKDF with Blake2B instead of SHA-256 as hash.
+///
+/// # Arguments
+///
+/// * `pswd` - A string that holds the password.
+/// * `salt` - A byte array that holds the salt. [u0; 16].
+/// * `iv` - A byte array that holds the initial value. [u0; 16].
+pub(crate) fn derive_key_blake2b(pswd: &str, salt: &[u8; 16], iv: &[u8; 16]) -> [u8; 32] {
+    let hk = SimpleHkdf::<Blake2b512>::new(Some(salt), iv);
+    let mut key = [0u8; 32];
+    hk.expand(pswd.as_bytes(), &mut key)
+        .expect("Error generating key.");
+    key
+}
+
+/// Returns a [u8; 32] array with the value of the key, derived with HKDF
+/// using the hash of the given key derivation function. Argon2id uses
+/// SHA-256, the hash stores had before the choice was recorded.
+///
+/// # Arguments
+///
+/// * `kdf` - Key derivation function.
+/// * `pswd` - A string that holds the password.
+/// * `salt` - A byte array that holds the salt. [u0; 16].
+/// * `iv` - A byte array that holds the initial value. [u0; 16].
+pub(crate) fn derive_hkdf_key(kdf: Kdf, pswd: &str, salt: &[u8; 16], iv: &[u8; 16]) -> [u8; 32] {
+    match kdf {
+        Kdf::HkdfBlake2b => derive_key_blake2b(pswd, salt, iv),
+        Kdf::HkdfSha256 | Kdf::Argon2id => derive_key(pswd, salt, iv),
+    }
+}
+
+/// Returns a [u8; 32] array with the value of the key, derived with the given
+/// key derivation function.
+///
+/// # Arguments
+///
+/// * `kdf` - Key derivation function.
+/// * `params` - Cost parameters, used by Argon2id only.
+/// * `pswd` - A string that holds the password.
+/// * `salt` - A byte array that holds the salt. [u0; 16].
+/// * `iv` - A byte array that holds the initial value. [u0; 16].
+pub(crate) fn derive_key_with(
+    kdf: Kdf,
+    params: &KdfParams,
+    pswd: &str,
+    salt: &[u8; 16],
+    iv: &[u8; 16],
+) -> Result<[u8; 32], Error> {
+    match kdf {
+        Kdf::HkdfSha256 | Kdf::HkdfBlake2b => Ok(derive_hkdf_key(kdf, pswd, salt, iv)),
+        Kdf::Argon2id => {
+            let params = Params::new(
+                params.memory,
+                params.iterations,
+                params.parallelism,
+                Some(32),
+            );
+            let params = params.map_err(|_| Error::InvalidKdfParams)?;
+            let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+            let mut key = [0u8; 32];
+            argon2
+                .hash_password_into(pswd.as_bytes(), salt, &mut key)
+                .map_err(|_| Error::InvalidKdfParams)?;
+            Ok(key)
+        }
+    }
+}
+
 /// Encrypts data using AES 256 and returns bytes as a Vec<u8>.
 /// If key or iv are larger than needed, it will be truncated.
 ///
@@ -137,6 +244,102 @@ pub(crate) fn decrypt(data: &[u8], key: &[u8; 32], iv: &[u8; 16]) -> Result<Vec<
         .map_err(|_| Error::CannotDecryptFileError)
 }
 
+/// Encrypts data with the given cipher. ChaCha20-Poly1305 uses the first 12
+/// bytes of the iv as nonce.
+///
+/// # Arguments
+///
+/// * `cipher` - Cipher to use.
+/// * `data` - Data to be encrypted.
+/// * `key` - A byte array that holds the key. [u0; 32].
+/// * `iv` - A byte array that holds the initial value. [u0; 16].
+pub(crate) fn encrypt_with(
+    cipher: Cipher,
+    data: &[u8],
+    key: &[u8; 32],
+    iv: &[u8; 16],
+) -> Result<Vec<u8>, Error> {
+    match cipher {
+        Cipher::Aes256Gcm => encrypt(data, key, iv),
+        Cipher::ChaCha20Poly1305 => ChaCha20Poly1305::new(key.into())
+            .encrypt(Nonce::from_slice(&iv[..12]), data)
+            .map_err(|_| Error::CannotEncryptFileError),
+    }
+}
+
+/// Decrypts data with the given cipher.
+///
+/// # Arguments
+///
+/// * `cipher` - Cipher to use.
+/// * `data` - Data to be decrypted.
+/// * `key` - A byte array that holds the key. [u0; 32].
+/// * `iv` - A byte array that holds the initial value. [u0; 16].
+pub(crate) fn decrypt_with(
+    cipher: Cipher,
+    data: &[u8],
+    key: &[u8; 32],
+    iv: &[u8; 16],
+) -> Result<Vec<u8>, Error> {
+    match cipher {
+        Cipher::Aes256Gcm => decrypt(data, key, iv),
+        Cipher::ChaCha20Poly1305 => ChaCha20Poly1305::new(key.into())
+            .decrypt(Nonce::from_slice(&iv[..12]), data)
+            .map_err(|_| Error::CannotDecryptFileError),
+    }
+}
+
+/// Same as `encrypt_with`, but writes the encrypted data to `out`, reusing
+/// its allocation. The previous content of `out` is discarded.
+///
+/// # Arguments
+///
+/// * `cipher` - Cipher to use.
+/// * `data` - Data to be encrypted.
+/// * `key` - A byte array that holds the key. [u0; 32].
+/// * `iv` - A byte array that holds the initial value. [u0; 16].
+/// * `out` - Buffer that receives the encrypted data.
+pub(crate) fn encrypt_into(
+    cipher: Cipher,
+    data: &[u8],
+    key: &[u8; 32],
+    iv: &[u8; 16],
+    out: &mut Vec<u8>,
+) -> Result<(), Error> {
+    out.clear();
+    out.extend_from_slice(data);
+    match cipher {
+        Cipher::Aes256Gcm => AesGcm::<Aes256, U16>::new(key.into())
+            .encrypt_in_place(Nonce::from_slice(iv), b"", out),
+        Cipher::ChaCha20Poly1305 => ChaCha20Poly1305::new(key.into())
+            .encrypt_in_place(Nonce::from_slice(&iv[..12]), b"", out),
+    }
+    .map_err(|_| Error::CannotEncryptFileError)
+}
+
+/// Same as `decrypt_with`, but decrypts the data in its own buffer.
+///
+/// # Arguments
+///
+/// * `cipher` - Cipher to use.
+/// * `data` - Data to be decrypted. Holds the decrypted data on success.
+/// * `key` - A byte array that holds the key. [u0; 32].
+/// * `iv` - A byte array that holds the initial value. [u0; 16].
+pub(crate) fn decrypt_in_place(
+    cipher: Cipher,
+    data: &mut Vec<u8>,
+    key: &[u8; 32],
+    iv: &[u8; 16],
+) -> Result<(), Error> {
+    match cipher {
+        Cipher::Aes256Gcm => AesGcm::<Aes256, U16>::new(key.into())
+            .decrypt_in_place(Nonce::from_slice(iv), b"", data),
+        Cipher::ChaCha20Poly1305 => ChaCha20Poly1305::new(key.into())
+            .decrypt_in_place(Nonce::from_slice(&iv[..12]), b"", data),
+    }
+    .map_err(|_| Error::CannotDecryptFileError)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -161,6 +364,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_hasher() {
+        let mut hasher = Hasher::new();
+        hasher.update("Álan ".as_bytes());
+        hasher.update("Crístoffer".as_bytes());
+        let chunked = hasher.finalize();
+        let mut hasher = Hasher::new();
+        hasher.update("Álan Crístoffer".as_bytes());
+        assert_eq!(chunked, hasher.finalize());
+    }
+
     #[test]
     fn test_derive_key() {
         let pswd = "123456";
@@ -212,4 +426,68 @@ mod tests {
         let cipher = decrypt(&msg, &dkey, &iv).unwrap();
         assert_eq!("Hello World!", std::str::from_utf8(&cipher).unwrap());
     }
+
+    #[test]
+    fn test_derive_key_with() {
+        let salt = [1u8; 16];
+        let iv = [2u8; 16];
+        let params = KdfParams {
+            memory: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let hkdf = derive_key_with(Kdf::HkdfSha256, &params, "123456", &salt, &iv).unwrap();
+        assert_eq!(hkdf, derive_key("123456", &salt, &iv));
+        let blake2b = derive_key_with(Kdf::HkdfBlake2b, &params, "123456", &salt, &iv).unwrap();
+        assert_eq!(blake2b, derive_key_blake2b("123456", &salt, &iv));
+        assert_ne!(blake2b, hkdf);
+        let argon = derive_key_with(Kdf::Argon2id, &params, "123456", &salt, &iv).unwrap();
+        assert_ne!(argon, hkdf);
+        assert_eq!(
+            argon,
+            derive_key_with(Kdf::Argon2id, &params, "123456", &salt, &iv).unwrap()
+        );
+        let params = KdfParams {
+            memory: 0,
+            ..params
+        };
+        assert_eq!(
+            derive_key_with(Kdf::Argon2id, &params, "123456", &salt, &iv),
+            Err(Error::InvalidKdfParams)
+        );
+    }
+
+    #[test]
+    fn test_encrypt_with() {
+        let key = [3u8; 32];
+        let iv = [4u8; 16];
+        for cipher in [Cipher::Aes256Gcm, Cipher::ChaCha20Poly1305] {
+            let encrypted = encrypt_with(cipher, b"Hello World!", &key, &iv).unwrap();
+            let decrypted = decrypt_with(cipher, &encrypted, &key, &iv).unwrap();
+            assert_eq!(b"Hello World!".to_vec(), decrypted);
+        }
+        let aes = encrypt_with(Cipher::Aes256Gcm, b"Hello World!", &key, &iv).unwrap();
+        assert!(decrypt_with(Cipher::ChaCha20Poly1305, &aes, &key, &iv).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_into() {
+        let key = [3u8; 32];
+        let iv = [4u8; 16];
+        let chunks: Vec<Vec<u8>> = (0..20)
+            .map(|i| (0..i * 1000).map(|j| (j % 256) as u8).collect())
+            .collect();
+        for cipher in [Cipher::Aes256Gcm, Cipher::ChaCha20Poly1305] {
+            // Chunks shrink and grow, so the reused buffer has leftovers.
+            let mut buffer = vec![];
+            for chunk in chunks.iter().chain(chunks.iter().rev()) {
+                encrypt_into(cipher, chunk, &key, &iv, &mut buffer).unwrap();
+                assert_eq!(buffer, encrypt_with(cipher, chunk, &key, &iv).unwrap());
+                decrypt_in_place(cipher, &mut buffer, &key, &iv).unwrap();
+                assert_eq!(&buffer, chunk);
+            }
+        }
+        let mut aes = encrypt_with(Cipher::Aes256Gcm, b"Hello World!", &key, &iv).unwrap();
+        assert!(decrypt_in_place(Cipher::ChaCha20Poly1305, &mut aes, &key, &iv).is_err());
+    }
 }
@@ -8,7 +8,19 @@ use super::path::Path;
 use super::store::Error;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeBounds;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Maximum length, in bytes, of a node name.
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// Maximum length, in bytes, of a full path in the filesystem.
+pub const MAX_PATH_LENGTH: usize = 4096;
+
+/// Metadata keys the store keeps for the content of a file node, which only
+/// describe that node.
+const CONTENT_KEYS: [&str; 2] = ["hash", "mimetype"];
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Node {
@@ -17,8 +29,22 @@ pub struct Node {
     size: u64,
     is_file: bool,
     metadata: HashMap<String, String>,
+    /// Types of the metadata values set with `set_typed_metadata`. Values
+    /// are kept as strings in `metadata`; keys missing here are plain strings.
+    #[serde(default)]
+    metadata_types: HashMap<String, MetadataType>,
     data: Vec<u64>,
     tags: Vec<String>,
+    #[serde(default)]
+    created: u64,
+    #[serde(default)]
+    modified: u64,
+    #[serde(default)]
+    accessed: u64,
+    /// Target of the symbolic link the node was added as. Kept apart from
+    /// `metadata`, which callers can set freely.
+    #[serde(default)]
+    link: Option<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -27,6 +53,10 @@ pub struct Data {
     pub key: [u8; 32],
     pub iv: [u8; 16],
     pub salt: [u8; 16],
+    /// Length of the plaintext stored in this part. Zero for parts written
+    /// before it was recorded.
+    #[serde(default)]
+    pub len: u64,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -46,6 +76,133 @@ pub struct File {
     pub metadata: HashMap<String, String>,
     pub data: Vec<Data>,
     pub tags: Vec<String>,
+    /// Creation time, in seconds since the Unix epoch. Zero if unknown.
+    #[serde(default)]
+    pub created: u64,
+    /// Last modification time, in seconds since the Unix epoch. Zero if unknown.
+    #[serde(default)]
+    pub modified: u64,
+    /// Last time the content was read, in seconds since the Unix epoch. Zero
+    /// if never read since access tracking was enabled, see
+    /// `Store::with_access_tracking`.
+    #[serde(default)]
+    pub accessed: u64,
+    /// Target of the symbolic link the file was added as, see
+    /// `Store::with_follow_links`. None for other files.
+    #[serde(default)]
+    pub link: Option<String>,
+}
+
+impl File {
+    /// Returns the number of encrypted parts the file is split into.
+    pub fn part_count(&self) -> usize {
+        self.data.len()
+    }
+}
+
+/// Type of a metadata value, recorded next to its string form.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+enum MetadataType {
+    Int,
+    Float,
+    Bool,
+    Timestamp,
+}
+
+/// A typed metadata value. See `Store::metadata_set_typed`.
+///
+/// Values are ordered only against values of the same type, except for `Int`
+/// and `Float`, which are compared as numbers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetadataValue {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// Seconds since the Unix epoch.
+    Timestamp(u64),
+}
+
+impl MetadataValue {
+    fn kind(&self) -> Option<MetadataType> {
+        match self {
+            MetadataValue::Str(_) => None,
+            MetadataValue::Int(_) => Some(MetadataType::Int),
+            MetadataValue::Float(_) => Some(MetadataType::Float),
+            MetadataValue::Bool(_) => Some(MetadataType::Bool),
+            MetadataValue::Timestamp(_) => Some(MetadataType::Timestamp),
+        }
+    }
+
+    /// Reads back a value from its string form. Falls back to `Str` if the
+    /// string does not parse as the recorded type.
+    fn parse(kind: Option<MetadataType>, value: &str) -> MetadataValue {
+        let parsed = match kind {
+            None => None,
+            Some(MetadataType::Int) => value.parse().ok().map(MetadataValue::Int),
+            Some(MetadataType::Float) => value.parse().ok().map(MetadataValue::Float),
+            Some(MetadataType::Bool) => value.parse().ok().map(MetadataValue::Bool),
+            Some(MetadataType::Timestamp) => value.parse().ok().map(MetadataValue::Timestamp),
+        };
+        parsed.unwrap_or_else(|| MetadataValue::Str(value.into()))
+    }
+}
+
+impl std::fmt::Display for MetadataValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetadataValue::Str(value) => write!(f, "{value}"),
+            MetadataValue::Int(value) => write!(f, "{value}"),
+            MetadataValue::Float(value) => write!(f, "{value}"),
+            MetadataValue::Bool(value) => write!(f, "{value}"),
+            MetadataValue::Timestamp(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl PartialOrd for MetadataValue {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        use MetadataValue::*;
+        match (self, other) {
+            (Str(a), Str(b)) => a.partial_cmp(b),
+            (Int(a), Int(b)) => a.partial_cmp(b),
+            (Int(a), Float(b)) => (*a as f64).partial_cmp(b),
+            (Float(a), Int(b)) => a.partial_cmp(&(*b as f64)),
+            (Float(a), Float(b)) => a.partial_cmp(b),
+            (Bool(a), Bool(b)) => a.partial_cmp(b),
+            (Timestamp(a), Timestamp(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+/// Checks that a node name can be safely stored in the filesystem.
+/// Empty names, `.`, `..`, names containing `/` or control characters and
+/// names longer than `MAX_NAME_LENGTH` are rejected, as they would break path
+/// resolution.
+///
+/// # Arguments
+///
+/// * `name` - Name to be checked.
+pub fn validate_name(name: &str) -> Result<(), Error> {
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.len() > MAX_NAME_LENGTH
+        || name.contains('/')
+        || name.chars().any(char::is_control)
+    {
+        return Err(Error::InvalidName);
+    }
+    Ok(())
 }
 
 impl Filesystem {
@@ -75,7 +232,7 @@ impl Filesystem {
     }
 
     /// Returns a data id which is not in use on the filesystem.
-    fn next_data_id(&self) -> u64 {
+    pub fn next_data_id(&self) -> u64 {
         let len = self.data.len() as u64;
         match self
             .data
@@ -90,20 +247,38 @@ impl Filesystem {
         }
     }
 
-    /// Checks if a path exists in the filesystem
+    /// Checks if a path exists in the filesystem.
+    /// A path that goes through a file (like `/file/child`) does not exist.
     ///
     /// # Arguments
     ///
     /// * `path` - Path to be checked.
     pub fn exists(&self, path: &str) -> Result<bool, Error> {
+        Ok(self.lookup(path)?.is_some())
+    }
+
+    /// Finds the id of the node at a path, without creating anything.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the node.
+    ///
+    /// # Returns
+    ///
+    /// * The id of the node, or None if the path does not exist.
+    pub fn lookup(&self, path: &str) -> Result<Option<u64>, Error> {
         let path: String = path.into();
         let path = Path::new(&path).ok_or(Error::CannotParseError)?;
         let mut node_id: u64 = 0;
+        let mut is_file = false;
         let default: Vec<u64> = vec![];
         for component in path.components() {
             if component == "/" {
                 continue;
             }
+            if is_file {
+                return Ok(None);
+            }
             match self
                 .graph
                 .get(&node_id.to_string())
@@ -112,11 +287,14 @@ impl Filesystem {
                 .filter_map(|&id| self.nodes.iter().find(|node| node.id == id))
                 .find(|node| node.name == component)
             {
-                Some(node) => node_id = node.id,
-                None => return Ok(false),
+                Some(node) => {
+                    node_id = node.id;
+                    is_file = node.is_file;
+                }
+                None => return Ok(None),
             }
         }
-        Ok(true)
+        Ok(Some(node_id))
     }
 
     /// Creates a folder or folder tree.
@@ -132,6 +310,9 @@ impl Filesystem {
     pub fn mkdirp(&mut self, path: &str) -> Result<u64, Error> {
         let path: String = path.into();
         let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+        if path.path.len() > MAX_PATH_LENGTH {
+            return Err(Error::InvalidName);
+        }
         let mut node_id: u64 = 0;
         let default: Vec<u64> = vec![];
         for component in path.components() {
@@ -154,14 +335,20 @@ impl Filesystem {
                 }
 
                 None => {
+                    validate_name(&component)?;
                     let node = Node {
                         id: self.next_node_id(),
                         name: component,
                         size: 0,
                         is_file: false,
                         metadata: HashMap::new(),
+                        metadata_types: HashMap::new(),
                         data: vec![],
                         tags: vec![],
+                        created: now(),
+                        modified: now(),
+                        accessed: 0,
+                        link: None,
                     };
                     let parent_children = self.graph.get(&node_id.to_string()).unwrap_or(&default);
                     let mut new_entry = vec![node.id];
@@ -193,6 +380,9 @@ impl Filesystem {
         }
         let path: String = path.into();
         let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+        if path.path.len() > MAX_PATH_LENGTH {
+            return Err(Error::InvalidName);
+        }
         let default = vec![];
         let parent_id = self.mkdirp(&path.parent)?;
         let children = self.graph.get(&parent_id.to_string()).unwrap_or(&default);
@@ -203,14 +393,20 @@ impl Filesystem {
         {
             Some(node) => Ok(node.id),
             None => {
+                validate_name(&path.name)?;
                 let node = Node {
                     id: self.next_node_id(),
                     name: path.name,
                     size: 0,
                     is_file: true,
                     metadata: HashMap::new(),
+                    metadata_types: HashMap::new(),
                     data: vec![],
                     tags: vec![],
+                    created: now(),
+                    modified: now(),
+                    accessed: 0,
+                    link: None,
                 };
                 let mut new_children = vec![node.id];
                 new_children.extend(children);
@@ -241,6 +437,10 @@ impl Filesystem {
                 metadata: HashMap::new(),
                 data: vec![],
                 tags: vec![],
+                created: 0,
+                modified: 0,
+                accessed: 0,
+                link: None,
             });
         }
         let node = self
@@ -248,21 +448,57 @@ impl Filesystem {
             .iter()
             .find(|node| node.id == id)
             .ok_or(Error::FileDoesNotExistError)?;
-        let file = File {
+        let data = self
+            .data
+            .iter()
+            .filter(|data| node.data.contains(&data.id))
+            .map(|data| (data.id, data))
+            .collect();
+        Ok(self.file(node, &data))
+    }
+
+    /// Builds the File object of a node.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The node.
+    /// * `data` - Data objects by id. Must contain the node's data.
+    fn file(&self, node: &Node, data: &HashMap<u64, &Data>) -> File {
+        File {
             id: node.id,
             name: node.name.clone(),
             size: node.size,
             is_file: node.is_file,
             metadata: node.metadata.clone(),
             tags: node.tags.clone(),
-            data: self
+            created: node.created,
+            modified: node.modified,
+            accessed: node.accessed,
+            link: node.link.clone(),
+            data: node
                 .data
                 .iter()
-                .filter(|data| node.data.contains(&data.id))
-                .cloned()
+                .filter_map(|id| data.get(id))
+                .map(|&data| data.clone())
                 .collect(),
-        };
-        Ok(file)
+        }
+    }
+
+    /// Makes a file a symbolic link to `target`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the file.
+    /// * `target` - Path the link points to.
+    pub fn set_link(&mut self, id: u64, target: &str) -> Result<(), Error> {
+        let node = self
+            .nodes
+            .iter_mut()
+            .find(|node| node.id == id && node.is_file)
+            .ok_or(Error::InternalStructureError)?;
+        node.link = Some(target.into());
+        node.modified = now();
+        Ok(())
     }
 
     /// Sets the size of a file.
@@ -278,6 +514,38 @@ impl Filesystem {
             .find(|node| node.id == id && node.is_file)
             .ok_or(Error::InternalStructureError)?;
         node.size = size;
+        node.modified = now();
+        Ok(())
+    }
+
+    /// Records that the content of a file was just read.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the file that was read.
+    pub fn set_accessed(&mut self, id: u64) -> Result<(), Error> {
+        let node = self
+            .nodes
+            .iter_mut()
+            .find(|node| node.id == id && node.is_file)
+            .ok_or(Error::InternalStructureError)?;
+        node.accessed = now();
+        Ok(())
+    }
+
+    /// Sets the plaintext length of a data part.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the Data.
+    /// * `len` - Length of its plaintext.
+    pub fn set_data_len(&mut self, id: u64, len: u64) -> Result<(), Error> {
+        let data = self
+            .data
+            .iter_mut()
+            .find(|data| data.id == id)
+            .ok_or(Error::InternalStructureError)?;
+        data.len = len;
         Ok(())
     }
 
@@ -302,48 +570,197 @@ impl Filesystem {
         Ok(children)
     }
 
-    /// Moves a node
+    /// Lists part of the children of a folder, in the same order as `ls`.
+    /// Only the nodes in the page are read, unless hidden nodes are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the folder.
+    /// * `offset` - Number of children to skip.
+    /// * `limit` - Most children to return.
+    /// * `hidden` - Whether to list children whose names start with a dot.
+    ///
+    /// # Returns
+    ///
+    /// * The children in the page and the number of children of the folder
+    ///   that are listed.
+    pub fn ls_page(
+        &self,
+        id: u64,
+        offset: usize,
+        limit: usize,
+        hidden: bool,
+    ) -> (Vec<File>, usize) {
+        let mut children = self.graph.get(&id.to_string()).cloned().unwrap_or_default();
+        if !hidden {
+            children.retain(|&child| {
+                self.nodes
+                    .iter()
+                    .find(|node| node.id == child)
+                    .is_some_and(|node| !node.name.starts_with('.'))
+            });
+        }
+        let page = children
+            .iter()
+            .skip(offset)
+            .take(limit)
+            .filter_map(|&id| self.get(id).ok())
+            .collect();
+        (page, children.len())
+    }
+
+    /// Returns the ids of all nodes below a folder, at any depth.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the folder. A file has no descendants.
+    pub fn descendants(&self, id: u64) -> Vec<u64> {
+        let mut ids = vec![];
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if let Some(children) = self.graph.get(&id.to_string()) {
+                ids.extend(children);
+                stack.extend(children.iter().rev());
+            }
+        }
+        ids
+    }
+
+    /// Moves a node, giving it a new name.
     ///
     /// # Arguments
     ///
     /// * `id` - Id of the node to move;
     /// * `parent` - Id of the new parent;
-    pub fn mv(&mut self, id: u64, parent: u64) -> Result<(), Error> {
-        let node = self
-            .nodes
-            .iter()
-            .find(|node| node.id == id)
-            .ok_or(Error::FileDoesNotExistError)?;
-        let new_parent = self
-            .nodes
-            .iter()
-            .find(|node| node.id == parent)
-            .ok_or(Error::FolderDoesNotExistError)?;
-        let old_parent = self
+    /// * `name` - Name of the node in the new parent;
+    pub fn mv(&mut self, id: u64, parent: u64, name: &str) -> Result<(), Error> {
+        validate_name(name)?;
+        if id == 0 {
+            return Err(Error::CannotMoveError);
+        }
+        if !self.nodes.iter().any(|node| node.id == id) {
+            return Err(Error::FileDoesNotExistError);
+        }
+        if parent != 0 {
+            let new_parent = self
+                .nodes
+                .iter()
+                .find(|node| node.id == parent)
+                .ok_or(Error::FolderDoesNotExistError)?;
+            if new_parent.is_file {
+                return Err(Error::FolderDoesNotExistError);
+            }
+        }
+        // A folder cannot be moved into itself or into one of its descendants.
+        let mut ancestor = parent;
+        while ancestor != 0 {
+            if ancestor == id {
+                return Err(Error::CannotMoveError);
+            }
+            ancestor = self.parent(ancestor).ok_or(Error::InternalStructureError)?;
+        }
+        let default = vec![];
+        if self
             .graph
+            .get(&parent.to_string())
+            .unwrap_or(&default)
             .iter()
-            .find(|(_, value)| value.contains(&id))
-            .map(|(key, _)| key.clone())
-            .ok_or(Error::InternalStructureError)?;
-        let old_children = self
+            .filter(|&&child_id| child_id != id)
+            .filter_map(|&child_id| self.nodes.iter().find(|node| node.id == child_id))
+            .any(|node| node.name == name)
+        {
+            return Err(Error::FileAlreadyExistsError);
+        }
+        let old_parent = self.parent(id).ok_or(Error::InternalStructureError)?;
+        let old_children: Vec<u64> = self
             .graph
-            .get(&old_parent)
-            .ok_or(Error::InternalStructureError)?
+            .get(&old_parent.to_string())
+            .unwrap_or(&default)
             .iter()
-            .filter(|&id| id != &node.id)
+            .filter(|&&child_id| child_id != id)
             .copied()
             .collect();
-        self.graph.insert(old_parent.clone(), old_children);
-        let new_children = self
-            .graph
-            .get(&old_parent)
-            .ok_or(Error::InternalStructureError)?;
+        self.graph.insert(old_parent.to_string(), old_children);
         let mut children = vec![id];
-        children.extend(new_children);
-        self.graph.insert(new_parent.id.to_string(), children);
+        children.extend(self.graph.get(&parent.to_string()).unwrap_or(&default));
+        self.graph.insert(parent.to_string(), children);
+        let node = self
+            .nodes
+            .iter_mut()
+            .find(|node| node.id == id)
+            .ok_or(Error::InternalStructureError)?;
+        node.name = name.into();
+        node.modified = now();
         Ok(())
     }
 
+    /// Creates a new file node that shares the data of an existing file, so
+    /// the same content appears in two places without being duplicated. The
+    /// new node gets a copy of the metadata, but not the tags. Shared data is
+    /// only removed when no node references it anymore.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the file to link;
+    /// * `parent` - Id of the folder where the link is created;
+    /// * `name` - Name of the link;
+    ///
+    /// # Returns
+    ///
+    /// * The id of the new node.
+    pub fn link(&mut self, id: u64, parent: u64, name: &str) -> Result<u64, Error> {
+        validate_name(name)?;
+        let source = self
+            .nodes
+            .iter()
+            .find(|node| node.id == id && node.is_file)
+            .ok_or(Error::FileDoesNotExistError)?;
+        if parent != 0
+            && !self
+                .nodes
+                .iter()
+                .any(|node| node.id == parent && !node.is_file)
+        {
+            return Err(Error::FolderDoesNotExistError);
+        }
+        let default = vec![];
+        let children = self.graph.get(&parent.to_string()).unwrap_or(&default);
+        if self
+            .nodes
+            .iter()
+            .any(|node| node.name == name && children.contains(&node.id))
+        {
+            return Err(Error::FileAlreadyExistsError);
+        }
+        let node = Node {
+            id: self.next_node_id(),
+            name: name.into(),
+            tags: vec![],
+            created: now(),
+            modified: now(),
+            accessed: 0,
+            ..source.clone()
+        };
+        let mut new_children = vec![node.id];
+        new_children.extend(children);
+        self.graph.insert(parent.to_string(), new_children);
+        let node_id = node.id;
+        self.nodes.push(node);
+        Ok(node_id)
+    }
+
+    /// Returns the id of a node's parent folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the node.
+    fn parent(&self, id: u64) -> Option<u64> {
+        self.graph
+            .iter()
+            .find(|(_, children)| children.contains(&id))
+            .and_then(|(key, _)| key.parse::<u64>().ok())
+    }
+
     /// Removes entry. If it is a folder, removes the tree.
     ///
     /// # Arguments
@@ -402,8 +819,9 @@ impl Filesystem {
         let keep: Vec<&u64> = self.graph.values().flatten().collect();
         // Turns ids into Node objects.
         self.nodes.retain(|node| keep.contains(&&node.id));
-        // Gets the ids of all referenced data.
-        let data_keep: Vec<u64> = self
+        // Gets the ids of all data referenced by the surviving nodes, so data
+        // shared between nodes is kept while any of them is still alive.
+        let data_keep: HashSet<u64> = self
             .nodes
             .iter()
             .flat_map(|node| node.data.clone())
@@ -421,6 +839,15 @@ impl Filesystem {
         Ok(removed_data)
     }
 
+    /// Checks if a Data object is still in the filesystem.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the Data object.
+    pub fn has_data(&self, id: u64) -> bool {
+        self.data.iter().any(|data| data.id == id)
+    }
+
     /// Appends Data to a file
     ///
     /// # Arguments
@@ -449,16 +876,51 @@ impl Filesystem {
         };
 
         node.data.push(data.id);
+        node.link = None;
+        node.modified = now();
         self.data.push(data);
         self.get(id)
     }
 
+    /// Replaces a Data object in every file that references it, so that files
+    /// sharing it keep sharing the replacement.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the Data object to replace.
+    /// * `data` - New Data object. Its id is kept and must not be in use.
+    pub fn replace_data(&mut self, id: u64, data: &Data) -> Result<(), Error> {
+        if !self.has_data(id) || self.has_data(data.id) {
+            return Err(Error::InternalStructureError);
+        }
+
+        for node in self.nodes.iter_mut() {
+            for data_id in node.data.iter_mut().filter(|data_id| **data_id == id) {
+                *data_id = data.id;
+            }
+        }
+        self.data.retain(|data| data.id != id);
+        self.data.push(data.clone());
+        Ok(())
+    }
+
     /// Truncates file.
     ///
     /// # Arguments
     ///
     /// * `id` - Id of the file to truncate
-    pub fn truncate(&mut self, id: u64) -> Result<(), Error> {
+    ///
+    /// # Returns
+    ///
+    /// * A vector of Data objects that were removed. Data still referenced by
+    ///   other nodes is kept and not returned.
+    pub fn truncate(&mut self, id: u64) -> Result<Vec<Data>, Error> {
+        let shared: Vec<u64> = self
+            .nodes
+            .iter()
+            .filter(|node| node.id != id)
+            .flat_map(|node| node.data.clone())
+            .collect();
         let node = self
             .nodes
             .iter_mut()
@@ -469,9 +931,110 @@ impl Filesystem {
             return Err(Error::FileDoesNotExistError);
         }
 
-        self.data.retain(|data| !node.data.contains(&data.id));
+        let removed = |data: &Data| node.data.contains(&data.id) && !shared.contains(&data.id);
+        let removed_data: Vec<Data> = self
+            .data
+            .iter()
+            .filter(|data| removed(data))
+            .cloned()
+            .collect();
+        self.data.retain(|data| !removed(data));
+        node.data.clear();
+        node.link = None;
+        node.size = 0;
+        node.modified = now();
+        Ok(removed_data)
+    }
+
+    /// Empties a file like `truncate`, but keeps its Data objects, so that
+    /// their ids are not reused while new content is written to it. They are
+    /// then removed by `drop_data`, or given back by `restore_data`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the file.
+    ///
+    /// # Returns
+    ///
+    /// * The file as it was.
+    pub fn detach_data(&mut self, id: u64) -> Result<File, Error> {
+        let previous = self.get(id)?;
+        let node = self
+            .nodes
+            .iter_mut()
+            .find(|node| node.id == id && node.is_file)
+            .ok_or(Error::FileDoesNotExistError)?;
+
         node.data.clear();
-        Ok(())
+        node.link = None;
+        node.size = 0;
+        node.modified = now();
+        Ok(previous)
+    }
+
+    /// Removes the Data objects a file had before `detach_data` that no file
+    /// uses anymore.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous` - The file as returned by `detach_data`.
+    ///
+    /// # Returns
+    ///
+    /// * The removed Data objects.
+    pub fn drop_data(&mut self, previous: &File) -> Vec<Data> {
+        let used: HashSet<u64> = self
+            .nodes
+            .iter()
+            .flat_map(|node| node.data.iter().copied())
+            .collect();
+        let dropped = |data: &Data| {
+            !used.contains(&data.id) && previous.data.iter().any(|old| old.id == data.id)
+        };
+        let (dropped, kept): (Vec<Data>, Vec<Data>) = self.data.drain(..).partition(dropped);
+        self.data = kept;
+        dropped
+    }
+
+    /// Gives a file the content it had before `detach_data` or `append`,
+    /// removing what was written to it since. Its tags and metadata are left
+    /// as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous` - The file as it was before the write.
+    ///
+    /// # Returns
+    ///
+    /// * The Data objects written since, that no other file uses.
+    pub fn restore_data(&mut self, previous: &File) -> Result<Vec<Data>, Error> {
+        let node = self
+            .nodes
+            .iter_mut()
+            .find(|node| node.id == previous.id)
+            .ok_or(Error::FileDoesNotExistError)?;
+
+        let kept: HashSet<u64> = previous.data.iter().map(|data| data.id).collect();
+        let written: HashSet<u64> = node
+            .data
+            .iter()
+            .copied()
+            .filter(|id| !kept.contains(id))
+            .collect();
+        node.data = previous.data.iter().map(|data| data.id).collect();
+        node.link = previous.link.clone();
+        node.size = previous.size;
+        node.modified = previous.modified;
+
+        let used: HashSet<u64> = self
+            .nodes
+            .iter()
+            .flat_map(|node| node.data.iter().copied())
+            .collect();
+        let dropped = |data: &Data| written.contains(&data.id) && !used.contains(&data.id);
+        let (dropped, kept): (Vec<Data>, Vec<Data>) = self.data.drain(..).partition(dropped);
+        self.data = kept;
+        Ok(dropped)
     }
 
     /// Sorts nodes and data id.
@@ -494,32 +1057,81 @@ impl Filesystem {
             .find(|node| node.id == id)
             .ok_or(Error::FileDoesNotExistError)?;
         node.metadata.insert(key.into(), value.into());
+        node.metadata_types.remove(key);
+        node.modified = now();
         Ok(())
     }
 
-    /// Gets file/folder metadata
+    /// Sets file/folder metadata, remembering the type of the value.
     ///
     /// # Arguments
     ///
     /// * `id` - id of the affected node;
     /// * `key` - metadata key;
-    ///
-    /// # Returns
-    ///
-    /// * The value associated with such key.
-    pub fn get_metadata(&mut self, id: u64, key: &str) -> Result<String, Error> {
+    /// * `value` - metadata value;
+    pub fn set_typed_metadata(
+        &mut self,
+        id: u64,
+        key: &str,
+        value: &MetadataValue,
+    ) -> Result<(), Error> {
         let node = self
             .nodes
-            .iter()
+            .iter_mut()
             .find(|node| node.id == id)
             .ok_or(Error::FileDoesNotExistError)?;
-        let key: String = key.into();
-        match node.metadata.get(&key) {
+        node.metadata.insert(key.into(), value.to_string());
+        match value.kind() {
+            Some(kind) => node.metadata_types.insert(key.into(), kind),
+            None => node.metadata_types.remove(key),
+        };
+        node.modified = now();
+        Ok(())
+    }
+
+    /// Gets file/folder metadata
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - id of the affected node;
+    /// * `key` - metadata key;
+    ///
+    /// # Returns
+    ///
+    /// * The value associated with such key.
+    pub fn get_metadata(&mut self, id: u64, key: &str) -> Result<String, Error> {
+        let node = self
+            .nodes
+            .iter()
+            .find(|node| node.id == id)
+            .ok_or(Error::FileDoesNotExistError)?;
+        let key: String = key.into();
+        match node.metadata.get(&key) {
             Some(value) => Ok(value.clone()),
             None => Err(Error::NoSuchMetadataKey),
         }
     }
 
+    /// Gets file/folder metadata as the type it was set with. Values set with
+    /// `set_metadata` are returned as `MetadataValue::Str`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - id of the affected node;
+    /// * `key` - metadata key;
+    pub fn get_typed_metadata(&self, id: u64, key: &str) -> Result<MetadataValue, Error> {
+        let node = self
+            .nodes
+            .iter()
+            .find(|node| node.id == id)
+            .ok_or(Error::FileDoesNotExistError)?;
+        let value = node.metadata.get(key).ok_or(Error::NoSuchMetadataKey)?;
+        Ok(MetadataValue::parse(
+            node.metadata_types.get(key).copied(),
+            value,
+        ))
+    }
+
     /// Removes a key from the node's metadata
     ///
     /// # Arguments
@@ -532,12 +1144,89 @@ impl Filesystem {
             .iter_mut()
             .find(|node| node.id == id)
             .ok_or(Error::FileDoesNotExistError)?;
+        node.metadata_types.remove(key);
         match node.metadata.remove(key) {
             Some(_) => Ok(()),
             None => Err(Error::NoSuchMetadataKey),
         }
     }
 
+    /// Renames a key in the node's metadata
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the affected node;
+    /// * `old_key` - Current name of the key;
+    /// * `new_key` - New name of the key;
+    /// * `overwrite` - Whether to replace the value of an existing `new_key`;
+    pub fn rename_metadata(
+        &mut self,
+        id: u64,
+        old_key: &str,
+        new_key: &str,
+        overwrite: bool,
+    ) -> Result<(), Error> {
+        let node = self
+            .nodes
+            .iter_mut()
+            .find(|node| node.id == id)
+            .ok_or(Error::FileDoesNotExistError)?;
+        if !node.metadata.contains_key(old_key) {
+            return Err(Error::NoSuchMetadataKey);
+        }
+        if old_key == new_key {
+            return Ok(());
+        }
+        if !overwrite && node.metadata.contains_key(new_key) {
+            return Err(Error::MetadataKeyAlreadyExists);
+        }
+        let value = node
+            .metadata
+            .remove(old_key)
+            .ok_or(Error::NoSuchMetadataKey)?;
+        node.metadata.insert(new_key.into(), value);
+        match node.metadata_types.remove(old_key) {
+            Some(kind) => node.metadata_types.insert(new_key.into(), kind),
+            None => node.metadata_types.remove(new_key),
+        };
+        node.modified = now();
+        Ok(())
+    }
+
+    /// Copies all metadata from one node into another, replacing the values
+    /// of keys present in both. The keys describing the content, `hash` and
+    /// `mimetype`, are not copied.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Id of the node to copy from;
+    /// * `to` - Id of the node to copy to;
+    pub fn copy_metadata(&mut self, from: u64, to: u64) -> Result<(), Error> {
+        let source = self
+            .nodes
+            .iter()
+            .find(|node| node.id == from)
+            .ok_or(Error::FileDoesNotExistError)?;
+        let mut metadata = source.metadata.clone();
+        let mut types = source.metadata_types.clone();
+        for key in CONTENT_KEYS {
+            metadata.remove(key);
+            types.remove(key);
+        }
+        let node = self
+            .nodes
+            .iter_mut()
+            .find(|node| node.id == to)
+            .ok_or(Error::FileDoesNotExistError)?;
+        for key in metadata.keys() {
+            node.metadata_types.remove(key);
+        }
+        node.metadata.extend(metadata);
+        node.metadata_types.extend(types);
+        node.modified = now();
+        Ok(())
+    }
+
     /// Returns a node's path
     ///
     /// # Arguments
@@ -548,27 +1237,45 @@ impl Filesystem {
     ///
     /// * The node's path
     pub fn path(&self, id: u64) -> Result<String, Error> {
+        self.path_with(id, &self.index())
+    }
+
+    /// Indexes every node by id, together with the id of its parent.
+    fn index(&self) -> HashMap<u64, (&Node, u64)> {
+        let parents: HashMap<u64, u64> = self
+            .graph
+            .iter()
+            .filter_map(|(parent, children)| Some((parent.parse::<u64>().ok()?, children)))
+            .flat_map(|(parent, children)| children.iter().map(move |&child| (child, parent)))
+            .collect();
+        self.nodes
+            .iter()
+            .filter_map(|node| Some((node.id, (node, *parents.get(&node.id)?))))
+            .collect()
+    }
+
+    /// Returns a node's path. Nodes and parents are looked up in an index
+    /// built by `index`, so that computing the paths of many nodes is not
+    /// quadratic.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the node.
+    /// * `index` - Index returned by `index`.
+    ///
+    /// # Returns
+    ///
+    /// * The node's path
+    fn path_with(&self, id: u64, index: &HashMap<u64, (&Node, u64)>) -> Result<String, Error> {
         let mut node_id = id;
-        let mut path = vec![id];
+        let mut path = vec![];
         while node_id != 0 {
-            let (key, _) = self
-                .graph
-                .iter()
-                .find(|(_, v)| v.contains(&node_id))
-                .ok_or(Error::FileDoesNotExistError)?;
-            node_id = key
-                .parse::<u64>()
-                .map_err(|_| Error::InternalStructureError)?;
-            path.push(node_id);
+            let (node, parent) = index.get(&node_id).ok_or(Error::FileDoesNotExistError)?;
+            path.push(node.name.as_str());
+            node_id = *parent;
         }
-        let path: Vec<String> = path
-            .iter()
-            .rev()
-            .filter_map(|&id| self.nodes.iter().find(|node| node.id == id))
-            .map(|node| node.name.clone())
-            .collect();
-        let path: String = "/".to_string() + &path.join("/");
-        Ok(path)
+        path.reverse();
+        Ok("/".to_string() + &path.join("/"))
     }
 
     /// Lists all nodes in the store.
@@ -578,18 +1285,60 @@ impl Filesystem {
     /// * A list of File objects for all nodes in the store. In this case, the
     /// name is the full path of the element.
     pub fn ls_all(&self) -> Result<Vec<File>, Error> {
+        let index = self.index();
+        let data = self.data.iter().map(|data| (data.id, data)).collect();
         let nodes = self
             .nodes
             .iter()
-            .filter_map(|node| self.get(node.id).ok())
-            .map(|file| File {
-                name: self.path(file.id).unwrap(),
-                ..file
+            .filter_map(|node| {
+                let name = self.path_with(node.id, &index).ok()?;
+                Some(File {
+                    name,
+                    ..self.file(node, &data)
+                })
             })
             .collect();
         Ok(nodes)
     }
 
+    /// Walks the filesystem depth-first, yielding every node as it is
+    /// reached instead of collecting them all first.
+    ///
+    /// # Returns
+    ///
+    /// * An iterator of File objects for all nodes in the store. As in
+    ///   `ls_all`, the name is the full path of the element.
+    pub fn iter(&self) -> Walk<'_> {
+        let mut walk = Walk {
+            fs: self,
+            nodes: self.nodes.iter().map(|node| (node.id, node)).collect(),
+            data: self.data.iter().map(|data| (data.id, data)).collect(),
+            stack: vec![],
+        };
+        walk.push_children(0, "/");
+        walk
+    }
+
+    /// Returns the number of file nodes.
+    pub fn file_count(&self) -> usize {
+        self.nodes.iter().filter(|node| node.is_file).count()
+    }
+
+    /// Returns the number of folder nodes, not counting the root.
+    pub fn folder_count(&self) -> usize {
+        self.nodes.iter().filter(|node| !node.is_file).count()
+    }
+
+    /// Returns the sum of the sizes of all files.
+    pub fn total_size(&self) -> u64 {
+        self.nodes.iter().map(|node| node.size).sum()
+    }
+
+    /// Returns the number of Data objects, that is, of encrypted parts.
+    pub fn data_count(&self) -> usize {
+        self.data.len()
+    }
+
     /// Adds a tag to a file
     ///
     /// # Arguments
@@ -641,6 +1390,35 @@ impl Filesystem {
         Ok(())
     }
 
+    /// Renames a tag on every node that has it. Nodes that already have the
+    /// new tag just lose the old one.
+    ///
+    /// # Arguments
+    ///
+    /// * `old` - Tag to rename.
+    /// * `new` - New name of the tag.
+    ///
+    /// # Returns
+    ///
+    /// * The number of nodes that had the tag.
+    pub fn rename_tag(&mut self, old: &str, new: &str) -> usize {
+        let mut count = 0;
+        for node in self.nodes.iter_mut() {
+            if !node.tags.iter().any(|tag| tag == old) {
+                continue;
+            }
+            count += 1;
+            if node.tags.iter().any(|tag| tag == new) {
+                node.tags.retain(|tag| tag != old);
+            } else {
+                for tag in node.tags.iter_mut().filter(|tag| *tag == old) {
+                    *tag = new.into();
+                }
+            }
+        }
+        count
+    }
+
     /// List all tags in the filesystem.
     ///
     /// # Returns
@@ -654,6 +1432,19 @@ impl Filesystem {
             .collect()
     }
 
+    /// Counts how many nodes have each tag.
+    ///
+    /// # Returns
+    ///
+    /// * A map from each tag in the filesystem to the number of its nodes.
+    pub fn count_tags(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for tag in self.nodes.iter().flat_map(|node| &node.tags) {
+            *counts.entry(tag.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
     /// Lists files that contains or not a certaing tag. Accepts a list of tags
     /// returns a list of File objects for all nodes matching.
     ///
@@ -669,19 +1460,284 @@ impl Filesystem {
         let (include, exclude): (Vec<String>, Vec<String>) =
             tags.iter().cloned().partition(|tag| !tag.starts_with('!'));
         let exclude: Vec<String> = exclude.iter().map(|tag| tag.replace('!', "")).collect();
+        let index = self.index();
+        let data = self.data.iter().map(|data| (data.id, data)).collect();
         self.nodes
             .iter()
             .filter(|node| {
                 node.tags.iter().filter(|tag| include.contains(tag)).count() == include.len()
             })
             .filter(|node| node.tags.iter().filter(|tag| exclude.contains(tag)).count() == 0)
-            .filter_map(|node| self.get(node.id).ok())
-            .map(|file| File {
-                name: self.path(file.id).unwrap(),
-                ..file
+            .filter_map(|node| {
+                let name = self.path_with(node.id, &index).ok()?;
+                Some(File {
+                    name,
+                    ..self.file(node, &data)
+                })
+            })
+            .collect()
+    }
+
+    /// Lists files whose metadata value for a key lies in a range. Values are
+    /// compared as described in `MetadataValue`, so a numeric range only
+    /// matches numeric values.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Metadata key to look at;
+    /// * `range` - Range the value must lie in.
+    ///
+    /// # Returns
+    ///
+    /// * A list of files matching, named by their paths.
+    pub fn search_metadata<R: RangeBounds<MetadataValue>>(&self, key: &str, range: R) -> Vec<File> {
+        let index = self.index();
+        let data = self.data.iter().map(|data| (data.id, data)).collect();
+        self.nodes
+            .iter()
+            .filter(|node| {
+                node.metadata.get(key).is_some_and(|value| {
+                    let kind = node.metadata_types.get(key).copied();
+                    range.contains(&MetadataValue::parse(kind, value))
+                })
+            })
+            .filter_map(|node| {
+                let name = self.path_with(node.id, &index).ok()?;
+                Some(File {
+                    name,
+                    ..self.file(node, &data)
+                })
+            })
+            .collect()
+    }
+
+    /// Lists files and folders whose name contains a text, ignoring case.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - Text to look for in the names.
+    ///
+    /// # Returns
+    ///
+    /// * A list of files matching, named by their paths.
+    pub fn search_name(&self, text: &str) -> Vec<File> {
+        let text = text.to_lowercase();
+        let index = self.index();
+        let data = self.data.iter().map(|data| (data.id, data)).collect();
+        self.nodes
+            .iter()
+            .filter(|node| node.name.to_lowercase().contains(&text))
+            .filter_map(|node| {
+                let name = self.path_with(node.id, &index).ok()?;
+                Some(File {
+                    name,
+                    ..self.file(node, &data)
+                })
             })
             .collect()
     }
+
+    /// Builds an index of the tags and names of the nodes, for
+    /// `search_tag_indexed` and `search_name_indexed`.
+    pub fn search_index(&self) -> SearchIndex {
+        let mut index = SearchIndex::default();
+        for (position, node) in self.nodes.iter().enumerate() {
+            index.nodes.insert(node.id, position);
+            for tag in &node.tags {
+                index.tags.entry(tag.clone()).or_default().insert(node.id);
+            }
+            for trigram in trigrams(&node.name) {
+                index.trigrams.entry(trigram).or_default().insert(node.id);
+            }
+        }
+        for (position, data) in self.data.iter().enumerate() {
+            index.data.insert(data.id, position);
+        }
+        for (parent, children) in &self.graph {
+            let Ok(parent) = parent.parse::<u64>() else {
+                continue;
+            };
+            for &child in children {
+                index.parents.insert(child, parent);
+            }
+        }
+        index
+    }
+
+    /// Same as `search_tag`, but only looks at the nodes that `index` has
+    /// for the tags. Searches for files without tags alone look at every
+    /// node.
+    ///
+    /// # Arguments
+    ///
+    /// * `tags` - List of tags to search for. If the tag starts with !, search
+    ///   for files not containing that tag.
+    /// * `index` - Index built by `search_index`.
+    pub fn search_tag_indexed(&self, tags: Vec<String>, index: &SearchIndex) -> Vec<File> {
+        let (include, exclude): (Vec<String>, Vec<String>) =
+            tags.iter().cloned().partition(|tag| !tag.starts_with('!'));
+        if include.is_empty() {
+            return self.search_tag(tags);
+        }
+        let exclude: Vec<String> = exclude.iter().map(|tag| tag.replace('!', "")).collect();
+
+        let sets = include.iter().map(|tag| index.tags.get(tag));
+        self.indexed_files(index, sets, |node| {
+            include.iter().all(|tag| node.tags.contains(tag))
+                && !node.tags.iter().any(|tag| exclude.contains(tag))
+        })
+    }
+
+    /// Same as `search_name`, but only looks at the nodes that `index` has
+    /// for the trigrams of `text`. Texts shorter than three characters have
+    /// none, so every node is looked at.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - Text to look for in the names.
+    /// * `index` - Index built by `search_index`.
+    pub fn search_name_indexed(&self, text: &str, index: &SearchIndex) -> Vec<File> {
+        let trigrams = trigrams(text);
+        if trigrams.is_empty() {
+            return self.search_name(text);
+        }
+
+        let text = text.to_lowercase();
+        let sets = trigrams.iter().map(|trigram| index.trigrams.get(trigram));
+        self.indexed_files(index, sets, |node| node.name.to_lowercase().contains(&text))
+    }
+
+    /// Returns the nodes in all of `sets` that `matches` accepts, in the
+    /// order of `nodes`. Nodes are checked again because the filesystem may
+    /// have changed since `index` was built; removed nodes are skipped.
+    fn indexed_files<'a, I, F>(&self, index: &SearchIndex, sets: I, matches: F) -> Vec<File>
+    where
+        I: Iterator<Item = Option<&'a HashSet<u64>>>,
+        F: Fn(&Node) -> bool,
+    {
+        let mut ids: Option<HashSet<u64>> = None;
+        for set in sets {
+            let Some(set) = set else {
+                return vec![];
+            };
+            ids = Some(match ids {
+                Some(ids) => ids.intersection(set).copied().collect(),
+                None => set.clone(),
+            });
+        }
+        let mut ids: Vec<u64> = ids.unwrap_or_default().into_iter().collect();
+        ids.sort_by_key(|id| index.nodes.get(id));
+
+        ids.into_iter()
+            .filter_map(|id| self.indexed_node(id, index))
+            .filter(|node| matches(node))
+            .filter_map(|node| self.indexed_file(node, index))
+            .collect()
+    }
+
+    /// Finds a node by its position in `index`, or by looking at every node
+    /// if it moved since.
+    fn indexed_node(&self, id: u64, index: &SearchIndex) -> Option<&Node> {
+        index
+            .nodes
+            .get(&id)
+            .and_then(|&position| self.nodes.get(position))
+            .filter(|node| node.id == id)
+            .or_else(|| self.nodes.iter().find(|node| node.id == id))
+    }
+
+    /// Same as `file`, but named by its path, with the path and parts looked
+    /// up in `index`.
+    fn indexed_file(&self, node: &Node, index: &SearchIndex) -> Option<File> {
+        let mut path = vec![];
+        let mut id = node.id;
+        while id != 0 {
+            path.push(self.indexed_node(id, index)?.name.as_str());
+            id = *index.parents.get(&id)?;
+        }
+        path.reverse();
+
+        let data = node
+            .data
+            .iter()
+            .filter_map(|&id| {
+                index
+                    .data
+                    .get(&id)
+                    .and_then(|&position| self.data.get(position))
+                    .filter(|data| data.id == id)
+                    .or_else(|| self.data.iter().find(|data| data.id == id))
+            })
+            .map(|data| (data.id, data))
+            .collect();
+        Some(File {
+            name: "/".to_string() + &path.join("/"),
+            ..self.file(node, &data)
+        })
+    }
+}
+
+/// Splits a name into the lowercase sequences of three characters in it.
+fn trigrams(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.to_lowercase().chars().collect();
+    chars
+        .windows(3)
+        .map(|window| window.iter().collect())
+        .collect()
+}
+
+/// Nodes of a Filesystem by tag and by the trigrams of their names, so that
+/// searches do not look at every node. It is kept in memory only, and
+/// rebuilt with `Filesystem::search_index` when the filesystem changes.
+#[derive(Clone, Debug, Default)]
+pub struct SearchIndex {
+    tags: HashMap<String, HashSet<u64>>,
+    trigrams: HashMap<String, HashSet<u64>>,
+    // Positions of the nodes and parts in the filesystem when indexed.
+    nodes: HashMap<u64, usize>,
+    data: HashMap<u64, usize>,
+    parents: HashMap<u64, u64>,
+}
+
+/// Depth-first iterator over the nodes of a Filesystem. See
+/// `Filesystem::iter`.
+pub struct Walk<'a> {
+    fs: &'a Filesystem,
+    nodes: HashMap<u64, &'a Node>,
+    data: HashMap<u64, &'a Data>,
+    stack: Vec<(u64, String)>,
+}
+
+impl Walk<'_> {
+    /// Pushes the children of a node in reverse order, so that popping them
+    /// visits them in order.
+    fn push_children(&mut self, id: u64, path: &str) {
+        let parent = path.trim_end_matches('/');
+        let Some(children) = self.fs.graph.get(&id.to_string()) else {
+            return;
+        };
+        for id in children.iter().rev() {
+            if let Some(node) = self.nodes.get(id) {
+                self.stack.push((*id, format!("{parent}/{}", node.name)));
+            }
+        }
+    }
+}
+
+impl Iterator for Walk<'_> {
+    type Item = File;
+
+    fn next(&mut self) -> Option<File> {
+        let (id, path) = self.stack.pop()?;
+        let node = self.nodes[&id];
+        if !node.is_file {
+            self.push_children(id, &path);
+        }
+        Some(File {
+            name: path,
+            ..self.fs.file(node, &self.data)
+        })
+    }
 }
 
 #[cfg(test)]
@@ -699,8 +1755,13 @@ mod tests {
             size: 0,
             is_file: false,
             metadata: HashMap::new(),
+            metadata_types: HashMap::new(),
             data: vec![],
             tags: vec![],
+            created: 0,
+            modified: 0,
+            accessed: 0,
+            link: None,
         });
         assert_eq!(fs.next_node_id(), 2);
         fs.nodes.push(Node {
@@ -709,8 +1770,13 @@ mod tests {
             size: 0,
             is_file: false,
             metadata: HashMap::new(),
+            metadata_types: HashMap::new(),
             data: vec![],
             tags: vec![],
+            created: 0,
+            modified: 0,
+            accessed: 0,
+            link: None,
         });
         assert_eq!(fs.next_node_id(), 3);
         fs.nodes.push(Node {
@@ -719,8 +1785,13 @@ mod tests {
             size: 0,
             is_file: false,
             metadata: HashMap::new(),
+            metadata_types: HashMap::new(),
             data: vec![],
             tags: vec![],
+            created: 0,
+            modified: 0,
+            accessed: 0,
+            link: None,
         });
         assert_eq!(fs.next_node_id(), 3);
     }
@@ -736,6 +1807,7 @@ mod tests {
             key,
             iv: rand,
             salt: rand,
+            len: 0,
         });
         assert_eq!(fs.next_data_id(), 2);
         fs.data.push(Data {
@@ -743,6 +1815,7 @@ mod tests {
             key,
             iv: rand,
             salt: rand,
+            len: 0,
         });
         assert_eq!(fs.next_data_id(), 3);
         fs.data.push(Data {
@@ -750,6 +1823,7 @@ mod tests {
             key,
             iv: rand,
             salt: rand,
+            len: 0,
         });
         assert_eq!(fs.next_data_id(), 3);
     }
@@ -763,8 +1837,13 @@ mod tests {
             size: 0,
             is_file: false,
             metadata: HashMap::new(),
+            metadata_types: HashMap::new(),
             data: vec![],
             tags: vec![],
+            created: 0,
+            modified: 0,
+            accessed: 0,
+            link: None,
         });
         fs.nodes.push(Node {
             id: 2,
@@ -772,8 +1851,13 @@ mod tests {
             size: 0,
             is_file: false,
             metadata: HashMap::new(),
+            metadata_types: HashMap::new(),
             data: vec![],
             tags: vec![],
+            created: 0,
+            modified: 0,
+            accessed: 0,
+            link: None,
         });
         fs.nodes.push(Node {
             id: 3,
@@ -781,8 +1865,13 @@ mod tests {
             size: 0,
             is_file: false,
             metadata: HashMap::new(),
+            metadata_types: HashMap::new(),
             data: vec![],
             tags: vec![],
+            created: 0,
+            modified: 0,
+            accessed: 0,
+            link: None,
         });
         fs.graph.insert("0".into(), vec![1]);
         fs.graph.insert("1".into(), vec![2]);
@@ -791,6 +1880,39 @@ mod tests {
         assert_eq!(fs.exists("/f1/f3/f2").unwrap(), false);
     }
 
+    #[test]
+    fn test_filesystem_exists_through_file() {
+        let mut fs = Filesystem::new();
+        let id = fs.touch("/a").unwrap();
+        fs.nodes.push(Node {
+            id: 2,
+            name: "b".into(),
+            size: 0,
+            is_file: true,
+            metadata: HashMap::new(),
+            metadata_types: HashMap::new(),
+            data: vec![],
+            tags: vec![],
+            created: 0,
+            modified: 0,
+            accessed: 0,
+            link: None,
+        });
+        fs.graph.insert(id.to_string(), vec![2]);
+        assert!(fs.exists("/a").unwrap());
+        assert!(!fs.exists("/a/b").unwrap());
+        assert!(!fs.exists("/a/b/c").unwrap());
+    }
+
+    #[test]
+    fn test_filesystem_lookup() {
+        let mut fs = Filesystem::new();
+        let id = fs.touch("/a/b").unwrap();
+        assert_eq!(fs.lookup("/").unwrap(), Some(0));
+        assert_eq!(fs.lookup("/a/b").unwrap(), Some(id));
+        assert_eq!(fs.lookup("/a/c").unwrap(), None);
+    }
+
     #[test]
     fn test_filesystem_mkdirp() {
         let mut fs = Filesystem::new();
@@ -804,8 +1926,13 @@ mod tests {
             size: 0,
             is_file: true,
             metadata: HashMap::new(),
+            metadata_types: HashMap::new(),
             data: vec![],
             tags: vec![],
+            created: 0,
+            modified: 0,
+            accessed: 0,
+            link: None,
         });
         fs.graph.insert("0".into(), vec![1, 10]);
         assert_eq!(fs.mkdirp("/f5/f6"), Err(Error::CannotCreateDirectoryError));
@@ -824,6 +1951,29 @@ mod tests {
         assert_eq!(node.is_file, true)
     }
 
+    #[test]
+    fn test_filesystem_validate_name() {
+        assert!(validate_name("b").is_ok());
+        assert!(validate_name("file.txt").is_ok());
+        assert_eq!(validate_name(""), Err(Error::InvalidName));
+        assert_eq!(validate_name("."), Err(Error::InvalidName));
+        assert_eq!(validate_name(".."), Err(Error::InvalidName));
+        assert_eq!(validate_name("b/c"), Err(Error::InvalidName));
+        assert_eq!(validate_name("b\nc"), Err(Error::InvalidName));
+        let long = "a".repeat(MAX_NAME_LENGTH + 1);
+        assert_eq!(validate_name(&long), Err(Error::InvalidName));
+    }
+
+    #[test]
+    fn test_filesystem_touch_invalid_name() {
+        let mut fs = Filesystem::new();
+        let long = format!("/a/{}", "b".repeat(MAX_NAME_LENGTH + 1));
+        assert_eq!(fs.touch(&long), Err(Error::InvalidName));
+        assert_eq!(fs.mkdirp(&long), Err(Error::InvalidName));
+        assert_eq!(fs.touch("/a/b\tc"), Err(Error::InvalidName));
+        assert!(!fs.exists("/a/b\tc").unwrap());
+    }
+
     #[test]
     fn test_filesystem_get() {
         let mut fs = Filesystem::new();
@@ -870,11 +2020,32 @@ mod tests {
         let mut fs = Filesystem::new();
         let id = fs.touch("/a/b").unwrap();
         let parent = fs.mkdirp("/c").unwrap();
-        fs.mv(id, parent).unwrap();
+        fs.touch("/c/d").unwrap();
+        fs.mv(id, parent, "b").unwrap();
         let children = fs.ls(1).unwrap();
         assert_eq!(children.len(), 0);
         let children = fs.ls(parent).unwrap();
-        assert_eq!(children.len(), 1);
+        assert_eq!(children.len(), 2);
+        fs.mv(id, 0, "e").unwrap();
+        assert!(fs.exists("/e").unwrap());
+        assert!(!fs.exists("/c/b").unwrap());
+        assert!(fs.exists("/c/d").unwrap());
+    }
+
+    #[test]
+    fn test_filesystem_mv_invalid() {
+        let mut fs = Filesystem::new();
+        let a = fs.mkdirp("/a").unwrap();
+        let b = fs.mkdirp("/a/b").unwrap();
+        let c = fs.touch("/c").unwrap();
+        fs.touch("/a/c").unwrap();
+        assert_eq!(fs.mv(a, b, "a"), Err(Error::CannotMoveError));
+        assert_eq!(fs.mv(a, a, "a"), Err(Error::CannotMoveError));
+        assert_eq!(fs.mv(c, a, "c"), Err(Error::FileAlreadyExistsError));
+        assert_eq!(fs.mv(b, c, "b"), Err(Error::FolderDoesNotExistError));
+        assert_eq!(fs.mv(c, 0, "x/y"), Err(Error::InvalidName));
+        assert!(fs.exists("/a/b").unwrap());
+        assert!(fs.exists("/c").unwrap());
     }
 
     #[test]
@@ -892,6 +2063,7 @@ mod tests {
             key,
             iv,
             salt,
+            len: 0,
         };
         fs.append(id, &data).unwrap();
         fs.rm(1).unwrap();
@@ -916,6 +2088,7 @@ mod tests {
             key,
             iv,
             salt,
+            len: 0,
         };
         fs.append(id, &data).unwrap();
         fs.graph = HashMap::new();
@@ -926,6 +2099,32 @@ mod tests {
         assert_eq!(fs.data.len(), 0);
     }
 
+    #[test]
+    fn test_filesystem_rm_shared_data() {
+        let mut fs = Filesystem::new();
+        let b = fs.touch("/a/b").unwrap();
+        let c = fs.touch("/a/c").unwrap();
+        let salt = crypto::uuid();
+        let iv = crypto::uuid();
+        let pswd = hex::encode(crypto::uuid());
+        let key = crypto::derive_key(&pswd, &salt, &iv);
+        let data = Data {
+            id: 0,
+            key,
+            iv,
+            salt,
+            len: 0,
+        };
+        let file = fs.append(b, &data).unwrap();
+        let node = fs.nodes.iter_mut().find(|node| node.id == c).unwrap();
+        node.data.push(file.data[0].id);
+        assert!(fs.rm(b).unwrap().is_empty());
+        assert!(fs.has_data(file.data[0].id));
+        assert_eq!(fs.get(c).unwrap().data.len(), 1);
+        assert_eq!(fs.rm(c).unwrap().len(), 1);
+        assert!(!fs.has_data(file.data[0].id));
+    }
+
     #[test]
     fn test_filesystem_append() {
         let mut fs = Filesystem::new();
@@ -939,6 +2138,7 @@ mod tests {
             key,
             iv,
             salt,
+            len: 0,
         };
         let file = fs.append(id, &data).unwrap();
         assert_eq!(file.data[0].id, 1);
@@ -948,6 +2148,100 @@ mod tests {
         assert_eq!(file.data.len(), 3);
     }
 
+    #[test]
+    fn test_filesystem_link() {
+        let mut fs = Filesystem::new();
+        let id = fs.touch("/a/file").unwrap();
+        let folder = fs.mkdirp("/b").unwrap();
+        let salt = crypto::uuid();
+        let iv = crypto::uuid();
+        let pswd = hex::encode(crypto::uuid());
+        let key = crypto::derive_key(&pswd, &salt, &iv);
+        let data = Data {
+            id: 0,
+            key,
+            iv,
+            salt,
+            len: 0,
+        };
+        fs.append(id, &data).unwrap();
+        fs.add_tag(id, "tag").unwrap();
+        let link = fs.link(id, folder, "link").unwrap();
+        assert_eq!(fs.lookup("/b/link").unwrap(), Some(link));
+        assert_eq!(fs.get(link).unwrap().data[0].id, 1);
+        assert!(fs.get(link).unwrap().tags.is_empty());
+        assert_eq!(
+            fs.link(id, folder, "link"),
+            Err(Error::FileAlreadyExistsError)
+        );
+        assert_eq!(fs.link(folder, 0, "c"), Err(Error::FileDoesNotExistError));
+        assert_eq!(fs.link(id, id, "c"), Err(Error::FolderDoesNotExistError));
+        assert!(fs.truncate(id).unwrap().is_empty());
+        assert_eq!(fs.data.len(), 1);
+        assert_eq!(fs.truncate(link).unwrap().len(), 1);
+        assert_eq!(fs.data.len(), 0);
+    }
+
+    #[test]
+    fn test_filesystem_timestamps() {
+        let mut fs = Filesystem::new();
+        let id = fs.touch("/a/b").unwrap();
+        let file = fs.get(id).unwrap();
+        assert!(file.created > 0);
+        assert!(file.modified >= file.created);
+        assert!(fs.get(1).unwrap().created > 0);
+
+        let node = fs.nodes.iter_mut().find(|node| node.id == id).unwrap();
+        node.modified = 0;
+        let salt = crypto::uuid();
+        let iv = crypto::uuid();
+        let key = crypto::derive_key("haha", &salt, &iv);
+        let data = Data {
+            id: 0,
+            key,
+            iv,
+            salt,
+            len: 0,
+        };
+        let file = fs.append(id, &data).unwrap();
+        assert!(file.modified > 0);
+
+        assert_eq!(file.accessed, 0);
+        fs.set_accessed(id).unwrap();
+        assert!(fs.get(id).unwrap().accessed > 0);
+        assert_eq!(fs.set_accessed(1), Err(Error::InternalStructureError));
+    }
+
+    #[test]
+    fn test_filesystem_timestamps_default() {
+        #[derive(Serialize)]
+        struct OldNode {
+            id: u64,
+            name: String,
+            size: u64,
+            is_file: bool,
+            metadata: HashMap<String, String>,
+            data: Vec<u64>,
+            tags: Vec<String>,
+        }
+
+        let old = OldNode {
+            id: 1,
+            name: "a".into(),
+            size: 0,
+            is_file: true,
+            metadata: HashMap::new(),
+            data: vec![],
+            tags: vec![],
+        };
+        let bytes = flexbuffers::to_vec(&old).unwrap();
+        let node: Node = flexbuffers::from_slice(&bytes).unwrap();
+        assert_eq!(node.name, "a");
+        assert_eq!(node.created, 0);
+        assert_eq!(node.modified, 0);
+        assert_eq!(node.accessed, 0);
+    }
+
     #[test]
     fn test_filesystem_truncate() {
         let mut fs = Filesystem::new();
@@ -961,11 +2255,60 @@ mod tests {
             key,
             iv,
             salt,
+            len: 0,
         };
         fs.append(id, &data).unwrap();
-        fs.truncate(id).unwrap();
+        fs.set_size(id, 50).unwrap();
+        let removed = fs.truncate(id).unwrap();
+        assert_eq!(removed.len(), 1);
         let file = fs.get(id).unwrap();
         assert_eq!(file.data.len(), 0);
+        assert_eq!(file.size, 0);
+    }
+
+    #[test]
+    fn test_filesystem_detach_data() {
+        let mut fs = Filesystem::new();
+        let id = fs.touch("/a").unwrap();
+        let data = |len| Data {
+            id: 0,
+            key: [0u8; 32],
+            iv: crypto::uuid(),
+            salt: crypto::uuid(),
+            len,
+        };
+        fs.append(id, &data(10)).unwrap();
+        fs.set_size(id, 10).unwrap();
+
+        let previous = fs.detach_data(id).unwrap();
+        assert_eq!(fs.get(id).unwrap().size, 0);
+        // The old id is still taken, so new parts do not reuse it.
+        let written = fs.append(id, &data(5)).unwrap();
+        assert_ne!(written.data[0].id, previous.data[0].id);
+
+        let removed = fs.restore_data(&previous).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].len, 5);
+        let file = fs.get(id).unwrap();
+        assert_eq!(file.size, 10);
+        assert_eq!(file.data[0].id, previous.data[0].id);
+
+        let previous = fs.detach_data(id).unwrap();
+        fs.append(id, &data(5)).unwrap();
+        let dropped = fs.drop_data(&previous);
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].len, 10);
+        assert_eq!(fs.data_count(), 1);
+
+        // Appended parts are removed the same way, keeping the old ones.
+        let previous = fs.get(id).unwrap();
+        fs.append(id, &data(7)).unwrap();
+        let removed = fs.restore_data(&previous).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].len, 7);
+        let ids = |file: &File| file.data.iter().map(|data| data.id).collect::<Vec<_>>();
+        assert_eq!(ids(&fs.get(id).unwrap()), ids(&previous));
+        assert_eq!(fs.data_count(), 1);
     }
 
     #[test]
@@ -980,6 +2323,99 @@ mod tests {
         assert_eq!(val, Err(Error::NoSuchMetadataKey));
     }
 
+    #[test]
+    fn test_filesystem_rename_metadata() {
+        let mut fs = Filesystem::new();
+        let id = fs.touch("/a").unwrap();
+        fs.set_metadata(id, "a", "1").unwrap();
+        fs.set_metadata(id, "b", "2").unwrap();
+        let val = fs.rename_metadata(id, "c", "d", false);
+        assert_eq!(val, Err(Error::NoSuchMetadataKey));
+        let val = fs.rename_metadata(id, "a", "b", false);
+        assert_eq!(val, Err(Error::MetadataKeyAlreadyExists));
+        fs.rename_metadata(id, "a", "c", false).unwrap();
+        assert_eq!(fs.get_metadata(id, "c").unwrap(), "1");
+        assert_eq!(fs.get_metadata(id, "a"), Err(Error::NoSuchMetadataKey));
+        fs.rename_metadata(id, "c", "b", true).unwrap();
+        assert_eq!(fs.get_metadata(id, "b").unwrap(), "1");
+        assert_eq!(fs.get(id).unwrap().metadata.len(), 1);
+    }
+
+    #[test]
+    fn test_filesystem_copy_metadata() {
+        let mut fs = Filesystem::new();
+        let a = fs.touch("/a").unwrap();
+        let b = fs.touch("/b").unwrap();
+        fs.set_metadata(a, "a", "1").unwrap();
+        fs.set_metadata(a, "b", "2").unwrap();
+        fs.set_metadata(b, "b", "3").unwrap();
+        fs.set_metadata(b, "c", "4").unwrap();
+        fs.copy_metadata(a, b).unwrap();
+        assert_eq!(fs.get_metadata(b, "a").unwrap(), "1");
+        assert_eq!(fs.get_metadata(b, "b").unwrap(), "2");
+        assert_eq!(fs.get_metadata(b, "c").unwrap(), "4");
+        assert_eq!(fs.get(a).unwrap().metadata.len(), 2);
+        assert_eq!(fs.copy_metadata(a, 10), Err(Error::FileDoesNotExistError));
+        fs.set_metadata(a, "hash", "aaaa").unwrap();
+        fs.set_metadata(a, "mimetype", "text/plain").unwrap();
+        fs.set_metadata(b, "hash", "bbbb").unwrap();
+        fs.copy_metadata(a, b).unwrap();
+        assert_eq!(fs.get_metadata(b, "hash").unwrap(), "bbbb");
+        assert!(fs.get_metadata(b, "mimetype").is_err());
+    }
+
+    #[test]
+    fn test_filesystem_typed_metadata() {
+        let mut fs = Filesystem::new();
+        let a = fs.touch("/a").unwrap();
+        let b = fs.touch("/b").unwrap();
+        fs.set_typed_metadata(a, "n", &MetadataValue::Int(3))
+            .unwrap();
+        assert_eq!(fs.get_metadata(a, "n").unwrap(), "3");
+        assert_eq!(fs.get_typed_metadata(a, "n"), Ok(MetadataValue::Int(3)));
+        fs.rename_metadata(a, "n", "m", false).unwrap();
+        assert_eq!(fs.get_typed_metadata(a, "m"), Ok(MetadataValue::Int(3)));
+        fs.copy_metadata(a, b).unwrap();
+        assert_eq!(fs.get_typed_metadata(b, "m"), Ok(MetadataValue::Int(3)));
+        fs.set_metadata(a, "m", "3").unwrap();
+        assert_eq!(
+            fs.get_typed_metadata(a, "m"),
+            Ok(MetadataValue::Str("3".into()))
+        );
+        fs.copy_metadata(a, b).unwrap();
+        assert_eq!(
+            fs.get_typed_metadata(b, "m"),
+            Ok(MetadataValue::Str("3".into()))
+        );
+    }
+
+    #[test]
+    fn test_filesystem_counts() {
+        let mut fs = Filesystem::new();
+        let a = fs.touch("/a/b").unwrap();
+        let b = fs.touch("/a/c/d").unwrap();
+        fs.set_size(a, 10).unwrap();
+        fs.set_size(b, 5).unwrap();
+        let salt = crypto::uuid();
+        let iv = crypto::uuid();
+        let pswd = hex::encode(crypto::uuid());
+        let key = crypto::derive_key(&pswd, &salt, &iv);
+        let data = Data {
+            id: 0,
+            key,
+            iv,
+            salt,
+            len: 0,
+        };
+        fs.append(a, &data).unwrap();
+        fs.append(b, &data).unwrap();
+        fs.append(b, &data).unwrap();
+        assert_eq!(fs.file_count(), 2);
+        assert_eq!(fs.folder_count(), 2);
+        assert_eq!(fs.total_size(), 15);
+        assert_eq!(fs.data_count(), 3);
+    }
+
     #[test]
     fn test_filesystem_path() {
         let mut fs = Filesystem::new();
@@ -988,6 +2424,22 @@ mod tests {
         assert_eq!(path, "/a/b/c/d");
     }
 
+    #[test]
+    fn test_filesystem_descendants() {
+        let mut fs = Filesystem::new();
+        let a = fs.mkdirp("/a").unwrap();
+        let b = fs.mkdirp("/a/b").unwrap();
+        let c = fs.touch("/a/b/c").unwrap();
+        let d = fs.touch("/a/d").unwrap();
+        fs.touch("/e").unwrap();
+
+        let mut ids = fs.descendants(a);
+        ids.sort();
+        assert_eq!(ids, vec![b, c, d]);
+        assert_eq!(fs.descendants(b), vec![c]);
+        assert!(fs.descendants(c).is_empty());
+    }
+
     #[test]
     fn test_filesystem_add_tag() {
         let mut fs = Filesystem::new();
@@ -1039,6 +2491,20 @@ mod tests {
         assert_eq!(tags[2], "tag3");
     }
 
+    #[test]
+    fn test_filesystem_count_tags() {
+        let mut fs = Filesystem::new();
+        let id = fs.touch("/a").unwrap();
+        fs.add_tag(id, "tag1").unwrap();
+        fs.add_tag(id, "tag2").unwrap();
+        let id = fs.touch("/b").unwrap();
+        fs.add_tag(id, "tag1").unwrap();
+        let counts = fs.count_tags();
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts["tag1"], 2);
+        assert_eq!(counts["tag2"], 1);
+    }
+
     #[test]
     fn test_filesystem_clear_tag() {
         let mut fs = Filesystem::new();
@@ -1050,4 +2516,282 @@ mod tests {
         let tags = fs.list_tag();
         assert_eq!(tags.len(), 0);
     }
+
+    #[test]
+    fn test_filesystem_data_len() {
+        #[derive(Serialize)]
+        struct LegacyData {
+            id: u64,
+            key: [u8; 32],
+            iv: [u8; 16],
+            salt: [u8; 16],
+        }
+
+        let rand = crypto::uuid();
+        let legacy = LegacyData {
+            id: 1,
+            key: [0u8; 32],
+            iv: rand,
+            salt: rand,
+        };
+        let bytes = flexbuffers::to_vec(legacy).unwrap();
+        let data: Data = flexbuffers::from_slice(&bytes).unwrap();
+        assert_eq!(data.len, 0);
+
+        let mut fs = Filesystem::new();
+        let id = fs.touch("/a").unwrap();
+        let file = fs.append(id, &data).unwrap();
+        assert_eq!(file.data[0].len, 0);
+        fs.set_data_len(file.data[0].id, 42).unwrap();
+        assert_eq!(fs.get(id).unwrap().data[0].len, 42);
+        assert!(fs.set_data_len(100, 42).is_err());
+    }
+
+    #[test]
+    fn test_filesystem_replace_data() {
+        let rand = crypto::uuid();
+        let data = Data {
+            id: 0,
+            key: [0u8; 32],
+            iv: rand,
+            salt: rand,
+            len: 10,
+        };
+
+        let mut fs = Filesystem::new();
+        let id = fs.touch("/a").unwrap();
+        let old_id = fs.append(id, &data).unwrap().data[0].id;
+        let link_id = fs.link(id, 0, "b").unwrap();
+
+        let new_id = fs.next_data_id();
+        assert_ne!(new_id, old_id);
+        let new = Data {
+            id: new_id,
+            key: [1u8; 32],
+            ..data
+        };
+        fs.replace_data(old_id, &new).unwrap();
+        assert!(!fs.has_data(old_id));
+        assert_eq!(fs.data_count(), 1);
+        for id in [id, link_id] {
+            let file = fs.get(id).unwrap();
+            assert_eq!(file.data.len(), 1);
+            assert_eq!(file.data[0].id, new_id);
+            assert_eq!(file.data[0].key, [1u8; 32]);
+        }
+
+        assert!(fs.replace_data(old_id, &new).is_err());
+        assert!(fs.replace_data(new_id, &new).is_err());
+    }
+
+    #[test]
+    fn test_filesystem_iter() {
+        let mut fs = Filesystem::new();
+        fs.touch("/a/b/c").unwrap();
+        fs.touch("/a/d").unwrap();
+        fs.mkdirp("/e/f").unwrap();
+        fs.touch("/g").unwrap();
+
+        let paths: Vec<String> = fs.iter().map(|file| file.name).collect();
+        for (i, path) in paths.iter().enumerate() {
+            let parent = &path[..path.rfind('/').unwrap()];
+            if !parent.is_empty() {
+                assert!(paths[..i].iter().any(|p| p == parent));
+            }
+        }
+
+        let mut paths = paths;
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec!["/a", "/a/b", "/a/b/c", "/a/d", "/e", "/e/f", "/g"]
+        );
+        let mut expected: Vec<String> = fs.ls_all().unwrap().into_iter().map(|f| f.name).collect();
+        expected.sort();
+        assert_eq!(paths, expected);
+        assert_eq!(Filesystem::new().iter().count(), 0);
+    }
+
+    #[test]
+    fn test_filesystem_path_index() {
+        // Finds the path of a node scanning the graph, one level at a time.
+        fn scan_path(fs: &Filesystem, id: u64) -> String {
+            let mut node_id = id;
+            let mut names = vec![];
+            while node_id != 0 {
+                names.push(
+                    fs.nodes
+                        .iter()
+                        .find(|n| n.id == node_id)
+                        .unwrap()
+                        .name
+                        .clone(),
+                );
+                let (parent, _) = fs
+                    .graph
+                    .iter()
+                    .find(|(_, children)| children.contains(&node_id))
+                    .unwrap();
+                node_id = parent.parse().unwrap();
+            }
+            names.reverse();
+            "/".to_string() + &names.join("/")
+        }
+
+        let mut fs = Filesystem::new();
+        for i in 0..50 {
+            for j in 0..20 {
+                fs.touch(&format!("/d{i}/s{j}/f")).unwrap();
+            }
+        }
+        assert_eq!(fs.nodes.len(), 50 + 50 * 20 * 2);
+
+        let files = fs.ls_all().unwrap();
+        assert_eq!(files.len(), fs.nodes.len());
+        for file in &files {
+            assert_eq!(file.name, scan_path(&fs, file.id));
+        }
+        for file in fs.search_tag(vec![]) {
+            assert_eq!(file.name, scan_path(&fs, file.id));
+        }
+        let index = fs.index();
+        assert_eq!(fs.path_with(0, &index).unwrap(), "/");
+        assert!(fs.path_with(100000, &index).is_err());
+    }
+}
+
+/// Checks the filesystem invariants after random sequences of operations.
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Mkdirp(String),
+        Touch(String),
+        // Indexes into the nodes and folders present when the op runs.
+        Mv(usize, usize, String),
+        Rm(usize),
+    }
+
+    /// Few short names, so that operations often collide with each other.
+    fn name() -> impl Strategy<Value = String> {
+        prop::sample::select(vec!["a", "b", "c"]).prop_map(String::from)
+    }
+
+    fn path() -> impl Strategy<Value = String> {
+        prop::collection::vec(name(), 1..4).prop_map(|names| "/".to_string() + &names.join("/"))
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            path().prop_map(Op::Mkdirp),
+            path().prop_map(Op::Touch),
+            (any::<usize>(), any::<usize>(), name())
+                .prop_map(|(id, to, name)| Op::Mv(id, to, name)),
+            any::<usize>().prop_map(Op::Rm),
+        ]
+    }
+
+    fn apply(fs: &mut Filesystem, op: &Op) {
+        let mut ids: Vec<u64> = fs.nodes.iter().map(|node| node.id).collect();
+        ids.sort();
+        let mut folders: Vec<u64> = fs
+            .nodes
+            .iter()
+            .filter(|node| !node.is_file)
+            .map(|node| node.id)
+            .collect();
+        folders.push(0);
+        folders.sort();
+
+        // Failing operations are fine, as long as they leave the invariants
+        // intact.
+        match op {
+            Op::Mkdirp(path) => {
+                fs.mkdirp(path).ok();
+            }
+            Op::Touch(path) => {
+                fs.touch(path).ok();
+            }
+            Op::Mv(_, _, _) | Op::Rm(_) if ids.is_empty() => {}
+            Op::Mv(id, to, name) => {
+                fs.mv(ids[id % ids.len()], folders[to % folders.len()], name)
+                    .ok();
+            }
+            Op::Rm(id) => {
+                fs.rm(ids[id % ids.len()]).ok();
+            }
+        }
+    }
+
+    fn check_invariants(fs: &Filesystem) {
+        let ids: HashSet<u64> = fs.nodes.iter().map(|node| node.id).collect();
+        assert_eq!(ids.len(), fs.nodes.len(), "duplicate node ids");
+        assert!(!ids.contains(&0), "a node uses the root id");
+
+        let mut parents = HashMap::new();
+        for (parent, children) in &fs.graph {
+            let parent: u64 = parent.parse().unwrap();
+            assert!(
+                parent == 0 || fs.nodes.iter().any(|n| n.id == parent && !n.is_file),
+                "{parent} has children but is not a folder"
+            );
+            let mut names = HashSet::new();
+            for child in children {
+                assert!(
+                    parents.insert(*child, parent).is_none(),
+                    "{child} has two parents"
+                );
+                let node = fs.nodes.iter().find(|node| node.id == *child);
+                let node = node.unwrap_or_else(|| panic!("{child} is listed but does not exist"));
+                assert!(
+                    names.insert(&node.name),
+                    "duplicate name {} in {parent}",
+                    node.name
+                );
+            }
+        }
+
+        for node in &fs.nodes {
+            // Walking up must reach the root, so there are no orphans or cycles.
+            let mut ancestor = node.id;
+            for _ in 0..=fs.nodes.len() {
+                if ancestor == 0 {
+                    break;
+                }
+                ancestor = *parents
+                    .get(&ancestor)
+                    .unwrap_or_else(|| panic!("{ancestor} is an orphan"));
+            }
+            assert_eq!(ancestor, 0, "{} is in a cycle", node.id);
+
+            let path = fs.path(node.id).unwrap();
+            assert!(fs.exists(&path).unwrap());
+            assert_eq!(fs.lookup(&path).unwrap(), Some(node.id));
+        }
+
+        let data: HashSet<u64> = fs.data.iter().map(|data| data.id).collect();
+        for node in &fs.nodes {
+            assert!(node.data.iter().all(|id| data.contains(id)));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_filesystem_invariants(ops in prop::collection::vec(op(), 1..50)) {
+            let mut fs = Filesystem::new();
+            for op in &ops {
+                apply(&mut fs, op);
+                check_invariants(&fs);
+            }
+
+            // Cleaning a consistent filesystem changes nothing.
+            let nodes = fs.nodes.len();
+            prop_assert!(fs.clean().unwrap().is_empty());
+            prop_assert_eq!(nodes, fs.nodes.len());
+            check_invariants(&fs);
+        }
+    }
 }
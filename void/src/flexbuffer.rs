@@ -0,0 +1,247 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Structural check of untrusted flexbuffers.
+//!
+//! The flexbuffers reader follows the offsets and lengths stored in the
+//! buffer without checking them, and panics when they point outside of it.
+//! Store files can come from anywhere, so every value reachable from the root
+//! is checked here before the bytes are handed to the deserializer.
+
+const NULL: u8 = 0;
+const INT: u8 = 1;
+const UINT: u8 = 2;
+const FLOAT: u8 = 3;
+const KEY: u8 = 4;
+const STRING: u8 = 5;
+const INDIRECT_INT: u8 = 6;
+const INDIRECT_FLOAT: u8 = 8;
+const MAP: u8 = 9;
+const VECTOR: u8 = 10;
+const VECTOR_INT: u8 = 11;
+const VECTOR_FLOAT: u8 = 13;
+const VECTOR_KEY: u8 = 14;
+const VECTOR_STRING: u8 = 15;
+const VECTOR_INT2: u8 = 16;
+const VECTOR_FLOAT4: u8 = 24;
+const BLOB: u8 = 25;
+const BOOL: u8 = 26;
+const VECTOR_BOOL: u8 = 36;
+
+/// Maximum nesting of maps and vectors. Offsets may point back at their own
+/// container, so this also stops cycles.
+const MAX_DEPTH: usize = 64;
+
+struct Checker<'a> {
+    buffer: &'a [u8],
+    // Values left to visit. Containers can be shared between parents, which
+    // would make the deserializer visit them once per parent.
+    budget: usize,
+}
+
+/// Returns whether the deserializer can read the buffer without going out of
+/// its bounds.
+///
+/// # Arguments
+///
+/// * `buffer` - Serialized flexbuffer.
+pub(crate) fn is_valid(buffer: &[u8]) -> bool {
+    let len = buffer.len();
+    if len < 3 {
+        return false;
+    }
+
+    let Some(root_width) = byte_width(buffer[len - 1] as usize) else {
+        return false;
+    };
+    let Some((ty, width)) = unpack_type(buffer[len - 2]) else {
+        return false;
+    };
+    let Some(address) = (len - 2).checked_sub(root_width) else {
+        return false;
+    };
+
+    let mut checker = Checker {
+        buffer,
+        budget: len.saturating_mul(2),
+    };
+    checker.value(address, ty, width, root_width, 0).is_some()
+}
+
+fn byte_width(n: usize) -> Option<usize> {
+    matches!(n, 1 | 2 | 4 | 8).then_some(n)
+}
+
+fn unpack_type(packed: u8) -> Option<(u8, usize)> {
+    let ty = packed >> 2;
+    let known = ty <= BOOL || ty == VECTOR_BOOL;
+    known.then_some((ty, 1 << (packed & 3)))
+}
+
+impl Checker<'_> {
+    /// Checks the value whose slot is at `address`.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Address of the slot holding the value or its offset.
+    /// * `ty` - Type of the value.
+    /// * `width` - Byte width of the value.
+    /// * `parent_width` - Byte width of the slot.
+    /// * `depth` - Number of containers around the value.
+    fn value(
+        &mut self,
+        address: usize,
+        ty: u8,
+        width: usize,
+        parent_width: usize,
+        depth: usize,
+    ) -> Option<()> {
+        self.budget = self.budget.checked_sub(1)?;
+        if depth > MAX_DEPTH {
+            return None;
+        }
+
+        let address = match ty {
+            NULL | INT | UINT | FLOAT | BOOL => address,
+            _ => self.deref(address, parent_width)?,
+        };
+
+        match ty {
+            NULL => Some(()),
+            INT..=FLOAT | INDIRECT_INT..=INDIRECT_FLOAT | BOOL => self.range(address, width),
+            KEY => self.buffer.get(address..)?.contains(&0).then_some(()),
+            STRING | BLOB => self.range(address, self.length(address, width)?),
+            MAP => self.map(address, width, depth),
+            VECTOR => {
+                let len = self.length(address, width)?;
+                let types = address.checked_add(len.checked_mul(width)?)?;
+                self.range(types, len)?;
+                for i in 0..len {
+                    let (ty, child_width) = unpack_type(self.buffer[types + i])?;
+                    self.value(address + i * width, ty, child_width, width, depth + 1)?;
+                }
+                Some(())
+            }
+            VECTOR_KEY | VECTOR_STRING => {
+                let len = self.length(address, width)?;
+                self.range(address, len.checked_mul(width)?)?;
+                for i in 0..len {
+                    self.value(address + i * width, KEY, width, width, depth + 1)?;
+                }
+                Some(())
+            }
+            VECTOR_INT..=VECTOR_FLOAT | VECTOR_BOOL => {
+                let len = self.length(address, width)?;
+                self.range(address, len.checked_mul(width)?)
+            }
+            VECTOR_INT2..=VECTOR_FLOAT4 => {
+                let len = (ty - VECTOR_INT2) as usize / 3 + 2;
+                self.range(address, len * width)
+            }
+            _ => None,
+        }
+    }
+
+    fn map(&mut self, address: usize, width: usize, depth: usize) -> Option<()> {
+        let keys_offset = address.checked_sub(3 * width).filter(|&a| a > 0)?;
+        let keys_width = byte_width(self.read(address - 2 * width, width)?)?;
+        let keys = self.deref(keys_offset, width)?;
+        let len = self.length(address, width)?;
+
+        let types = address.checked_add(len.checked_mul(width)?)?;
+        self.range(types, len)?;
+        self.range(keys, len.checked_mul(keys_width)?)?;
+        for i in 0..len {
+            let (ty, child_width) = unpack_type(self.buffer[types + i])?;
+            self.value(address + i * width, ty, child_width, width, depth + 1)?;
+            self.value(
+                keys + i * keys_width,
+                KEY,
+                keys_width,
+                keys_width,
+                depth + 1,
+            )?;
+        }
+        Some(())
+    }
+
+    /// Checks that `len` bytes starting at `address` are inside the buffer.
+    fn range(&self, address: usize, len: usize) -> Option<()> {
+        let end = address.checked_add(len)?;
+        (end <= self.buffer.len()).then_some(())
+    }
+
+    fn read(&self, address: usize, width: usize) -> Option<usize> {
+        let bytes = self.buffer.get(address..address.checked_add(width)?)?;
+        let mut value = [0u8; 8];
+        value[..width].copy_from_slice(bytes);
+        usize::try_from(u64::from_le_bytes(value)).ok()
+    }
+
+    /// Reads the length stored before a string, blob or vector.
+    fn length(&self, address: usize, width: usize) -> Option<usize> {
+        self.read(address.checked_sub(width)?, width)
+    }
+
+    /// Follows the offset stored at `address`.
+    fn deref(&self, address: usize, width: usize) -> Option<usize> {
+        address.checked_sub(self.read(address, width)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::Filesystem;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_is_valid() {
+        let mut fs = Filesystem::new();
+        fs.mkdirp("/a/b/c").unwrap();
+        let id = fs.touch("/a/b/c/file").unwrap();
+        fs.set_metadata(id, "key", "value").unwrap();
+        let bytes = flexbuffers::to_vec(&fs).unwrap();
+        assert!(is_valid(&bytes));
+
+        let map = HashMap::from([("x", vec![1.5f64, 2.0]), ("y", vec![])]);
+        assert!(is_valid(&flexbuffers::to_vec(&map).unwrap()));
+        assert!(is_valid(&flexbuffers::to_vec(vec![7u8; 300]).unwrap()));
+        assert!(is_valid(
+            &flexbuffers::to_vec(("a", 1u64, -1i8, true)).unwrap()
+        ));
+
+        assert!(!is_valid(b""));
+        assert!(!is_valid(&[0, 0]));
+        assert!(!is_valid(&bytes[..bytes.len() / 2]));
+
+        // A vector whose only element points back at the vector.
+        assert!(!is_valid(&[1, 0, 0x28, 2, 0x28, 1]));
+    }
+
+    #[test]
+    fn test_is_valid_mutations() {
+        let mut fs = Filesystem::new();
+        fs.mkdirp("/a/b/c").unwrap();
+        let bytes = flexbuffers::to_vec(&fs).unwrap();
+
+        // Whatever is accepted must not make the reader panic.
+        let mut seed = 0x9e3779b97f4a7c15u64;
+        for _ in 0..20000 {
+            let mut mutated = bytes.clone();
+            for _ in 0..4 {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                let i = seed as usize % mutated.len();
+                mutated[i] = (seed >> 32) as u8;
+            }
+            if is_valid(&mutated) {
+                flexbuffers::from_slice::<Filesystem>(&mutated).ok();
+            }
+        }
+    }
+}
@@ -4,9 +4,14 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
+mod backend;
 mod crypto;
 mod filesystem;
+mod flexbuffer;
+#[cfg(feature = "s3")]
+mod object_backend;
 mod path;
+mod shared;
 mod store;
 
 pub use store::*;
@@ -0,0 +1,198 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Stores kept in S3 compatible object storage.
+//!
+//! Each index and part file of the store is an object under a common prefix,
+//! named like the file it replaces. Everything is encrypted before it is
+//! uploaded, so the object storage never sees file contents, names or keys.
+
+use super::backend::{legacy_part_name, parse_part_name, part_name, Backend};
+use super::store::Error;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, PutPayload};
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+/// Keeps a store in an `object_store` bucket, like S3 or MinIO.
+///
+/// The backend blocks on its own runtime, so it must not be used from inside
+/// an asynchronous task.
+#[derive(Debug)]
+pub struct ObjectStoreBackend {
+    store: Arc<dyn ObjectStore>,
+    prefix: ObjectPath,
+    runtime: Runtime,
+}
+
+impl ObjectStoreBackend {
+    /// Keeps the store in `store`, with every object under `prefix`.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - Object store that holds the store.
+    /// * `prefix` - Prefix of the keys of the store objects, like a folder.
+    pub fn new(store: Arc<dyn ObjectStore>, prefix: &str) -> Result<ObjectStoreBackend, Error> {
+        let prefix = ObjectPath::parse(prefix).map_err(|_| Error::CannotParseError)?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        Ok(ObjectStoreBackend {
+            store,
+            prefix,
+            runtime,
+        })
+    }
+
+    /// Keeps the store in an S3 bucket. Credentials, region and endpoint are
+    /// read from the usual `AWS_*` environment variables, so `AWS_ENDPOINT`
+    /// points it at MinIO or other S3 compatible servers.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - Name of the bucket.
+    /// * `prefix` - Prefix of the keys of the store objects, like a folder.
+    pub fn s3(bucket: &str, prefix: &str) -> Result<ObjectStoreBackend, Error> {
+        let store = AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .build()
+            .map_err(|_| Error::CannotParseError)?;
+        ObjectStoreBackend::new(Arc::new(store), prefix)
+    }
+
+    fn object(&self, name: &str) -> ObjectPath {
+        self.prefix.child(name)
+    }
+
+    fn get(&self, name: &str) -> Result<Vec<u8>, object_store::Error> {
+        self.runtime.block_on(async {
+            let result = self.store.get(&self.object(name)).await?;
+            Ok(result.bytes().await?.to_vec())
+        })
+    }
+
+    fn put(&self, name: &str, bytes: &[u8]) -> Result<(), Error> {
+        let payload = PutPayload::from(bytes.to_vec());
+        let object = self.object(name);
+        self.runtime
+            .block_on(self.store.put(&object, payload))
+            .map_err(|_| Error::CannotWriteFileError)?;
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<object_store::ObjectMeta>, Error> {
+        let list = self.store.list_with_delimiter(Some(&self.prefix));
+        let list = self
+            .runtime
+            .block_on(list)
+            .map_err(|_| Error::CannotReadFileError)?;
+        Ok(list.objects)
+    }
+}
+
+impl Backend for ObjectStoreBackend {
+    fn read_index(&self, name: &str) -> Result<Vec<u8>, Error> {
+        self.get(name).map_err(|err| match err {
+            object_store::Error::NotFound { .. } => Error::FileDoesNotExistError,
+            _ => Error::CannotReadFileError,
+        })
+    }
+
+    fn write_index(&self, name: &str, bytes: &[u8]) -> Result<(), Error> {
+        // Objects are replaced whole, never left half written.
+        self.put(name, bytes)
+    }
+
+    fn read_part(&self, id: u64, buffer: &mut Vec<u8>) -> Result<(), Error> {
+        let part = match self.get(&part_name(id)) {
+            Err(object_store::Error::NotFound { .. }) => self.get(&legacy_part_name(id)),
+            part => part,
+        };
+        *buffer = part.map_err(|_| Error::CannotReadFileError)?;
+        Ok(())
+    }
+
+    fn write_part(&self, id: u64, content: &[u8]) -> Result<(), Error> {
+        self.put(&part_name(id), content)
+    }
+
+    fn remove_part(&self, id: u64) -> Result<(), Error> {
+        for name in [part_name(id), legacy_part_name(id)] {
+            let object = self.object(&name);
+            match self.runtime.block_on(self.store.delete(&object)) {
+                Err(object_store::Error::NotFound { .. }) | Ok(()) => {}
+                Err(_) => return Err(Error::CannotWriteFileError),
+            }
+        }
+        Ok(())
+    }
+
+    fn list_parts(&self) -> Result<Vec<u64>, Error> {
+        let mut parts: Vec<u64> = self
+            .list()?
+            .iter()
+            .filter_map(|object| parse_part_name(object.location.filename()?))
+            .collect();
+        parts.sort();
+        parts.dedup();
+        Ok(parts)
+    }
+
+    fn part_location(&self, id: u64) -> String {
+        self.object(&part_name(id)).to_string()
+    }
+
+    fn size(&self) -> Result<u64, Error> {
+        Ok(self.list()?.iter().map(|object| object.size).sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Kdf, Store, StoreBuilder};
+    use object_store::memory::InMemory;
+
+    #[test]
+    fn test_object_store_backend() {
+        let objects = Arc::new(InMemory::new());
+        let backend = Arc::new(ObjectStoreBackend::new(objects.clone(), "stores/a").unwrap());
+        let (mut store, _) = StoreBuilder::new("", "1234")
+            .kdf(Kdf::HkdfSha256)
+            .chunk_size(4)
+            .backend(backend.clone())
+            .build()
+            .unwrap();
+        store.add_bytes(b"hello world", "/a", None).unwrap();
+
+        let mut parts = backend.list_parts().unwrap();
+        parts.sort();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(
+            store.part_files("/a").unwrap()[0],
+            format!("stores/a/{}", part_name(parts[0]))
+        );
+        let index = backend
+            .runtime
+            .block_on(objects.head(&"stores/a/Store.void".into()));
+        assert!(index.is_ok());
+
+        let mut store = Store::open_backend(backend.clone(), "1234").unwrap();
+        assert_eq!(store.read_range("/a", 0, 11).unwrap(), b"hello world");
+
+        // Another prefix is another store.
+        let other = ObjectStoreBackend::new(objects, "stores/b").unwrap();
+        assert_eq!(
+            Store::open_backend(Arc::new(other), "1234").err(),
+            Some(Error::FileDoesNotExistError)
+        );
+
+        store.remove("/a").unwrap();
+        assert!(backend.list_parts().unwrap().is_empty());
+    }
+}
@@ -152,6 +152,53 @@ impl Path {
     }
 }
 
+/// Compiles a glob into a regex matching full store paths. `*` and `?`
+/// match any characters but `/`, `**/` matches any number of folders and
+/// `[...]` matches one character of a set, `[!...]` one not in it. A glob
+/// without `/` matches the name of a node at any depth, like `*.tmp`; one
+/// with `/` matches the whole path, like `/a/**/*.tmp`.
+///
+/// # Arguments
+///
+/// * `glob` - Pattern to compile.
+pub(crate) fn glob_regex(glob: &str) -> Option<regex::Regex> {
+    let mut regex = if glob.starts_with('/') {
+        String::from("^")
+    } else if glob.contains('/') {
+        String::from("^/")
+    } else {
+        String::from("^(?:.*/)?")
+    };
+
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.next_if_eq(&'*').is_some() => {
+                if chars.next_if_eq(&'/').is_some() {
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let set: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                let (negated, set) = match set.strip_prefix('!') {
+                    Some(set) => ("^", set),
+                    None => ("", set.as_str()),
+                };
+                let set = set.replace('\\', "\\\\").replace('[', "\\[");
+                regex.push_str(&format!("[{negated}{set}]"));
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+
+    regex::Regex::new(&regex).ok()
+}
+
 impl From<path::PathBuf> for Path {
     fn from(path: path::PathBuf) -> Self {
         let path = path.abs().unwrap();
@@ -241,6 +288,32 @@ mod tests {
         assert!(new_path.is_none());
     }
 
+    #[test]
+    fn test_glob_regex() {
+        let glob = glob_regex("*.tmp").unwrap();
+        assert!(glob.is_match("/a.tmp"));
+        assert!(glob.is_match("/a/b/c.tmp"));
+        assert!(!glob.is_match("/a.tmp/b"));
+        assert!(!glob.is_match("/atmp"));
+
+        let glob = glob_regex("/a/*/c").unwrap();
+        assert!(glob.is_match("/a/b/c"));
+        assert!(!glob.is_match("/a/b/b/c"));
+        assert!(!glob.is_match("/x/a/b/c"));
+
+        let glob = glob_regex("a/**/c?").unwrap();
+        assert!(glob.is_match("/a/c1"));
+        assert!(glob.is_match("/a/b/b/c2"));
+        assert!(!glob.is_match("/a/b/c"));
+
+        let glob = glob_regex("file[0-9][!a]").unwrap();
+        assert!(glob.is_match("/file1b"));
+        assert!(!glob.is_match("/file1a"));
+        assert!(!glob.is_match("/filex1"));
+
+        assert!(glob_regex("[").is_none());
+    }
+
     #[test]
     fn test_join_path() {
         let path = Path::new(&"/a".into()).unwrap();
@@ -0,0 +1,207 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! A store that can be used from many threads.
+
+use super::store::{
+    Cipher, Compression, DiffReport, Error, File, FilesystemSnapshot, Kdf, KdfParams,
+    MetadataValue, Progress, SortKey, Store, StoreStats,
+};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::ops::RangeBounds;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A store shared between threads. Clones refer to the same store, and calls
+/// made through any of them run one at a time, so every change is made to,
+/// and saved from, the same index.
+///
+/// # Example
+///
+/// ```ignore
+/// let store = SharedStore::new(Store::open("store", "password")?);
+/// let other = store.clone();
+/// std::thread::spawn(move || other.add_bytes(b"data", "/file", None));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SharedStore(Arc<Mutex<Store>>);
+
+/// Defines methods that lock the store and call the method of the same name.
+macro_rules! delegate {
+    ($(fn $name:ident(&self $(, $arg:ident: $ty:ty)*) -> $ret:ty;)*) => {
+        $(
+            #[doc = concat!("Locks the store and calls `Store::", stringify!($name), "`.")]
+            pub fn $name(&self $(, $arg: $ty)*) -> $ret {
+                self.lock().$name($($arg),*)
+            }
+        )*
+    };
+}
+
+impl SharedStore {
+    /// Shares `store` between threads.
+    pub fn new(store: Store) -> SharedStore {
+        SharedStore(Arc::new(Mutex::new(store)))
+    }
+
+    /// Locks the store, waiting for calls from other threads to finish. The
+    /// other threads wait until the guard is dropped.
+    pub fn lock(&self) -> MutexGuard<'_, Store> {
+        // A thread that panicked while holding the lock may leave unsaved
+        // changes in the index, which the next change saves like any other.
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Returns the store, if this is its last reference.
+    pub fn into_inner(self) -> Result<Store, SharedStore> {
+        match Arc::try_unwrap(self.0) {
+            Ok(mutex) => Ok(mutex.into_inner().unwrap_or_else(|err| err.into_inner())),
+            Err(store) => Err(SharedStore(store)),
+        }
+    }
+
+    delegate! {
+        fn key(&self) -> [u8; 32];
+        fn rename_store(&self, new_folder: &str) -> Result<(), Error>;
+        fn save_to_bytes(&self) -> Result<Vec<u8>, Error>;
+        fn add(&self, file_path: &str, store_path: &str) -> Result<(), Error>;
+        fn add_overwrite(&self, file_path: &str, store_path: &str, overwrite: bool) -> Result<(), Error>;
+        fn add_returning(&self, file_path: &str, store_path: &str) -> Result<String, Error>;
+        fn add_bytes(&self, data: &[u8], store_path: &str, mimetype: Option<&str>) -> Result<(), Error>;
+        fn add_bytes_detecting_mimetype(&self, data: &[u8], store_path: &str) -> Result<(), Error>;
+        fn append_bytes(&self, path: &str, data: &[u8]) -> Result<(), Error>;
+        fn touch(&self, path: &str) -> Result<(), Error>;
+        fn mkdir(&self, path: &str) -> Result<(), Error>;
+        fn mkdir_p(&self, path: &str) -> Result<(), Error>;
+        fn sync(&self, src_dir: &str, store_prefix: &str, delete: bool) -> Result<(), Error>;
+        fn get(&self, store_path: &str, file_path: &str) -> Result<(), Error>;
+        fn get_filtered(&self, store_path: &str, file_path: &str, predicate: &dyn Fn(&File) -> bool) -> Result<(), Error>;
+        fn read_range(&self, path: &str, offset: u64, len: u64) -> Result<Vec<u8>, Error>;
+        fn content_hash(&self, path: &str) -> Result<[u8; 32], Error>;
+        fn diff(&self, other: &Store) -> Result<DiffReport, Error>;
+        fn remove(&self, path: &str) -> Result<(), Error>;
+        fn remove_id(&self, id: u64) -> Result<(), Error>;
+        fn trash(&self, path: &str) -> Result<String, Error>;
+        fn restore_from_trash(&self, path: &str) -> Result<String, Error>;
+        fn empty_trash(&self) -> Result<(), Error>;
+        fn plan_remove(&self, query: &str) -> Result<Vec<File>, Error>;
+        fn remove_matching(&self, query: &str) -> Result<Vec<File>, Error>;
+        fn mv(&self, src: &str, dst: &str) -> Result<(), Error>;
+        fn mv_id(&self, src_id: u64, dst: &str) -> Result<(), Error>;
+        fn link(&self, src: &str, dst: &str) -> Result<(), Error>;
+        fn list(&self, path: &str) -> Result<Vec<File>, Error>;
+        fn list_page(&self, path: &str, offset: usize, limit: usize) -> Result<(Vec<File>, usize), Error>;
+        fn list_sorted(&self, path: &str, by: SortKey, desc: bool) -> Result<Vec<File>, Error>;
+        fn list_sorted_folders_first(&self, path: &str, by: SortKey, desc: bool, folders_first: bool) -> Result<Vec<File>, Error>;
+        fn list_with_sizes(&self, path: &str) -> Result<Vec<File>, Error>;
+        fn exists(&self, path: &str) -> Result<bool, Error>;
+        fn is_dir(&self, path: &str) -> Result<bool, Error>;
+        fn is_file(&self, path: &str) -> Result<bool, Error>;
+        fn stat(&self, path: &str) -> Result<File, Error>;
+        fn id_of(&self, path: &str) -> Result<u64, Error>;
+        fn path_of(&self, id: u64) -> Result<String, Error>;
+        fn disk_usage(&self, path: &str) -> Result<u64, Error>;
+        fn cipher(&self) -> Cipher;
+        fn kdf(&self) -> Kdf;
+        fn kdf_params(&self) -> KdfParams;
+        fn compression(&self) -> Compression;
+        fn chunk_size(&self) -> u64;
+        fn quota_bytes(&self) -> Option<u64>;
+        fn set_quota_bytes(&self, quota_bytes: Option<u64>) -> Result<(), Error>;
+        fn set_description(&self, description: &str) -> Result<(), Error>;
+        fn get_description(&self) -> Result<Option<String>, Error>;
+        fn statistics(&self) -> Result<StoreStats, Error>;
+        fn part_files(&self, path: &str) -> Result<Vec<String>, Error>;
+        fn truncate(&self, path: &str) -> Result<(), Error>;
+        fn rekey_file(&self, path: &str) -> Result<(), Error>;
+        fn metadata_set(&self, path: &str, key: &str, value: &str) -> Result<(), Error>;
+        fn metadata_set_id(&self, id: u64, key: &str, value: &str) -> Result<(), Error>;
+        fn metadata_set_recursive(&self, path: &str, key: &str, value: &str) -> Result<(), Error>;
+        fn metadata_remove(&self, path: &str, key: &str) -> Result<(), Error>;
+        fn metadata_rename(&self, path: &str, old_key: &str, new_key: &str, overwrite: bool) -> Result<(), Error>;
+        fn metadata_copy(&self, from_path: &str, to_path: &str) -> Result<(), Error>;
+        fn metadata_get(&self, path: &str, key: &str) -> Result<String, Error>;
+        fn metadata_list(&self, path: &str) -> Result<HashMap<String, String>, Error>;
+        fn metadata_set_typed(&self, path: &str, key: &str, value: MetadataValue) -> Result<(), Error>;
+        fn metadata_get_typed(&self, path: &str, key: &str) -> Result<MetadataValue, Error>;
+        fn tag_add(&self, path: &str, tag: &str) -> Result<(), Error>;
+        fn tag_add_id(&self, id: u64, tag: &str) -> Result<(), Error>;
+        fn tag_rm(&self, path: &str, tag: &str) -> Result<(), Error>;
+        fn tag_rm_id(&self, id: u64, tag: &str) -> Result<(), Error>;
+        fn tag_add_recursive(&self, path: &str, tag: &str) -> Result<(), Error>;
+        fn tag_rm_recursive(&self, path: &str, tag: &str) -> Result<(), Error>;
+        fn tag_clear(&self, path: &str) -> Result<(), Error>;
+        fn tag_rename(&self, old: &str, new: &str) -> Result<(), Error>;
+        fn tag_list(&self) -> Vec<String>;
+        fn tag_counts(&self) -> HashMap<String, usize>;
+        fn tag_get(&self, path: &str) -> Result<Vec<String>, Error>;
+        fn tag_search(&self, tags: Vec<String>) -> Vec<File>;
+        fn find(&self, text: &str) -> Vec<File>;
+        fn least_recently_accessed(&self, n: usize) -> Vec<File>;
+        fn gc(&self) -> Result<usize, Error>;
+        fn snapshot(&self) -> FilesystemSnapshot;
+        fn restore_snapshot(&self, snapshot: FilesystemSnapshot) -> Result<(), Error>;
+    }
+
+    /// Locks the store and calls `Store::add_from_reader`.
+    pub fn add_from_reader<R: Read>(
+        &self,
+        reader: &mut R,
+        store_path: &str,
+        overwrite: bool,
+    ) -> Result<(), Error> {
+        self.lock().add_from_reader(reader, store_path, overwrite)
+    }
+
+    /// Locks the store and calls `Store::export_tar`.
+    pub fn export_tar<W: Write>(&self, store_path: &str, writer: W) -> Result<(), Error> {
+        self.lock().export_tar(store_path, writer)
+    }
+
+    /// Locks the store and calls `Store::import_tar`.
+    pub fn import_tar<R: Read>(&self, reader: R, prefix: &str) -> Result<(), Error> {
+        self.lock().import_tar(reader, prefix)
+    }
+
+    /// Locks the store and calls `Store::rekey_all`.
+    pub fn rekey_all<F: FnMut(usize, usize)>(&self, progress: F) -> Result<(), Error> {
+        self.lock().rekey_all(progress)
+    }
+
+    /// Locks the store and calls `Store::add_with_progress`.
+    pub fn add_with_progress<F: FnMut(Progress)>(
+        &self,
+        file_path: &str,
+        store_path: &str,
+        overwrite: bool,
+        progress: F,
+    ) -> Result<(), Error> {
+        self.lock()
+            .add_with_progress(file_path, store_path, overwrite, progress)
+    }
+
+    /// Locks the store and calls `Store::get_with_progress`.
+    pub fn get_with_progress<F: FnMut(Progress)>(
+        &self,
+        store_path: &str,
+        file_path: &str,
+        progress: F,
+    ) -> Result<(), Error> {
+        self.lock()
+            .get_with_progress(store_path, file_path, progress)
+    }
+
+    /// Locks the store and calls `Store::metadata_search`.
+    pub fn metadata_search<R: RangeBounds<MetadataValue>>(&self, key: &str, range: R) -> Vec<File> {
+        self.lock().metadata_search(key, range)
+    }
+}
+
+impl From<Store> for SharedStore {
+    fn from(store: Store) -> SharedStore {
+        SharedStore::new(store)
+    }
+}
@@ -4,18 +4,33 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use crate::filesystem::{Data, File, Filesystem};
+pub use crate::filesystem::{Data, File, MetadataValue, Walk};
+use crate::filesystem::{Filesystem, SearchIndex};
 
+use super::backend::{part_name, INDEX};
+pub use super::backend::{Backend, FsBackend, MemoryBackend, MAX_SHARD_LEVELS};
 use super::crypto;
+use super::flexbuffer;
+#[cfg(feature = "s3")]
+pub use super::object_backend::ObjectStoreBackend;
+use super::path::glob_regex;
 pub use super::path::{EasyPath, Path};
+pub use super::shared::SharedStore;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
 use flexbuffers::{FlexbufferSerializer, Reader};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Display;
 use std::fs;
 use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+pub use zeroize::Zeroizing;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Error {
     CannotCreateDirectoryError,
     CannotCreateFileError,
@@ -32,17 +47,287 @@ pub enum Error {
     FolderDoesNotExistError,
     StoreFileAlreadyExistsError,
     NoSuchMetadataKey,
+    MetadataKeyAlreadyExists,
     InternalStructureError,
+    InvalidName,
+    CannotMoveError,
+    NoRecoveryKeyError,
+    WrongPassword,
+    StoreCorrupted,
+    InvalidKdfParams,
+    InvalidChunkSize,
+    MaxDepthExceeded,
+    KeyfileRequired,
+    WeakPassword,
+    QuotaExceeded,
+    /// An operation on a file failed, for the reason given by the kind.
+    IoError(std::io::ErrorKind),
+}
+
+impl Error {
+    /// Stable identifier of the error, suitable as a translation key or to be
+    /// sent to other processes. Unlike the `Debug` output, it does not change
+    /// if a variant gains or loses fields.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::CannotCreateDirectoryError => "cannot_create_directory",
+            Error::CannotCreateFileError => "cannot_create_file",
+            Error::CannotEncryptFileError => "cannot_encrypt_file",
+            Error::CannotDecryptFileError => "cannot_decrypt_file",
+            Error::CannotDeserializeError => "cannot_deserialize",
+            Error::CannotParseError => "cannot_parse",
+            Error::CannotReadFileError => "cannot_read_file",
+            Error::CannotRemoveFilesError(_) => "cannot_remove_files",
+            Error::CannotSerializeError => "cannot_serialize",
+            Error::CannotWriteFileError => "cannot_write_file",
+            Error::FileAlreadyExistsError => "file_already_exists",
+            Error::FileDoesNotExistError => "file_does_not_exist",
+            Error::FolderDoesNotExistError => "folder_does_not_exist",
+            Error::StoreFileAlreadyExistsError => "store_file_already_exists",
+            Error::NoSuchMetadataKey => "no_such_metadata_key",
+            Error::MetadataKeyAlreadyExists => "metadata_key_already_exists",
+            Error::InternalStructureError => "internal_structure",
+            Error::InvalidName => "invalid_name",
+            Error::CannotMoveError => "cannot_move",
+            Error::NoRecoveryKeyError => "no_recovery_key",
+            Error::WrongPassword => "wrong_password",
+            Error::StoreCorrupted => "store_corrupted",
+            Error::InvalidKdfParams => "invalid_kdf_params",
+            Error::InvalidChunkSize => "invalid_chunk_size",
+            Error::MaxDepthExceeded => "max_depth_exceeded",
+            Error::KeyfileRequired => "keyfile_required",
+            Error::WeakPassword => "weak_password",
+            Error::QuotaExceeded => "quota_exceeded",
+            Error::IoError(_) => "io",
+        }
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{self:?}")
+        match self {
+            Error::CannotCreateDirectoryError => write!(f, "Could not create the folder."),
+            Error::CannotCreateFileError => write!(f, "Could not create the file."),
+            Error::CannotEncryptFileError => write!(f, "Could not encrypt the file."),
+            Error::CannotDecryptFileError => write!(f, "Could not decrypt the file."),
+            Error::CannotDeserializeError => write!(f, "Could not read the store data."),
+            Error::CannotParseError => write!(f, "The path is not valid."),
+            Error::CannotReadFileError => write!(f, "Could not read the file."),
+            Error::CannotRemoveFilesError(paths) => {
+                write!(f, "Could not remove the files {}.", paths.join(", "))
+            }
+            Error::CannotSerializeError => write!(f, "Could not save the store data."),
+            Error::CannotWriteFileError => write!(f, "Could not write the file."),
+            Error::FileAlreadyExistsError => write!(f, "The file already exists."),
+            Error::FileDoesNotExistError => write!(f, "The file does not exist."),
+            Error::FolderDoesNotExistError => write!(f, "The folder does not exist."),
+            Error::StoreFileAlreadyExistsError => {
+                write!(f, "The file already exists in the store.")
+            }
+            Error::NoSuchMetadataKey => write!(f, "The file has no such metadata key."),
+            Error::MetadataKeyAlreadyExists => {
+                write!(f, "The file already has this metadata key.")
+            }
+            Error::InternalStructureError => write!(f, "The store index is inconsistent."),
+            Error::InvalidName => write!(f, "The name is not valid."),
+            Error::CannotMoveError => write!(f, "Could not move the file."),
+            Error::NoRecoveryKeyError => {
+                write!(f, "The store was created without a recovery phrase.")
+            }
+            Error::WrongPassword => write!(f, "The password is wrong."),
+            Error::StoreCorrupted => write!(f, "The store is corrupted."),
+            Error::InvalidKdfParams => {
+                write!(f, "The key derivation parameters are not valid.")
+            }
+            Error::InvalidChunkSize => write!(f, "The chunk size is not valid."),
+            Error::MaxDepthExceeded => write!(f, "The folder is nested too deeply."),
+            Error::KeyfileRequired => write!(f, "The store can only be opened with its key file."),
+            Error::WeakPassword => write!(f, "The password is too weak."),
+            Error::QuotaExceeded => write!(f, "The store would grow larger than its quota."),
+            Error::IoError(kind) => write!(f, "A file operation failed: {kind}."),
+        }
     }
 }
 
 impl std::error::Error for Error {}
 
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Error {
+        Error::IoError(error.kind())
+    }
+}
+
+/// Cipher used to encrypt the filesystem and the file parts of a store.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Cipher {
+    #[default]
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+impl Cipher {
+    /// Largest part, in bytes, the cipher can safely encrypt under a single
+    /// nonce: 2^36 - 32 bytes for AES-GCM and 2^38 - 64 bytes for
+    /// ChaCha20-Poly1305. Every part gets its own key and nonce, so this only
+    /// limits the chunk size.
+    pub fn max_chunk_size(&self) -> u64 {
+        match self {
+            Cipher::Aes256Gcm => (1 << 36) - 32,
+            Cipher::ChaCha20Poly1305 => (1 << 38) - 64,
+        }
+    }
+}
+
+impl Display for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cipher::Aes256Gcm => write!(f, "AES-256-GCM"),
+            Cipher::ChaCha20Poly1305 => write!(f, "ChaCha20-Poly1305"),
+        }
+    }
+}
+
+/// Key derivation function that turns the password into the store key. It is
+/// recorded in the store header, so stores keep opening with the function
+/// they were created with if the default changes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Kdf {
+    #[default]
+    HkdfSha256,
+    Argon2id,
+    HkdfBlake2b,
+}
+
+impl Display for Kdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kdf::HkdfSha256 => write!(f, "HKDF-SHA256"),
+            Kdf::Argon2id => write!(f, "Argon2id"),
+            Kdf::HkdfBlake2b => write!(f, "HKDF-BLAKE2b"),
+        }
+    }
+}
+
+/// Cost parameters of the key derivation function. Only used by Argon2id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Memory size, in KiB.
+    pub memory: u32,
+    /// Number of iterations.
+    pub iterations: u32,
+    /// Degree of parallelism.
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            memory: 19456,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+/// Rules the password of a new store must follow, see
+/// `StoreBuilder::password_policy`. The default only rejects blank passwords.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    /// Fewest characters the password may have.
+    pub min_length: usize,
+    /// Whether empty passwords, and those made only of whitespace, are
+    /// rejected.
+    pub reject_blank: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 0,
+            reject_blank: true,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Returns `WeakPassword` if the password does not follow the policy.
+    pub fn check(&self, password: &str) -> Result<(), Error> {
+        if password.chars().count() < self.min_length
+            || (self.reject_blank && password.trim().is_empty())
+        {
+            return Err(Error::WeakPassword);
+        }
+        Ok(())
+    }
+}
+
+/// What is needed to open a store. It is recorded in the store header, so
+/// opening a store that needs a key file with a password fails early.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Credentials {
+    #[default]
+    Password,
+    Keyfile,
+    /// Both the password and the key file, neither opens the store alone.
+    PasswordAndKeyfile,
+}
+
+impl Credentials {
+    /// Whether the store cannot be opened without its key file.
+    pub fn needs_keyfile(&self) -> bool {
+        *self != Credentials::Password
+    }
+}
+
+/// Compression applied to each part before it is encrypted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+}
+
+/// What `Store::list_sorted` orders files by. Files that compare equal are
+/// ordered by name.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Modified,
+}
+
+/// Size of the parts files are split into, unless configured otherwise.
+pub const DEFAULT_CHUNK_SIZE: u64 = 52428800; // 50MB
+
+/// Largest size of the parts files are split into. A whole part is kept in
+/// memory while it is encrypted or decrypted, once per thread.
+pub const MAX_CHUNK_SIZE: u64 = 1 << 30; // 1GB
+
+/// How deep folders added to the store can be nested, unless configured
+/// otherwise.
+pub const DEFAULT_MAX_DEPTH: usize = 1024;
+
+/// Folder `Store::trash` moves files into. Its name starts with a dot, so it
+/// is hidden from `Store::list` unless enabled with `Store::with_hidden`.
+pub const TRASH: &str = "/.trash";
+
+/// Whether a path is the trash or inside it.
+fn in_trash(path: &str) -> bool {
+    path.strip_prefix(TRASH)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// The content hash recorded in the `hash` metadata key of a file, if any.
+fn recorded_hash(file: &File) -> Option<[u8; 32]> {
+    let hash = hex::decode(file.metadata.get("hash")?).ok()?;
+    <[u8; 32]>::try_from(hash).ok()
+}
+
+fn default_chunk_size() -> u64 {
+    DEFAULT_CHUNK_SIZE
+}
+
 trait FlexBufferSerializable {
     fn fb_serialize(&self) -> Result<Vec<u8>, Error>;
     fn fb_deserialize(bytes: &[u8]) -> Result<Box<Self>, Error>;
@@ -54,6 +339,50 @@ struct StoreFile {
     fs_hash: [u8; 32],
     iv: [u8; 16],
     salt: [u8; 16],
+    // The filesystem key, encrypted with the password key. Empty on stores
+    // without a recovery key, where the password key encrypts the filesystem.
+    #[serde(default)]
+    password_key: Vec<u8>,
+    // The filesystem key, encrypted with the recovery key.
+    #[serde(default)]
+    recovery_key: Vec<u8>,
+    #[serde(default)]
+    key_iv: [u8; 16],
+    #[serde(default)]
+    cipher: Cipher,
+    #[serde(default)]
+    kdf: Kdf,
+    // Known bytes encrypted with the filesystem key, checked on open before
+    // decrypting the filesystem. Empty on stores created before it existed.
+    #[serde(default)]
+    canary: Vec<u8>,
+    #[serde(default)]
+    canary_iv: [u8; 16],
+    #[serde(default)]
+    kdf_params: KdfParams,
+    #[serde(default)]
+    compression: Compression,
+    #[serde(default = "default_chunk_size")]
+    chunk_size: u64,
+    // A label for the store, encrypted with the filesystem key. Empty if the
+    // store has no description.
+    #[serde(default)]
+    description: Vec<u8>,
+    #[serde(default)]
+    description_iv: [u8; 16],
+    // Levels of subfolders part files are sharded into, see
+    // `FsBackend::with_shard_levels`.
+    #[serde(default)]
+    shard_levels: u8,
+    #[serde(default)]
+    credentials: Credentials,
+    // Most bytes of files the store can hold, none if it has no limit.
+    #[serde(default)]
+    quota_bytes: Option<u64>,
+    // Iv that encrypts the filesystem, new on every save. Zeroed on stores
+    // saved before it existed, where `iv` encrypts the filesystem.
+    #[serde(default)]
+    fs_iv: [u8; 16],
 }
 
 impl FlexBufferSerializable for StoreFile {
@@ -67,6 +396,10 @@ impl FlexBufferSerializable for StoreFile {
     }
 
     fn fb_deserialize(bytes: &[u8]) -> Result<Box<Self>, Error> {
+        if !flexbuffer::is_valid(bytes) {
+            return Err(Error::CannotDeserializeError);
+        }
+
         let reader = match Reader::get_root(bytes) {
             Ok(reader) => reader,
             Err(_) => return Err(Error::CannotDeserializeError),
@@ -79,6 +412,40 @@ impl FlexBufferSerializable for StoreFile {
     }
 }
 
+/// A store saved by `Store::save_to_bytes`.
+#[derive(Serialize, Deserialize)]
+struct StoreBlob {
+    index: Vec<u8>,
+    // The encrypted parts, by id.
+    parts: Vec<(u64, Vec<u8>)>,
+}
+
+impl FlexBufferSerializable for StoreBlob {
+    fn fb_serialize(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = FlexbufferSerializer::new();
+        match self.serialize(&mut bytes) {
+            Ok(_) => Ok(bytes.view().into()),
+            Err(_) => Err(Error::CannotSerializeError),
+        }
+    }
+
+    fn fb_deserialize(bytes: &[u8]) -> Result<Box<Self>, Error> {
+        if !flexbuffer::is_valid(bytes) {
+            return Err(Error::CannotDeserializeError);
+        }
+
+        let reader = match Reader::get_root(bytes) {
+            Ok(reader) => reader,
+            Err(_) => return Err(Error::CannotDeserializeError),
+        };
+
+        match StoreBlob::deserialize(reader) {
+            Ok(blob) => Ok(Box::new(blob)),
+            Err(_) => Err(Error::CannotDeserializeError),
+        }
+    }
+}
+
 impl FlexBufferSerializable for Filesystem {
     fn fb_serialize(&self) -> Result<Vec<u8>, Error> {
         let mut bytes = FlexbufferSerializer::new();
@@ -89,6 +456,10 @@ impl FlexBufferSerializable for Filesystem {
     }
 
     fn fb_deserialize(bytes: &[u8]) -> Result<Box<Self>, Error> {
+        if !flexbuffer::is_valid(bytes) {
+            return Err(Error::CannotDeserializeError);
+        }
+
         let reader = match Reader::get_root(bytes) {
             Ok(reader) => reader,
             Err(_) => return Err(Error::CannotDeserializeError),
@@ -101,13 +472,587 @@ impl FlexBufferSerializable for Filesystem {
     }
 }
 
+/// Entry points for the fuzz targets in `void/fuzz`. Not part of the API.
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing {
+    use super::*;
+
+    /// Deserializes untrusted bytes as the header of a store.
+    pub fn deserialize_store_file(bytes: &[u8]) -> Result<(), Error> {
+        StoreFile::fb_deserialize(bytes).map(|_| ())
+    }
+
+    /// Deserializes untrusted bytes as the filesystem of a store.
+    pub fn deserialize_filesystem(bytes: &[u8]) -> Result<(), Error> {
+        Filesystem::fb_deserialize(bytes).map(|_| ())
+    }
+}
+
+/// Returns the hex encoded content hash of a file on disk, the same that is
+/// saved in the `hash` metadata of files in the store.
+///
+/// # Arguments
+///
+/// * `path` - File path in the disk.
+fn hash_file(path: &Path) -> Result<String, Error> {
+    let mut file = fs::File::open(&path.path).map_err(|_| Error::CannotReadFileError)?;
+    let mut bytes = vec![0u8; 1048576]; // 1MB
+    let mut hasher = crypto::Hasher::new();
+
+    loop {
+        let bytes_read = file
+            .read(bytes.as_mut_slice())
+            .map_err(|_| Error::CannotReadFileError)?;
+
+        if bytes_read == 0 {
+            break;
+        }
+
+        hasher.update(&bytes[..bytes_read]);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Estimates how hard a password is to guess, from 0 to 100, to show as a
+/// strength meter or to warn about weak passwords. It is the entropy, in
+/// bits, of a random password of the same length drawn from the classes of
+/// characters it uses (lowercase, uppercase, digits, symbols and others),
+/// capped at 100. A character repeating the one before it adds nothing.
+///
+/// # Example
+///
+/// ```
+/// assert_eq!(void::password_strength(""), 0);
+/// assert!(void::password_strength("1234") < void::password_strength("c0rrect h0rse"));
+/// ```
+pub fn password_strength(password: &str) -> u8 {
+    let has = |class: fn(&char) -> bool| password.chars().any(|c| class(&c));
+    let pool: u32 = [
+        (has(char::is_ascii_lowercase), 26),
+        (has(char::is_ascii_uppercase), 26),
+        (has(char::is_ascii_digit), 10),
+        (has(char::is_ascii_punctuation), 33),
+        (has(|c| *c == ' '), 1),
+        (has(|c| !c.is_ascii()), 100),
+    ]
+    .iter()
+    .filter(|(has, _)| *has)
+    .map(|(_, size)| size)
+    .sum();
+
+    let mut length = 0;
+    let mut previous = None;
+    for c in password.chars() {
+        if previous != Some(c) {
+            length += 1;
+        }
+        previous = Some(c);
+    }
+
+    if pool == 0 {
+        return 0;
+    }
+    let bits = length as f64 * f64::from(pool).log2();
+    bits.min(100.0).round() as u8
+}
+
+/// Turns a recovery phrase into the password used to derive the recovery key.
+/// Separators, spaces and case are ignored.
+fn normalize_recovery_phrase(phrase: &str) -> String {
+    phrase
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Turns the contents of a key file, and the password if the store needs
+/// both, into the secret the store key is derived from. The key file is
+/// hashed so files of any size and content can be used.
+fn keyfile_secret(keyfile: &[u8], password: &str) -> Zeroizing<String> {
+    let mut hasher = crypto::Hasher::new();
+    hasher.update(keyfile);
+    Zeroizing::new(hex::encode(hasher.finalize()) + password)
+}
+
+/// Copies a folder and everything in it.
+///
+/// # Arguments
+///
+/// * `from` - Folder to copy.
+/// * `to` - Path of the copy. Its parent folders are created if missing.
+fn copy_folder(from: &str, to: &str) -> Result<(), Error> {
+    for entry in walkdir::WalkDir::new(from) {
+        let entry = entry.map_err(|_| Error::CannotReadFileError)?;
+        let relative = entry
+            .path()
+            .strip_prefix(from)
+            .map_err(|_| Error::CannotParseError)?;
+        let target = std::path::Path::new(to).join(relative);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Known plaintext of the store canary.
+const CANARY: &[u8] = b"void store canary";
+
+/// Encrypts the canary with a store key.
+///
+/// # Returns
+///
+/// * The encrypted canary and the iv used to encrypt it.
+fn make_canary(cipher: Cipher, key: &[u8; 32]) -> Result<(Vec<u8>, [u8; 16]), Error> {
+    let iv = crypto::uuid();
+    Ok((crypto::encrypt_with(cipher, CANARY, key, &iv)?, iv))
+}
+
+/// Compresses a part before encryption.
+fn compress(compression: Compression, data: &[u8]) -> Result<Cow<'_, [u8]>, Error> {
+    match compression {
+        Compression::None => Ok(Cow::Borrowed(data)),
+        Compression::Deflate => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder
+                .write_all(data)
+                .map_err(|_| Error::CannotWriteFileError)?;
+            let data = encoder.finish().map_err(|_| Error::CannotWriteFileError)?;
+            Ok(Cow::Owned(data))
+        }
+    }
+}
+
+/// Decompresses a part after decryption.
+fn decompress(compression: Compression, data: Vec<u8>) -> Result<Vec<u8>, Error> {
+    match compression {
+        Compression::None => Ok(data),
+        Compression::Deflate => {
+            let mut decompressed = Vec::new();
+            DeflateDecoder::new(data.as_slice())
+                .read_to_end(&mut decompressed)
+                .map_err(|_| Error::CannotDecryptFileError)?;
+            Ok(decompressed)
+        }
+    }
+}
+
+/// Creates the Data object of a new part, with a fresh random key and iv.
+///
+/// # Arguments
+///
+/// * `len` - Length of the plaintext of the part.
+fn new_data(len: u64) -> Data {
+    let salt = crypto::uuid();
+    let iv = crypto::uuid();
+    let pswd = hex::encode(crypto::uuid());
+    let key = crypto::derive_key(&pswd, &salt, &iv);
+
+    Data {
+        id: 0,
+        key,
+        iv,
+        salt,
+        len,
+    }
+}
+
+/// Compresses and encrypts a chunk with a fresh random key and iv.
+///
+/// # Arguments
+///
+/// * `cipher` - Cipher of the store.
+/// * `compression` - Compression of the store.
+/// * `chunk` - Plaintext of the part.
+///
+/// # Returns
+///
+/// * The Data object of the part, without an id, and its encrypted content.
+fn encrypt_chunk(
+    cipher: Cipher,
+    compression: Compression,
+    chunk: &[u8],
+) -> Result<(Data, Vec<u8>), Error> {
+    let mut content = vec![];
+    let data = encrypt_chunk_into(cipher, compression, chunk, &mut content)?;
+    Ok((data, content))
+}
+
+/// Same as `encrypt_chunk`, but writes the encrypted content to `out`, so
+/// that its allocation is reused from chunk to chunk.
+///
+/// # Returns
+///
+/// * The Data object of the part, without an id.
+fn encrypt_chunk_into(
+    cipher: Cipher,
+    compression: Compression,
+    chunk: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<Data, Error> {
+    let data = new_data(chunk.len() as u64);
+    let content = compress(compression, chunk)?;
+    crypto::encrypt_into(cipher, &content, &data.key, &data.iv, out)?;
+    Ok(data)
+}
+
+/// Reads a file from disk and encrypts it, chunk by chunk, sending each part
+/// to `parts`. Stops early if `stop` is set.
+///
+/// # Arguments
+///
+/// * `index` - Index of the file, sent along with its parts.
+/// * `path` - Path of the file on disk.
+/// * `store` - Cipher, compression and chunk size of the store.
+///
+/// # Returns
+///
+/// * The size, mimetype and content hash of the file.
+fn encrypt_file(
+    index: usize,
+    path: &str,
+    store: (Cipher, Compression, u64),
+    parts: &std::sync::mpsc::SyncSender<Encrypted>,
+    stop: &AtomicBool,
+) -> Result<(u64, String, crypto::Hasher), Error> {
+    let (cipher, compression, chunk_size) = store;
+    let mut file = fs::File::open(path)?;
+    let mimetype = tree_magic::from_filepath(std::path::Path::new(path));
+    let mut bytes = vec![0u8; chunk_size as usize];
+    let mut hasher = crypto::Hasher::new();
+    let mut size: u64 = 0;
+
+    while !stop.load(Ordering::Relaxed) {
+        let bytes_read =
+            read_chunk(&mut file, &mut bytes).map_err(|_| Error::CannotReadFileError)?;
+        if bytes_read == 0 {
+            break;
+        }
+
+        let chunk = &bytes[..bytes_read];
+        hasher.update(chunk);
+        size += bytes_read as u64;
+        let (data, content) = encrypt_chunk(cipher, compression, chunk)?;
+        // The receiver only hangs up after an error, which stops us anyway.
+        if parts.send(Encrypted::Part(index, data, content)).is_err() {
+            break;
+        }
+    }
+
+    Ok((size, mimetype, hasher))
+}
+
+/// What the threads that encrypt files send to the one that updates the
+/// index.
+enum Encrypted {
+    /// An encrypted part of the file with the given index.
+    Part(usize, Data, Vec<u8>),
+    /// The file with the given index was fully read, or failed.
+    Done(usize, Result<(u64, String, crypto::Hasher), Error>),
+}
+
+/// Reads from `reader` until `buffer` is full or the stream ends, so that
+/// every chunk but the last has the size of the buffer.
+///
+/// # Returns
+///
+/// * The number of bytes read.
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut total = 0;
+    while total < buffer.len() {
+        match reader.read(&mut buffer[total..]) {
+            Ok(0) => break,
+            Ok(size) => total += size,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(total)
+}
+
+/// Reads the decrypted contents of a file in the store, one part at a time.
+struct FileReader<'a> {
+    store: &'a Store,
+    data: Vec<Data>,
+    index: usize,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl<'a> FileReader<'a> {
+    fn new(store: &'a Store, data: Vec<Data>) -> FileReader<'a> {
+        FileReader {
+            store,
+            data,
+            index: 0,
+            buffer: vec![],
+            position: 0,
+        }
+    }
+}
+
+impl Read for FileReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.buffer.len() {
+            if self.index == self.data.len() {
+                return Ok(0);
+            }
+            let data = &self.data[self.index];
+            self.buffer = self.store.read_part(data).map_err(std::io::Error::other)?;
+            self.index += 1;
+            self.position = 0;
+        }
+        let size = buf.len().min(self.buffer.len() - self.position);
+        buf[..size].copy_from_slice(&self.buffer[self.position..self.position + size]);
+        self.position += size;
+        Ok(size)
+    }
+}
+
+/// A copy of the index of a store, taken by `Store::snapshot`, that
+/// `Store::restore_snapshot` reverts the index to. It holds no file content.
 #[derive(Debug, Clone)]
+pub struct FilesystemSnapshot {
+    fs: Filesystem,
+}
+
+/// Totals about the contents of a store.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoreStats {
+    /// Number of files.
+    pub files: usize,
+    /// Number of folders, not counting the root.
+    pub folders: usize,
+    /// Sum of the sizes of all files, in bytes.
+    pub total_bytes: u64,
+    /// Number of distinct tags.
+    pub tags: usize,
+    /// Number of encrypted parts.
+    pub parts: usize,
+    /// Size of the store folder on disk, in bytes.
+    pub disk_size: u64,
+}
+
+/// Differences between two stores, see `Store::diff`. Paths are sorted.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct DiffReport {
+    /// Files and folders only in the store `diff` was called on.
+    pub only_in_self: Vec<String>,
+    /// Files and folders only in the other store.
+    pub only_in_other: Vec<String>,
+    /// Files in both stores with different contents, or that are a file in
+    /// one store and a folder in the other.
+    pub changed: Vec<String>,
+}
+
+/// How far an `add` or `get` got, passed to the callbacks of
+/// `Store::add_with_progress` and `Store::get_with_progress` after each part
+/// and each file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// Files processed so far.
+    pub files: u64,
+    /// Files to process.
+    pub total_files: u64,
+    /// Bytes processed so far.
+    pub bytes: u64,
+    /// Bytes to process.
+    pub total_bytes: u64,
+}
+
+/// An open store.
+///
+/// Stores cannot be cloned: two copies of the index would both save to the
+/// same store file, and whichever saved last would silently drop the changes
+/// made through the other, and could delete parts the other still refers to.
+/// To use a store from many threads, share it with `SharedStore`.
+///
+/// ```compile_fail
+/// fn copy(store: &void::Store) -> void::Store {
+///     store.clone()
+/// }
+/// ```
+#[derive(Debug)]
 pub struct Store {
     fs: Filesystem,
     iv: [u8; 16],
     key: [u8; 32],
-    path: String,
+    backend: Arc<dyn Backend>,
     salt: [u8; 16],
+    password_key: Vec<u8>,
+    recovery_key: Vec<u8>,
+    key_iv: [u8; 16],
+    cipher: Cipher,
+    kdf: Kdf,
+    canary: Vec<u8>,
+    canary_iv: [u8; 16],
+    kdf_params: KdfParams,
+    compression: Compression,
+    chunk_size: u64,
+    description: Vec<u8>,
+    description_iv: [u8; 16],
+    shard_levels: u8,
+    credentials: Credentials,
+    quota_bytes: Option<u64>,
+    backups: usize,
+    mirrored: bool,
+    track_access: bool,
+    threads: usize,
+    max_depth: usize,
+    follow_links: bool,
+    preserve: bool,
+    show_hidden: bool,
+    search_index: Option<SearchIndex>,
+    #[cfg(feature = "mmap")]
+    mmap: bool,
+}
+
+/// Creates stores with non-default options.
+///
+/// # Example
+///
+/// ```ignore
+/// let (store, phrase) = StoreBuilder::new("store", "password")
+///     .cipher(Cipher::ChaCha20Poly1305)
+///     .kdf(Kdf::Argon2id)
+///     .with_recovery(true)
+///     .build()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct StoreBuilder {
+    path: String,
+    password: Zeroizing<String>,
+    cipher: Cipher,
+    kdf: Kdf,
+    kdf_params: KdfParams,
+    compression: Compression,
+    chunk_size: u64,
+    recovery: bool,
+    shard_levels: u8,
+    password_policy: PasswordPolicy,
+    quota_bytes: Option<u64>,
+    // Contents of the key file that replaces the password.
+    keyfile: Option<Vec<u8>>,
+    // Where the store is kept, instead of a folder in `path`.
+    backend: Option<Arc<dyn Backend>>,
+}
+
+impl StoreBuilder {
+    /// Starts building a store with the default options.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path where the store should be created.
+    /// * `password` - Password that encrypts the store.
+    pub fn new<S: Into<String>>(path: S, password: S) -> StoreBuilder {
+        StoreBuilder {
+            path: path.into(),
+            password: Zeroizing::new(password.into()),
+            cipher: Cipher::default(),
+            kdf: Kdf::default(),
+            kdf_params: KdfParams::default(),
+            compression: Compression::default(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            recovery: false,
+            shard_levels: 0,
+            password_policy: PasswordPolicy::default(),
+            quota_bytes: None,
+            keyfile: None,
+            backend: None,
+        }
+    }
+
+    /// Sets the cipher that encrypts the store.
+    pub fn cipher(mut self, cipher: Cipher) -> StoreBuilder {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Sets the function that derives the key from the password.
+    pub fn kdf(mut self, kdf: Kdf) -> StoreBuilder {
+        self.kdf = kdf;
+        self
+    }
+
+    /// Sets the cost parameters of the key derivation function.
+    pub fn kdf_params(mut self, kdf_params: KdfParams) -> StoreBuilder {
+        self.kdf_params = kdf_params;
+        self
+    }
+
+    /// Sets the compression applied to file parts.
+    pub fn compression(mut self, compression: Compression) -> StoreBuilder {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the size, in bytes, of the parts files are split into. Each part
+    /// is kept in memory while it is written or read, so `build` returns
+    /// `InvalidChunkSize` if it is zero, larger than `MAX_CHUNK_SIZE` or
+    /// larger than `Cipher::max_chunk_size`.
+    pub fn chunk_size(mut self, chunk_size: u64) -> StoreBuilder {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Shards the part files of the store folder into `levels` levels of
+    /// subfolders, so that large stores do not put all their parts in a
+    /// single folder. See `FsBackend::with_shard_levels`. Ignored when the
+    /// store is kept in another backend.
+    pub fn shard_levels(mut self, levels: u8) -> StoreBuilder {
+        self.shard_levels = levels.min(MAX_SHARD_LEVELS);
+        self
+    }
+
+    /// Sets the rules the password must follow. `build` returns
+    /// `WeakPassword` if it does not. Stores opened with a key file alone
+    /// have no password to check.
+    pub fn password_policy(mut self, policy: PasswordPolicy) -> StoreBuilder {
+        self.password_policy = policy;
+        self
+    }
+
+    /// Limits the total size of the files in the store to `quota_bytes`.
+    /// Adding past it returns `QuotaExceeded`.
+    pub fn quota_bytes(mut self, quota_bytes: u64) -> StoreBuilder {
+        self.quota_bytes = Some(quota_bytes);
+        self
+    }
+
+    /// Sets whether the store can also be opened with a recovery phrase.
+    pub fn with_recovery(mut self, recovery: bool) -> StoreBuilder {
+        self.recovery = recovery;
+        self
+    }
+
+    /// Derives the store key from the contents of a key file. If the
+    /// password is not empty, both are needed to open the store, otherwise
+    /// the key file alone opens it.
+    pub fn keyfile(mut self, keyfile: &[u8]) -> StoreBuilder {
+        self.keyfile = Some(keyfile.to_vec());
+        self
+    }
+
+    /// Keeps the store in `backend` instead of a folder in `path`, which is
+    /// then ignored. The backend must be empty.
+    pub fn backend(mut self, backend: Arc<dyn Backend>) -> StoreBuilder {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Creates the store.
+    ///
+    /// # Returns
+    ///
+    /// * The Store object and, if requested, the recovery phrase.
+    pub fn build(self) -> Result<(Store, Option<String>), Error> {
+        Store::create_store(self)
+    }
 }
 
 impl Store {
@@ -117,20 +1062,33 @@ impl Store {
     ///
     /// * `path` - Path of the store.
     /// * `password` - Password that encrypts the store.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     fn save(&mut self) -> Result<(), Error> {
-        let store_folder = Path::new(&self.path).ok_or(Error::CannotParseError)?;
-        let store_journal = store_folder
-            .join("Store.void")
-            .ok_or(Error::CannotParseError)?;
+        self.fs.sort();
+        if self.search_index.is_some() {
+            self.search_index = Some(self.fs.search_index());
+        }
+        let serialized = self.serialize_index()?;
 
-        if !store_journal.exists() {
-            return Err(Error::FileDoesNotExistError);
+        self.rotate_backups()?;
+        self.backend.write_index(INDEX, &serialized)?;
+
+        if self.mirrored {
+            self.backend
+                .write_index(&format!("{INDEX}.mirror"), &serialized)?;
         }
 
-        self.fs.sort();
+        Ok(())
+    }
+
+    /// Encrypts the filesystem and serializes it with the store header, as
+    /// it is saved in the store file. Each call uses a new iv, so no two
+    /// saves, backups included, share one.
+    fn serialize_index(&self) -> Result<Vec<u8>, Error> {
         let fs_bytes = self.fs.fb_serialize()?;
 
-        let fs = crypto::encrypt(fs_bytes.as_slice(), &self.key, &self.iv)?;
+        let fs_iv = crypto::uuid();
+        let fs = crypto::encrypt_with(self.cipher, fs_bytes.as_slice(), &self.key, &fs_iv)?;
         let fs_hash_vec = crypto::hash(fs.as_slice(), &self.salt);
         let mut fs_hash = [0u8; 32];
 
@@ -141,421 +1099,3309 @@ impl Store {
             fs_hash,
             iv: self.iv,
             salt: self.salt,
+            password_key: self.password_key.clone(),
+            recovery_key: self.recovery_key.clone(),
+            key_iv: self.key_iv,
+            cipher: self.cipher,
+            kdf: self.kdf,
+            canary: self.canary.clone(),
+            canary_iv: self.canary_iv,
+            kdf_params: self.kdf_params,
+            compression: self.compression,
+            chunk_size: self.chunk_size,
+            description: self.description.clone(),
+            description_iv: self.description_iv,
+            shard_levels: self.shard_levels,
+            credentials: self.credentials,
+            quota_bytes: self.quota_bytes,
+            fs_iv,
         };
 
-        let serialized = match store_file.fb_serialize() {
-            Ok(bytes) => bytes,
-            Err(err) => return Err(err),
-        };
-
-        match fs::write(store_journal.path, serialized.as_slice()) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(Error::CannotWriteFileError),
-        }
+        store_file.fb_serialize()
     }
 
-    /// Creates a new store and return a Store object.
+    /// Makes `save` keep copies of the previous store files. The most recent
+    /// one is `Store.void.bak`, older ones are `Store.void.bak.1`,
+    /// `Store.void.bak.2` and so on, up to `backups` copies. Zero, the
+    /// default, keeps no backups.
     ///
     /// # Arguments
     ///
-    /// * `path` - Path where the store should be created.
-    /// * `password` - Password that encrypts the store.
-    pub fn create<S: Into<String>>(path: S, password: S) -> Result<Store, Error> {
-        let path: String = path.into();
-        let password: String = password.into();
-
-        let store_folder = Path::new(&path).ok_or(Error::CannotParseError)?;
-        let store_journal = store_folder
-            .join("Store.void")
-            .ok_or(Error::CannotParseError)?;
+    /// * `backups` - Number of backups to keep.
+    pub fn with_backups(mut self, backups: usize) -> Store {
+        self.backups = backups;
+        self
+    }
 
-        if store_folder.exists() {
-            return Err(Error::FileAlreadyExistsError);
-        }
+    /// Makes `save` also write a copy of the store file to
+    /// `Store.void.mirror`. When the store file cannot be read or is
+    /// corrupted, `open` reads the mirror instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `mirrored` - Whether to keep the mirror up to date.
+    pub fn with_mirrored_index(mut self, mirrored: bool) -> Store {
+        self.mirrored = mirrored;
+        self
+    }
 
-        if fs::create_dir_all(&store_folder.path).is_err() {
-            return Err(Error::CannotCreateDirectoryError);
-        }
+    /// Makes `get` and `read_range` record when each file was last read, see
+    /// `least_recently_accessed`. Recording saves the store after every read,
+    /// once per call, so it is off by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `track_access` - Whether to record reads.
+    pub fn with_access_tracking(mut self, track_access: bool) -> Store {
+        self.track_access = track_access;
+        self
+    }
 
-        if fs::write(store_journal.path, "").is_err() {
-            return Err(Error::CannotWriteFileError);
-        }
+    /// Makes `add` and `sync` return `MaxDepthExceeded` for folders nested
+    /// deeper than `max_depth` below the added one, before adding anything,
+    /// instead of walking them. Defaults to `DEFAULT_MAX_DEPTH`.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_depth` - Deepest level of nesting allowed.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Store {
+        self.max_depth = max_depth;
+        self
+    }
 
-        let salt = crypto::uuid();
-        let iv = crypto::uuid();
-        let key = crypto::derive_key(&password, &salt, &iv);
+    /// Makes `add` store symbolic links as links instead of adding what they
+    /// point to. A stored link is an empty file with its target in
+    /// `File::link`, and `get` recreates it as a link on Unix.
+    /// Links are followed by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `follow_links` - Whether to add the targets of links.
+    pub fn with_follow_links(mut self, follow_links: bool) -> Store {
+        self.follow_links = follow_links;
+        self
+    }
 
-        let mut store = Store {
-            fs: Filesystem::new(),
-            iv,
-            key,
-            path: store_folder.path,
-            salt,
-        };
+    /// Makes `add` record the modification time and, on Unix, the
+    /// permissions of files in the `mtime` and `mode` metadata keys, and `get`
+    /// restore them on the files it writes. Off by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `preserve` - Whether to record and restore file attributes.
+    pub fn with_preserve(mut self, preserve: bool) -> Store {
+        self.preserve = preserve;
+        self
+    }
 
-        store.save()?;
-        Ok(store)
+    /// Makes `list` show hidden files and folders, those whose name starts
+    /// with a dot, like the trash folder. Hidden by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `show_hidden` - Whether to list hidden files and folders.
+    pub fn with_hidden(mut self, show_hidden: bool) -> Store {
+        self.show_hidden = show_hidden;
+        self
     }
 
-    /// Opens an existing store and return a Store object.
+    /// Keeps an index of the tags and names of files in memory, so that
+    /// `tag_search` and `find` do not look at every file. It is built here
+    /// and again each time the store is saved, and is never written to the
+    /// store. Disabled by default.
     ///
     /// # Arguments
     ///
-    /// * `path` - Path of the store to be opened.
-    /// * `password` - Password that encrypts the store.
-    pub fn open<S: Into<String>>(path: S, password: S) -> Result<Store, Error> {
-        let path: String = path.into();
-        let password: String = password.into();
+    /// * `enabled` - Whether to keep the index.
+    pub fn with_search_index(mut self, enabled: bool) -> Store {
+        self.search_index = enabled.then(|| self.fs.search_index());
+        self
+    }
 
-        let store_folder = Path::new(&path).ok_or(Error::CannotParseError)?;
-        let store_journal = store_folder
-            .join("Store.void")
-            .ok_or(Error::CannotParseError)?;
+    /// Makes `add` encrypt the files of a folder in `threads` parallel
+    /// threads, and the chunks of a single file too. The index is still
+    /// updated by a single thread, in the order parts are encrypted. One, the
+    /// default, encrypts one chunk at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `threads` - Number of files encrypted at the same time.
+    pub fn with_threads(mut self, threads: usize) -> Store {
+        self.threads = threads.max(1);
+        self
+    }
 
-        if !store_folder.exists() {
-            return Err(Error::FolderDoesNotExistError);
-        } else if !store_journal.exists() {
-            return Err(Error::FileDoesNotExistError);
+    /// Makes reads map part files into memory instead of copying them into a
+    /// buffer before decrypting, which halves the memory used by large parts.
+    ///
+    /// # Arguments
+    ///
+    /// * `mmap` - Whether to map part files.
+    #[cfg(feature = "mmap")]
+    pub fn with_mmap(mut self, mmap: bool) -> Store {
+        self.mmap = mmap;
+        self
+    }
+
+    /// Shifts the existing backups by one, dropping the oldest, and copies the
+    /// current store file to `Store.void.bak`.
+    fn rotate_backups(&self) -> Result<(), Error> {
+        if self.backups == 0 {
+            return Ok(());
         }
+        let current = match self.backend.read_index(INDEX) {
+            Ok(bytes) if !bytes.is_empty() => bytes,
+            _ => return Ok(()),
+        };
 
-        let bytes = fs::read(store_journal.path).map_err(|_| Error::CannotReadFileError)?;
-        let store_file = StoreFile::fb_deserialize(bytes.as_slice())?;
+        let backup = |i: usize| match i {
+            0 => format!("{INDEX}.bak"),
+            i => format!("{INDEX}.bak.{i}"),
+        };
+        for i in (1..self.backups).rev() {
+            match self.backend.read_index(&backup(i - 1)) {
+                Ok(bytes) => self.backend.write_index(&backup(i), &bytes)?,
+                Err(Error::FileDoesNotExistError) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        self.backend.write_index(&backup(0), &current)?;
 
-        let salt = store_file.salt;
-        let iv = store_file.iv;
-        let key = crypto::derive_key(password.as_str(), &salt, &iv);
+        Ok(())
+    }
 
-        let fs = store_file.fs.as_slice();
-        let fs = crypto::decrypt(fs, &key, &iv);
-        let fs = fs.map_err(|_| Error::CannotDecryptFileError)?;
-        let fs = Filesystem::fb_deserialize(fs.as_slice())?;
+    /// Encrypts a file on disk into the store, creating the file node if it
+    /// does not exist and replacing its content if it does, see
+    /// `write_node`. Does not save the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - File path in the disk.
+    /// * `store_path` - Path of the file in the store.
+    /// * `written` - Called with the length of each part once it is written.
+    ///
+    /// # Returns
+    ///
+    /// * The id of the file node.
+    fn write_file(
+        &mut self,
+        file_path: &Path,
+        store_path: &Path,
+        written: &mut dyn FnMut(u64),
+    ) -> Result<u64, Error> {
+        let file_handle = fs::File::open(&file_path.path);
+        let mut file_handle = file_handle.map_err(|_| Error::CannotReadFileError)?;
 
-        let store = Store {
-            fs: *fs,
-            iv,
-            key,
-            path: store_folder.path,
-            salt,
-        };
+        let file_std_path = std::path::Path::new(&file_path.path);
+        let mimetype = tree_magic::from_filepath(file_std_path);
 
-        Ok(store)
+        let existing = self.fs.lookup(&store_path.path)?;
+        let node_id = self.fs.touch(&store_path.path)?;
+        self.write_node(node_id, existing.is_some(), |store| {
+            store.write_data(node_id, &mut file_handle, Some(mimetype), written)
+        })?;
+
+        Ok(node_id)
     }
 
-    /// Encrypts a file and adds it to the store.
-    /// The arguments work like the `rsync` unix command when it comes to
-    /// trailling slashes, so a trailling slash on the source, if it is a
-    /// folder, makes the contents be copied into destination. Without the
-    /// slash, the folder itself is copied.
+    /// Writes the content of a file node with `write`. If `replace` is set,
+    /// the node is an existing file whose content is replaced: its parts are
+    /// kept until the new content is written, and given back if `write`
+    /// fails. Otherwise the node was created for the write, and is removed
+    /// if it fails. Tags and metadata of replaced files are kept either way.
     ///
     /// # Arguments
     ///
-    /// * `file_path` - File path in the disk.
-    /// * `store_path` - Path in store where to save.
-    pub fn add(&mut self, file_path: &str, store_path: &str) -> Result<(), Error> {
-        let source_contents = file_path.ends_with('/');
+    /// * `node_id` - Id of the file node.
+    /// * `replace` - Whether the node existed before the write.
+    /// * `write` - Writes the new content into the empty node.
+    fn write_node<F>(&mut self, node_id: u64, replace: bool, write: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Store) -> Result<(), Error>,
+    {
+        let previous = if replace {
+            Some(self.fs.detach_data(node_id)?)
+        } else {
+            None
+        };
 
-        let file_path: String = file_path.into();
-        let store_path: String = store_path.into();
+        match write(self) {
+            Ok(()) => self.keep_write(previous),
+            Err(err) => {
+                self.undo_write(node_id, previous)?;
+                Err(err)
+            }
+        }
+    }
 
-        let file_path = Path::new(&file_path).ok_or(Error::CannotParseError)?;
-        let store_path = Path::new(&store_path).ok_or(Error::CannotParseError)?;
-        let store_folder = Path::new(&self.path).ok_or(Error::CannotParseError)?;
+    /// Removes the parts a replaced file had before a successful write, see
+    /// `write_node`.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous` - The file before the write, if it existed.
+    fn keep_write(&mut self, previous: Option<File>) -> Result<(), Error> {
+        match previous {
+            Some(previous) => {
+                let data = self.fs.drop_data(&previous);
+                self.remove_parts(&data)
+            }
+            None => Ok(()),
+        }
+    }
 
-        if file_path.is_dir() {
-            let store_path = if self.fs.exists(&store_path.path)? {
-                let id = self.fs.touch(&store_path.path)?;
-                let node = self.fs.get(id)?;
-                if node.is_file {
-                    return Err(Error::CannotCreateDirectoryError);
-                } else if source_contents || &store_path.path == "/" {
-                    store_path
-                } else {
-                    store_path
-                        .join(&file_path.name)
-                        .ok_or(Error::CannotParseError)?
-                }
-            } else {
-                store_path
-            };
+    /// Undoes a failed write, see `write_node`.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - Id of the file node.
+    /// * `previous` - The file before the write, if it existed.
+    fn undo_write(&mut self, node_id: u64, previous: Option<File>) -> Result<(), Error> {
+        let data = match previous {
+            Some(previous) => self.fs.restore_data(&previous)?,
+            None => self.fs.rm(node_id)?,
+        };
+        self.remove_parts(&data)
+    }
 
-            for entry in walkdir::WalkDir::new(&file_path.path)
-                .follow_links(true)
-                .into_iter()
-                .filter_map(Result::ok)
-            {
-                let file_path = if source_contents {
-                    &file_path.path
-                } else {
-                    &file_path.parent
-                };
+    /// Encrypts everything read from `reader` into a file node, in parts of
+    /// the chunk size of the store. The node must not have any data. Fails
+    /// if reading or writing fails, or if the contents would take the store
+    /// over its quota, leaving the parts written so far in the node, see
+    /// `write_node`. Does not save the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - Id of the file node.
+    /// * `reader` - Source of the file contents.
+    /// * `mimetype` - Mimetype of the contents. Detected from the first chunk
+    ///   if not given.
+    /// * `written` - Called with the length of each part once it is written.
+    fn write_data<R: Read>(
+        &mut self,
+        node_id: u64,
+        reader: &mut R,
+        mimetype: Option<String>,
+        written: &mut dyn FnMut(u64),
+    ) -> Result<(), Error> {
+        if self.threads > 1 {
+            return self.write_data_parallel(node_id, reader, mimetype, written);
+        }
 
-                let entry_path: Path = entry.path().to_path_buf().into();
-                let store_path = entry_path
-                    .with_root(file_path, &store_path.path)
-                    .ok_or(Error::CannotParseError)?;
+        let mut bytes = vec![0u8; self.chunk_size as usize];
+        let mut encrypted = vec![];
+        let mut hasher = crypto::Hasher::new();
+        let mut mimetype = mimetype;
+        let mut size: u64 = 0;
 
-                if entry
-                    .metadata()
-                    .map_err(|_| Error::CannotReadFileError)?
-                    .is_dir()
-                {
-                    self.fs.mkdirp(&store_path.path)?;
-                } else {
-                    self.add(&entry_path.path, &store_path.path)?;
-                }
+        loop {
+            let bytes_read =
+                read_chunk(reader, bytes.as_mut_slice()).map_err(|_| Error::CannotReadFileError)?;
+
+            if bytes_read == 0 {
+                break;
             }
-        } else {
-            let store_path = if self.fs.exists(&store_path.path)? {
-                let id = self.fs.touch(&store_path.path)?;
-                let node = self.fs.get(id)?;
-                if node.is_file {
-                    return Err(Error::FileAlreadyExistsError);
-                } else {
-                    store_path
-                        .join(&file_path.name)
-                        .ok_or(Error::CannotParseError)?
-                }
-            } else {
-                store_path
-            };
 
-            let file_handle = fs::File::open(&file_path.path);
-            let mut file_handle = file_handle.map_err(|_| Error::CannotReadFileError)?;
+            let bytes_read = &bytes[..bytes_read];
+            if mimetype.is_none() {
+                mimetype = Some(tree_magic::from_u8(bytes_read));
+            }
+            hasher.update(bytes_read);
+            size += bytes_read.len() as u64;
+            self.check_quota(size, 0)?;
+            self.write_chunk(node_id, bytes_read, &mut encrypted)?;
+            written(bytes_read.len() as u64);
+        }
 
-            let file_std_path = std::path::Path::new(&file_path.path);
-            let mimetype = tree_magic::from_filepath(file_std_path);
+        let mimetype = mimetype.unwrap_or_else(|| tree_magic::from_u8(&[]));
+        self.finish_data(node_id, size, Some(&mimetype), hasher)
+    }
 
-            let file_size = file_std_path
-                .metadata()
-                .map_err(|_| Error::CannotReadFileError)?
-                .len();
+    /// Same as `write_data`, but chunks are encrypted in `threads` parallel
+    /// threads while the next ones are read. Parts are still appended in the
+    /// order they were read. At most `threads` chunks are in memory at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - Id of the file node.
+    /// * `reader` - Source of the file contents.
+    /// * `mimetype` - Mimetype of the contents. Detected from the first chunk
+    ///   if not given.
+    /// * `written` - Called with the length of each part once it is written.
+    fn write_data_parallel<R: Read>(
+        &mut self,
+        node_id: u64,
+        reader: &mut R,
+        mimetype: Option<String>,
+        written: &mut dyn FnMut(u64),
+    ) -> Result<(), Error> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .build()
+            .map_err(|_| Error::InternalStructureError)?;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let (cipher, compression) = (self.cipher, self.compression);
+        let mut hasher = crypto::Hasher::new();
+        let mut mimetype = mimetype;
+        let mut size: u64 = 0;
 
-            let node_id = self.fs.touch(&store_path.path)?;
-            self.fs.set_size(node_id, file_size)?;
-            self.fs.set_metadata(node_id, "mimetype", &mimetype)?;
-            let mut bytes = vec![0u8; 52428800]; // 50MB
+        // Encrypted chunks that arrived before the ones read earlier.
+        let mut pending = BTreeMap::new();
+        let (mut read, mut parts_written) = (0, 0);
+        let mut eof = false;
+        let mut result = Ok(());
 
-            loop {
-                let bytes_read = match file_handle.read(bytes.as_mut_slice()) {
+        while result.is_ok() && !(eof && parts_written == read) {
+            if !eof && read - parts_written < self.threads {
+                let mut bytes = vec![0u8; self.chunk_size as usize];
+                let bytes_read = match read_chunk(reader, bytes.as_mut_slice()) {
                     Ok(size) => size,
                     Err(_) => {
-                        let data = self.fs.rm(node_id)?;
-                        for d in data {
-                            let part_name = hex::encode(d.id.to_be_bytes());
-                            let part_name = format!("{part_name:0>32}");
-                            let part_file = store_folder
-                                .join(part_name)
-                                .ok_or(Error::CannotParseError)?;
-                            fs::remove_file(part_file.path).ok();
-                        }
-                        return Err(Error::CannotReadFileError);
+                        result = Err(Error::CannotReadFileError);
+                        continue;
                     }
                 };
-
                 if bytes_read == 0 {
-                    break;
+                    eof = true;
+                    continue;
                 }
 
-                let salt = crypto::uuid();
-                let iv = crypto::uuid();
-                let pswd = hex::encode(crypto::uuid());
-                let key = crypto::derive_key(&pswd, &salt, &iv);
-
-                let data = Data {
-                    id: 0,
-                    key,
-                    iv,
-                    salt,
-                };
+                bytes.truncate(bytes_read);
+                if mimetype.is_none() {
+                    mimetype = Some(tree_magic::from_u8(&bytes));
+                }
+                hasher.update(&bytes);
+                size += bytes_read as u64;
+                if let Err(err) = self.check_quota(size, 0) {
+                    result = Err(err);
+                    continue;
+                }
 
-                let file = self.fs.append(node_id, &data)?;
-                let data = file
-                    .data
-                    .iter()
-                    .last()
-                    .ok_or(Error::InternalStructureError)?;
-
-                let bytes_read = &bytes[..bytes_read];
-                let content = crypto::encrypt(bytes_read, &key, &iv)?;
-                let part_name = hex::encode(data.id.to_be_bytes());
-                let part_name = format!("{part_name:0>32}");
-                let part_file = store_folder
-                    .join(part_name)
-                    .ok_or(Error::CannotParseError)?;
+                let sender = sender.clone();
+                let index = read;
+                pool.spawn(move || {
+                    let encrypted = encrypt_chunk(cipher, compression, &bytes);
+                    sender.send((index, encrypted)).ok();
+                });
+                read += 1;
+                continue;
+            }
 
-                if fs::write(part_file.path, content).is_err() {
-                    let data = self.fs.rm(node_id)?;
-                    for d in data {
-                        let part_name = hex::encode(d.id.to_be_bytes());
-                        let part_name = format!("{part_name:0>32}");
-                        let part_file = store_folder
-                            .join(part_name)
-                            .ok_or(Error::CannotParseError)?;
-                        fs::remove_file(part_file.path).ok();
+            let (index, encrypted) = receiver.recv().map_err(|_| Error::InternalStructureError)?;
+            pending.insert(index, encrypted);
+            while let Some(encrypted) = pending.remove(&parts_written) {
+                let written_part = encrypted.and_then(|(data, content)| {
+                    self.write_part(node_id, &data, &content).map(|()| data.len)
+                });
+                match written_part {
+                    Ok(len) => written(len),
+                    Err(err) => {
+                        result = Err(err);
+                        break;
                     }
-                    return Err(Error::CannotWriteFileError);
-                };
+                }
+                parts_written += 1;
             }
         }
 
-        self.save()?;
+        result?;
+
+        let mimetype = mimetype.unwrap_or_else(|| tree_magic::from_u8(&[]));
+        self.finish_data(node_id, size, Some(&mimetype), hasher)
+    }
+
+    /// Encrypts a chunk as a new part of a file node.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - Id of the file node.
+    /// * `chunk` - Plaintext of the part.
+    /// * `buffer` - Scratch buffer for the encrypted content, reused from
+    ///   chunk to chunk.
+    fn write_chunk(
+        &mut self,
+        node_id: u64,
+        chunk: &[u8],
+        buffer: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        let data = encrypt_chunk_into(self.cipher, self.compression, chunk, buffer)?;
+        self.write_part(node_id, &data, buffer)
+    }
+
+    /// Appends an encrypted part to a file node and writes its part file.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - Id of the file node.
+    /// * `data` - Data object of the part. Its id is assigned here.
+    /// * `content` - Encrypted content of the part.
+    fn write_part(&mut self, node_id: u64, data: &Data, content: &[u8]) -> Result<(), Error> {
+        let file = self.fs.append(node_id, data)?;
+        let data = file
+            .data
+            .iter()
+            .last()
+            .ok_or(Error::InternalStructureError)?;
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(part = data.id, size = data.len, "encrypting chunk");
+        self.backend.write_part(data.id, content)
+    }
+
+    /// Records the size, mimetype and content hash of a file node after all
+    /// its parts were written. No mimetype is recorded if it is `None`.
+    fn finish_data(
+        &mut self,
+        node_id: u64,
+        size: u64,
+        mimetype: Option<&str>,
+        hasher: crypto::Hasher,
+    ) -> Result<(), Error> {
+        let hash = hex::encode(hasher.finalize());
+        self.fs.set_size(node_id, size)?;
+        if let Some(mimetype) = mimetype {
+            self.fs.set_metadata(node_id, "mimetype", mimetype)?;
+        }
+        self.fs.set_metadata(node_id, "hash", &hash)?;
+
         Ok(())
     }
 
-    /// Decrypts a file from the store and saves it on disk.
+    /// Reads and decrypts a part file.
     ///
     /// # Arguments
     ///
-    /// * `store_path` - Path of folder/file in the store.
-    /// * `file_path` - Path in the disk where to save.
-    pub fn get(&mut self, store_path: &str, file_path: &str) -> Result<(), Error> {
-        let file_path: String = file_path.into();
-        let store_path: String = store_path.into();
+    /// * `data` - Data object of the part.
+    fn read_part(&self, data: &Data) -> Result<Vec<u8>, Error> {
+        let mut content = vec![];
+        self.read_part_into(data, &mut content)?;
+        Ok(content)
+    }
 
-        let store_path = Path::new(&store_path).ok_or(Error::CannotParseError)?;
-        let file_path = Path::new(&file_path).ok_or(Error::CannotParseError)?;
+    /// Same as `read_part`, but reads and decrypts the part in `buffer`, so
+    /// that its allocation is reused from part to part.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data object of the part.
+    /// * `buffer` - Receives the decrypted content of the part.
+    fn read_part_into(&self, data: &Data, buffer: &mut Vec<u8>) -> Result<(), Error> {
+        #[cfg(feature = "mmap")]
+        if let Some(cipher) = self.mmap.then(|| self.backend.map_part(data.id)).flatten() {
+            let content = crypto::decrypt_with(self.cipher, &cipher?, &data.key, &data.iv)?;
+            *buffer = decompress(self.compression, content)?;
+            return Ok(());
+        }
 
-        if file_path.exists() {
-            return Err(Error::FileAlreadyExistsError);
+        self.backend.read_part(data.id, buffer)?;
+        crypto::decrypt_in_place(self.cipher, buffer, &data.key, &data.iv)?;
+        *buffer = decompress(self.compression, std::mem::take(buffer))?;
+        Ok(())
+    }
+
+    /// Deletes the part files of data removed from the filesystem. Parts whose
+    /// data is still in the filesystem, because another node shares it, are
+    /// kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data objects whose part files should be deleted.
+    ///
+    /// # Returns
+    ///
+    /// * `CannotRemoveFilesError` with the paths of the part files that could
+    ///   not be deleted, if any. Missing part files are not an error.
+    fn remove_parts(&self, data: &[Data]) -> Result<(), Error> {
+        let mut failed = vec![];
+        for d in data.iter().filter(|d| !self.fs.has_data(d.id)) {
+            if self.backend.remove_part(d.id).is_err() {
+                failed.push(self.backend.part_location(d.id));
+            }
         }
 
-        if !self.fs.exists(&store_path.path)? {
-            return Err(Error::FileDoesNotExistError);
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::CannotRemoveFilesError(failed))
         }
+    }
 
-        let id = self.fs.touch(&store_path.path)?;
-        let file = self.fs.get(id)?;
+    /// Creates a new store and return a Store object.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path where the store should be created.
+    /// * `password` - Password that encrypts the store.
+    pub fn create<S: Into<String>>(path: S, password: S) -> Result<Store, Error> {
+        let (store, _) = StoreBuilder::new(path, password).build()?;
+        Ok(store)
+    }
 
-        if file.is_file {
-            let disk_path = Path::new(&store_path.path)
-                .ok_or(Error::CannotParseError)?
-                .with_root(&store_path.path, &file_path.path)
-                .ok_or(Error::CannotParseError)?;
+    /// Creates a new store that can also be opened with a recovery phrase, in
+    /// case the password is forgotten. The phrase is only returned here and
+    /// must be kept safe by the user.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path where the store should be created.
+    /// * `password` - Password that encrypts the store.
+    ///
+    /// # Returns
+    ///
+    /// * The Store object and the recovery phrase.
+    pub fn create_with_recovery<S: Into<String>>(
+        path: S,
+        password: S,
+    ) -> Result<(Store, String), Error> {
+        let (store, phrase) = StoreBuilder::new(path, password)
+            .with_recovery(true)
+            .build()?;
+        Ok((store, phrase.ok_or(Error::InternalStructureError)?))
+    }
 
-            if !Path::new(&disk_path.parent)
-                .ok_or(Error::CannotParseError)?
-                .exists()
-            {
-                fs::create_dir_all(disk_path.parent)
-                    .map_err(|_| Error::CannotCreateDirectoryError)?;
+    /// Creates the store folder and journal.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Path, password and options of the store.
+    fn create_store(options: StoreBuilder) -> Result<(Store, Option<String>), Error> {
+        Store::check_chunk_size(options.chunk_size, options.cipher, options.compression)?;
+
+        if options.keyfile.is_none() || !options.password.is_empty() {
+            options.password_policy.check(&options.password)?;
+        }
+
+        if options.backend.is_none() {
+            let store_folder = Path::new(&options.path).ok_or(Error::CannotParseError)?;
+            if store_folder.exists() {
+                return Err(Error::FileAlreadyExistsError);
             }
+        }
 
-            let file_handle = fs::File::create(&disk_path.path);
-            let mut file_handle = file_handle.map_err(|_| Error::CannotWriteFileError)?;
+        let salt = crypto::uuid();
+        let iv = crypto::uuid();
+        let key_iv = crypto::uuid();
+        let (credentials, secret) = match &options.keyfile {
+            Some(keyfile) if options.password.is_empty() => {
+                (Credentials::Keyfile, keyfile_secret(keyfile, ""))
+            }
+            Some(keyfile) => (
+                Credentials::PasswordAndKeyfile,
+                keyfile_secret(keyfile, &options.password),
+            ),
+            None => (Credentials::Password, options.password.clone()),
+        };
+        let password_key = crypto::derive_key_with(
+            options.kdf,
+            &options.kdf_params,
+            secret.as_str(),
+            &salt,
+            &iv,
+        )?;
+
+        let backend = match options.backend {
+            Some(backend) => backend,
+            None => {
+                let backend = FsBackend::create(&options.path)?;
+                Arc::new(backend.with_shard_levels(options.shard_levels))
+            }
+        };
+
+        let mut store = Store {
+            fs: Filesystem::new(),
+            iv,
+            key: password_key,
+            backend,
+            salt,
+            password_key: vec![],
+            recovery_key: vec![],
+            key_iv,
+            cipher: options.cipher,
+            kdf: options.kdf,
+            canary: vec![],
+            canary_iv: [0u8; 16],
+            kdf_params: options.kdf_params,
+            compression: options.compression,
+            chunk_size: options.chunk_size,
+            description: vec![],
+            description_iv: [0u8; 16],
+            shard_levels: options.shard_levels,
+            credentials,
+            quota_bytes: options.quota_bytes,
+            backups: 0,
+            mirrored: false,
+            track_access: false,
+            threads: 1,
+            max_depth: DEFAULT_MAX_DEPTH,
+            follow_links: true,
+            preserve: false,
+            show_hidden: false,
+            search_index: None,
+            #[cfg(feature = "mmap")]
+            mmap: false,
+        };
+
+        let phrase = if options.recovery {
+            let phrase = hex::encode(rand::random::<[u8; 32]>());
+            let recovery_key = crypto::derive_hkdf_key(options.kdf, &phrase, &salt, &iv);
+            let key = rand::random::<[u8; 32]>();
+
+            store.key = key;
+            store.password_key = crypto::encrypt_with(store.cipher, &key, &password_key, &key_iv)?;
+            store.recovery_key = crypto::encrypt_with(store.cipher, &key, &recovery_key, &key_iv)?;
+
+            let groups: Vec<String> = phrase
+                .as_bytes()
+                .chunks(8)
+                .map(|group| String::from_utf8_lossy(group).into())
+                .collect();
+            Some(groups.join("-"))
+        } else {
+            None
+        };
+
+        (store.canary, store.canary_iv) = make_canary(store.cipher, &store.key)?;
+        store.save()?;
+        Ok((store, phrase))
+    }
+
+    /// Opens an existing store and return a Store object.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the store to be opened.
+    /// * `password` - Password that encrypts the store.
+    pub fn open<S: Into<String>>(path: S, password: S) -> Result<Store, Error> {
+        Store::open_secret(path.into(), Zeroizing::new(password.into()))
+    }
+
+    /// Same as `create`, but takes the password in a string that is wiped
+    /// from memory when dropped, after the key is derived from it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path where the store should be created.
+    /// * `password` - Password that encrypts the store.
+    pub fn create_secret<S: Into<String>>(
+        path: S,
+        password: Zeroizing<String>,
+    ) -> Result<Store, Error> {
+        let mut builder = StoreBuilder::new(path.into(), String::new());
+        builder.password = password;
+        let (store, _) = builder.build()?;
+        Ok(store)
+    }
+
+    /// Same as `open`, but takes the password in a string that is wiped from
+    /// memory when dropped, after the key is derived from it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the store to be opened.
+    /// * `password` - Password that encrypts the store.
+    pub fn open_secret<S: Into<String>>(
+        path: S,
+        password: Zeroizing<String>,
+    ) -> Result<Store, Error> {
+        let path: String = path.into();
+
+        let (backend, store_file) = Store::open_folder(&path)?;
+        Store::unlock(backend, store_file, Credentials::Password, &password)
+    }
+
+    /// Creates a new store that is opened with a key file instead of a
+    /// password. Any file can be used, and it must be kept unchanged: the
+    /// store cannot be opened if its contents are lost or modified.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path where the store should be created.
+    /// * `keyfile` - Contents of the key file.
+    pub fn create_with_keyfile<S: Into<String>>(path: S, keyfile: &[u8]) -> Result<Store, Error> {
+        let (store, _) = StoreBuilder::new(path.into(), String::new())
+            .keyfile(keyfile)
+            .build()?;
+        Ok(store)
+    }
+
+    /// Opens an existing store created with `create_with_keyfile`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the store to be opened.
+    /// * `keyfile` - Contents of the key file.
+    pub fn open_with_keyfile<S: Into<String>>(path: S, keyfile: &[u8]) -> Result<Store, Error> {
+        let path: String = path.into();
+        let (backend, store_file) = Store::open_folder(&path)?;
+        Store::unlock(
+            backend,
+            store_file,
+            Credentials::Keyfile,
+            &keyfile_secret(keyfile, ""),
+        )
+    }
+
+    /// Creates a new store that needs both a password and a key file to be
+    /// opened. With an empty password, the key file alone opens it, as with
+    /// `create_with_keyfile`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path where the store should be created.
+    /// * `password` - Password that encrypts the store.
+    /// * `keyfile` - Contents of the key file.
+    pub fn create_with_password_and_keyfile<S: Into<String>>(
+        path: S,
+        password: S,
+        keyfile: &[u8],
+    ) -> Result<Store, Error> {
+        let (store, _) = StoreBuilder::new(path, password).keyfile(keyfile).build()?;
+        Ok(store)
+    }
+
+    /// Opens an existing store created with
+    /// `create_with_password_and_keyfile`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the store to be opened.
+    /// * `password` - Password that encrypts the store.
+    /// * `keyfile` - Contents of the key file.
+    pub fn open_with_password_and_keyfile<S: Into<String>>(
+        path: S,
+        password: S,
+        keyfile: &[u8],
+    ) -> Result<Store, Error> {
+        let path: String = path.into();
+        let password = Zeroizing::new(password.into());
+        let (backend, store_file) = Store::open_folder(&path)?;
+        Store::unlock(
+            backend,
+            store_file,
+            Credentials::PasswordAndKeyfile,
+            &keyfile_secret(keyfile, &password),
+        )
+    }
+
+    /// Returns what is needed to open a store, as recorded in its header,
+    /// without opening it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the store.
+    pub fn required_credentials(path: &str) -> Result<Credentials, Error> {
+        let (_, store_file) = Store::open_folder(path)?;
+        Ok(store_file.credentials)
+    }
+
+    /// Opens a store saved with `save_to_bytes`. The store is kept in memory:
+    /// changes to it are only kept by calling `save_to_bytes` again.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Bytes returned by `save_to_bytes`.
+    /// * `password` - Password that encrypts the store.
+    pub fn open_from_bytes(bytes: &[u8], password: &str) -> Result<Store, Error> {
+        let blob = StoreBlob::fb_deserialize(bytes)?;
+        let parts = blob.parts.into_iter().collect();
+        let backend = MemoryBackend::new(blob.index, parts);
+        Store::open_backend(Arc::new(backend), password)
+    }
+
+    /// Saves the store, with the encrypted content of all its files, to a
+    /// single buffer that `open_from_bytes` can open. Parts are kept
+    /// encrypted, so the buffer is as safe to keep as the store folder.
+    pub fn save_to_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        self.fs.sort();
+        let index = self.serialize_index()?;
+
+        let mut ids: Vec<u64> = self
+            .fs
+            .iter()
+            .flat_map(|file| file.data)
+            .map(|d| d.id)
+            .collect();
+        ids.sort();
+        ids.dedup();
+
+        let mut parts = vec![];
+        for id in ids {
+            let mut part = vec![];
+            self.backend.read_part(id, &mut part)?;
+            parts.push((id, part));
+        }
+
+        StoreBlob { index, parts }.fb_serialize()
+    }
+
+    /// Opens a store kept in a backend other than a folder, created with
+    /// `StoreBuilder::backend`.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Where the store is kept.
+    /// * `password` - Password that encrypts the store.
+    pub fn open_backend(backend: Arc<dyn Backend>, password: &str) -> Result<Store, Error> {
+        let store_file = Store::read_store_file(backend.as_ref())?;
+        Store::unlock(backend, store_file, Credentials::Password, password)
+    }
+
+    /// Opens the backend of a store folder, sharded as recorded in its store
+    /// file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the store folder.
+    fn open_folder(path: &str) -> Result<(Arc<dyn Backend>, Box<StoreFile>), Error> {
+        let backend = FsBackend::open(path)?;
+        let store_file = Store::read_store_file(&backend)?;
+        let backend = backend.with_shard_levels(store_file.shard_levels);
+        Ok((Arc::new(backend), store_file))
+    }
+
+    /// Derives the key from the password, or the key file, and opens the
+    /// store.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Where the store is kept.
+    /// * `store_file` - The store file read from the backend.
+    /// * `credentials` - What `password` was made from.
+    /// * `password` - Password, or key file secret, that encrypts the store.
+    fn unlock(
+        backend: Arc<dyn Backend>,
+        store_file: Box<StoreFile>,
+        credentials: Credentials,
+        password: &str,
+    ) -> Result<Store, Error> {
+        if credentials != store_file.credentials {
+            if store_file.credentials.needs_keyfile() && !credentials.needs_keyfile() {
+                return Err(Error::KeyfileRequired);
+            }
+            return Err(Error::WrongPassword);
+        }
+
+        let key = crypto::derive_key_with(
+            store_file.kdf,
+            &store_file.kdf_params,
+            password,
+            &store_file.salt,
+            &store_file.iv,
+        )?;
+
+        let key = if store_file.password_key.is_empty() {
+            key
+        } else {
+            let wrapped = store_file.password_key.as_slice();
+            let key = crypto::decrypt_with(store_file.cipher, wrapped, &key, &store_file.key_iv);
+            let key = key.map_err(|_| Error::WrongPassword)?;
+            key.try_into().map_err(|_| Error::StoreCorrupted)?
+        };
+
+        Store::open_store(backend, *store_file, key)
+    }
+
+    /// Opens an existing store using the recovery phrase returned by
+    /// `create_with_recovery`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the store to be opened.
+    /// * `phrase` - Recovery phrase of the store.
+    pub fn open_with_recovery<S: Into<String>>(path: S, phrase: S) -> Result<Store, Error> {
+        let path: String = path.into();
+        let phrase: String = phrase.into();
+
+        let (backend, store_file) = Store::open_folder(&path)?;
+
+        if store_file.recovery_key.is_empty() {
+            return Err(Error::NoRecoveryKeyError);
+        }
+
+        let phrase = normalize_recovery_phrase(&phrase);
+        let key =
+            crypto::derive_hkdf_key(store_file.kdf, &phrase, &store_file.salt, &store_file.iv);
+        let wrapped = store_file.recovery_key.as_slice();
+        let key = crypto::decrypt_with(store_file.cipher, wrapped, &key, &store_file.key_iv);
+        let key = key.map_err(|_| Error::WrongPassword)?;
+        let key = key.try_into().map_err(|_| Error::StoreCorrupted)?;
+
+        Store::open_store(backend, *store_file, key)
+    }
+
+    /// Opens an existing store with its key, as returned by `Store::key`,
+    /// skipping the key derivation.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the store to be opened.
+    /// * `key` - Key that encrypts the store.
+    pub fn open_with_key<S: Into<String>>(path: S, key: [u8; 32]) -> Result<Store, Error> {
+        let path: String = path.into();
+        let (backend, store_file) = Store::open_folder(&path)?;
+        Store::open_store(backend, *store_file, key)
+    }
+
+    /// Returns the key that encrypts the store. It can be used to reopen the
+    /// store with `open_with_key` and must be kept as secret as the password.
+    pub fn key(&self) -> [u8; 32] {
+        self.key
+    }
+
+    /// Closes the store once everything saved is kept where it survives a
+    /// crash or power loss. Changes are saved as they are made, but the
+    /// operating system may hold part files and folder entries in memory
+    /// for a while after they are written; see `Backend::sync`.
+    pub fn close(self) -> Result<(), Error> {
+        self.backend.sync()
+    }
+
+    /// Moves or renames the store folder and keeps using it from there. The
+    /// folder is copied and then removed when it cannot be renamed, as when
+    /// moving it to another filesystem.
+    ///
+    /// # Arguments
+    ///
+    /// * `new_folder` - New path of the store folder. It must not exist.
+    pub fn rename_store(&mut self, new_folder: &str) -> Result<(), Error> {
+        let folder = self
+            .backend
+            .folder()
+            .ok_or(Error::IoError(std::io::ErrorKind::Unsupported))?
+            .to_string();
+        let new_folder: String = new_folder.into();
+        let new_folder = Path::new(&new_folder).ok_or(Error::CannotParseError)?;
+
+        if new_folder.exists() {
+            return Err(Error::FileAlreadyExistsError);
+        }
+        if new_folder.path.starts_with(&format!("{folder}/")) {
+            return Err(Error::CannotMoveError);
+        }
+
+        if fs::rename(&folder, &new_folder.path).is_err() {
+            // A partial copy is removed, so the store is left where it was.
+            if let Err(err) = copy_folder(&folder, &new_folder.path) {
+                fs::remove_dir_all(&new_folder.path).ok();
+                return Err(err);
+            }
+            fs::remove_dir_all(&folder)?;
+        }
+
+        let backend = FsBackend::open(&new_folder.path)?;
+        self.backend = Arc::new(backend.with_shard_levels(self.shard_levels));
+        Ok(())
+    }
+
+    /// Checks that parts of `chunk_size` bytes can be allocated and encrypted
+    /// with the cipher, returning `InvalidChunkSize` if not.
+    ///
+    /// # Arguments
+    ///
+    /// * `chunk_size` - Size of the parts, in bytes.
+    /// * `cipher` - Cipher that encrypts the parts.
+    /// * `compression` - Compression applied to the parts before encryption.
+    fn check_chunk_size(
+        chunk_size: u64,
+        cipher: Cipher,
+        compression: Compression,
+    ) -> Result<(), Error> {
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(Error::InvalidChunkSize);
+        }
+        // Deflate grows data it cannot compress by 5 bytes per block of up
+        // to 65535 bytes, which must fit under the cipher limit too.
+        let max_chunk_size = match compression {
+            Compression::None => cipher.max_chunk_size(),
+            Compression::Deflate => {
+                let max = cipher.max_chunk_size();
+                max - (max / 65535 + 1) * 5
+            }
+        };
+        if chunk_size > max_chunk_size {
+            return Err(Error::InvalidChunkSize);
+        }
+
+        Ok(())
+    }
+
+    /// Reads and deserializes the journal of a store, falling back to its
+    /// mirror when the journal cannot be read or is corrupted.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Where the store is kept.
+    fn read_store_file(backend: &dyn Backend) -> Result<Box<StoreFile>, Error> {
+        match Store::read_journal(backend, INDEX) {
+            Err(err @ (Error::StoreCorrupted | Error::CannotReadFileError)) => {
+                match Store::read_journal(backend, &format!("{INDEX}.mirror")) {
+                    Err(Error::FileDoesNotExistError) => Err(err),
+                    store_file => store_file,
+                }
+            }
+            store_file => store_file,
+        }
+    }
+
+    /// Reads and checks a store file.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Where the store is kept.
+    /// * `name` - Name of the store file, `Store.void` or its mirror.
+    fn read_journal(backend: &dyn Backend, name: &str) -> Result<Box<StoreFile>, Error> {
+        let bytes = backend.read_index(name)?;
+        let store_file = StoreFile::fb_deserialize(bytes.as_slice());
+        let store_file = store_file.map_err(|_| Error::StoreCorrupted)?;
+
+        // The hash does not depend on the password, so a mismatch means the
+        // file was damaged, and a decryption failure after it matches means
+        // the key is wrong.
+        if crypto::hash(&store_file.fs, &store_file.salt) != store_file.fs_hash {
+            return Err(Error::StoreCorrupted);
+        }
+        // The header is not encrypted, and writes trust the chunk size to
+        // split and allocate parts.
+        let chunk_size = store_file.chunk_size;
+        Store::check_chunk_size(chunk_size, store_file.cipher, store_file.compression)
+            .map_err(|_| Error::StoreCorrupted)?;
+
+        Ok(store_file)
+    }
+
+    /// Decrypts the filesystem of a store.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Where the store is kept.
+    /// * `store_file` - Deserialized journal of the store.
+    /// * `key` - Key that encrypts the filesystem.
+    fn open_store(
+        backend: Arc<dyn Backend>,
+        store_file: StoreFile,
+        key: [u8; 32],
+    ) -> Result<Store, Error> {
+        let iv = store_file.iv;
+
+        // Checks the key before feeding anything decrypted with it to the
+        // deserializer. Stores without a canary get one on the next save.
+        let (canary, canary_iv) = if store_file.canary.is_empty() {
+            make_canary(store_file.cipher, &key)?
+        } else {
+            let canary = store_file.canary.as_slice();
+            let canary =
+                crypto::decrypt_with(store_file.cipher, canary, &key, &store_file.canary_iv);
+            if canary.ok().as_deref() != Some(CANARY) {
+                return Err(Error::WrongPassword);
+            }
+            (store_file.canary, store_file.canary_iv)
+        };
+
+        let fs_iv = if store_file.fs_iv == [0u8; 16] {
+            iv
+        } else {
+            store_file.fs_iv
+        };
+        let fs = store_file.fs.as_slice();
+        let fs = crypto::decrypt_with(store_file.cipher, fs, &key, &fs_iv);
+        let fs = fs.map_err(|_| Error::WrongPassword)?;
+        let fs = Filesystem::fb_deserialize(fs.as_slice());
+        let fs = fs.map_err(|_| Error::StoreCorrupted)?;
+
+        let store = Store {
+            fs: *fs,
+            iv,
+            key,
+            backend,
+            salt: store_file.salt,
+            password_key: store_file.password_key,
+            recovery_key: store_file.recovery_key,
+            key_iv: store_file.key_iv,
+            cipher: store_file.cipher,
+            kdf: store_file.kdf,
+            canary,
+            canary_iv,
+            kdf_params: store_file.kdf_params,
+            compression: store_file.compression,
+            chunk_size: store_file.chunk_size,
+            description: store_file.description,
+            description_iv: store_file.description_iv,
+            shard_levels: store_file.shard_levels,
+            credentials: store_file.credentials,
+            quota_bytes: store_file.quota_bytes,
+            backups: 0,
+            mirrored: false,
+            track_access: false,
+            threads: 1,
+            max_depth: DEFAULT_MAX_DEPTH,
+            follow_links: true,
+            preserve: false,
+            show_hidden: false,
+            search_index: None,
+            #[cfg(feature = "mmap")]
+            mmap: false,
+        };
+
+        Ok(store)
+    }
+
+    /// Encrypts a file and adds it to the store.
+    /// The arguments work like the `rsync` unix command when it comes to
+    /// trailling slashes, so a trailling slash on the source, if it is a
+    /// folder, makes the contents be copied into destination. Without the
+    /// slash, the folder itself is copied. Nothing is added if it would take
+    /// the store over its quota, see `StoreBuilder::quota_bytes`.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - File path in the disk.
+    /// * `store_path` - Path in store where to save.
+    pub fn add(&mut self, file_path: &str, store_path: &str) -> Result<(), Error> {
+        self.add_overwrite(file_path, store_path, false)
+    }
+
+    /// Same as `add`, but returns the path of the added file or folder in
+    /// the store. It differs from `store_path` when a file is added into an
+    /// existing folder, or a folder is added without a trailing slash.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - File path in the disk.
+    /// * `store_path` - Path in store where to save.
+    pub fn add_returning(&mut self, file_path: &str, store_path: &str) -> Result<String, Error> {
+        self.add_into(file_path, store_path, false, |_| {})
+    }
+
+    /// Same as `add`, but optionally replaces the content of files that
+    /// already exist in the store instead of returning
+    /// `FileAlreadyExistsError`. Replaced files keep their metadata and tags.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - File path in the disk.
+    /// * `store_path` - Path in store where to save.
+    /// * `overwrite` - Whether to replace existing files.
+    pub fn add_overwrite(
+        &mut self,
+        file_path: &str,
+        store_path: &str,
+        overwrite: bool,
+    ) -> Result<(), Error> {
+        self.add_with_progress(file_path, store_path, overwrite, |_| {})
+    }
+
+    /// Same as `add_overwrite`, but calls `progress` once the files to add
+    /// are known and again after each part is written and each file is
+    /// added.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - File path in the disk.
+    /// * `store_path` - Path in store where to save.
+    /// * `overwrite` - Whether to replace existing files.
+    /// * `progress` - Called with the files and bytes added so far.
+    pub fn add_with_progress<F: FnMut(Progress)>(
+        &mut self,
+        file_path: &str,
+        store_path: &str,
+        overwrite: bool,
+        progress: F,
+    ) -> Result<(), Error> {
+        self.add_into(file_path, store_path, overwrite, progress)?;
+        Ok(())
+    }
+
+    /// Adds a file or folder and saves the store, see `add_with_progress`.
+    ///
+    /// # Returns
+    ///
+    /// * The path of the added file or folder in the store.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "add", level = "debug", skip(self, progress))
+    )]
+    fn add_into<F: FnMut(Progress)>(
+        &mut self,
+        file_path: &str,
+        store_path: &str,
+        overwrite: bool,
+        mut progress: F,
+    ) -> Result<String, Error> {
+        let source_contents = file_path.ends_with('/');
+
+        let file_path: String = file_path.into();
+        let store_path: String = store_path.into();
+
+        let file_path = Path::new(&file_path).ok_or(Error::CannotParseError)?;
+        let store_path = Path::new(&store_path).ok_or(Error::CannotParseError)?;
+
+        let added = if file_path.is_dir() && !self.is_stored_link(&file_path) {
+            let store_path = if self.fs.exists(&store_path.path)? {
+                let id = self.fs.touch(&store_path.path)?;
+                let node = self.fs.get(id)?;
+                if node.is_file {
+                    return Err(Error::CannotCreateDirectoryError);
+                } else if source_contents || &store_path.path == "/" {
+                    store_path
+                } else {
+                    store_path
+                        .join(&file_path.name)
+                        .ok_or(Error::CannotParseError)?
+                }
+            } else {
+                store_path
+            };
+
+            let root = if source_contents {
+                &file_path.path
+            } else {
+                &file_path.parent
+            };
+
+            // The whole tree is listed before anything is added, so a tree
+            // that is too deep leaves the store untouched.
+            let mut entries = vec![];
+            for entry in self.walk(&file_path.path, self.follow_links) {
+                let entry = entry?;
+                let entry_path: Path = entry.path().to_path_buf().into();
+                let store_path = entry_path
+                    .with_root(root, &store_path.path)
+                    .ok_or(Error::CannotParseError)?;
+                let metadata = entry.metadata().map_err(|_| Error::CannotReadFileError)?;
+                entries.push((entry_path, store_path, metadata.is_dir(), metadata.len()));
+            }
+
+            let mut bytes = 0;
+            let mut replaced = 0;
+            for (entry_path, store_path, is_dir, size) in &entries {
+                if !is_dir && !self.is_stored_link(entry_path) {
+                    bytes += size;
+                    replaced += self.replaced_size(store_path, overwrite)?;
+                }
+            }
+            self.check_quota(bytes, replaced)?;
+
+            let mut report = Progress::default();
+            let mut files = vec![];
+            let mut links = vec![];
+            for (entry_path, store_path, is_dir, size) in entries {
+                if is_dir {
+                    self.fs.mkdirp(&store_path.path)?;
+                    continue;
+                }
+                report.total_files += 1;
+                if self.is_stored_link(&entry_path) {
+                    links.push((entry_path, store_path));
+                } else {
+                    report.total_bytes += size;
+                    files.push((entry_path, store_path));
+                }
+            }
+            progress(report);
+
+            for (entry_path, store_path) in links {
+                self.add_file(&entry_path, store_path, overwrite, &mut |_| {})?;
+                report.files += 1;
+                progress(report);
+            }
+            if self.threads > 1 && !files.is_empty() {
+                self.add_files(files, overwrite, &mut report, &mut progress)?;
+            } else {
+                for (entry_path, store_path) in files {
+                    let mut written = |len| {
+                        report.bytes += len;
+                        progress(report);
+                    };
+                    self.add_file(&entry_path, store_path, overwrite, &mut written)?;
+                    report.files += 1;
+                    progress(report);
+                }
+            }
+
+            if source_contents {
+                store_path.path
+            } else {
+                let added = file_path.with_root(root, &store_path.path);
+                added.ok_or(Error::CannotParseError)?.path
+            }
+        } else {
+            let mut report = Progress {
+                total_files: 1,
+                ..Progress::default()
+            };
+            if !self.is_stored_link(&file_path) {
+                report.total_bytes = fs::metadata(&file_path.path).map_or(0, |m| m.len());
+            }
+            self.check_quota(
+                report.total_bytes,
+                self.replaced_size(&store_path, overwrite)?,
+            )?;
+            progress(report);
+            let mut written = |len| {
+                report.bytes += len;
+                progress(report);
+            };
+            let added = self.add_file(&file_path, store_path, overwrite, &mut written)?;
+            report.files = 1;
+            progress(report);
+            added
+        };
+
+        self.save()?;
+        Ok(added)
+    }
+
+    /// Adds a single file from disk, without saving the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - File path in the disk.
+    /// * `store_path` - Path in store where to save.
+    /// * `overwrite` - Whether to replace an existing file.
+    /// * `written` - Called with the length of each part once it is written.
+    ///
+    /// # Returns
+    ///
+    /// * The path of the added file in the store.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "add", level = "debug", skip(self, written))
+    )]
+    fn add_file(
+        &mut self,
+        file_path: &Path,
+        store_path: Path,
+        overwrite: bool,
+        written: &mut dyn FnMut(u64),
+    ) -> Result<String, Error> {
+        let store_path = self.file_destination(file_path, store_path, overwrite)?;
+        if self.is_stored_link(file_path) {
+            let target = fs::read_link(&file_path.path).map_err(|_| Error::CannotReadFileError)?;
+            let existing = self.fs.lookup(&store_path.path)?;
+            let id = self.fs.touch(&store_path.path)?;
+            let target = target.to_string_lossy();
+            self.write_node(id, existing.is_some(), |store| {
+                store.fs.set_link(id, &target)
+            })?;
+        } else {
+            let id = self.write_file(file_path, &store_path, written)?;
+            self.record_attributes(id, file_path)?;
+        }
+        Ok(store_path.path)
+    }
+
+    /// Records the modification time and permissions of a file from disk in
+    /// the metadata of its node, if enabled with `with_preserve`.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - Id of the file node.
+    /// * `file_path` - File path in the disk.
+    fn record_attributes(&mut self, node_id: u64, file_path: &Path) -> Result<(), Error> {
+        if !self.preserve {
+            return Ok(());
+        }
+
+        let metadata = fs::metadata(&file_path.path).map_err(|_| Error::CannotReadFileError)?;
+        if let Some(mtime) = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        {
+            self.fs
+                .set_metadata(node_id, "mtime", &mtime.as_secs().to_string())?;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = metadata.permissions().mode() & 0o7777;
+            self.fs
+                .set_metadata(node_id, "mode", &format!("{mode:o}"))?;
+        }
+        Ok(())
+    }
+
+    /// Restores the modification time and permissions recorded by
+    /// `record_attributes`, if enabled with `with_preserve`.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file in the store.
+    /// * `handle` - The file written to disk.
+    fn restore_attributes(&self, file: &File, handle: &fs::File) -> Result<(), Error> {
+        if !self.preserve {
+            return Ok(());
+        }
+
+        if let Some(mtime) = file.metadata.get("mtime").and_then(|m| m.parse().ok()) {
+            handle.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime))?;
+        }
+        #[cfg(unix)]
+        if let Some(mode) = file
+            .metadata
+            .get("mode")
+            .and_then(|mode| u32::from_str_radix(mode, 8).ok())
+        {
+            use std::os::unix::fs::PermissionsExt;
+            handle.set_permissions(fs::Permissions::from_mode(mode))?;
+        }
+        Ok(())
+    }
+
+    /// Whether a file from disk is a link that `add` stores as a link, see
+    /// `with_follow_links`.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - File path in the disk.
+    fn is_stored_link(&self, file_path: &Path) -> bool {
+        !self.follow_links
+            && fs::symlink_metadata(&file_path.path)
+                .map(|metadata| metadata.file_type().is_symlink())
+                .unwrap_or(false)
+    }
+
+    /// Walks a folder in the disk down to `max_depth` levels below it.
+    /// Entries that cannot be read are skipped, and a deeper entry ends the
+    /// walk with `MaxDepthExceeded`. The folder of the store itself is
+    /// skipped, so a store is never added into itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Folder in the disk.
+    /// * `follow_links` - Whether to walk into the targets of links.
+    fn walk(
+        &self,
+        dir: &str,
+        follow_links: bool,
+    ) -> impl Iterator<Item = Result<walkdir::DirEntry, Error>> {
+        let max_depth = self.max_depth;
+        let store_folder = self
+            .backend
+            .folder()
+            .and_then(|folder| fs::canonicalize(folder).ok());
+        walkdir::WalkDir::new(dir)
+            .follow_links(follow_links)
+            .max_depth(max_depth.saturating_add(1))
+            .into_iter()
+            .filter_entry(move |entry| {
+                let Some(store_folder) = &store_folder else {
+                    return true;
+                };
+                // Only folders named like the store folder are resolved.
+                !entry.file_type().is_dir()
+                    || Some(entry.file_name()) != store_folder.file_name()
+                    || fs::canonicalize(entry.path()).ok().as_ref() != Some(store_folder)
+            })
+            .filter_map(Result::ok)
+            .map(move |entry| {
+                if entry.depth() > max_depth {
+                    Err(Error::MaxDepthExceeded)
+                } else {
+                    Ok(entry)
+                }
+            })
+    }
+
+    /// Finds where a file from disk goes in the store: into `store_path` if
+    /// it is a folder, otherwise at `store_path` itself. An existing file is
+    /// only accepted if `overwrite` is set, and keeps its content until it is
+    /// replaced, see `write_node`.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - File path in the disk.
+    /// * `store_path` - Path in store where to save.
+    /// * `overwrite` - Whether to replace an existing file.
+    fn file_destination(
+        &mut self,
+        file_path: &Path,
+        store_path: Path,
+        overwrite: bool,
+    ) -> Result<Path, Error> {
+        if !self.fs.exists(&store_path.path)? {
+            return Ok(store_path);
+        }
+
+        let id = self.fs.touch(&store_path.path)?;
+        let node = self.fs.get(id)?;
+        if node.is_file && overwrite {
+            Ok(store_path)
+        } else if node.is_file {
+            Err(Error::FileAlreadyExistsError)
+        } else {
+            store_path
+                .join(&file_path.name)
+                .ok_or(Error::CannotParseError)
+        }
+    }
+
+    /// Adds files encrypting them in parallel, in `threads` threads. Only
+    /// the calling thread changes the filesystem, appending parts in the
+    /// order they arrive, so data ids and part files stay consistent. If a
+    /// file fails, the files not yet finished are undone like in
+    /// `write_node` and the error is returned. Does not save the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - Paths of the files in the disk and in the store.
+    /// * `overwrite` - Whether to replace existing files.
+    /// * `report` - Files and bytes added so far, updated as parts are
+    ///   written and files finish.
+    /// * `progress` - Called after each part is written and each file
+    ///   finishes.
+    fn add_files(
+        &mut self,
+        files: Vec<(Path, Path)>,
+        overwrite: bool,
+        report: &mut Progress,
+        progress: &mut dyn FnMut(Progress),
+    ) -> Result<(), Error> {
+        let mut nodes = vec![];
+        let mut previous = vec![];
+        for (file_path, store_path) in &files {
+            let store_path = self.file_destination(file_path, store_path.clone(), overwrite)?;
+            let existing = self.fs.lookup(&store_path.path)?;
+            let node_id = self.fs.touch(&store_path.path)?;
+            nodes.push(node_id);
+            previous.push(match existing {
+                Some(_) => Some(self.fs.detach_data(node_id)?),
+                None => None,
+            });
+        }
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .build()
+            .map_err(|_| Error::InternalStructureError)?;
+        let settings = (self.cipher, self.compression, self.chunk_size);
+        let paths: Vec<&str> = files.iter().map(|(path, _)| path.path.as_str()).collect();
+        // Bounds the encrypted parts waiting to be written.
+        let (sender, receiver) = std::sync::mpsc::sync_channel(self.threads);
+        let stop = AtomicBool::new(false);
+        let mut finished = vec![false; nodes.len()];
+        let mut result = Ok(());
+
+        std::thread::scope(|scope| {
+            let stop = &stop;
+            scope.spawn(move || {
+                pool.install(|| {
+                    paths
+                        .par_iter()
+                        .enumerate()
+                        .for_each_with(sender, |sender, (index, path)| {
+                            let done = encrypt_file(index, path, settings, sender, stop);
+                            sender.send(Encrypted::Done(index, done)).ok();
+                        })
+                })
+            });
+
+            for message in receiver {
+                if stop.load(Ordering::Relaxed) {
+                    continue;
+                }
+                let written = match message {
+                    Encrypted::Part(index, data, content) => {
+                        let written = self.write_part(nodes[index], &data, &content);
+                        if written.is_ok() {
+                            report.bytes += data.len;
+                            progress(*report);
+                        }
+                        written
+                    }
+                    Encrypted::Done(index, Ok((size, mimetype, hasher))) => {
+                        let done = self
+                            .finish_data(nodes[index], size, Some(&mimetype), hasher)
+                            .and_then(|()| self.record_attributes(nodes[index], &files[index].0));
+                        if done.is_ok() {
+                            finished[index] = true;
+                            report.files += 1;
+                            progress(*report);
+                        }
+                        done
+                    }
+                    Encrypted::Done(_, Err(err)) => Err(err),
+                };
+                if let Err(err) = written {
+                    stop.store(true, Ordering::Relaxed);
+                    result = Err(err);
+                }
+            }
+        });
+
+        for ((node_id, previous), done) in nodes.into_iter().zip(previous).zip(finished) {
+            if done {
+                self.keep_write(previous)?;
+            } else {
+                self.undo_write(node_id, previous)?;
+            }
+        }
+
+        result
+    }
+
+    /// Adds a file to the store reading its content from `reader`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Where to read the content from.
+    /// * `store_path` - Path of the file in the store. Missing parent folders
+    ///   are created.
+    /// * `overwrite` - Whether to replace the file if it already exists.
+    pub fn add_from_reader<R: Read>(
+        &mut self,
+        reader: &mut R,
+        store_path: &str,
+        overwrite: bool,
+    ) -> Result<(), Error> {
+        let store_path: String = store_path.into();
+        let store_path = Path::new(&store_path).ok_or(Error::CannotParseError)?;
+
+        let existing = self.fs.lookup(&store_path.path)?;
+        if let Some(id) = existing {
+            if !self.fs.get(id)?.is_file || !overwrite {
+                return Err(Error::FileAlreadyExistsError);
+            }
+        }
+
+        let node_id = self.fs.touch(&store_path.path)?;
+        self.write_node(node_id, existing.is_some(), |store| {
+            store.write_data(node_id, reader, None, &mut |_| {})
+        })?;
+        self.save()
+    }
+
+    /// Adds a file to the store from memory, splitting `data` into parts
+    /// without copying it first.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Content of the file.
+    /// * `store_path` - Path of the file in the store. Missing parent folders
+    ///   are created.
+    /// * `mimetype` - Mimetype of the content. If `None`, no mimetype is
+    ///   recorded, see `add_bytes_detecting_mimetype`.
+    pub fn add_bytes(
+        &mut self,
+        data: &[u8],
+        store_path: &str,
+        mimetype: Option<&str>,
+    ) -> Result<(), Error> {
+        let store_path: String = store_path.into();
+        let store_path = Path::new(&store_path).ok_or(Error::CannotParseError)?;
+
+        if self.fs.lookup(&store_path.path)?.is_some() {
+            return Err(Error::FileAlreadyExistsError);
+        }
+        self.check_quota(data.len() as u64, 0)?;
+
+        let mut hasher = crypto::Hasher::new();
+        hasher.update(data);
+
+        let node_id = self.fs.touch(&store_path.path)?;
+        self.write_node(node_id, false, |store| {
+            let mut encrypted = vec![];
+            for chunk in data.chunks(store.chunk_size as usize) {
+                store.write_chunk(node_id, chunk, &mut encrypted)?;
+            }
+            store.finish_data(node_id, data.len() as u64, mimetype, hasher)
+        })?;
+
+        self.save()
+    }
+
+    /// Same as `add_bytes`, but the mimetype is detected from the first chunk
+    /// of the content.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Content of the file.
+    /// * `store_path` - Path of the file in the store. Missing parent folders
+    ///   are created.
+    pub fn add_bytes_detecting_mimetype(
+        &mut self,
+        data: &[u8],
+        store_path: &str,
+    ) -> Result<(), Error> {
+        let mimetype = tree_magic::from_u8(&data[..data.len().min(self.chunk_size as usize)]);
+        self.add_bytes(data, store_path, Some(&mimetype))
+    }
+
+    /// Appends data to the end of a file, encrypted as new parts, without
+    /// rewriting the parts it already has. The file is created, like with
+    /// `add_bytes`, if it does not exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file in the store.
+    /// * `data` - Content to append.
+    pub fn append_bytes(&mut self, path: &str, data: &[u8]) -> Result<(), Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        let Some(id) = self.fs.lookup(&path.path)? else {
+            return self.add_bytes(data, &path.path, None);
+        };
+        let file = self.fs.get(id)?;
+        if !file.is_file {
+            return Err(Error::FileDoesNotExistError);
+        }
+        self.check_quota(data.len() as u64, 0)?;
+        let size = file.size;
+
+        // Put back if a part cannot be written, so the file keeps its old
+        // content instead of being removed like a file being added.
+        if let Err(err) = self.append_parts(id, data) {
+            self.undo_write(id, Some(file))?;
+            return Err(err);
+        }
+
+        self.fs.set_size(id, size + data.len() as u64)?;
+        // The hash of the old content would be returned by `content_hash`.
+        self.fs.rm_metadata(id, "hash").ok();
+
+        self.save()
+    }
+
+    /// Encrypts data as new parts at the end of a file node, without
+    /// updating its size, see `append_bytes`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the file node.
+    /// * `data` - Content to append.
+    fn append_parts(&mut self, id: u64, data: &[u8]) -> Result<(), Error> {
+        let mut encrypted = vec![];
+        for chunk in data.chunks(self.chunk_size as usize) {
+            let part = encrypt_chunk_into(self.cipher, self.compression, chunk, &mut encrypted)?;
+            let appended = self.fs.append(id, &part)?;
+            let part = appended.data.last().ok_or(Error::InternalStructureError)?;
+            self.backend.write_part(part.id, &encrypted)?;
+        }
+        Ok(())
+    }
+
+    /// Creates an empty file, and any missing parent folders. Does nothing if
+    /// the file already exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file in the store.
+    pub fn touch(&mut self, path: &str) -> Result<(), Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        match self.fs.lookup(&path.path)? {
+            Some(id) if self.fs.get(id)?.is_file => Ok(()),
+            Some(_) => Err(Error::FileAlreadyExistsError),
+            None => {
+                let node_id = self.fs.touch(&path.path)?;
+                self.write_node(node_id, false, |store| {
+                    store.write_data(node_id, &mut std::io::empty(), None, &mut |_| {})
+                })?;
+                self.save()
+            }
+        }
+    }
+
+    /// Creates a folder. Its parent must already exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the folder in the store.
+    pub fn mkdir(&mut self, path: &str) -> Result<(), Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        if self.fs.lookup(&path.path)?.is_some() {
+            return Err(Error::FileAlreadyExistsError);
+        }
+        let parent = self
+            .fs
+            .lookup(&path.parent)?
+            .ok_or(Error::FolderDoesNotExistError)?;
+        if self.fs.get(parent)?.is_file {
+            return Err(Error::CannotCreateDirectoryError);
+        }
+
+        self.fs.mkdirp(&path.path)?;
+        self.save()
+    }
+
+    /// Creates a folder and any missing parent folders, like `mkdir -p`. Does
+    /// nothing if the folder already exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the folder in the store.
+    pub fn mkdir_p(&mut self, path: &str) -> Result<(), Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        if let Some(id) = self.fs.lookup(&path.path)? {
+            if self.fs.get(id)?.is_file {
+                return Err(Error::FileAlreadyExistsError);
+            }
+            return Ok(());
+        }
+
+        self.fs.mkdirp(&path.path)?;
+        self.save()
+    }
+
+    /// Synchronizes a folder on disk into the store, like `rsync`. Files whose
+    /// size and content hash did not change are skipped, changed files are
+    /// rewritten (keeping their metadata and tags) and new files are added.
+    /// Links are followed or stored as links, as in `add`, see
+    /// `with_follow_links`. If a file cannot be synchronized, the ones
+    /// synchronized before it are saved and the error is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `src_dir` - Folder in the disk.
+    /// * `store_prefix` - Folder in the store that mirrors `src_dir`.
+    /// * `delete` - Whether to remove from the store the entries under
+    ///   `store_prefix` that are no longer present in `src_dir`.
+    pub fn sync(&mut self, src_dir: &str, store_prefix: &str, delete: bool) -> Result<(), Error> {
+        let src_dir: String = src_dir.into();
+        let store_prefix: String = store_prefix.into();
+
+        let src_dir = Path::new(&src_dir).ok_or(Error::CannotParseError)?;
+        let store_prefix = Path::new(&store_prefix).ok_or(Error::CannotParseError)?;
+
+        if !src_dir.is_dir() {
+            return Err(Error::FolderDoesNotExistError);
+        }
+
+        // Replaced files lose their old parts as they are rewritten, so what
+        // was done cannot be undone and is saved instead.
+        let synced = self.sync_entries(&src_dir, &store_prefix, delete);
+        self.save()?;
+        synced
+    }
+
+    /// Synchronizes the entries of a folder without saving, see `sync`.
+    fn sync_entries(
+        &mut self,
+        src_dir: &Path,
+        store_prefix: &Path,
+        delete: bool,
+    ) -> Result<(), Error> {
+        let mut synced: HashSet<String> = HashSet::new();
+
+        for entry in self.walk(&src_dir.path, self.follow_links) {
+            let entry = entry?;
+            let entry_path: Path = entry.path().to_path_buf().into();
+            let store_path = entry_path
+                .with_root(&src_dir.path, &store_prefix.path)
+                .ok_or(Error::CannotParseError)?;
+            let metadata = entry.metadata().map_err(|_| Error::CannotReadFileError)?;
+
+            synced.insert(store_path.path.clone());
+
+            if metadata.is_dir() {
+                self.fs.mkdirp(&store_path.path)?;
+                continue;
+            }
+
+            if self.is_stored_link(&entry_path) {
+                let target =
+                    fs::read_link(&entry_path.path).map_err(|_| Error::CannotReadFileError)?;
+                let target = target.to_string_lossy();
+                if let Some(id) = self.fs.lookup(&store_path.path)? {
+                    let file = self.fs.get(id)?;
+                    if !file.is_file {
+                        return Err(Error::FileAlreadyExistsError);
+                    }
+                    if file.link.as_deref() == Some(&target) {
+                        continue;
+                    }
+                }
+                self.add_file(&entry_path, store_path, true, &mut |_| {})?;
+                continue;
+            }
+
+            if let Some(id) = self.fs.lookup(&store_path.path)? {
+                let file = self.fs.get(id)?;
+                if !file.is_file {
+                    return Err(Error::FileAlreadyExistsError);
+                }
+
+                if file.size == metadata.len()
+                    && file.metadata.get("hash") == Some(&hash_file(&entry_path)?)
+                {
+                    continue;
+                }
+
+                self.check_quota(metadata.len(), file.size)?;
+            } else {
+                self.check_quota(metadata.len(), 0)?;
+            }
+
+            self.write_file(&entry_path, &store_path, &mut |_| {})?;
+        }
+
+        if delete {
+            let prefix = if store_prefix.path == "/" {
+                store_prefix.path.clone()
+            } else {
+                format!("{}/", store_prefix.path)
+            };
+
+            // The trash is never in the folder, but its files are only
+            // removed when it is emptied.
+            let stale: Vec<String> = self
+                .fs
+                .ls_all()?
+                .into_iter()
+                .map(|file| file.name)
+                .filter(|path| path.starts_with(&prefix) && !synced.contains(path))
+                .filter(|path| !in_trash(path) || in_trash(&store_prefix.path))
+                .collect();
+
+            for path in stale {
+                if let Some(id) = self.fs.lookup(&path)? {
+                    let data = self.fs.rm(id)?;
+                    self.remove_parts(&data)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decrypts a file from the store and saves it on disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `store_path` - Path of folder/file in the store.
+    /// * `file_path` - Path in the disk where to save.
+    pub fn get(&mut self, store_path: &str, file_path: &str) -> Result<(), Error> {
+        self.get_with_progress(store_path, file_path, |_| {})
+    }
+
+    /// Same as `get`, but calls `progress` once the files to write are known
+    /// and again after each part and each file is written.
+    ///
+    /// # Arguments
+    ///
+    /// * `store_path` - Path of folder/file in the store.
+    /// * `file_path` - Path in the disk where to save.
+    /// * `progress` - Called with the files and bytes written so far.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "get", level = "debug", skip(self, progress))
+    )]
+    pub fn get_with_progress<F: FnMut(Progress)>(
+        &mut self,
+        store_path: &str,
+        file_path: &str,
+        mut progress: F,
+    ) -> Result<(), Error> {
+        let mut read = vec![];
+        let result = self.get_into(store_path, file_path, None, &mut read, &mut progress);
+        self.record_access(&read)?;
+        result
+    }
+
+    /// Same as `get`, but only writes the files that `predicate` accepts,
+    /// for example those with a tag. Folders are created only where an
+    /// accepted file is written, besides the one at `file_path` itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `store_path` - Path of folder/file in the store.
+    /// * `file_path` - Path in the disk where to save.
+    /// * `predicate` - Called with each file, named by its name; returns
+    ///   whether to write it.
+    pub fn get_filtered(
+        &mut self,
+        store_path: &str,
+        file_path: &str,
+        predicate: &dyn Fn(&File) -> bool,
+    ) -> Result<(), Error> {
+        let mut read = vec![];
+        let result = self.get_into(
+            store_path,
+            file_path,
+            Some(predicate),
+            &mut read,
+            &mut |_| {},
+        );
+        self.record_access(&read)?;
+        result
+    }
+
+    /// Same as `get`, but collects the ids of the files that were read
+    /// instead of recording their access.
+    ///
+    /// # Arguments
+    ///
+    /// * `store_path` - Path of folder/file in the store.
+    /// * `file_path` - Path in the disk to save the file/folder to.
+    /// * `predicate` - Which files to write, all if `None`.
+    /// * `read` - Receives the ids of the files read.
+    /// * `progress` - Called after each part and each file is written.
+    fn get_into(
+        &mut self,
+        store_path: &str,
+        file_path: &str,
+        predicate: Option<&dyn Fn(&File) -> bool>,
+        read: &mut Vec<u64>,
+        progress: &mut dyn FnMut(Progress),
+    ) -> Result<(), Error> {
+        let file_path: String = file_path.into();
+        let store_path: String = store_path.into();
+
+        let store_path = Path::new(&store_path).ok_or(Error::CannotParseError)?;
+        let file_path = Path::new(&file_path).ok_or(Error::CannotParseError)?;
+
+        if file_path.exists() {
+            return Err(Error::FileAlreadyExistsError);
+        }
+
+        if !self.fs.exists(&store_path.path)? {
+            return Err(Error::FileDoesNotExistError);
+        }
+
+        let id = self.fs.touch(&store_path.path)?;
+        let accepts = |file: &File| predicate.is_none_or(|predicate| predicate(file));
+
+        let mut report = Progress::default();
+        for node in std::iter::once(id).chain(self.fs.descendants(id)) {
+            let file = self.fs.get(node)?;
+            if file.is_file && accepts(&file) {
+                report.total_files += 1;
+                report.total_bytes += file.size;
+            }
+        }
+        progress(report);
+
+        // Folders are walked with a stack instead of recursion, so deep trees
+        // cannot overflow the call stack.
+        let root = id;
+        let mut stack = vec![(id, file_path)];
+        while let Some((id, file_path)) = stack.pop() {
+            let file = self.fs.get(id)?;
+            if file.is_file && !accepts(&file) {
+                continue;
+            }
+
+            // Files already on disk are never overwritten, at any depth.
+            if file_path.exists() {
+                return Err(Error::FileAlreadyExistsError);
+            }
+
+            if !file.is_file {
+                // Filtered folders are created by the files written in them.
+                if predicate.is_none() || id == root {
+                    std::fs::create_dir_all(&file_path.path)
+                        .map_err(|_| Error::CannotCreateDirectoryError)?;
+                }
+                for child in self.fs.ls(id)?.into_iter().rev() {
+                    let to = file_path.join(&child.name).ok_or(Error::CannotParseError)?;
+                    stack.push((child.id, to));
+                }
+                continue;
+            }
+
+            if !Path::new(&file_path.parent)
+                .ok_or(Error::CannotParseError)?
+                .exists()
+            {
+                fs::create_dir_all(&file_path.parent)
+                    .map_err(|_| Error::CannotCreateDirectoryError)?;
+            }
+
+            #[cfg(unix)]
+            if let Some(target) = &file.link {
+                std::os::unix::fs::symlink(target, &file_path.path)?;
+                report.files += 1;
+                progress(report);
+                continue;
+            }
+
+            let file_handle = fs::File::create(&file_path.path);
+            let mut file_handle = file_handle.map_err(|_| Error::CannotWriteFileError)?;
+
+            let mut measured = Vec::new();
+            let mut content = Vec::new();
+            for data in &file.data {
+                #[cfg(feature = "tracing")]
+                tracing::trace!(part = data.id, "decrypting chunk");
+                self.read_part_into(data, &mut content)?;
+                if data.len == 0 {
+                    measured.push((data.id, content.len() as u64));
+                }
+
+                file_handle
+                    .write_all(content.as_slice())
+                    .map_err(|_| Error::CannotWriteFileError)?;
+                report.bytes += content.len() as u64;
+                progress(report);
+            }
+            self.restore_attributes(&file, &file_handle)?;
+            self.record_part_lengths(&measured)?;
+            read.push(id);
+            report.files += 1;
+            progress(report);
+        }
+
+        Ok(())
+    }
+
+    /// Reads part of a file, decrypting only the chunks that overlap the
+    /// requested range. Reading past the end of the file returns fewer bytes.
+    /// Parts from older stores, whose length was not recorded, are decrypted
+    /// once to compute it and the store is saved with the new lengths.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file in the store.
+    /// * `offset` - Position of the first byte to read.
+    /// * `len` - Number of bytes to read.
+    pub fn read_range(&mut self, path: &str, offset: u64, len: u64) -> Result<Vec<u8>, Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        let id = self
+            .fs
+            .lookup(&path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+        let file = self.fs.get(id)?;
+        if !file.is_file {
+            return Err(Error::FileDoesNotExistError);
+        }
+
+        let end = offset.saturating_add(len).min(file.size);
+        let mut bytes = Vec::with_capacity(end.saturating_sub(offset) as usize);
+        let mut start = 0u64;
+        let mut measured = Vec::new();
+
+        for data in &file.data {
+            if start >= end {
+                break;
+            }
+
+            // Parts written before their length was recorded must be
+            // decrypted to know where they end.
+            let (part_len, content) = if data.len == 0 {
+                let content = self.read_part(data)?;
+                measured.push((data.id, content.len() as u64));
+                (content.len() as u64, Some(content))
+            } else {
+                (data.len, None)
+            };
+
+            if start + part_len > offset {
+                let content = match content {
+                    Some(content) => content,
+                    None => self.read_part(data)?,
+                };
+                let from = offset.saturating_sub(start) as usize;
+                let to = ((end - start) as usize).min(content.len());
+                bytes.extend_from_slice(&content[from.min(to)..to]);
+            }
+
+            start += part_len;
+        }
+
+        self.record_part_lengths(&measured)?;
+        self.record_access(&[id])?;
+        Ok(bytes)
+    }
+
+    /// Returns the BLAKE2b hash of the content of a file, without writing it
+    /// to disk. The hash is recorded in the `hash` metadata key when the file
+    /// is added, and is only computed, by decrypting the file one part at a
+    /// time, when that key is missing. The computed hash is then saved there.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file in the store.
+    pub fn content_hash(&mut self, path: &str) -> Result<[u8; 32], Error> {
+        let file = self.file_at(path)?;
+        if let Some(hash) = recorded_hash(&file) {
+            return Ok(hash);
+        }
+
+        let hash = self.compute_hash(&file)?;
+        self.fs.set_metadata(file.id, "hash", &hex::encode(hash))?;
+        self.save()?;
+        Ok(hash)
+    }
+
+    /// Same as `content_hash`, but a computed hash is not recorded, so the
+    /// store is left unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file in the store.
+    fn peek_hash(&self, path: &str) -> Result<[u8; 32], Error> {
+        let file = self.file_at(path)?;
+        match recorded_hash(&file) {
+            Some(hash) => Ok(hash),
+            None => self.compute_hash(&file),
+        }
+    }
+
+    /// Returns the file at a path, failing with `FileDoesNotExistError` if
+    /// there is none or it is a folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file in the store.
+    fn file_at(&self, path: &str) -> Result<File, Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        let id = self
+            .fs
+            .lookup(&path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+        let file = self.fs.get(id)?;
+        if !file.is_file {
+            return Err(Error::FileDoesNotExistError);
+        }
+        Ok(file)
+    }
+
+    /// Hashes the content of a file by decrypting it one part at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file, with its data.
+    fn compute_hash(&self, file: &File) -> Result<[u8; 32], Error> {
+        let mut hasher = crypto::Hasher::new();
+        let mut content = Vec::new();
+        for data in &file.data {
+            self.read_part_into(data, &mut content)?;
+            hasher.update(&content);
+        }
+        Ok(hasher.finalize())
+    }
+
+    /// Records the lengths of parts that were decrypted to measure them.
+    ///
+    /// # Arguments
+    ///
+    /// * `lengths` - Pairs of Data id and plaintext length.
+    fn record_part_lengths(&mut self, lengths: &[(u64, u64)]) -> Result<(), Error> {
+        if lengths.is_empty() {
+            return Ok(());
+        }
+        for (id, len) in lengths {
+            self.fs.set_data_len(*id, *len)?;
+        }
+        self.save()
+    }
+
+    /// Records that files were just read, if access tracking is enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - Ids of the files read.
+    fn record_access(&mut self, ids: &[u64]) -> Result<(), Error> {
+        if !self.track_access || ids.is_empty() {
+            return Ok(());
+        }
+        for id in ids {
+            self.fs.set_accessed(*id)?;
+        }
+        self.save()
+    }
+
+    /// Lists the files that were read the longest time ago, see
+    /// `with_access_tracking`. Files never read come first. The names of the
+    /// files are their paths.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - Maximum number of files to list.
+    pub fn least_recently_accessed(&self, n: usize) -> Vec<File> {
+        let mut files: Vec<File> = self.iter().filter(|file| file.is_file).collect();
+        files.sort_by(|a, b| a.accessed.cmp(&b.accessed).then(a.name.cmp(&b.name)));
+        files.truncate(n);
+        files
+    }
+
+    /// Exports a file or folder from the store as an unencrypted tar archive.
+    /// Exporting a folder includes the folder itself, except for the root,
+    /// whose contents are exported.
+    ///
+    /// # Arguments
+    ///
+    /// * `store_path` - Path of folder/file in the store.
+    /// * `writer` - Where to write the archive.
+    pub fn export_tar<W: Write>(&self, store_path: &str, writer: W) -> Result<(), Error> {
+        let store_path: String = store_path.into();
+        let store_path = Path::new(&store_path).ok_or(Error::CannotParseError)?;
+
+        let id = self
+            .fs
+            .lookup(&store_path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+
+        let mut stack: Vec<(u64, String)> = if id == 0 {
+            self.fs
+                .ls(0)?
+                .into_iter()
+                .map(|child| (child.id, child.name))
+                .collect()
+        } else {
+            vec![(id, store_path.name)]
+        };
+
+        let mut builder = tar::Builder::new(writer);
+
+        while let Some((id, name)) = stack.pop() {
+            let file = self.fs.get(id)?;
+            let mut header = tar::Header::new_gnu();
+            header.set_mtime(file.modified);
+
+            if file.is_file {
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_mode(0o644);
+                header.set_size(file.size);
+                let reader = FileReader::new(self, file.data);
+                builder
+                    .append_data(&mut header, &name, reader)
+                    .map_err(|_| Error::CannotWriteFileError)?;
+            } else {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_mode(0o755);
+                header.set_size(0);
+                builder
+                    .append_data(&mut header, &name, std::io::empty())
+                    .map_err(|_| Error::CannotWriteFileError)?;
+                for child in self.fs.ls(id)? {
+                    stack.push((child.id, format!("{name}/{}", child.name)));
+                }
+            }
+        }
+
+        builder
+            .into_inner()
+            .map_err(|_| Error::CannotWriteFileError)?
+            .flush()
+            .map_err(|_| Error::CannotWriteFileError)
+    }
+
+    /// Imports the files and folders of a tar archive into the store. Entries
+    /// other than files and folders, like links, are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Where to read the archive from.
+    /// * `prefix` - Folder in the store where the archive is extracted.
+    pub fn import_tar<R: Read>(&mut self, reader: R, prefix: &str) -> Result<(), Error> {
+        let prefix: String = prefix.into();
+        let prefix = Path::new(&prefix).ok_or(Error::CannotParseError)?;
+
+        // Put back if an entry cannot be imported, so a broken archive leaves
+        // the store as it was.
+        let index = self.fs.clone();
+        if let Err(err) = self.import_entries(reader, &prefix) {
+            let written: Vec<Data> = self.fs.iter().flat_map(|file| file.data).collect();
+            self.fs = index;
+            self.remove_parts(&written)?;
+            return Err(err);
+        }
+
+        self.save()
+    }
+
+    /// Imports the entries of a tar archive without saving, see `import_tar`.
+    fn import_entries<R: Read>(&mut self, reader: R, prefix: &Path) -> Result<(), Error> {
+        let mut archive = tar::Archive::new(reader);
+        let entries = archive.entries().map_err(|_| Error::CannotReadFileError)?;
+
+        for entry in entries {
+            let mut entry = entry.map_err(|_| Error::CannotReadFileError)?;
+            let entry_path = entry.path().map_err(|_| Error::CannotParseError)?;
+            let entry_path = entry_path.to_string_lossy();
+            let store_path = prefix
+                .join(entry_path.trim_start_matches('/'))
+                .ok_or(Error::CannotParseError)?;
+            let entry_type = entry.header().entry_type();
+
+            if entry_type.is_dir() {
+                self.fs.mkdirp(&store_path.path)?;
+            } else if entry_type.is_file() {
+                if self.fs.exists(&store_path.path)? {
+                    return Err(Error::FileAlreadyExistsError);
+                }
+                self.check_quota(entry.header().size().unwrap_or(0), 0)?;
+                let node_id = self.fs.touch(&store_path.path)?;
+                self.write_node(node_id, false, |store| {
+                    store.write_data(node_id, &mut entry, None, &mut |_| {})
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes a file or folder from the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of folder/file in the store.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub fn remove(&mut self, path: &str) -> Result<(), Error> {
+        let id = self.id_of(path)?;
+        self.remove_id(id)
+    }
+
+    /// Same as `remove`, but takes the id of the node, see `id_of`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the node.
+    pub fn remove_id(&mut self, id: u64) -> Result<(), Error> {
+        self.fs.get(id)?;
+        let data = self.fs.rm(id)?;
+        let removed = self.remove_parts(&data);
+
+        self.save()?;
+        removed
+    }
+
+    /// Moves a file or folder to the trash instead of removing it. It goes to
+    /// `/.trash/<timestamp>/<path>`, keeping its part files, until the trash
+    /// is emptied with `empty_trash`. `restore_from_trash` moves it back.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of folder/file in the store.
+    ///
+    /// # Returns
+    ///
+    /// * The path of the file or folder in the trash.
+    pub fn trash(&mut self, path: &str) -> Result<String, Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        let id = self
+            .fs
+            .lookup(&path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+        if id == 0 || in_trash(&path.path) {
+            return Err(Error::CannotMoveError);
+        }
+
+        // Files trashed from the same path in the same second go to the next
+        // free timestamp.
+        let mut timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let trashed = loop {
+            let trashed = format!("{TRASH}/{timestamp}{}", path.path);
+            if self.fs.lookup(&trashed)?.is_none() {
+                break Path::new(&trashed).ok_or(Error::CannotParseError)?;
+            }
+            timestamp += 1;
+        };
+
+        let parent = self.fs.mkdirp(&trashed.parent)?;
+        self.fs.mv(id, parent, &trashed.name)?;
+
+        self.save()?;
+        Ok(trashed.path)
+    }
+
+    /// Moves a file or folder from the trash back to where it was trashed
+    /// from. Folders left empty in the trash are removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path in the trash, as returned by `trash`.
+    ///
+    /// # Returns
+    ///
+    /// * The path the file or folder was restored to.
+    pub fn restore_from_trash(&mut self, path: &str) -> Result<String, Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        let (timestamp, original) = path
+            .path
+            .strip_prefix(&format!("{TRASH}/"))
+            .and_then(|path| path.split_once('/'))
+            .ok_or(Error::CannotMoveError)?;
+        let original = format!("/{original}");
+        let original = Path::new(&original).ok_or(Error::CannotParseError)?;
+
+        let id = self
+            .fs
+            .lookup(&path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+        if self.fs.exists(&original.path)? {
+            return Err(Error::FileAlreadyExistsError);
+        }
+
+        let parent = self.fs.mkdirp(&original.parent)?;
+        self.fs.mv(id, parent, &original.name)?;
+
+        let stamp_folder = format!("{TRASH}/{timestamp}");
+        let mut folder = path.parent;
+        while folder.starts_with(&stamp_folder) {
+            let Some(id) = self.fs.lookup(&folder)? else {
+                break;
+            };
+            if !self.fs.ls(id)?.is_empty() {
+                break;
+            }
+            self.fs.rm(id)?;
+            folder = Path::new(&folder).ok_or(Error::CannotParseError)?.parent;
+        }
+
+        self.save()?;
+        Ok(original.path)
+    }
+
+    /// Removes everything in the trash, with its part files.
+    pub fn empty_trash(&mut self) -> Result<(), Error> {
+        let Some(id) = self.fs.lookup(TRASH)? else {
+            return Ok(());
+        };
+
+        let data = self.fs.rm(id)?;
+        let removed = self.remove_parts(&data);
+
+        self.save()?;
+        removed
+    }
+
+    /// Lists what `remove_matching` would remove, without removing anything.
+    /// Nodes inside a matched folder are not listed, as they go with it.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - A glob, like `*.tmp` or `/a/**/b?`, or `tag:` followed by
+    ///   tags separated by spaces, as in `tag_search`. A `tag:` without
+    ///   tags is refused with `Error::CannotParseError`. Files in the trash
+    ///   only match globs starting with the trash folder.
+    ///
+    /// # Returns
+    ///
+    /// * The matching File objects, named by their full paths and sorted.
+    pub fn plan_remove(&self, query: &str) -> Result<Vec<File>, Error> {
+        let mut matches: Vec<File> = match query.strip_prefix("tag:") {
+            Some(tags) => {
+                let tags: Vec<String> = tags.split_whitespace().map(String::from).collect();
+                // No tags would match every file.
+                if tags.is_empty() {
+                    return Err(Error::CannotParseError);
+                }
+                self.tag_search(tags)
+            }
+            None => {
+                let glob = glob_regex(query).ok_or(Error::CannotParseError)?;
+                self.fs
+                    .iter()
+                    .filter(|file| glob.is_match(&file.name))
+                    .collect()
+            }
+        };
+        // Trashed files are left for `empty_trash`, unless the glob is for
+        // the trash itself.
+        if !in_trash(query) {
+            matches.retain(|file| !in_trash(&file.name));
+        }
+        // Sorting by components puts the nodes of a folder right after it.
+        matches.sort_by(|a, b| a.name.split('/').cmp(b.name.split('/')));
+
+        let mut plan: Vec<File> = vec![];
+        for file in matches {
+            let inside = plan.last().is_some_and(|folder| {
+                !folder.is_file && file.name.starts_with(&format!("{}/", folder.name))
+            });
+            if !inside {
+                plan.push(file);
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Removes every file and folder matching a query, with their part
+    /// files, saving the store once. See `plan_remove` for the queries.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - Glob or `tag:` query of the nodes to remove.
+    ///
+    /// # Returns
+    ///
+    /// * The removed File objects, as listed by `plan_remove`.
+    pub fn remove_matching(&mut self, query: &str) -> Result<Vec<File>, Error> {
+        let plan = self.plan_remove(query)?;
+
+        let mut data = vec![];
+        for file in &plan {
+            data.extend(self.fs.rm(file.id)?);
+        }
+        let removed = self.remove_parts(&data);
+
+        self.save()?;
+        removed.map(|_| plan)
+    }
+
+    /// Moves a file or folder. Works like the `mv` unix command: if `dst` is
+    /// an existing folder, `src` is moved into it and keeps its name.
+    /// Otherwise `src` is moved into the parent of `dst` (which is created if
+    /// needed) and renamed to the last component of `dst`. Existing files are
+    /// never overwritten.
+    ///
+    /// # Arguments
+    ///
+    /// * `src` - Source path.
+    /// * `dst` - Destination path.
+    pub fn mv(&mut self, src: &str, dst: &str) -> Result<(), Error> {
+        let src_id = self.id_of(src)?;
+        self.mv_id(src_id, dst)
+    }
+
+    /// Same as `mv`, but takes the id of the node to move, see `id_of`.
+    ///
+    /// # Arguments
+    ///
+    /// * `src_id` - Id of the node.
+    /// * `dst` - Destination path.
+    pub fn mv_id(&mut self, src_id: u64, dst: &str) -> Result<(), Error> {
+        let dst: String = dst.into();
+        let dst = Path::new(&dst).ok_or(Error::CannotParseError)?;
+        let src = self.fs.path(src_id)?;
+
+        match self.fs.lookup(&dst.path)? {
+            Some(dst_id) => {
+                if self.fs.get(dst_id)?.is_file {
+                    return Err(Error::FileAlreadyExistsError);
+                }
+                let name = self.fs.get(src_id)?.name;
+                self.fs.mv(src_id, dst_id, &name)?;
+            }
+            None => {
+                if src_id == 0 || dst.path.starts_with(&format!("{src}/")) {
+                    return Err(Error::CannotMoveError);
+                }
+                let dst_id = self.fs.mkdirp(&dst.parent)?;
+                self.fs.mv(src_id, dst_id, &dst.name)?;
+            }
+        }
+
+        self.save()
+    }
+
+    /// Makes a file appear in a second path without duplicating its encrypted
+    /// parts. Removing either path keeps the parts the other one still uses.
+    /// The destination works like in `mv`: an existing folder receives the
+    /// link with the source's name, otherwise the link is created at `dst`.
+    ///
+    /// # Arguments
+    ///
+    /// * `src` - Path of the file in the store.
+    /// * `dst` - Path of the link in the store.
+    pub fn link(&mut self, src: &str, dst: &str) -> Result<(), Error> {
+        let src: String = src.into();
+        let dst: String = dst.into();
+        let src = Path::new(&src).ok_or(Error::CannotParseError)?;
+        let dst = Path::new(&dst).ok_or(Error::CannotParseError)?;
+
+        let src_id = self
+            .fs
+            .lookup(&src.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+
+        match self.fs.lookup(&dst.path)? {
+            Some(dst_id) => {
+                if self.fs.get(dst_id)?.is_file {
+                    return Err(Error::FileAlreadyExistsError);
+                }
+                let name = self.fs.get(src_id)?.name;
+                self.fs.link(src_id, dst_id, &name)?;
+            }
+            None => {
+                let dst_id = self.fs.mkdirp(&dst.parent)?;
+                self.fs.link(src_id, dst_id, &dst.name)?;
+            }
+        }
+
+        self.save()
+    }
+
+    /// Lists files in the store. Files and folders whose name starts with a
+    /// dot are hidden, unless enabled with `with_hidden`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of folder/file in the store.
+    ///
+    /// # Returns
+    ///
+    /// * A list of File objects with this folder's direct children.
+    pub fn list(&mut self, path: &str) -> Result<Vec<File>, Error> {
+        let mut files = if path == "*" {
+            // Named by their full paths, so files in hidden folders are
+            // hidden too.
+            self.fs.ls_all()?
+        } else {
+            let path: String = path.into();
+            let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+            let id = self
+                .fs
+                .lookup(&path.path)?
+                .ok_or(Error::FolderDoesNotExistError)?;
+            let file = self.fs.get(id)?;
+            if file.is_file {
+                return Ok(vec![file]);
+            }
+            self.fs.ls(id)?
+        };
+
+        if !self.show_hidden {
+            files.retain(|file| !file.name.split('/').any(|name| name.starts_with('.')));
+        }
+        Ok(files)
+    }
+
+    /// Lists a page of the children of a folder, for folders too large to
+    /// list at once. Pages are in the same order as `list`, as long as the
+    /// folder does not change between calls, and hidden files are skipped
+    /// the same way.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of folder/file in the store.
+    /// * `offset` - Number of entries to skip.
+    /// * `limit` - Most entries to return.
+    ///
+    /// # Returns
+    ///
+    /// * The entries in the page and the total number of entries. A file is
+    ///   listed as a folder with only itself in it.
+    pub fn list_page(
+        &mut self,
+        path: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<File>, usize), Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        let id = self
+            .fs
+            .lookup(&path.path)?
+            .ok_or(Error::FolderDoesNotExistError)?;
+        let file = self.fs.get(id)?;
+        if file.is_file {
+            let page = if offset == 0 && limit > 0 {
+                vec![file]
+            } else {
+                vec![]
+            };
+            return Ok((page, 1));
+        }
+
+        Ok(self.fs.ls_page(id, offset, limit, self.show_hidden))
+    }
+
+    /// Lists files in the store, sorted.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of folder/file in the store.
+    /// * `by` - What to sort by.
+    /// * `desc` - Sorts in descending order.
+    ///
+    /// # Returns
+    ///
+    /// * The sorted list of File objects with this folder's direct children.
+    pub fn list_sorted(&mut self, path: &str, by: SortKey, desc: bool) -> Result<Vec<File>, Error> {
+        self.list_sorted_folders_first(path, by, desc, false)
+    }
+
+    /// Lists files in the store, sorted, optionally with all folders before
+    /// the files.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of folder/file in the store.
+    /// * `by` - What to sort by.
+    /// * `desc` - Sorts in descending order.
+    /// * `folders_first` - Lists folders before files, whatever the order.
+    pub fn list_sorted_folders_first(
+        &mut self,
+        path: &str,
+        by: SortKey,
+        desc: bool,
+        folders_first: bool,
+    ) -> Result<Vec<File>, Error> {
+        let mut files = self.list(path)?;
+
+        files.sort_by(|a, b| {
+            let by_name = || a.name.cmp(&b.name);
+            let order = match by {
+                SortKey::Name => by_name(),
+                SortKey::Size => a.size.cmp(&b.size).then_with(by_name),
+                SortKey::Modified => a.modified.cmp(&b.modified).then_with(by_name),
+            };
+            let order = if desc { order.reverse() } else { order };
+
+            if folders_first {
+                a.is_file.cmp(&b.is_file).then(order)
+            } else {
+                order
+            }
+        });
+
+        Ok(files)
+    }
+
+    /// Lists files in the store with the size of each folder set to the sum
+    /// of the sizes of all files under it, as `disk_usage` computes it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of folder/file in the store.
+    ///
+    /// # Returns
+    ///
+    /// * A list of File objects with this folder's direct children.
+    pub fn list_with_sizes(&mut self, path: &str) -> Result<Vec<File>, Error> {
+        let mut files = self.list(path)?;
+
+        // Folders listed with "*" are nested in each other, so each one is
+        // only summed once.
+        let mut sizes = HashMap::new();
+        for file in files.iter_mut().filter(|file| !file.is_file) {
+            file.size = self.folder_size(file.id, &mut sizes)?;
+        }
+
+        Ok(files)
+    }
+
+    /// Sums the sizes of all files under a folder, remembering the size of
+    /// every folder visited in `sizes`. Folders are visited with a stack, so
+    /// deep trees do not overflow the call stack.
+    fn folder_size(&self, id: u64, sizes: &mut HashMap<u64, u64>) -> Result<u64, Error> {
+        // A folder is pushed again after its subfolders, so their sizes are
+        // known when it is popped the second time.
+        let mut stack = vec![(id, false)];
+        while let Some((folder, summed)) = stack.pop() {
+            if sizes.contains_key(&folder) {
+                continue;
+            }
+
+            let children = self.fs.ls(folder)?;
+            if summed {
+                let size = children
+                    .iter()
+                    .map(|child| {
+                        if child.is_file {
+                            child.size
+                        } else {
+                            sizes.get(&child.id).copied().unwrap_or(0)
+                        }
+                    })
+                    .sum();
+                sizes.insert(folder, size);
+            } else {
+                stack.push((folder, true));
+                stack.extend(
+                    children
+                        .iter()
+                        .filter(|child| !child.is_file)
+                        .map(|child| (child.id, false)),
+                );
+            }
+        }
+
+        Ok(sizes.get(&id).copied().unwrap_or(0))
+    }
+
+    /// Iterates over all files and folders in the store, depth-first, without
+    /// listing them all in memory first. The name of each File is its full
+    /// path.
+    pub fn iter(&self) -> Walk<'_> {
+        self.fs.iter()
+    }
+
+    /// Checks whether a file or folder exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of folder/file in the store.
+    pub fn exists(&self, path: &str) -> Result<bool, Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        self.fs.exists(&path.path)
+    }
+
+    /// Checks whether a path is a folder. The root is a folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of folder/file in the store.
+    pub fn is_dir(&self, path: &str) -> Result<bool, Error> {
+        Ok(!self.is_file(path)?)
+    }
+
+    /// Checks whether a path is a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of folder/file in the store.
+    pub fn is_file(&self, path: &str) -> Result<bool, Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        let id = self
+            .fs
+            .lookup(&path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+
+        Ok(self.fs.get(id)?.is_file)
+    }
+
+    /// Returns information about a file or folder, like its size, metadata
+    /// and timestamps.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of folder/file in the store.
+    ///
+    /// # Returns
+    ///
+    /// * A File object for the node. The name is the node's name.
+    pub fn stat(&self, path: &str) -> Result<File, Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        let id = self
+            .fs
+            .lookup(&path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+
+        self.fs.get(id)
+    }
+
+    /// Returns the id of a file or folder. Unlike its path, the id does not
+    /// change when the node is moved or renamed, so it can be kept to find
+    /// the node again with `path_of`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of folder/file in the store.
+    pub fn id_of(&self, path: &str) -> Result<u64, Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        self.fs
+            .lookup(&path.path)?
+            .ok_or(Error::FileDoesNotExistError)
+    }
+
+    /// Returns the current path of a file or folder from its id, see
+    /// `id_of`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the node.
+    pub fn path_of(&self, id: u64) -> Result<String, Error> {
+        self.fs.path(id)
+    }
+
+    /// Computes the size of a file, or the sum of the sizes of all files under
+    /// a folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of folder/file in the store.
+    ///
+    /// # Returns
+    ///
+    /// * The aggregate size in bytes.
+    pub fn disk_usage(&self, path: &str) -> Result<u64, Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        let id = self
+            .fs
+            .lookup(&path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+
+        let mut size = 0;
+        let mut stack = vec![self.fs.get(id)?];
+        while let Some(file) = stack.pop() {
+            if file.is_file {
+                size += file.size;
+            } else {
+                stack.extend(self.fs.ls(file.id)?);
+            }
+        }
+
+        Ok(size)
+    }
+
+    /// Returns the cipher recorded in the store header.
+    pub fn cipher(&self) -> Cipher {
+        self.cipher
+    }
+
+    /// Returns the key derivation function recorded in the store header.
+    pub fn kdf(&self) -> Kdf {
+        self.kdf
+    }
+
+    /// Returns the key derivation parameters recorded in the store header.
+    pub fn kdf_params(&self) -> KdfParams {
+        self.kdf_params
+    }
+
+    /// Returns the compression recorded in the store header.
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Returns the size of the parts files are split into.
+    pub fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+
+    /// Returns the most bytes of files the store can hold, if it is limited.
+    pub fn quota_bytes(&self) -> Option<u64> {
+        self.quota_bytes
+    }
+
+    /// Limits the total size of the files in the store, or removes the limit
+    /// with `None`. Files already in the store are kept even if they are
+    /// over the new quota, but nothing else can be added until enough is
+    /// removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `quota_bytes` - Most bytes of files the store can hold.
+    pub fn set_quota_bytes(&mut self, quota_bytes: Option<u64>) -> Result<(), Error> {
+        self.quota_bytes = quota_bytes;
+        self.save()
+    }
 
-            let store_path = Path::new(&self.path).ok_or(Error::CannotParseError)?;
-            for data in &file.data {
-                let part_name = hex::encode(data.id.to_be_bytes());
-                let part_name = format!("{part_name:0>32}");
-                let part_path = store_path.join(part_name).ok_or(Error::CannotParseError)?;
-                let cipher = fs::read(part_path.path).map_err(|_| Error::CannotReadFileError)?;
-                let content = crypto::decrypt(cipher.as_slice(), &data.key, &data.iv);
-                let content = content.map_err(|_| Error::CannotDecryptFileError)?;
+    /// Returns `QuotaExceeded` if adding `bytes` to the store, after
+    /// removing `replaced` bytes of files that are overwritten, would take it
+    /// over its quota.
+    fn check_quota(&self, bytes: u64, replaced: u64) -> Result<(), Error> {
+        let Some(quota) = self.quota_bytes else {
+            return Ok(());
+        };
 
-                file_handle
-                    .write_all(content.as_slice())
-                    .map_err(|_| Error::CannotWriteFileError)?;
-            }
-        } else {
-            std::fs::create_dir_all(&file_path.path)
-                .map_err(|_| Error::CannotCreateDirectoryError)?;
-            let children = self.fs.ls(id)?;
-            for child in children {
-                let from = store_path
-                    .join(&child.name)
-                    .ok_or(Error::CannotParseError)?;
-                let to = file_path.join(&child.name).ok_or(Error::CannotParseError)?;
-                self.get(&from.path, &to.path)?;
-            }
+        let total = self.fs.total_size().saturating_sub(replaced);
+        if total.saturating_add(bytes) > quota {
+            return Err(Error::QuotaExceeded);
         }
 
         Ok(())
     }
 
-    /// Removes a file or folder from the store.
+    /// Size of the file at `store_path` that adding with `overwrite` would
+    /// replace, or zero if there is none.
+    fn replaced_size(&self, store_path: &Path, overwrite: bool) -> Result<u64, Error> {
+        if !overwrite {
+            return Ok(0);
+        }
+
+        match self.fs.lookup(&store_path.path)? {
+            Some(id) => {
+                let file = self.fs.get(id)?;
+                Ok(if file.is_file { file.size } else { 0 })
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Compares the files and folders of two stores. The contents of files
+    /// in both are compared by `content_hash`, so they are only decrypted if
+    /// their hash was not recorded. Computed hashes are not recorded, so
+    /// neither store is changed. The trash is not compared.
     ///
     /// # Arguments
     ///
-    /// * `path` - Path of folder/file in the store.
-    pub fn remove(&mut self, path: &str) -> Result<(), Error> {
-        let path: String = path.into();
-
-        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
-        let store_folder = Path::new(&self.path).ok_or(Error::CannotParseError)?;
+    /// * `other` - Store to compare with.
+    pub fn diff(&self, other: &Store) -> Result<DiffReport, Error> {
+        let entries = |store: &Store| -> BTreeMap<String, bool> {
+            store
+                .iter()
+                .filter(|f| !in_trash(&f.name))
+                .map(|f| (f.name, f.is_file))
+                .collect()
+        };
+        let ours = entries(self);
+        let theirs = entries(other);
 
-        if !self.fs.exists(&path.path)? {
-            return Err(Error::FileDoesNotExistError);
+        let mut report = DiffReport::default();
+        for (path, &is_file) in &ours {
+            let changed = match theirs.get(path) {
+                None => {
+                    report.only_in_self.push(path.clone());
+                    continue;
+                }
+                Some(&other_is_file) if other_is_file != is_file => true,
+                Some(_) => is_file && self.peek_hash(path)? != other.peek_hash(path)?,
+            };
+            if changed {
+                report.changed.push(path.clone());
+            }
         }
+        report.only_in_other = theirs
+            .into_keys()
+            .filter(|path| !ours.contains_key(path))
+            .collect();
 
-        let id = self.fs.touch(&path.path)?;
-        let data = self.fs.rm(id)?;
+        Ok(report)
+    }
 
-        for d in data {
-            let part_name = hex::encode(d.id.to_be_bytes());
-            let part_name = format!("{part_name:0>32}");
-            let part_path = store_folder
-                .join(part_name)
-                .ok_or(Error::CannotParseError)?;
-            fs::remove_file(part_path.path).ok();
+    /// Sets a description of the store, like a label to tell stores apart. It
+    /// is encrypted with the store key, so only who can open the store reads
+    /// it. An empty description removes it.
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - Description of the store.
+    pub fn set_description(&mut self, description: &str) -> Result<(), Error> {
+        if description.is_empty() {
+            self.description = vec![];
+        } else {
+            let iv = crypto::uuid();
+            self.description =
+                crypto::encrypt_with(self.cipher, description.as_bytes(), &self.key, &iv)?;
+            self.description_iv = iv;
         }
 
         self.save()
     }
 
-    // Moves a file or folder.
-    //
-    // # Arguments
-    //
-    // * `src` - Source path.
-    // * `dst` - Destination path.
-    pub fn mv(&mut self, src: &str, dst: &str) -> Result<(), Error> {
-        let src: String = src.into();
-        let dst: String = dst.into();
-        let src = Path::new(&src).ok_or(Error::CannotParseError)?;
-        let dst = Path::new(&dst).ok_or(Error::CannotParseError)?;
-
-        if !self.fs.exists(&src.path)? {
-            return Err(Error::FileDoesNotExistError);
+    /// Returns the description of the store, if it has one.
+    pub fn get_description(&self) -> Result<Option<String>, Error> {
+        if self.description.is_empty() {
+            return Ok(None);
         }
 
-        let src_id = self.fs.touch(&src.path)?;
-        let dst_id = self.fs.mkdirp(&dst.parent)?;
+        let description = crypto::decrypt_with(
+            self.cipher,
+            &self.description,
+            &self.key,
+            &self.description_iv,
+        )?;
+        let description = String::from_utf8(description).map_err(|_| Error::StoreCorrupted)?;
+        Ok(Some(description))
+    }
+
+    /// Computes totals about the store, like the number of files and the space
+    /// it takes on disk.
+    pub fn statistics(&self) -> Result<StoreStats, Error> {
+        let disk_size = self.backend.size()?;
 
-        self.fs.mv(src_id, dst_id)
+        Ok(StoreStats {
+            files: self.fs.file_count(),
+            folders: self.fs.folder_count(),
+            total_bytes: self.fs.total_size(),
+            tags: self.fs.list_tag().len(),
+            parts: self.fs.data_count(),
+            disk_size,
+        })
     }
 
-    /// Lists files in the store.
+    /// Lists the files on disk that hold the encrypted parts of a file.
     ///
     /// # Arguments
     ///
-    /// * `path` - Path of folder/file in the store.
+    /// * `path` - Path of the file in the store.
     ///
     /// # Returns
     ///
-    /// * A list of File objects with this folder's direct children.
-    pub fn list(&mut self, path: &str) -> Result<Vec<File>, Error> {
-        if path == "*" {
-            return self.fs.ls_all();
-        }
-
+    /// * The absolute paths of the part files, in the order they are read.
+    ///   Stores opened with `open_from_bytes` only return their names.
+    pub fn part_files(&self, path: &str) -> Result<Vec<String>, Error> {
         let path: String = path.into();
         let path = Path::new(&path).ok_or(Error::CannotParseError)?;
 
-        if !self.fs.exists(&path.path)? {
-            return Err(Error::FolderDoesNotExistError);
+        let id = self
+            .fs
+            .lookup(&path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+        let file = self.fs.get(id)?;
+
+        if !file.is_file {
+            return Err(Error::FileDoesNotExistError);
         }
 
-        if path.path != "/" {
-            let id = self.fs.touch(&path.path)?;
-            let file = self.fs.get(id)?;
-            if file.is_file {
-                Ok(vec![file])
-            } else {
-                self.fs.ls(id)
+        Ok(file
+            .data
+            .iter()
+            .map(|data| self.backend.part_location(data.id))
+            .collect())
+    }
+
+    /// Lists the part files found in a store folder, which is all that can
+    /// be salvaged when `Store.void` is lost. Part files are named after the
+    /// id of their data only, and each is encrypted with its own key, which is
+    /// kept in the lost index, so their contents and the paths they belonged
+    /// to cannot be recovered without it. Use `with_backups` to keep copies of
+    /// the index.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the store folder.
+    ///
+    /// # Returns
+    ///
+    /// * The names of the part files, sorted by data id.
+    pub fn salvage(path: &str) -> Result<Vec<String>, Error> {
+        let mut parts = FsBackend::open(path)?.list_parts()?;
+        parts.sort();
+
+        Ok(parts.into_iter().map(part_name).collect())
+    }
+
+    /// Removes part files that no file of the store uses anymore, like those
+    /// left behind when a store is copied while being written to or when
+    /// removing a part failed.
+    ///
+    /// # Returns
+    ///
+    /// * The number of part files removed.
+    pub fn gc(&mut self) -> Result<usize, Error> {
+        let mut removed = 0;
+        for id in self.backend.list_parts()? {
+            if !self.fs.has_data(id) {
+                self.backend.remove_part(id)?;
+                removed += 1;
             }
-        } else {
-            self.fs.ls(0)
         }
+        Ok(removed)
+    }
+
+    /// Takes a copy of the index of the store, which `restore_snapshot` can
+    /// revert to, to undo changes. Only the index is copied, not the parts.
+    pub fn snapshot(&self) -> FilesystemSnapshot {
+        FilesystemSnapshot {
+            fs: self.fs.clone(),
+        }
+    }
+
+    /// Reverts the index of the store to a snapshot and saves it. The part
+    /// files of files added or changed since the snapshot are left orphaned
+    /// until `gc` removes them. Files removed since the snapshot come back,
+    /// but their parts were already removed, so they cannot be read.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - Snapshot taken with `snapshot`.
+    pub fn restore_snapshot(&mut self, snapshot: FilesystemSnapshot) -> Result<(), Error> {
+        self.fs = snapshot.fs;
+        self.save()
     }
 
     /// Truncates a file.
@@ -572,9 +4418,106 @@ impl Store {
         }
 
         let id = self.fs.touch(&path.path)?;
-        self.fs.truncate(id)?;
+        let data = self.fs.truncate(id)?;
+        // The hash of the old content would be returned by `content_hash`.
+        self.fs.rm_metadata(id, "hash").ok();
+        let removed = self.remove_parts(&data);
 
-        self.save()
+        self.save()?;
+        removed
+    }
+
+    /// Re-encrypts the parts of a file with fresh random keys, so that a
+    /// leaked part key no longer decrypts anything in the store. Each part is
+    /// written to a new part file and the old one is deleted once the store
+    /// is saved. Files linked to this one share its parts and are rekeyed
+    /// too.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file in the store.
+    pub fn rekey_file(&mut self, path: &str) -> Result<(), Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        let id = self
+            .fs
+            .lookup(&path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+        let file = self.fs.get(id)?;
+
+        if !file.is_file {
+            return Err(Error::FileDoesNotExistError);
+        }
+
+        for old in file.data {
+            let data = self.rekey_part(&old, self.fs.next_data_id())?;
+
+            // The store is saved before the old part is deleted, so that it
+            // never references a missing part.
+            self.fs.replace_data(old.id, &data)?;
+            self.save()?;
+            self.remove_parts(&[old])?;
+        }
+
+        Ok(())
+    }
+
+    /// Re-encrypts the parts of every file in the store with fresh random
+    /// keys. The index is saved once, after all parts were written, and only
+    /// then are the old part files deleted. If a part fails, the parts already
+    /// rekeyed are kept and the error is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `progress` - Called after each part with the number of parts
+    ///   rekeyed so far and the total number of parts.
+    pub fn rekey_all<F: FnMut(usize, usize)>(&mut self, mut progress: F) -> Result<(), Error> {
+        let mut parts: Vec<Data> = self.fs.iter().flat_map(|file| file.data).collect();
+        parts.sort_by_key(|data| data.id);
+        parts.dedup_by_key(|data| data.id);
+
+        // Old ids stay referenced by the index on disk until it is saved, so
+        // new parts get ids above all of them.
+        let first_id = parts.last().map_or(0, |data| data.id) + 1;
+        let total = parts.len();
+        let mut replaced = vec![];
+        let mut result = Ok(());
+        for (i, old) in parts.into_iter().enumerate() {
+            match self.rekey_part(&old, first_id + i as u64) {
+                Ok(data) => self.fs.replace_data(old.id, &data)?,
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+            replaced.push(old);
+            progress(replaced.len(), total);
+        }
+
+        self.save()?;
+        self.remove_parts(&replaced)?;
+        result
+    }
+
+    /// Decrypts a part and writes it to a new part file, encrypted with fresh
+    /// random keys. Does not change the filesystem.
+    ///
+    /// # Arguments
+    ///
+    /// * `old` - Data object of the part.
+    /// * `id` - Id of the new part. Must not be in use.
+    ///
+    /// # Returns
+    ///
+    /// * The Data object of the new part.
+    fn rekey_part(&self, old: &Data, id: u64) -> Result<Data, Error> {
+        let content = self.read_part(old)?;
+        let (data, content) = encrypt_chunk(self.cipher, self.compression, &content)?;
+        let data = Data { id, ..data };
+
+        self.backend.write_part(data.id, &content)?;
+        Ok(data)
     }
 
     /// Sets file/folder metadata
@@ -585,18 +4528,39 @@ impl Store {
     /// * `key` - metadata key;
     /// * `value` - metadata value;
     pub fn metadata_set(&mut self, path: &str, key: &str, value: &str) -> Result<(), Error> {
-        let path: String = path.into();
-        let key: String = key.into();
-        let value: String = value.into();
+        let id = self.id_of(path)?;
+        self.metadata_set_id(id, key, value)
+    }
 
-        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+    /// Same as `metadata_set`, but takes the id of the node, see `id_of`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - id of the affected node;
+    /// * `key` - metadata key;
+    /// * `value` - metadata value;
+    pub fn metadata_set_id(&mut self, id: u64, key: &str, value: &str) -> Result<(), Error> {
+        self.fs.set_metadata(id, key, value)?;
 
-        if !self.fs.exists(&path.path)? {
-            return Err(Error::FileDoesNotExistError);
-        }
+        self.save()
+    }
 
-        let id = self.fs.touch(&path.path)?;
-        self.fs.set_metadata(id, &key, &value)?;
+    /// Sets metadata on a node and on everything below it, saving once.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the node;
+    /// * `key` - metadata key;
+    /// * `value` - metadata value;
+    pub fn metadata_set_recursive(
+        &mut self,
+        path: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<(), Error> {
+        for id in self.subtree(path)? {
+            self.fs.set_metadata(id, key, value)?;
+        }
 
         self.save()
     }
@@ -623,6 +4587,62 @@ impl Store {
         self.save()
     }
 
+    /// Renames a key in the node's metadata, keeping its value.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the affected node;
+    /// * `old_key` - Current name of the key;
+    /// * `new_key` - New name of the key;
+    /// * `overwrite` - Whether to replace the value of `new_key` if it
+    ///   already exists, instead of returning `MetadataKeyAlreadyExists`;
+    pub fn metadata_rename(
+        &mut self,
+        path: &str,
+        old_key: &str,
+        new_key: &str,
+        overwrite: bool,
+    ) -> Result<(), Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        let id = self
+            .fs
+            .lookup(&path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+        self.fs.rename_metadata(id, old_key, new_key, overwrite)?;
+
+        self.save()
+    }
+
+    /// Copies all metadata from one node to another. Keys that exist in both
+    /// nodes get the value from `from_path`; other keys of `to_path` are kept.
+    /// The `hash` and `mimetype` keys describe the content of `to_path`, so
+    /// they are not copied.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_path` - Path of the node to copy the metadata from;
+    /// * `to_path` - Path of the node to copy the metadata to;
+    pub fn metadata_copy(&mut self, from_path: &str, to_path: &str) -> Result<(), Error> {
+        let from_path: String = from_path.into();
+        let to_path: String = to_path.into();
+        let from_path = Path::new(&from_path).ok_or(Error::CannotParseError)?;
+        let to_path = Path::new(&to_path).ok_or(Error::CannotParseError)?;
+
+        let from = self
+            .fs
+            .lookup(&from_path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+        let to = self
+            .fs
+            .lookup(&to_path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+        self.fs.copy_metadata(from, to)?;
+
+        self.save()
+    }
+
     /// Gets file/folder metadata
     ///
     /// # Arguments
@@ -647,6 +4667,68 @@ impl Store {
         self.fs.get_metadata(id, &key)
     }
 
+    /// Sets file/folder metadata, keeping the type of the value. The value is
+    /// still visible as a string through `metadata_get` and `metadata_list`,
+    /// and setting the key with `metadata_set` makes it a string again.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the affected node;
+    /// * `key` - metadata key;
+    /// * `value` - metadata value;
+    pub fn metadata_set_typed(
+        &mut self,
+        path: &str,
+        key: &str,
+        value: MetadataValue,
+    ) -> Result<(), Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        let id = self
+            .fs
+            .lookup(&path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+        self.fs.set_typed_metadata(id, key, &value)?;
+
+        self.save()
+    }
+
+    /// Gets file/folder metadata as the type it was set with. Values set with
+    /// `metadata_set` are returned as `MetadataValue::Str`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the affected node;
+    /// * `key` - metadata key;
+    pub fn metadata_get_typed(&self, path: &str, key: &str) -> Result<MetadataValue, Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        let id = self
+            .fs
+            .lookup(&path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+        self.fs.get_typed_metadata(id, key)
+    }
+
+    /// Lists files whose value for a metadata key lies in a range. The name of
+    /// the files are their paths. `Int` and `Float` values are compared as
+    /// numbers; values of other types only match bounds of the same type.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Metadata key to look at;
+    /// * `range` - Range the value must lie in, e.g.
+    ///   `MetadataValue::Int(1)..=MetadataValue::Int(10)`.
+    pub fn metadata_search<R: std::ops::RangeBounds<MetadataValue>>(
+        &self,
+        key: &str,
+        range: R,
+    ) -> Vec<File> {
+        self.fs.search_metadata(key, range)
+    }
+
     /// Returns file/folder metadata
     ///
     /// # Arguments
@@ -677,14 +4759,17 @@ impl Store {
     /// * `id` - Id of the file to add the tag to.
     /// * `tag` - Name of the tag to add.
     pub fn tag_add(&mut self, path: &str, tag: &str) -> Result<(), Error> {
-        let path: String = path.into();
-        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
-
-        if !self.fs.exists(&path.path)? {
-            return Err(Error::FileDoesNotExistError);
-        }
+        let id = self.id_of(path)?;
+        self.tag_add_id(id, tag)
+    }
 
-        let id = self.fs.touch(&path.path)?;
+    /// Same as `tag_add`, but takes the id of the node, see `id_of`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the file to add the tag to.
+    /// * `tag` - Name of the tag to add.
+    pub fn tag_add_id(&mut self, id: u64, tag: &str) -> Result<(), Error> {
         self.fs.add_tag(id, tag)?;
 
         self.save()
@@ -697,19 +4782,65 @@ impl Store {
     /// * `id` - Node's id.
     /// * `tag` - Tag to remove.
     pub fn tag_rm(&mut self, path: &str, tag: &str) -> Result<(), Error> {
-        let path: String = path.into();
-        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+        let id = self.id_of(path)?;
+        self.tag_rm_id(id, tag)
+    }
 
-        if !self.fs.exists(&path.path)? {
-            return Err(Error::FileDoesNotExistError);
+    /// Same as `tag_rm`, but takes the id of the node, see `id_of`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Node's id.
+    /// * `tag` - Tag to remove.
+    pub fn tag_rm_id(&mut self, id: u64, tag: &str) -> Result<(), Error> {
+        self.fs.rm_tag(id, tag)?;
+
+        self.save()
+    }
+
+    /// Adds a tag to a node and to everything below it, saving once.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the node.
+    /// * `tag` - Name of the tag to add.
+    pub fn tag_add_recursive(&mut self, path: &str, tag: &str) -> Result<(), Error> {
+        for id in self.subtree(path)? {
+            self.fs.add_tag(id, tag)?;
         }
 
-        let id = self.fs.touch(&path.path)?;
-        self.fs.rm_tag(id, tag)?;
+        self.save()
+    }
+
+    /// Removes a tag from a node and from everything below it, saving once.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the node.
+    /// * `tag` - Tag to remove.
+    pub fn tag_rm_recursive(&mut self, path: &str, tag: &str) -> Result<(), Error> {
+        for id in self.subtree(path)? {
+            self.fs.rm_tag(id, tag)?;
+        }
 
         self.save()
     }
 
+    /// Returns the id of a node followed by the ids of all nodes below it.
+    /// The root is not a node, so only what is below it is returned for `/`.
+    fn subtree(&self, path: &str) -> Result<Vec<u64>, Error> {
+        let path: String = path.into();
+        let path = Path::new(&path).ok_or(Error::CannotParseError)?;
+
+        let id = self
+            .fs
+            .lookup(&path.path)?
+            .ok_or(Error::FileDoesNotExistError)?;
+        let mut ids = if id == 0 { vec![] } else { vec![id] };
+        ids.extend(self.fs.descendants(id));
+        Ok(ids)
+    }
+
     /// Clears all tags from a node.
     ///
     /// # Arguments
@@ -729,6 +4860,20 @@ impl Store {
         self.save()
     }
 
+    /// Renames a tag on every node of the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `old` - Tag to rename.
+    /// * `new` - New name of the tag.
+    pub fn tag_rename(&mut self, old: &str, new: &str) -> Result<(), Error> {
+        if old == new {
+            return Ok(());
+        }
+        self.fs.rename_tag(old, new);
+        self.save()
+    }
+
     /// List all tags in the filesystem.
     ///
     /// # Returns
@@ -738,6 +4883,16 @@ impl Store {
         self.fs.list_tag()
     }
 
+    /// Counts how many files and folders have each tag, for example to
+    /// size the tags of a tag cloud.
+    ///
+    /// # Returns
+    ///
+    /// * A map from each tag in the filesystem to the number of its nodes.
+    pub fn tag_counts(&self) -> HashMap<String, usize> {
+        self.fs.count_tags()
+    }
+
     /// List all tags in a node.
     ///
     /// # Arguments
@@ -774,6 +4929,26 @@ impl Store {
     ///
     /// * A list of files matching the given tags.
     pub fn tag_search(&self, tags: Vec<String>) -> Vec<File> {
-        self.fs.search_tag(tags)
+        match &self.search_index {
+            Some(index) => self.fs.search_tag_indexed(tags, index),
+            None => self.fs.search_tag(tags),
+        }
+    }
+
+    /// Lists files and folders whose name contains a text, ignoring case.
+    /// The names of the files are their paths.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - Text to look for in the names.
+    ///
+    /// # Returns
+    ///
+    /// * A list of files matching.
+    pub fn find(&self, text: &str) -> Vec<File> {
+        match &self.search_index {
+            Some(index) => self.fs.search_name_indexed(text, index),
+            None => self.fs.search_name(text),
+        }
     }
 }